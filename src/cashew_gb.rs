@@ -1,5 +1,41 @@
 use std::cmp;
 
+pub mod cdl;
+pub mod cheat;
+#[cfg(feature = "gbc")]
+mod colourisation;
+pub mod dap;
+pub mod debug;
+pub mod disasm;
+pub mod dmg07;
+mod header;
+pub mod hooks;
+pub mod ir;
+pub mod link;
+pub mod memory;
+pub mod midi;
+pub mod mobile;
+pub mod palette;
+pub mod printer;
+pub mod profile;
+pub mod sgb;
+pub mod single_step;
+pub mod trace;
+pub mod viewer;
+
+pub use header::{CartridgeHeader, HEADER_SIZE};
+pub use memory::Registers;
+pub use palette::{DmgPalette, PaletteConfig};
+pub use sgb::Sgb;
+
+use cdl::{CodeDataLog, CDL_DATA, CDL_DMA, CDL_OPCODE, CDL_OPERAND};
+use cheat::ActiveCheats;
+use debug::Debugger;
+use hooks::{Access, Event, Hooks};
+use profile::Profiler;
+use single_step::FlatMemory;
+use trace::Tracer;
+
 const LOG_CYCLE: u32 = 0;
 const LOG_EVERY: u32 = 10000;
 const LOG_SIZE: u32 = 100000;
@@ -54,14 +90,17 @@ const SERIAL_CYCLES_32KB: u16 = SERIAL_CYCLES / 32_16;
 #[cfg(feature = "gbc")]
 const SERIAL_CYCLES_64KB: u16 = SERIAL_CYCLES / 64_16;
 
-const DMG_CLOCK_FREQ: f32 = 4194304.0;
+/// Cycles a second of the clock `Gb::get_clock` counts.
+pub const DMG_CLOCK_FREQ: u32 = 4194304;
 const SCREEN_REFRESH_CYCLES: f32 = 70224.0;
-const VERTICAL_SYNC: f32 = DMG_CLOCK_FREQ / SCREEN_REFRESH_CYCLES;
+const VERTICAL_SYNC: f32 = DMG_CLOCK_FREQ as f32 / SCREEN_REFRESH_CYCLES;
 
-const RTC_CYCLES: u32 = DMG_CLOCK_FREQ as u32;
+const RTC_CYCLES: u32 = DMG_CLOCK_FREQ;
 
 const SERIAL_SC_TX_START: u8 = 0x80;
 const SERIAL_SC_CLOCK_SRC: u8 = 0x01;
+#[cfg(feature = "gbc")]
+const SERIAL_SC_HIGH_SPEED: u8 = 0x02;
 
 const STAT_LYC_INTR: u8 = 0x40;
 const STAT_MODE_2_INTR: u8 = 0x20;
@@ -122,8 +161,6 @@ pub const JOYPAD_LEFT: u8 = 0x20;
 pub const JOYPAD_UP: u8 = 0x40;
 pub const JOYPAD_DOWN: u8 = 0x80;
 
-const ROM_HEADER_CHECKSUM_LOC: u16 = 0x014D;
-
 const IO_JOYP: usize = 0x00;
 const IO_SB: usize = 0x01;
 const IO_SC: usize = 0x02;
@@ -144,9 +181,23 @@ const IO_OBP0: usize = 0x48;
 const IO_OBP1: usize = 0x49;
 const IO_WY: usize = 0x4A;
 const IO_WX: usize = 0x4B;
+const IO_KEY0: usize = 0x4C;
 const IO_BANK: usize = 0x50;
+const IO_RP: usize = 0x56;
 const IO_IE: usize = 0xFF;
 
+const IO_KEY0_DMG_MODE: u8 = 0x04;
+
+const IO_RP_LED: u8 = 0x01;
+/// Reads 0 while light is being received.
+const IO_RP_SIGNAL: u8 = 0x02;
+const IO_RP_UNUSED: u8 = 0x3C;
+const IO_RP_READ_ENABLE: u8 = 0xC0;
+
+pub const DMG_BOOTROM_SIZE: usize = 0x0100;
+pub const CGB_BOOTROM_SIZE: usize = 0x0900;
+const CGB_BOOTROM_HIGH_ADDR: usize = 0x0200;
+
 const IO_TAC_RATE_MASK: u8 = 0x3;
 const IO_TAC_ENABLE_MASK: u8 = 0x4;
 
@@ -181,7 +232,23 @@ pub enum GbInitError<'a, T> {
     GbInitInvalidChecksum,
 }
 
-enum GbSerialRxRet {
+#[derive(Clone, Copy, PartialEq)]
+pub enum GbBootrom {
+    GbBootromDmg,
+    GbBootromCgb,
+}
+impl GbBootrom {
+    /// Picks the model from a boot ROM dump's size.
+    pub fn from_size(size: usize) -> Option<GbBootrom> {
+        match size {
+            DMG_BOOTROM_SIZE => Some(GbBootrom::GbBootromDmg),
+            CGB_BOOTROM_SIZE => Some(GbBootrom::GbBootromCgb),
+            _ => None,
+        }
+    }
+}
+
+pub enum GbSerialRxRet {
     GbSerialRxSuccess,
     GbSerialRxNoConnection,
 }
@@ -279,6 +346,8 @@ struct Count {
     tima_count: u16,
     serial_count: u16,
     rtc_count: u32,
+    /// Never reset, so linked consoles can compare times.
+    clock: u64,
 }
 impl Count {
     fn new() -> Count {
@@ -288,6 +357,7 @@ impl Count {
             tima_count: 0,
             serial_count: 0,
             rtc_count: 0,
+            clock: 0,
         }
     }
 }
@@ -432,6 +502,7 @@ pub struct Gb<'a, T> {
     cart_mode_select: u8,
     rtc_latched: CartRtc,
     rtc_real: CartRtc,
+    header: CartridgeHeader,
     cpu_reg: CpuRegisters,
     counter: Count,
     wram: Vec<u8>,
@@ -448,7 +519,18 @@ pub struct Gb<'a, T> {
     gb_error: Option<fn(&Gb<T>, GbError, u16) -> ()>,
     gb_serial_tx: Option<fn(&Gb<T>, u8) -> ()>,
     gb_serial_rx: Option<fn(&Gb<T>, &mut u8) -> GbSerialRxRet>,
+    gb_ir_tx: Option<fn(&Gb<T>, bool) -> ()>,
+    gb_ir_rx: Option<fn(&Gb<T>) -> bool>,
     gb_bootrom_read: Option<fn(&Gb<T>, usize) -> u8>,
+    bootrom: GbBootrom,
+    sgb: Option<Box<Sgb>>,
+    cheats: ActiveCheats,
+    debugger: Option<Box<Debugger>>,
+    tracer: Option<Box<Tracer>>,
+    code_data_log: Option<Box<CodeDataLog>>,
+    profiler: Option<Box<Profiler>>,
+    hooks: Option<Box<Hooks<T>>>,
+    flat_memory: Option<Box<FlatMemory>>,
     pub cycle: u32, //rmv
     pub quit: bool, //rmv
     context: &'a T,
@@ -540,26 +622,63 @@ impl<'a, T> Gb<'a, T> {
         self.cpu_reg.f.set_z((temp & 0xFF) == 0x00);
     }
     fn _read(&self, addr: usize) -> u8 {
+        let val = self._read_memory(addr);
+        if self.flat_memory.is_some() {
+            self._log_bus(addr, val, false);
+        }
+        if let Some(debugger) = &self.debugger {
+            debugger.watch(addr, val, false);
+        }
+        if self.code_data_log.is_some() {
+            self._log_read(addr, CDL_DATA);
+        }
+        if self.hooks.is_some() {
+            self._hook_memory(Access::Read, addr, val);
+        }
+        val
+    }
+    /// `_read` for OAM DMA and HDMA, logged as theirs.
+    fn _read_dma(&self, addr: usize) -> u8 {
+        let val = self._read_memory(addr);
+        if let Some(debugger) = &self.debugger {
+            debugger.watch(addr, val, false);
+        }
+        if self.code_data_log.is_some() {
+            self._log_read(addr, CDL_DMA);
+        }
+        if self.hooks.is_some() {
+            self._hook_memory(Access::Read, addr, val);
+        }
+        val
+    }
+    fn _read_memory(&self, addr: usize) -> u8 {
+        if let Some(flat) = &self.flat_memory {
+            return flat.memory[addr];
+        }
         match addr >> 12 {
             0x0 => {
-                if self.hram_io[IO_BANK] == 0 && addr < 0x0100 {
+                if self.hram_io[IO_BANK] == 0
+                    && (addr < DMG_BOOTROM_SIZE
+                        || (self.bootrom == GbBootrom::GbBootromCgb
+                            && (CGB_BOOTROM_HIGH_ADDR..CGB_BOOTROM_SIZE).contains(&addr)))
+                {
                     return self.gb_bootrom_read.unwrap()(self, addr);
                 } else {
-                    return (self.gb_rom_read)(self, addr);
+                    return self._rom_read(addr, addr);
                 }
             }
             0x1 | 0x2 | 0x3 => {
-                return (self.gb_rom_read)(self, addr);
+                return self._rom_read(addr, addr);
             }
             0x4 | 0x5 | 0x6 | 0x7 => {
                 if self.mbc == 1 && self.cart_mode_select != 0 {
-                    return (self.gb_rom_read)(
-                        self,
+                    return self._rom_read(
+                        addr,
                         addr + (((self.selected_rom_bank as usize & 0x1F) - 1) * ROM_BANK_SIZE),
                     );
                 } else {
-                    return (self.gb_rom_read)(
-                        self,
+                    return self._rom_read(
+                        addr,
                         addr + ((self.selected_rom_bank as usize - 1) * (ROM_BANK_SIZE)),
                     );
                 }
@@ -604,7 +723,7 @@ impl<'a, T> Gb<'a, T> {
                     return self.wram[addr - ECHO_ADDR];
                 }
                 if addr < UNUSED_ADDR {
-                    return self.wram[addr - OAM_ADDR];
+                    return self.oam[addr - OAM_ADDR];
                 }
                 if addr < IO_ADDR {
                     return 0xFF;
@@ -645,8 +764,14 @@ impl<'a, T> Gb<'a, T> {
                         0x55 => {
                             return (self.cgb.dma_active << 7) | (self.cgb.dma_size - 1);
                         }
-                        0x56 => {
-                            return self.hram_io[0x56];
+                        0x56 if self.cgb.mode != 0 => {
+                            let mut rp = self.hram_io[IO_RP] | IO_RP_UNUSED | IO_RP_SIGNAL;
+                            if (rp & IO_RP_READ_ENABLE) == IO_RP_READ_ENABLE
+                                && self.gb_ir_rx.is_some_and(|rx| rx(self))
+                            {
+                                rp &= !IO_RP_SIGNAL;
+                            }
+                            return rp;
                         }
                         0x68 => {
                             return (self.cgb.bg_palette_id & 0x3F)
@@ -687,7 +812,32 @@ impl<'a, T> Gb<'a, T> {
     fn gb_read_pc(&mut self) -> u8 {
         let pc = self.cpu_reg.pc.bytes as usize;
         self.cpu_reg.pc.bytes = self.cpu_reg.pc.bytes.wrapping_add(1);
-        self._read(pc)
+        if self.code_data_log.is_some() {
+            self._log_read(pc, CDL_OPERAND);
+        }
+        let val = self._read_memory(pc);
+        if self.flat_memory.is_some() {
+            self._log_bus(pc, val, false);
+        }
+        val
+    }
+    /// Steps over the operands of a branch not taken, still fetching them.
+    fn gb_skip_pc(&mut self, size: u16) -> () {
+        for _ in 0..size {
+            self.gb_read_pc();
+        }
+    }
+    fn gb_read_opcode(&mut self) -> u8 {
+        let pc = self.cpu_reg.pc.bytes as usize;
+        self.cpu_reg.pc.bytes = self.cpu_reg.pc.bytes.wrapping_add(1);
+        if self.code_data_log.is_some() {
+            self._log_read(pc, CDL_OPCODE);
+        }
+        let val = self._read_memory(pc);
+        if self.flat_memory.is_some() {
+            self._log_bus(pc, val, false);
+        }
+        val
     }
     fn gb_read_sp(&mut self) -> u8 {
         let sp = self.cpu_reg.sp.bytes as usize;
@@ -695,6 +845,23 @@ impl<'a, T> Gb<'a, T> {
         self._read(sp)
     }
     fn _write(&mut self, addr: usize, val: u8) -> () {
+        if let Some(debugger) = &self.debugger {
+            debugger.watch(addr, val, true);
+        }
+        if self.flat_memory.is_some() {
+            self._log_bus(addr, val, true);
+        }
+        if self.hooks.is_some() {
+            self._write_hooked(addr, val);
+        } else {
+            self._write_memory(addr, val);
+        }
+    }
+    fn _write_memory(&mut self, addr: usize, val: u8) -> () {
+        if let Some(flat) = &mut self.flat_memory {
+            flat.memory[addr] = val;
+            return;
+        }
         match addr >> 12 {
             0x0 | 0x1 => {
                 if self.mbc > 0 && self.mbc != 2 && self.cart_ram != 0 {
@@ -897,11 +1064,21 @@ impl<'a, T> Gb<'a, T> {
                 }
                 match addr & 0xFF {
                     0x00 => {
+                        let mut joypad = self.direct.joypad;
+                        if let Some(sgb) = &mut self.sgb {
+                            if let Some(id) = sgb.write_joyp(val) {
+                                self.hram_io[IO_JOYP] = val | id;
+                                return;
+                            }
+                            if sgb.get_player() != 0 {
+                                joypad = sgb.get_joypad(sgb.get_player());
+                            }
+                        }
                         self.hram_io[IO_JOYP] = val;
                         if self.hram_io[IO_JOYP] & 0x10 == 0 {
-                            self.hram_io[IO_JOYP] |= self.direct.joypad >> 4;
+                            self.hram_io[IO_JOYP] |= joypad >> 4;
                         } else {
-                            self.hram_io[IO_JOYP] |= self.direct.joypad & 0x0F;
+                            self.hram_io[IO_JOYP] |= joypad & 0x0F;
                         }
                         return;
                     }
@@ -979,7 +1156,7 @@ impl<'a, T> Gb<'a, T> {
                         }
 
                         for i in 0..OAM_SIZE {
-                            self.oam[i] = self._read(dma_addr + i)
+                            self.oam[i] = self._read_dma(dma_addr + i)
                         }
                         return;
                     }
@@ -1032,6 +1209,18 @@ impl<'a, T> Gb<'a, T> {
                                     self.cgb.double_speed_prep = val & 1;
                                     return;
                                 }
+                                0x4C => {
+                                    /* Only the CGB boot ROM can drop to DMG mode. */
+                                    if self.hram_io[IO_BANK] == 0 {
+                                        self.hram_io[IO_KEY0] = val;
+                                        if val & IO_KEY0_DMG_MODE != 0 {
+                                            self.cgb.mode = 0;
+                                            self.cgb.vram_bank_offset = VRAM_ADDR;
+                                            self.cgb.wram_bank_offset = WRAM_1_ADDR - (1 << 12);
+                                        }
+                                    }
+                                    return;
+                                }
                                 0x4F => {
                                     self.cgb.vram_bank = val & 0x01;
                                     if self.cgb.mode != 0 {
@@ -1069,7 +1258,7 @@ impl<'a, T> Gb<'a, T> {
                                                     ((self.cgb.dma_dest as usize & 0x1FF0)
                                                         | 0x8000)
                                                         + i,
-                                                    self._read(
+                                                    self._read_dma(
                                                         (self.cgb.dma_source as usize & 0xFFF0) + i,
                                                     ),
                                                 );
@@ -1082,8 +1271,15 @@ impl<'a, T> Gb<'a, T> {
                                     self.cgb.dma_active = self.cgb.dma_mode ^ 1;
                                     return;
                                 }
-                                0x56 => {
-                                    self.hram_io[0x56] = val;
+                                0x56 if self.cgb.mode != 0 => {
+                                    let led_changed =
+                                        ((self.hram_io[IO_RP] ^ val) & IO_RP_LED) != 0;
+                                    self.hram_io[IO_RP] = val & (IO_RP_LED | IO_RP_READ_ENABLE);
+                                    if led_changed {
+                                        if let Some(tx) = self.gb_ir_tx {
+                                            tx(self, (val & IO_RP_LED) != 0);
+                                        }
+                                    }
                                     return;
                                 }
                                 0x68 => {
@@ -1094,18 +1290,10 @@ impl<'a, T> Gb<'a, T> {
                                 0x69 => {
                                     self.cgb.bg_palette[(self.cgb.bg_palette_id & 0x3F) as usize] =
                                         val;
-                                    let fix_palette_temp = ((self.cgb.bg_palette
-                                        [(self.cgb.bg_palette_id as usize & 0x3E) + 1]
-                                        as u16)
-                                        << 8)
-                                        + (self.cgb.bg_palette
-                                            [self.cgb.bg_palette_id as usize & 0x3E])
-                                            as u16;
-                                    self.cgb.fix_palette
-                                        [(self.cgb.bg_palette_id as usize & 0x3E) >> 1] =
-                                        ((fix_palette_temp & 0x7C00) >> 10)
-                                            | (fix_palette_temp & 0x03E0)
-                                            | ((fix_palette_temp & 0x001F) << 10);
+                                    self._update_fix_colour(
+                                        (self.cgb.bg_palette_id as usize & 0x3E) >> 1,
+                                        false,
+                                    );
                                     if self.cgb.bg_palette_inc != 0 {
                                         self.cgb.bg_palette_id += 1;
                                         self.cgb.bg_palette_id = (self.cgb.bg_palette_id) & 0x3F;
@@ -1120,18 +1308,10 @@ impl<'a, T> Gb<'a, T> {
                                 0x6B => {
                                     self.cgb.oam_palette[self.cgb.oam_palette_id as usize & 0x3F] =
                                         val;
-                                    let fix_palette_temp = ((self.cgb.oam_palette
-                                        [(self.cgb.oam_palette_id as usize & 0x3E) + 1]
-                                        as u16)
-                                        << 8)
-                                        + (self.cgb.oam_palette
-                                            [self.cgb.oam_palette_id as usize & 0x3E])
-                                            as u16;
-                                    self.cgb.fix_palette
-                                        [0x20 + ((self.cgb.oam_palette_id as usize & 0x3E) >> 1)] =
-                                        ((fix_palette_temp & 0x7C00) >> 10)
-                                            | (fix_palette_temp & 0x03E0)
-                                            | ((fix_palette_temp & 0x001F) << 10);
+                                    self._update_fix_colour(
+                                        (self.cgb.oam_palette_id as usize & 0x3E) >> 1,
+                                        true,
+                                    );
                                     if self.cgb.oam_palette_inc != 0 {
                                         self.cgb.oam_palette_id += 1;
                                         self.cgb.oam_palette_id = (self.cgb.oam_palette_id) & 0x3F;
@@ -1642,6 +1822,11 @@ impl<'a, T> Gb<'a, T> {
             }
         }
 
+        if let Some(sgb) = &mut self.sgb {
+            if !sgb.draw_line(self.hram_io[IO_LY], &mut pixels) {
+                return;
+            }
+        }
         self.display.lcd_draw_line.unwrap()(self, pixels, self.hram_io[IO_LY]);
     }
 
@@ -2077,6 +2262,11 @@ impl<'a, T> Gb<'a, T> {
             }
         }
 
+        if let Some(sgb) = &mut self.sgb {
+            if !sgb.draw_line(self.hram_io[IO_LY], &mut pixels) {
+                return;
+            }
+        }
         self.display.lcd_draw_line.unwrap()(self, pixels, self.hram_io[IO_LY]);
     }
 
@@ -2135,11 +2325,29 @@ impl<'a, T> Gb<'a, T> {
                 self.cpu_reg.pc.bytes = CONTROL_INTR_ADDR as u16;
                 self.hram_io[IO_IF] ^= CONTROL_INTR;
             }
+            if self.profiler.is_some() {
+                self._profile_interrupt();
+            }
+            if self.hooks.is_some() {
+                self._hook_event(Event::Interrupt(self.cpu_reg.pc.bytes));
+            }
 
             break;
         }
 
-        let opcode = self.gb_read_pc();
+        if self.debugger.is_some() && self._debug_break() {
+            return;
+        }
+        if self.tracer.is_some() {
+            self._trace();
+        }
+        let profile_start = self.profiler.is_some().then(|| self._profile_start());
+        if self.hooks.is_some() {
+            let pc = self.cpu_reg.pc.bytes as usize;
+            self._hook_memory(Access::Execute, pc, self._read_memory(pc));
+        }
+
+        let opcode = self.gb_read_opcode();
         let mut inst_cycles = OP_CYCLES[opcode as usize];
         self.cycle += 1;
 
@@ -2336,7 +2544,7 @@ impl<'a, T> Gb<'a, T> {
                     self.cpu_reg.pc.bytes = (self.cpu_reg.pc.bytes as i16 + temp) as u16;
                     inst_cycles += 4;
                 } else {
-                    self.cpu_reg.pc.bytes += 1;
+                    self.gb_skip_pc(1);
                 }
             }
             0x21 => {
@@ -2409,7 +2617,7 @@ impl<'a, T> Gb<'a, T> {
                     self.cpu_reg.pc.bytes = (self.cpu_reg.pc.bytes as i16 + temp) as u16;
                     inst_cycles += 4;
                 } else {
-                    self.cpu_reg.pc.bytes += 1;
+                    self.gb_skip_pc(1);
                 }
             }
             0x29 => {
@@ -2461,7 +2669,7 @@ impl<'a, T> Gb<'a, T> {
                     self.cpu_reg.pc.bytes = (self.cpu_reg.pc.bytes as i16 + temp) as u16;
                     inst_cycles += 4;
                 } else {
-                    self.cpu_reg.pc.bytes += 1
+                    self.gb_skip_pc(1)
                 };
             }
             0x31 => {
@@ -2506,7 +2714,7 @@ impl<'a, T> Gb<'a, T> {
                     self.cpu_reg.pc.bytes = (self.cpu_reg.pc.bytes as i16 + temp) as u16;
                     inst_cycles += 4;
                 } else {
-                    self.cpu_reg.pc.bytes += 1;
+                    self.gb_skip_pc(1);
                 }
             }
             0x39 => {
@@ -2720,7 +2928,7 @@ impl<'a, T> Gb<'a, T> {
                 }
 
                 if (self.hram_io[IO_SC] & SERIAL_SC_TX_START) != 0 {
-                    let serial_cycles = SERIAL_CYCLES - self.counter.serial_count;
+                    let serial_cycles = self._serial_cycles() - self.counter.serial_count;
 
                     if (serial_cycles as i16) < halt_cycles {
                         halt_cycles = serial_cycles as i16;
@@ -3009,17 +3217,17 @@ impl<'a, T> Gb<'a, T> {
             0xC2 => {
                 if self.cpu_reg.f.get_z() == 0 {
                     let c = self.gb_read_pc();
-                    let p = self._read(self.cpu_reg.pc.bytes as usize);
+                    let p = self.gb_read_pc();
                     self.cpu_reg.pc.set_lo(c);
                     self.cpu_reg.pc.set_hi(p);
                     inst_cycles += 4;
                 } else {
-                    self.cpu_reg.pc.bytes += 2;
+                    self.gb_skip_pc(2);
                 }
             }
             0xC3 => {
                 let c = self.gb_read_pc();
-                let p = self._read(self.cpu_reg.pc.bytes as usize);
+                let p = self.gb_read_pc();
                 self.cpu_reg.pc.set_lo(c);
                 self.cpu_reg.pc.set_hi(p);
             }
@@ -3035,7 +3243,7 @@ impl<'a, T> Gb<'a, T> {
                     self.cpu_reg.pc.set_hi(p);
                     inst_cycles += 12;
                 } else {
-                    self.cpu_reg.pc.bytes += 2;
+                    self.gb_skip_pc(2);
                 }
             }
             0xC5 => {
@@ -3073,12 +3281,12 @@ impl<'a, T> Gb<'a, T> {
             0xCA => {
                 if self.cpu_reg.f.get_z() != 0 {
                     let c = self.gb_read_pc();
-                    let p = self._read(self.cpu_reg.pc.bytes as usize);
+                    let p = self.gb_read_pc();
                     self.cpu_reg.pc.set_lo(c);
                     self.cpu_reg.pc.set_hi(p);
                     inst_cycles += 4;
                 } else {
-                    self.cpu_reg.pc.bytes += 2;
+                    self.gb_skip_pc(2);
                 }
             }
             0xCB => {
@@ -3096,7 +3304,7 @@ impl<'a, T> Gb<'a, T> {
                     self.cpu_reg.pc.set_hi(p);
                     inst_cycles += 12;
                 } else {
-                    self.cpu_reg.pc.bytes += 2;
+                    self.gb_skip_pc(2);
                 }
             }
             0xCD => {
@@ -3138,12 +3346,12 @@ impl<'a, T> Gb<'a, T> {
             0xD2 => {
                 if self.cpu_reg.f.get_c() == 0 {
                     let c = self.gb_read_pc();
-                    let p = self._read(self.cpu_reg.pc.bytes as usize);
+                    let p = self.gb_read_pc();
                     self.cpu_reg.pc.set_lo(c);
                     self.cpu_reg.pc.set_hi(p);
                     inst_cycles += 4;
                 } else {
-                    self.cpu_reg.pc.bytes += 2;
+                    self.gb_skip_pc(2);
                 }
             }
             0xD4 => {
@@ -3158,7 +3366,7 @@ impl<'a, T> Gb<'a, T> {
                     self.cpu_reg.pc.set_hi(p);
                     inst_cycles += 12;
                 } else {
-                    self.cpu_reg.pc.bytes += 2;
+                    self.gb_skip_pc(2);
                 }
             }
             0xD5 => {
@@ -3204,12 +3412,12 @@ impl<'a, T> Gb<'a, T> {
             0xDA => {
                 if self.cpu_reg.f.get_c() != 0 {
                     let c = self.gb_read_pc();
-                    let p = self._read(self.cpu_reg.pc.bytes as usize);
+                    let p = self.gb_read_pc();
                     self.cpu_reg.pc.set_lo(c);
                     self.cpu_reg.pc.set_hi(p);
                     inst_cycles += 4;
                 } else {
-                    self.cpu_reg.pc.bytes += 2;
+                    self.gb_skip_pc(2);
                 }
             }
             0xDC => {
@@ -3224,7 +3432,7 @@ impl<'a, T> Gb<'a, T> {
                     self.cpu_reg.pc.set_hi(p);
                     inst_cycles += 12;
                 } else {
-                    self.cpu_reg.pc.bytes += 2;
+                    self.gb_skip_pc(2);
                 }
             }
             0xDE => {
@@ -3381,6 +3589,15 @@ impl<'a, T> Gb<'a, T> {
 
         let mut do_while_condition = true;
         while do_while_condition {
+            #[cfg(feature = "gbc")]
+            {
+                self.counter.clock += (inst_cycles as u64) >> self.cgb.double_speed;
+            }
+            #[cfg(not(feature = "gbc"))]
+            {
+                self.counter.clock += inst_cycles as u64;
+            }
+
             self.counter.div_count += inst_cycles as u16;
             while self.counter.div_count >= DIV_CYCLES {
                 self.hram_io[IO_DIV] = self.hram_io[IO_DIV].wrapping_add(1);
@@ -3449,19 +3666,12 @@ impl<'a, T> Gb<'a, T> {
             }
 
             if (self.hram_io[IO_SC] & SERIAL_SC_TX_START) != 0 {
-                let mut serial_cycles = SERIAL_CYCLES_1KB;
+                let serial_cycles = self._serial_cycles();
 
                 if self.counter.serial_count == 0 && self.gb_serial_tx.is_some() {
                     self.gb_serial_tx.unwrap()(self, self.hram_io[IO_SB])
                 };
 
-                #[cfg(feature = "gbc")]
-                {
-                    if self.hram_io[IO_SC] & 0x3 != 0 {
-                        serial_cycles = SERIAL_CYCLES_32KB;
-                    }
-                }
-
                 self.counter.serial_count += inst_cycles as u16;
 
                 if self.counter.serial_count >= serial_cycles {
@@ -3541,13 +3751,20 @@ impl<'a, T> Gb<'a, T> {
                 } else {
                     self.hram_io[IO_STAT] &= 0xFB;
                 }
+                if self.hooks.is_some() {
+                    self._hook_event(Event::Ly(self.hram_io[IO_LY]));
+                }
 
                 if self.hram_io[IO_LY] == LCD_HEIGHT {
                     self.hram_io[IO_STAT] =
                         (self.hram_io[IO_STAT] & !STAT_MODE) | IO_STAT_MODE_VBLANK;
                     self.gb_frame = true;
                     self.hram_io[IO_IF] |= VBLANK_INTR;
+                    self._write_cheats();
                     self.lcd_blank = false;
+                    if self.hooks.is_some() {
+                        self._hook_event(Event::VBlank);
+                    }
 
                     if (self.hram_io[IO_STAT] & STAT_MODE_1_INTR) != 0 {
                         self.hram_io[IO_IF] |= LCDC_INTR;
@@ -3581,7 +3798,7 @@ impl<'a, T> Gb<'a, T> {
                             for i in 0..0x10_usize {
                                 self._write(
                                     ((self.cgb.dma_dest as usize & 0x1FF0) | 0x8000) + i,
-                                    self._read((self.cgb.dma_source as usize & 0xFFF0) + i),
+                                    self._read_dma((self.cgb.dma_source as usize & 0xFFF0) + i),
                                 );
                             }
                             self.cgb.dma_source += 0x10;
@@ -3632,6 +3849,22 @@ impl<'a, T> Gb<'a, T> {
 
             do_while_condition = self.gb_halt && (self.hram_io[IO_IF] & self.hram_io[IO_IE]) == 0;
         }
+
+        if let Some(start) = profile_start {
+            self._profile_step(opcode, start);
+        }
+    }
+
+    /// Cycles per byte, 8192Hz or the CGB's 262144Hz. The speed bit does nothing
+    /// with an external clock, the other side sets the pace then.
+    fn _serial_cycles(&self) -> u16 {
+        #[cfg(feature = "gbc")]
+        {
+            if self.cgb.mode != 0 && (self.hram_io[IO_SC] & SERIAL_SC_HIGH_SPEED) != 0 {
+                return SERIAL_CYCLES_32KB;
+            }
+        }
+        SERIAL_CYCLES
     }
 
     pub fn run_frame(&mut self) -> () {
@@ -3641,19 +3874,11 @@ impl<'a, T> Gb<'a, T> {
         }
     }
 
-    pub fn get_save_size(&mut self) -> usize {
-        const RAM_SIZE_LOCATION: usize = 0x0149;
-        const RAM_SIZES: [usize; 5] = [0x00, 0x800, 0x2000, 0x8000, 0x20000];
-        let ram_size = (self.gb_rom_read)(self, RAM_SIZE_LOCATION);
-
-        if self.mbc == 2 {
-            return 0x200;
-        }
-
-        return RAM_SIZES[ram_size as usize];
+    pub fn get_save_size(&self) -> usize {
+        self.header.get_save_size()
     }
 
-    fn gb_init_serial(
+    pub fn gb_init_serial(
         &mut self,
         gb_serial_tx: fn(&Gb<T>, u8) -> (),
         gb_serial_rx: fn(&Gb<T>, &mut u8) -> GbSerialRxRet,
@@ -3662,16 +3887,24 @@ impl<'a, T> Gb<'a, T> {
         self.gb_serial_rx = Some(gb_serial_rx)
     }
 
-    fn gb_colour_hash(&self) -> u8 {
-        let mut x: u8 = 0;
+    /// `gb_ir_tx` is called when the LED switches, `gb_ir_rx` asks whether the
+    /// receiver sees light, both at `get_clock`.
+    pub fn gb_init_ir(
+        &mut self,
+        gb_ir_tx: fn(&Gb<T>, bool) -> (),
+        gb_ir_rx: fn(&Gb<T>) -> bool,
+    ) -> () {
+        self.gb_ir_tx = Some(gb_ir_tx);
+        self.gb_ir_rx = Some(gb_ir_rx);
+    }
 
-        const ROM_TITLE_START_ADDR: usize = 0x0134;
-        const ROM_TITLE_END_ADDR: usize = 0x0143;
+    /// Cycles of the 4MiHz clock since power on, CGB double speed included.
+    pub fn get_clock(&self) -> u64 {
+        self.counter.clock
+    }
 
-        for i in ROM_TITLE_START_ADDR..(ROM_TITLE_END_ADDR + 1) {
-            x += (self.gb_rom_read)(self, i)
-        }
-        return x;
+    fn gb_colour_hash(&self) -> u8 {
+        self.header.get_title_checksum()
     }
 
     pub fn gb_reset(&mut self) -> () {
@@ -3685,8 +3918,23 @@ impl<'a, T> Gb<'a, T> {
 
         self.cycle = 0;
 
+        #[cfg(feature = "gbc")]
+        {
+            /* The CGB boot ROM starts in CGB mode and picks the mode itself. */
+            self.cgb.mode = if self.sgb.is_some() {
+                0
+            } else if self.gb_bootrom_read.is_some() && self.bootrom == GbBootrom::GbBootromCgb {
+                1
+            } else {
+                self.header.is_cgb_supported() as u8
+            };
+        }
+        if let Some(sgb) = &mut self.sgb {
+            **sgb = Sgb::new();
+        }
+
         if self.gb_bootrom_read.is_none() {
-            let hdr_chk = (self.gb_rom_read)(self, ROM_HEADER_CHECKSUM_LOC as usize) != 0;
+            let hdr_chk = self.header.get_header_checksum() != 0;
 
             self.cpu_reg.a = 0x01;
             self.cpu_reg.f.set_z(true);
@@ -3717,6 +3965,16 @@ impl<'a, T> Gb<'a, T> {
                     self.hram_io[IO_DIV] = 0xFF;
                 }
             }
+            if self.sgb.is_some() {
+                self.cpu_reg.a = 0x01;
+                self.cpu_reg.f.set_z(false);
+                self.cpu_reg.f.set_n(false);
+                self.cpu_reg.f.set_h(false);
+                self.cpu_reg.f.set_c(false);
+                self.cpu_reg.bc.bytes = 0x0014;
+                self.cpu_reg.de.bytes = 0x0000;
+                self.cpu_reg.hl.bytes = 0xC060;
+            }
 
             self.vram.fill(0x00);
         } else {
@@ -3786,6 +4044,10 @@ impl<'a, T> Gb<'a, T> {
             self.cgb.dma_size = 0;
             self.cgb.dma_source = 0;
             self.cgb.dma_dest = 0;
+
+            if self.gb_bootrom_read.is_none() && self.cgb.mode == 0 && self.sgb.is_none() {
+                self.gb_colourise(0xFF);
+            }
         }
     }
 
@@ -3793,8 +4055,73 @@ impl<'a, T> Gb<'a, T> {
         self.direct.joypad = joypad;
     }
 
-    pub fn get_palette(&self) -> &[u16; 0x40] {
-        &self.cgb.fix_palette
+    pub fn get_palette(&self) -> [u16; 0x40] {
+        if let Some(sgb) = &self.sgb {
+            return sgb.get_palette();
+        }
+        #[allow(unused_mut)]
+        let mut palette = self.cgb.fix_palette;
+        #[cfg(feature = "12-colour")]
+        {
+            /* DMG pixels carry their 12-colour palette instead of a CGB one. */
+            if self.cgb.mode == 0 {
+                palette[..4].copy_from_slice(&self.cgb.fix_palette[0x20..0x24]);
+                palette[LCD_PALETTE_OBJ as usize..][..4]
+                    .copy_from_slice(&self.cgb.fix_palette[0x24..0x28]);
+                palette[LCD_PALETTE_BG as usize..][..4].copy_from_slice(&self.cgb.fix_palette[..4]);
+            }
+        }
+        palette
+    }
+
+    /// Loads DMG colours into the CGB palettes used in DMG mode. Can be called
+    /// mid-game, CGB games keep their own palettes.
+    #[cfg(feature = "gbc")]
+    pub fn gb_set_dmg_palette(&mut self, palette: &DmgPalette) -> () {
+        if self.cgb.mode != 0 {
+            return;
+        }
+        for (i, colour) in palette.bg.iter().enumerate() {
+            self.cgb.bg_palette[i << 1..][..2].copy_from_slice(&colour.to_le_bytes());
+        }
+        for (i, colour) in palette.obj0.iter().chain(&palette.obj1).enumerate() {
+            self.cgb.oam_palette[i << 1..][..2].copy_from_slice(&colour.to_le_bytes());
+        }
+        self._update_fix_palette();
+    }
+
+    /// Picks DMG colours the way the CGB boot ROM does, from the title or from
+    /// a button combination held in `joypad` (active low, as in `set_joypad`).
+    #[cfg(feature = "gbc")]
+    pub fn gb_colourise(&mut self, joypad: u8) -> () {
+        let combination = colourisation::joypad_combination(!joypad).unwrap_or_else(|| {
+            colourisation::find_combination(&self.header, self.gb_colour_hash())
+        });
+        self.gb_set_dmg_palette(&colourisation::get_palette(combination));
+    }
+
+    #[cfg(feature = "gbc")]
+    fn _update_fix_palette(&mut self) -> () {
+        for i in 0..0x20_usize {
+            self._update_fix_colour(i, false);
+            self._update_fix_colour(i, true);
+        }
+    }
+
+    /// Converts colour `i` of the BG or OBJ palettes for the LCD.
+    #[cfg(feature = "gbc")]
+    fn _update_fix_colour(&mut self, i: usize, obj: bool) -> () {
+        let (colours, offset) = if obj {
+            (&self.cgb.oam_palette, 0x20)
+        } else {
+            (&self.cgb.bg_palette, 0)
+        };
+        let colour = u16::from_le_bytes([colours[i << 1], colours[(i << 1) + 1]]);
+        self.cgb.fix_palette[offset + i] = palette::to_rgb555(colour);
+    }
+
+    pub fn get_header(&self) -> &CartridgeHeader {
+        &self.header
     }
 
     pub fn new(
@@ -3804,23 +4131,6 @@ impl<'a, T> Gb<'a, T> {
         gb_cart_ram_write: fn(&Gb<T>, usize, u8) -> (),
         gb_error: Option<fn(&Gb<T>, GbError, u16) -> ()>,
     ) -> GbInitError<T> {
-        #[cfg(feature = "gbc")]
-        const CGB_FLAG: u16 = 0x0143;
-        const MBC_LOCATION: u16 = 0x0147;
-        const BANK_COUNT_LOCATION: u16 = 0x0148;
-        const RAM_SIZE_LOCATION: u16 = 0x0149;
-
-        const CART_MBC: [i8; 32] = [
-            0, 1, 1, 1, -1, 2, 2, -1, 0, 0, -1, 0, 0, 0, -1, 3, 3, 3, 3, 3, -1, -1, -1, -1, -1, 5,
-            5, 5, 5, 5, 5, -1,
-        ];
-        const CART_RAM: [u8; 32] = [
-            0, 0, 1, 1, 0, 1, 1, 0, 1, 1, 0, 0, 0, 0, 0, 0, 1, 0, 1, 1, 0, 0, 0, 0, 0, 0, 1, 1, 0,
-            0, 0, 0,
-        ];
-        const NUM_ROM_BANKS_MASK: [u16; 9] = [2, 4, 8, 16, 32, 64, 128, 256, 512];
-        const NUM_RAM_BANKS: [u8; 6] = [0, 1, 1, 4, 16, 8];
-
         let mut gb = Gb {
             gb_halt: true,
             gb_ime: true,
@@ -3836,6 +4146,7 @@ impl<'a, T> Gb<'a, T> {
             cart_mode_select: 0,
             rtc_latched: CartRtc::new(),
             rtc_real: CartRtc::new(),
+            header: CartridgeHeader::read(|_| 0),
             cpu_reg: CpuRegisters::new(),
             counter: Count::new(),
             wram: vec![0; WRAM_SIZE],
@@ -3852,62 +4163,52 @@ impl<'a, T> Gb<'a, T> {
             gb_error,
             gb_serial_tx: None,
             gb_serial_rx: None,
+            gb_ir_tx: None,
+            gb_ir_rx: None,
             gb_bootrom_read: None,
+            bootrom: GbBootrom::GbBootromDmg,
+            sgb: None,
+            cheats: ActiveCheats::default(),
+            debugger: None,
+            tracer: None,
+            code_data_log: None,
+            profiler: None,
+            hooks: None,
+            flat_memory: None,
             quit: false,
             cycle: 0,
             context,
         };
 
-        let mut x: u8 = 0;
-        for i in 0x0134..(0x014C + 1) {
-            x = x.wrapping_sub(gb_rom_read(&gb, i)).wrapping_sub(1);
-        }
+        gb.header = CartridgeHeader::read(|addr| gb_rom_read(&gb, addr));
 
-        if x != gb_rom_read(&gb, ROM_HEADER_CHECKSUM_LOC as usize) {
+        if !gb.header.is_header_checksum_valid() {
             return GbInitError::GbInitInvalidChecksum;
         }
 
-        gb.mbc = {
-            let mbc_value = gb_rom_read(&gb, MBC_LOCATION as usize);
-            if mbc_value as usize > std::mem::size_of::<[i8; 32]>() - 1
-                || CART_MBC[mbc_value as usize] == -1
-            {
-                return GbInitError::GbInitCartridgeUnsupported;
-            } else {
-                CART_MBC[mbc_value as usize]
-            }
+        let (Some(mbc), Some(num_rom_banks), Some(num_ram_banks)) = (
+            gb.header.get_mbc(),
+            gb.header.get_rom_banks(),
+            gb.header.get_ram_banks(),
+        ) else {
+            return GbInitError::GbInitCartridgeUnsupported;
         };
+        gb.mbc = mbc;
+        gb.cart_ram = gb.header.has_cart_ram() as u8;
+        gb.num_rom_banks_mask = num_rom_banks - 1;
+        gb.num_ram_banks = num_ram_banks;
 
-        gb.cart_ram = CART_RAM[gb_rom_read(&gb, MBC_LOCATION as usize) as usize];
-
-        gb.num_rom_banks_mask =
-            NUM_ROM_BANKS_MASK[gb_rom_read(&gb, BANK_COUNT_LOCATION as usize) as usize] - 1;
-
-        gb.num_ram_banks = NUM_RAM_BANKS[gb_rom_read(&gb, RAM_SIZE_LOCATION as usize) as usize];
-
-        gb.cgb = Cgb::new((gb_rom_read(&gb, CGB_FLAG as usize) & 0x80) >> 7);
+        #[cfg(feature = "gbc")]
+        {
+            gb.cgb = Cgb::new(gb.header.is_cgb_supported() as u8);
+        }
 
         gb.gb_reset();
         return GbInitError::GbInitNoError(gb);
     }
 
-    fn gb_get_rom_name(&self) -> String {
-        let mut title_loc: u16 = 0x134;
-        let title_end: u16 = 0x143;
-        let mut title_str = String::new();
-
-        while title_loc <= title_end {
-            let title_char = (self.gb_rom_read)(self, title_loc as usize);
-
-            if title_char >= b' ' && title_char <= b'_' {
-                title_str.push(title_char as char);
-            } else {
-                break;
-            }
-            title_loc += 1;
-        }
-
-        title_str
+    pub fn gb_get_rom_name(&self) -> String {
+        self.header.get_title()
     }
 
     #[cfg(feature = "lcd")]
@@ -3925,8 +4226,32 @@ impl<'a, T> Gb<'a, T> {
         return;
     }
 
-    fn gb_set_bootrom(&mut self, gb_bootrom_read: fn(&Gb<T>, usize) -> u8) {
+    /// Runs the boot ROM from the next `gb_reset`, instead of starting at 0x0100.
+    pub fn gb_set_bootrom(
+        &mut self,
+        gb_bootrom_read: fn(&Gb<T>, usize) -> u8,
+        bootrom: GbBootrom,
+    ) -> () {
         self.gb_bootrom_read = Some(gb_bootrom_read);
+        self.bootrom = bootrom;
+    }
+
+    pub fn gb_clear_bootrom(&mut self) -> () {
+        self.gb_bootrom_read = None;
+    }
+
+    /// Runs as a Super Game Boy from the next `gb_reset`. Pixels then carry
+    /// the SGB attribute palette and `get_palette` returns the SGB colours.
+    pub fn gb_set_sgb(&mut self, enabled: bool) -> () {
+        self.sgb = enabled.then(|| Box::new(Sgb::new()));
+    }
+
+    pub fn get_sgb(&self) -> Option<&Sgb> {
+        self.sgb.as_deref()
+    }
+
+    pub fn get_sgb_mut(&mut self) -> Option<&mut Sgb> {
+        self.sgb.as_deref_mut()
     }
 
     fn gb_set_rtc(&mut self, sec: u8, min: u8, hour: u8, yday: u16) {
@@ -3949,6 +4274,19 @@ struct SpriteData {
     x: u8,
 }
 
+/// The zlib CRC32, what IPS/UPS/BPS patches, PNG chunks and Mesen's `.cdl`
+/// files are checked with.
+pub fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0_u32;
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = (crc >> 1) ^ (0xEDB88320 & (crc & 1).wrapping_neg());
+        }
+    }
+    !crc
+}
+
 #[cfg(feature = "lcd")]
 #[cfg(feature = "high-lcd-accuracy")]
 fn compare_sprites(sd1: &Option<SpriteData>, sd2: &Option<SpriteData>) -> std::cmp::Ordering {
@@ -0,0 +1,190 @@
+use std::cell::Cell;
+
+use super::{
+    crc32, Gb, GbBootrom, CART_RAM_ADDR, CGB_BOOTROM_HIGH_ADDR, CGB_BOOTROM_SIZE, CRAM_BANK_SIZE,
+    DMG_BOOTROM_SIZE, IO_BANK, ROM_BANK_SIZE,
+};
+
+/* How a byte has been read, a byte of flags per ROM and cart RAM byte. */
+/// Fetched as the first byte of an instruction.
+pub const CDL_OPCODE: u8 = 0x01;
+/// Fetched as an instruction's operand, or the second byte of a CB opcode.
+pub const CDL_OPERAND: u8 = 0x02;
+/// Read by an instruction.
+pub const CDL_DATA: u8 = 0x04;
+/// Read by OAM DMA or HDMA.
+pub const CDL_DMA: u8 = 0x08;
+
+/// Mesen's `.cdl` starts with this and the ROM's CRC32.
+const MESEN_MAGIC: &[u8; 5] = b"CDLv2";
+const MESEN_CODE: u8 = 0x01;
+const MESEN_DATA: u8 = 0x02;
+
+/// The bytes of a ROM bank, or the whole cart RAM, by what read them. A byte
+/// read more than one way counts under each.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Coverage {
+    pub opcode: usize,
+    pub operand: usize,
+    pub data: usize,
+    pub dma: usize,
+    /// Never read at all.
+    pub unused: usize,
+}
+
+/// `CDL_*` flags for every byte of the ROM and cart RAM, by offset into them
+/// as the MBC maps it, so bank 2's 0x4000 is 0x8000. Reads through
+/// `gb_peek` aren't logged.
+pub struct CodeDataLog {
+    rom: Box<[Cell<u8>]>,
+    cart_ram: Box<[Cell<u8>]>,
+}
+impl CodeDataLog {
+    pub fn new(rom_size: usize, cart_ram_size: usize) -> CodeDataLog {
+        CodeDataLog {
+            rom: (0..rom_size).map(|_| Cell::new(0)).collect(),
+            cart_ram: (0..cart_ram_size).map(|_| Cell::new(0)).collect(),
+        }
+    }
+
+    /// The ROM's flags, bank after bank.
+    pub fn get_rom(&self) -> Vec<u8> {
+        self.rom.iter().map(Cell::get).collect()
+    }
+
+    pub fn get_cart_ram(&self) -> Vec<u8> {
+        self.cart_ram.iter().map(Cell::get).collect()
+    }
+
+    /// The flags of `addr` in ROM bank `bank`, 0 past the end of the ROM.
+    pub fn get_rom_flags(&self, bank: u16, addr: u16) -> u8 {
+        let offset = bank as usize * ROM_BANK_SIZE + (addr as usize % ROM_BANK_SIZE);
+        self.rom.get(offset).map_or(0, Cell::get)
+    }
+
+    /// `None` past the end of the ROM.
+    pub fn get_rom_coverage(&self, bank: u16) -> Option<Coverage> {
+        let start = bank as usize * ROM_BANK_SIZE;
+        let flags = self.rom.get(start..start + ROM_BANK_SIZE)?;
+        Some(coverage(flags))
+    }
+
+    pub fn get_cart_ram_coverage(&self) -> Coverage {
+        coverage(&self.cart_ram)
+    }
+
+    /// Forgets everything read so far.
+    pub fn clear(&self) {
+        self.rom
+            .iter()
+            .chain(self.cart_ram.iter())
+            .for_each(|flags| flags.set(0));
+    }
+
+    /// The `.cdl` Mesen loads for `rom`, with opcodes and operands as code
+    /// and DMA reads as data. It only covers the ROM.
+    pub fn to_mesen(&self, rom: &[u8]) -> Vec<u8> {
+        let mut cdl = Vec::with_capacity(MESEN_MAGIC.len() + 4 + self.rom.len());
+        cdl.extend_from_slice(MESEN_MAGIC);
+        cdl.extend_from_slice(&crc32(rom).to_le_bytes());
+        cdl.extend(self.rom.iter().map(|flags| {
+            let flags = flags.get();
+            let mut mesen = 0;
+            if flags & (CDL_OPCODE | CDL_OPERAND) != 0 {
+                mesen |= MESEN_CODE;
+            }
+            if flags & (CDL_DATA | CDL_DMA) != 0 {
+                mesen |= MESEN_DATA;
+            }
+            mesen
+        }));
+        cdl
+    }
+
+    fn mark(flags: &[Cell<u8>], offset: usize, flag: u8) {
+        if let Some(flags) = flags.get(offset) {
+            flags.set(flags.get() | flag);
+        }
+    }
+}
+
+fn coverage(flags: &[Cell<u8>]) -> Coverage {
+    let mut coverage = Coverage::default();
+    for flags in flags.iter().map(Cell::get) {
+        let count = |flag: u8, count: &mut usize| *count += (flags & flag != 0) as usize;
+        count(CDL_OPCODE, &mut coverage.opcode);
+        count(CDL_OPERAND, &mut coverage.operand);
+        count(CDL_DATA, &mut coverage.data);
+        count(CDL_DMA, &mut coverage.dma);
+        coverage.unused += (flags == 0) as usize;
+    }
+    coverage
+}
+
+impl<'a, T> Gb<'a, T> {
+    /// Starts logging into `log`, or stops with `None`. Returns the log
+    /// that was in use.
+    pub fn gb_set_code_data_log(&mut self, log: Option<CodeDataLog>) -> Option<CodeDataLog> {
+        std::mem::replace(&mut self.code_data_log, log.map(Box::new)).map(|log| *log)
+    }
+
+    pub fn get_code_data_log(&self) -> Option<&CodeDataLog> {
+        self.code_data_log.as_deref()
+    }
+
+    /// A log sized for the cartridge.
+    pub fn gb_new_code_data_log(&self) -> CodeDataLog {
+        let rom_size = (self.num_rom_banks_mask as usize + 1) * ROM_BANK_SIZE;
+        CodeDataLog::new(rom_size, self.get_save_size())
+    }
+
+    /// Marks what a read of `addr` lands on, with the offsets `_read_memory`
+    /// works out for it.
+    pub(super) fn _log_read(&self, addr: usize, flag: u8) -> () {
+        let Some(log) = &self.code_data_log else {
+            return;
+        };
+        if self.flat_memory.is_some() {
+            return;
+        }
+        match addr >> 12 {
+            0x0..=0x3 => {
+                let bootrom = self.hram_io[IO_BANK] == 0
+                    && (addr < DMG_BOOTROM_SIZE
+                        || (self.bootrom == GbBootrom::GbBootromCgb
+                            && (CGB_BOOTROM_HIGH_ADDR..CGB_BOOTROM_SIZE).contains(&addr)));
+                if !bootrom {
+                    CodeDataLog::mark(&log.rom, addr, flag);
+                }
+            }
+            0x4..=0x7 => {
+                let bank = if self.mbc == 1 && self.cart_mode_select != 0 {
+                    self.selected_rom_bank as usize & 0x1F
+                } else {
+                    self.selected_rom_bank as usize
+                };
+                let offset = addr - ROM_BANK_SIZE + bank * ROM_BANK_SIZE;
+                CodeDataLog::mark(&log.rom, offset, flag);
+            }
+            0xA | 0xB => {
+                if (self.mbc == 3 && self.cart_ram_bank >= 0x08)
+                    || self.cart_ram == 0
+                    || !self.enable_cart_ram
+                {
+                    return;
+                }
+                let offset = if self.mbc == 2 {
+                    addr & 0x1FF
+                } else if (self.cart_mode_select != 0 || self.mbc != 1)
+                    && self.cart_ram_bank < self.num_ram_banks
+                {
+                    addr - CART_RAM_ADDR + self.cart_ram_bank as usize * CRAM_BANK_SIZE
+                } else {
+                    addr - CART_RAM_ADDR
+                };
+                CodeDataLog::mark(&log.cart_ram, offset, flag);
+            }
+            _ => {}
+        }
+    }
+}
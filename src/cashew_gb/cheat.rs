@@ -0,0 +1,303 @@
+use super::palette::{split_key_value, strip_comment, unquote};
+use super::{
+    CartridgeHeader, Gb, CART_RAM_ADDR, CRAM_BANK_SIZE, ECHO_ADDR, HRAM_ADDR, INTR_EN_ADDR,
+    IO_ADDR, WRAM_0_ADDR, WRAM_1_ADDR,
+};
+
+pub const CHEAT_FILE: &str = "cheats.toml";
+
+const SECTION_CHEATS: &str = "cheats.";
+
+const GAME_GENIE_SIZE: usize = 6;
+const GAME_GENIE_COMPARE_SIZE: usize = 9;
+const GAME_GENIE_COMPARE_XOR: u8 = 0xBA;
+const GAME_SHARK_SIZE: usize = 8;
+const ROM_END: u16 = 0x8000;
+/// GameShark types from here select the CGB WRAM bank in the low bits.
+#[cfg(feature = "gbc")]
+const GAME_SHARK_WRAM_BANK: u8 = 0x90;
+
+#[derive(Debug, PartialEq)]
+pub enum GbCheatError {
+    /// Neither `ABC-DEF(-GHI)` nor `TTVVAAAA`.
+    GbCheatFormat,
+    GbCheatDigit,
+    /// Game Genie codes patch ROM, GameShark codes write RAM.
+    GbCheatAddress,
+    GbCheatSyntax {
+        line: usize,
+    },
+    GbCheatUnknownSection {
+        line: usize,
+    },
+    GbCheatCode {
+        line: usize,
+    },
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CheatCode {
+    /// Replaces the ROM byte read at `address`, only in banks where it was
+    /// `compare` when there is one.
+    GameGenie {
+        address: u16,
+        value: u8,
+        compare: Option<u8>,
+    },
+    /// Writes `value` to `address` every vblank. `bank` picks the cart RAM
+    /// bank, or the CGB WRAM bank from 0x90 up.
+    GameShark { bank: u8, address: u16, value: u8 },
+}
+impl CheatCode {
+    /// `ABC-DEF` or `ABC-DEF-GHI` for Game Genie, `TTVVAAAA` for GameShark,
+    /// dashes and spaces aside.
+    pub fn parse(code: &str) -> Result<CheatCode, GbCheatError> {
+        let digits = code
+            .chars()
+            .filter(|c| *c != '-' && !c.is_whitespace())
+            .map(|c| c.to_digit(16).map(|digit| digit as u8))
+            .collect::<Option<Vec<u8>>>()
+            .ok_or(GbCheatError::GbCheatDigit)?;
+        let byte = |i: usize| (digits[i] << 4) | digits[i + 1];
+
+        match digits.len() {
+            GAME_SHARK_SIZE if !code.contains('-') => {
+                let address = u16::from_le_bytes([byte(4), byte(6)]);
+                /* Echo RAM, OAM and IO aren't GameShark targets. */
+                let ram = (CART_RAM_ADDR..INTR_EN_ADDR).contains(&(address as usize))
+                    && !(ECHO_ADDR..HRAM_ADDR).contains(&(address as usize));
+                if !ram {
+                    return Err(GbCheatError::GbCheatAddress);
+                }
+                Ok(CheatCode::GameShark {
+                    bank: byte(0),
+                    address,
+                    value: byte(2),
+                })
+            }
+            GAME_GENIE_SIZE | GAME_GENIE_COMPARE_SIZE => {
+                /* The address is scrambled, its top nibble comes last and inverted. */
+                let address = (((digits[5] ^ 0x0F) as u16) << 12)
+                    | ((digits[2] as u16) << 8)
+                    | ((digits[3] as u16) << 4)
+                    | digits[4] as u16;
+                if address >= ROM_END {
+                    return Err(GbCheatError::GbCheatAddress);
+                }
+                /* The compare value is G and I, H is never used. */
+                let compare = (digits.len() == GAME_GENIE_COMPARE_SIZE).then(|| {
+                    ((digits[6] << 4) | digits[8]).rotate_right(2) ^ GAME_GENIE_COMPARE_XOR
+                });
+                Ok(CheatCode::GameGenie {
+                    address,
+                    value: byte(0),
+                    compare,
+                })
+            }
+            _ => Err(GbCheatError::GbCheatFormat),
+        }
+    }
+}
+
+/// One entry in the menu, some cheats need several codes.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Cheat {
+    pub name: String,
+    pub codes: Vec<CheatCode>,
+    pub enabled: bool,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum GameKey {
+    Title(String),
+    GlobalChecksum(u16),
+}
+
+/// Cheats for each game, read from the same TOML subset as palettes:
+///
+/// ```toml
+/// [cheats."TETRIS"]                      # by title
+/// "Always long bar" = "00A-17B-C49"
+///
+/// [cheats.0x91E6]                        # by global checksum
+/// "Max money" = ["01999DD1", "01999ED1"]
+/// ```
+///
+/// Every cheat starts enabled, the menu turns them off.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct CheatConfig {
+    games: Vec<(GameKey, Vec<Cheat>)>,
+}
+impl CheatConfig {
+    pub fn new() -> CheatConfig {
+        CheatConfig::default()
+    }
+
+    pub fn parse(text: &str) -> Result<CheatConfig, GbCheatError> {
+        let mut config = CheatConfig::new();
+
+        for (n, raw_line) in text.lines().enumerate() {
+            let line = n + 1;
+            let text = strip_comment(raw_line).trim();
+            if text.is_empty() {
+                continue;
+            }
+
+            if let Some(name) = text.strip_prefix('[').and_then(|t| t.strip_suffix(']')) {
+                let game = name
+                    .trim()
+                    .strip_prefix(SECTION_CHEATS)
+                    .ok_or(GbCheatError::GbCheatUnknownSection { line })?
+                    .trim();
+                let game = match game
+                    .strip_prefix("0x")
+                    .map(|hex| u16::from_str_radix(hex, 16))
+                {
+                    Some(Ok(checksum)) => GameKey::GlobalChecksum(checksum),
+                    _ => GameKey::Title(unquote(game).ok_or(GbCheatError::GbCheatSyntax { line })?),
+                };
+                config.games.push((game, Vec::new()));
+                continue;
+            }
+
+            let (name, value) =
+                split_key_value(text).ok_or(GbCheatError::GbCheatSyntax { line })?;
+            let Some((_, cheats)) = config.games.last_mut() else {
+                return Err(GbCheatError::GbCheatSyntax { line });
+            };
+            let codes = match value
+                .strip_prefix('[')
+                .and_then(|value| value.strip_suffix(']'))
+            {
+                Some(list) => list
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|item| !item.is_empty())
+                    .collect(),
+                None => vec![value],
+            };
+            let codes = codes
+                .into_iter()
+                .map(|code| unquote(code).and_then(|code| CheatCode::parse(&code).ok()))
+                .collect::<Option<Vec<CheatCode>>>()
+                .filter(|codes| !codes.is_empty())
+                .ok_or(GbCheatError::GbCheatCode { line })?;
+            cheats.push(Cheat {
+                name,
+                codes,
+                enabled: true,
+            });
+        }
+        Ok(config)
+    }
+
+    /// A game's cheats, by global checksum first then by title.
+    pub fn select(&self, header: &CartridgeHeader) -> Vec<Cheat> {
+        let title = GameKey::Title(header.get_title());
+        let checksum = GameKey::GlobalChecksum(header.get_global_checksum());
+        self.games
+            .iter()
+            .find(|(game, _)| *game == checksum)
+            .or_else(|| self.games.iter().find(|(game, _)| *game == title))
+            .map(|(_, cheats)| cheats.clone())
+            .unwrap_or_default()
+    }
+}
+
+/// The cheats on a `Gb` and, flattened out of the enabled ones, the codes
+/// the ROM read path and vblank apply.
+#[derive(Default)]
+pub(super) struct ActiveCheats {
+    cheats: Vec<Cheat>,
+    rom: Vec<(u16, u8, Option<u8>)>,
+    ram: Vec<(u8, u16, u8)>,
+}
+impl ActiveCheats {
+    fn update(&mut self) {
+        self.rom.clear();
+        self.ram.clear();
+        for code in self
+            .cheats
+            .iter()
+            .filter(|cheat| cheat.enabled)
+            .flat_map(|cheat| &cheat.codes)
+        {
+            match *code {
+                CheatCode::GameGenie {
+                    address,
+                    value,
+                    compare,
+                } => self.rom.push((address, value, compare)),
+                CheatCode::GameShark {
+                    bank,
+                    address,
+                    value,
+                } => self.ram.push((bank, address, value)),
+            }
+        }
+    }
+}
+
+impl<'a, T> Gb<'a, T> {
+    pub fn gb_set_cheats(&mut self, cheats: Vec<Cheat>) -> () {
+        self.cheats.cheats = cheats;
+        self.cheats.update();
+    }
+
+    pub fn get_cheats(&self) -> &[Cheat] {
+        &self.cheats.cheats
+    }
+
+    /// False if there's no cheat at `index`.
+    pub fn gb_set_cheat_enabled(&mut self, index: usize, enabled: bool) -> bool {
+        let Some(cheat) = self.cheats.cheats.get_mut(index) else {
+            return false;
+        };
+        cheat.enabled = enabled;
+        self.cheats.update();
+        true
+    }
+
+    /// ROM reads for `_read`, `addr` as the CPU sees it and `rom_addr` in the
+    /// ROM after banking.
+    pub(super) fn _rom_read(&self, addr: usize, rom_addr: usize) -> u8 {
+        let byte = (self.gb_rom_read)(self, rom_addr);
+        self.cheats
+            .rom
+            .iter()
+            .find(|(address, _, compare)| {
+                *address as usize == addr && compare.map_or(true, |compare| compare == byte)
+            })
+            .map_or(byte, |(_, value, _)| *value)
+    }
+
+    /// Applies the GameShark codes, at the start of vblank.
+    pub(super) fn _write_cheats(&mut self) -> () {
+        for i in 0..self.cheats.ram.len() {
+            let (bank, address, value) = self.cheats.ram[i];
+            let addr = address as usize;
+            match addr >> 12 {
+                0xA | 0xB => {
+                    let bank = bank & 0x0F;
+                    if self.cart_ram != 0 && bank < self.num_ram_banks {
+                        let offset = addr - CART_RAM_ADDR + bank as usize * CRAM_BANK_SIZE;
+                        (self.gb_cart_ram_write)(self, offset, value);
+                    }
+                }
+                0xC => self.wram[addr - WRAM_0_ADDR] = value,
+                0xD => {
+                    #[cfg(not(feature = "gbc"))]
+                    let offset = addr - WRAM_0_ADDR;
+                    #[cfg(feature = "gbc")]
+                    let offset = if bank >= GAME_SHARK_WRAM_BANK && self.cgb.mode != 0 {
+                        (bank as usize & 7).max(1) * super::WRAM_BANK_SIZE + (addr - WRAM_1_ADDR)
+                    } else {
+                        addr - self.cgb.wram_bank_offset
+                    };
+                    self.wram[offset] = value;
+                }
+                _ => self.hram_io[addr - IO_ADDR] = value,
+            }
+        }
+    }
+}
@@ -0,0 +1,1136 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::iter::Peekable;
+use std::path::Path;
+use std::str::Chars;
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::thread;
+
+use super::debug::{
+    Breakpoint, Condition, DebugRegister, RunUntil, StopReason, CALL_SIZE, OPCODE_CALL,
+    OPCODE_CALL_CC, OPCODE_RST, OPCODE_RST_MASK, RST_SIZE,
+};
+use super::disasm::SymbolTable;
+use super::{Gb, ROM_BANK_SIZE, VRAM_ADDR};
+
+/// The port editors are pointed at, `debugServer` in a VS Code launch config.
+pub const DAP_PORT: u16 = 4711;
+
+/// The SM83 is the one thread.
+const THREAD_ID: i64 = 1;
+/* variablesReference of the scopes, 0 is no children. */
+const REGISTERS_REFERENCE: i64 = 1;
+const STACK_REFERENCE: i64 = 2;
+/// How many words above SP the stack walk and the stack scope look at.
+const STACK_WORDS: u16 = 32;
+/// A watch reads up to this many bytes.
+const MAX_WATCH_BYTES: usize = 16;
+/// Messages from the wire are refused past these, they would exhaust the
+/// device's memory or stack.
+const MAX_MESSAGE_LENGTH: usize = 1024 * 1024;
+const MAX_JSON_DEPTH: usize = 32;
+const BASE64: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// The JSON DAP messages are made of, enough of it for them.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Json {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Json>),
+    /// Keys in the order they came.
+    Object(Vec<(String, Json)>),
+}
+static NULL: Json = Json::Null;
+impl Json {
+    pub fn parse(text: &str) -> Option<Json> {
+        let mut chars = text.chars().peekable();
+        let value = parse_value(&mut chars, MAX_JSON_DEPTH)?;
+        skip_whitespace(&mut chars);
+        chars.peek().is_none().then_some(value)
+    }
+
+    /// `Null` for a missing key or something that isn't an object.
+    pub fn get(&self, key: &str) -> &Json {
+        match self {
+            Json::Object(fields) => fields
+                .iter()
+                .find(|(name, _)| name == key)
+                .map_or(&NULL, |(_, value)| value),
+            _ => &NULL,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Json::String(text) => Some(text),
+            _ => None,
+        }
+    }
+
+    pub fn as_i64(&self) -> Option<i64> {
+        match self {
+            Json::Number(number) if number.fract() == 0.0 => Some(*number as i64),
+            _ => None,
+        }
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            Json::Bool(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    /// Empty for something that isn't an array.
+    pub fn as_array(&self) -> &[Json] {
+        match self {
+            Json::Array(values) => values,
+            _ => &[],
+        }
+    }
+}
+
+impl From<bool> for Json {
+    fn from(value: bool) -> Json {
+        Json::Bool(value)
+    }
+}
+
+impl From<i64> for Json {
+    fn from(value: i64) -> Json {
+        Json::Number(value as f64)
+    }
+}
+
+impl From<&str> for Json {
+    fn from(value: &str) -> Json {
+        Json::String(value.to_string())
+    }
+}
+
+impl From<String> for Json {
+    fn from(value: String) -> Json {
+        Json::String(value)
+    }
+}
+
+impl From<Vec<Json>> for Json {
+    fn from(values: Vec<Json>) -> Json {
+        Json::Array(values)
+    }
+}
+
+impl fmt::Display for Json {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Json::Null => write!(f, "null"),
+            Json::Bool(value) => write!(f, "{value}"),
+            Json::Number(number) => write!(f, "{number}"),
+            Json::String(text) => write_string(f, text),
+            Json::Array(values) => {
+                write!(f, "[")?;
+                for (i, value) in values.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ",")?;
+                    }
+                    write!(f, "{value}")?;
+                }
+                write!(f, "]")
+            }
+            Json::Object(fields) => {
+                write!(f, "{{")?;
+                for (i, (name, value)) in fields.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ",")?;
+                    }
+                    write_string(f, name)?;
+                    write!(f, ":{value}")?;
+                }
+                write!(f, "}}")
+            }
+        }
+    }
+}
+
+fn write_string(f: &mut fmt::Formatter, text: &str) -> fmt::Result {
+    write!(f, "\"")?;
+    for c in text.chars() {
+        match c {
+            '"' => write!(f, "\\\"")?,
+            '\\' => write!(f, "\\\\")?,
+            '\n' => write!(f, "\\n")?,
+            '\r' => write!(f, "\\r")?,
+            '\t' => write!(f, "\\t")?,
+            c if (c as u32) < 0x20 => write!(f, "\\u{:04x}", c as u32)?,
+            c => write!(f, "{c}")?,
+        }
+    }
+    write!(f, "\"")
+}
+
+fn skip_whitespace(chars: &mut Peekable<Chars>) {
+    while chars.next_if(|c| c.is_ascii_whitespace()).is_some() {}
+}
+
+/// A value nested at most `depth` arrays and objects deep.
+fn parse_value(chars: &mut Peekable<Chars>, depth: usize) -> Option<Json> {
+    skip_whitespace(chars);
+    match *chars.peek()? {
+        '{' | '[' if depth == 0 => None,
+        '{' => {
+            chars.next();
+            let mut fields = Vec::new();
+            skip_whitespace(chars);
+            if chars.next_if_eq(&'}').is_some() {
+                return Some(Json::Object(fields));
+            }
+            loop {
+                skip_whitespace(chars);
+                chars.next_if_eq(&'"')?;
+                let name = parse_string(chars)?;
+                skip_whitespace(chars);
+                chars.next_if_eq(&':')?;
+                fields.push((name, parse_value(chars, depth - 1)?));
+                skip_whitespace(chars);
+                match chars.next()? {
+                    ',' => continue,
+                    '}' => return Some(Json::Object(fields)),
+                    _ => return None,
+                }
+            }
+        }
+        '[' => {
+            chars.next();
+            let mut values = Vec::new();
+            skip_whitespace(chars);
+            if chars.next_if_eq(&']').is_some() {
+                return Some(Json::Array(values));
+            }
+            loop {
+                values.push(parse_value(chars, depth - 1)?);
+                skip_whitespace(chars);
+                match chars.next()? {
+                    ',' => continue,
+                    ']' => return Some(Json::Array(values)),
+                    _ => return None,
+                }
+            }
+        }
+        '"' => {
+            chars.next();
+            parse_string(chars).map(Json::String)
+        }
+        't' | 'f' | 'n' => {
+            let mut word = String::new();
+            while let Some(c) = chars.next_if(|c| c.is_ascii_alphabetic()) {
+                word.push(c);
+            }
+            match word.as_str() {
+                "true" => Some(Json::Bool(true)),
+                "false" => Some(Json::Bool(false)),
+                "null" => Some(Json::Null),
+                _ => None,
+            }
+        }
+        _ => {
+            let mut number = String::new();
+            while let Some(c) = chars.next_if(|c| c.is_ascii_digit() || "+-.eE".contains(*c)) {
+                number.push(c);
+            }
+            number.parse().ok().map(Json::Number)
+        }
+    }
+}
+
+/// The rest of a string, after its opening quote.
+fn parse_string(chars: &mut Peekable<Chars>) -> Option<String> {
+    let mut text = String::new();
+    loop {
+        match chars.next()? {
+            '"' => return Some(text),
+            '\\' => {
+                let c = match chars.next()? {
+                    'n' => '\n',
+                    'r' => '\r',
+                    't' => '\t',
+                    'b' => '\u{8}',
+                    'f' => '\u{c}',
+                    'u' => {
+                        let mut code = parse_hex4(chars)?;
+                        /* A surrogate pair spells anything past the BMP. */
+                        if (0xD800..0xDC00).contains(&code) {
+                            chars.next_if_eq(&'\\')?;
+                            chars.next_if_eq(&'u')?;
+                            let low = parse_hex4(chars)?;
+                            code = 0x10000 + ((code - 0xD800) << 10) + (low.checked_sub(0xDC00)?);
+                        }
+                        char::from_u32(code)?
+                    }
+                    c => c,
+                };
+                text.push(c);
+            }
+            c => text.push(c),
+        }
+    }
+}
+
+fn parse_hex4(chars: &mut Peekable<Chars>) -> Option<u32> {
+    let digits: String = chars.by_ref().take(4).collect();
+    u32::from_str_radix(&digits, 16).ok()
+}
+
+fn object(fields: Vec<(&str, Json)>) -> Json {
+    Json::Object(
+        fields
+            .into_iter()
+            .map(|(name, value)| (name.to_string(), value))
+            .collect(),
+    )
+}
+
+/// Reads a message framed by its `Content-Length` header, `None` once the
+/// stream ends. Lines before the header, like a console's boot log, are
+/// skipped.
+pub fn read_message(reader: &mut impl BufRead) -> io::Result<Option<Json>> {
+    let mut length = None;
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+        let line = line.trim();
+        if let Some(value) = line.strip_prefix("Content-Length:") {
+            length = value.trim().parse::<usize>().ok();
+        } else if line.is_empty() && length.is_some() {
+            break;
+        }
+    }
+
+    let length = length.unwrap_or(0);
+    if length > MAX_MESSAGE_LENGTH {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "message too long",
+        ));
+    }
+    let mut body = vec![0; length];
+    reader.read_exact(&mut body)?;
+    String::from_utf8(body)
+        .ok()
+        .and_then(|text| Json::parse(&text))
+        .map(Some)
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "message is not JSON"))
+}
+
+pub fn write_message(writer: &mut impl Write, message: &Json) -> io::Result<()> {
+    let body = message.to_string();
+    write!(writer, "Content-Length: {}\r\n\r\n{body}", body.len())?;
+    writer.flush()
+}
+
+/// What a `launch` request asked for.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Launch {
+    /// The ROM's path, whoever runs the server loads it.
+    pub program: String,
+    /// A `.sym` or `.map` file.
+    pub symbols: Option<String>,
+    /// Where to look for a source file that isn't at the path the editor has.
+    pub source_root: Option<String>,
+    pub stop_on_entry: bool,
+    seq: i64,
+}
+
+/// What a request before the launch left the server doing.
+enum BeforeLaunch {
+    Waiting,
+    Launch(Launch),
+    Disconnected,
+}
+
+/// Debugs a `Gb` for an editor speaking the Debug Adapter Protocol.
+///
+/// Source breakpoints go on a label's line or on the first instruction after
+/// it, found by reading the labels the source file defines and looking them
+/// up in the launch's `.sym` or `.map`. Other lines have no address to break
+/// at and stay unverified. Function breakpoints take a label or an address.
+/// Watches are a register, or an address or label with an optional `,count`
+/// of bytes, read with `gb_peek`.
+pub struct DapServer<W: Write> {
+    writer: W,
+    requests: Receiver<Json>,
+    seq: i64,
+    symbols: SymbolTable,
+    source_root: Option<String>,
+    /// The lines of each source file that run from a label, with the label.
+    sources: HashMap<String, Vec<(i64, String)>>,
+    /// The core's IDs of each source file's breakpoints.
+    source_breakpoints: HashMap<String, Vec<usize>>,
+    function_breakpoints: Vec<usize>,
+    stop_on_entry: bool,
+    running: bool,
+}
+impl<W: Write> DapServer<W> {
+    /// Requests are read from `reader` on a thread of their own, so a
+    /// running game can still be paused.
+    pub fn new(reader: impl Read + Send + 'static, writer: W) -> io::Result<DapServer<W>> {
+        let (sender, requests) = mpsc::channel();
+        thread::Builder::new()
+            .name("dap".to_string())
+            .spawn(move || {
+                let mut reader = BufReader::new(reader);
+                while let Ok(Some(request)) = read_message(&mut reader) {
+                    if sender.send(request).is_err() {
+                        break;
+                    }
+                }
+            })?;
+
+        Ok(DapServer {
+            writer,
+            requests,
+            seq: 0,
+            symbols: SymbolTable::new(),
+            source_root: None,
+            sources: HashMap::new(),
+            source_breakpoints: HashMap::new(),
+            function_breakpoints: Vec::new(),
+            stop_on_entry: false,
+            running: false,
+        })
+    }
+
+    /// Answers `initialize` until the client asks to launch. `None` if it
+    /// went away first.
+    pub fn wait_for_launch(&mut self) -> io::Result<Option<Launch>> {
+        while let Ok(request) = self.requests.recv() {
+            match self.before_launch(&request)? {
+                BeforeLaunch::Waiting => {}
+                BeforeLaunch::Launch(launch) => return Ok(Some(launch)),
+                BeforeLaunch::Disconnected => return Ok(None),
+            }
+        }
+        Ok(None)
+    }
+
+    /// `wait_for_launch` without waiting, for a game that keeps running
+    /// until a client turns up. Answers what has arrived and returns the
+    /// launch if it was asked for, `None` until then. A client disconnecting
+    /// first leaves the server waiting for the next one.
+    pub fn poll_launch(&mut self) -> io::Result<Option<Launch>> {
+        while let Ok(request) = self.requests.try_recv() {
+            if let BeforeLaunch::Launch(launch) = self.before_launch(&request)? {
+                return Ok(Some(launch));
+            }
+        }
+        Ok(None)
+    }
+
+    fn before_launch(&mut self, request: &Json) -> io::Result<BeforeLaunch> {
+        let args = request.get("arguments");
+        match request.get("command").as_str().unwrap_or("") {
+            "initialize" => self.respond(request, capabilities())?,
+            "launch" => {
+                let Some(program) = args.get("program").as_str() else {
+                    self.fail(request, "launch needs a program")?;
+                    return Ok(BeforeLaunch::Waiting);
+                };
+                return Ok(BeforeLaunch::Launch(Launch {
+                    program: program.to_string(),
+                    symbols: args.get("symbols").as_str().map(str::to_string),
+                    source_root: args.get("sourceRoot").as_str().map(str::to_string),
+                    stop_on_entry: args.get("stopOnEntry").as_bool().unwrap_or(false),
+                    seq: request.get("seq").as_i64().unwrap_or(0),
+                }));
+            }
+            "disconnect" => {
+                self.respond(request, Json::Null)?;
+                return Ok(BeforeLaunch::Disconnected);
+            }
+            command => self.fail(request, &format!("{command} needs a ROM, launch one"))?,
+        }
+        Ok(BeforeLaunch::Waiting)
+    }
+
+    /// Answers the launch when its ROM couldn't be loaded.
+    pub fn fail_launch(&mut self, launch: &Launch, message: &str) -> io::Result<()> {
+        self.send_response(launch.seq, "launch", Err(message), Json::Null)
+    }
+
+    /// Debugs `gb`, reset, for the launch until the client disconnects.
+    /// `frame` is called after each frame the game runs, to show it.
+    pub fn serve<T>(
+        &mut self,
+        gb: &mut Gb<T>,
+        launch: &Launch,
+        mut frame: impl FnMut(&mut Gb<T>),
+    ) -> io::Result<()> {
+        gb.gb_reset();
+        gb.gb_clear_debugger();
+        self.sources.clear();
+        self.source_breakpoints.clear();
+        self.function_breakpoints.clear();
+        self.source_root = launch.source_root.clone();
+        self.stop_on_entry = launch.stop_on_entry;
+        self.running = false;
+        self.symbols = SymbolTable::new();
+        if let Some(path) = &launch.symbols {
+            match load_symbols(path) {
+                Ok(symbols) => self.symbols = symbols,
+                Err(message) => self.output(&message)?,
+            }
+        }
+        self.send_response(launch.seq, "launch", Ok(()), Json::Null)?;
+        self.event("initialized", Json::Null)?;
+
+        loop {
+            let request = if self.running {
+                match self.requests.try_recv() {
+                    Ok(request) => Some(request),
+                    Err(TryRecvError::Empty) => None,
+                    Err(TryRecvError::Disconnected) => return Ok(()),
+                }
+            } else {
+                match self.requests.recv() {
+                    Ok(request) => Some(request),
+                    Err(_) => return Ok(()),
+                }
+            };
+
+            if let Some(request) = request {
+                if !self.handle(gb, &request)? {
+                    return Ok(());
+                }
+                continue;
+            }
+            let reason = gb.gb_debug_run(RunUntil::Frame);
+            frame(gb);
+            if reason != StopReason::Reached {
+                self.running = false;
+                self.stopped(reason)?;
+            }
+        }
+    }
+
+    /// False once the client has disconnected.
+    fn handle<T>(&mut self, gb: &mut Gb<T>, request: &Json) -> io::Result<bool> {
+        let args = request.get("arguments");
+        match request.get("command").as_str().unwrap_or("") {
+            "setBreakpoints" => {
+                let body = self.set_breakpoints(gb, args);
+                self.respond(request, body)?;
+            }
+            "setFunctionBreakpoints" => {
+                let body = self.set_function_breakpoints(gb, args);
+                self.respond(request, body)?;
+            }
+            "configurationDone" => {
+                self.respond(request, Json::Null)?;
+                if self.stop_on_entry {
+                    self.event("stopped", stopped("entry", None))?;
+                } else {
+                    self.running = true;
+                }
+            }
+            "threads" => {
+                let thread = object(vec![("id", THREAD_ID.into()), ("name", "SM83".into())]);
+                self.respond(request, object(vec![("threads", vec![thread].into())]))?;
+            }
+            "stackTrace" => {
+                let body = self.stack_trace(gb);
+                self.respond(request, body)?;
+            }
+            "scopes" => {
+                let scope = |name: &str, reference: i64| {
+                    object(vec![
+                        ("name", name.into()),
+                        ("variablesReference", reference.into()),
+                        ("expensive", false.into()),
+                    ])
+                };
+                let scopes = vec![
+                    scope("Registers", REGISTERS_REFERENCE),
+                    scope("Stack", STACK_REFERENCE),
+                ];
+                self.respond(request, object(vec![("scopes", scopes.into())]))?;
+            }
+            "variables" => {
+                let variables = match args.get("variablesReference").as_i64() {
+                    Some(REGISTERS_REFERENCE) => self.registers(gb),
+                    Some(STACK_REFERENCE) => self.stack(gb),
+                    _ => Vec::new(),
+                };
+                self.respond(request, object(vec![("variables", variables.into())]))?;
+            }
+            "evaluate" => {
+                let expression = args.get("expression").as_str().unwrap_or("");
+                match self.evaluate(gb, expression) {
+                    Ok(body) => self.respond(request, body)?,
+                    Err(message) => self.fail(request, &message)?,
+                }
+            }
+            "readMemory" => match memory_range(args) {
+                Some(addr) => {
+                    let count = args.get("count").as_i64().unwrap_or(0).clamp(0, 0x10000);
+                    let count = count.min(0x10000 - addr as i64);
+                    let bytes = (0..count)
+                        .map(|i| gb.gb_peek(addr.wrapping_add(i as u16)))
+                        .collect::<Vec<u8>>();
+                    let body = object(vec![
+                        ("address", memory_reference(addr).into()),
+                        ("data", base64_encode(&bytes).into()),
+                    ]);
+                    self.respond(request, body)?;
+                }
+                None => self.fail(request, "not an address")?,
+            },
+            "writeMemory" => {
+                let data = args.get("data").as_str().and_then(base64_decode);
+                match (memory_range(args), data) {
+                    (Some(addr), Some(data)) => {
+                        for (i, byte) in data.iter().enumerate() {
+                            gb.gb_poke(addr.wrapping_add(i as u16), *byte);
+                        }
+                        let written = object(vec![("bytesWritten", (data.len() as i64).into())]);
+                        self.respond(request, written)?;
+                    }
+                    _ => self.fail(request, "not an address and base64 data")?,
+                }
+            }
+            "continue" => {
+                self.running = true;
+                let body = object(vec![("allThreadsContinued", true.into())]);
+                self.respond(request, body)?;
+            }
+            "pause" => {
+                self.respond(request, Json::Null)?;
+                if self.running {
+                    self.running = false;
+                    self.event("stopped", stopped("pause", None))?;
+                }
+            }
+            /* Stepping over a call or out of a function runs until it
+             * returns, requests wait until then. */
+            command @ ("next" | "stepIn" | "stepOut") => {
+                self.respond(request, Json::Null)?;
+                if !self.running {
+                    let reason = match command {
+                        "next" => gb.gb_debug_step_over(),
+                        "stepIn" => gb.gb_debug_step(),
+                        _ => gb.gb_debug_step_out(),
+                    };
+                    self.stopped(reason)?;
+                }
+            }
+            "disconnect" | "terminate" => {
+                self.respond(request, Json::Null)?;
+                if request.get("command").as_str() == Some("terminate") {
+                    self.event("terminated", Json::Null)?;
+                }
+                return Ok(false);
+            }
+            "launch" => self.fail(request, "a ROM is already running")?,
+            command => self.fail(request, &format!("{command} is not supported"))?,
+        }
+        Ok(true)
+    }
+
+    fn set_breakpoints<T>(&mut self, gb: &mut Gb<T>, args: &Json) -> Json {
+        let path = args.get("source").get("path").as_str().unwrap_or("");
+        for id in self.source_breakpoints.remove(path).unwrap_or_default() {
+            gb.gb_remove_breakpoint(id);
+        }
+        if !self.sources.contains_key(path) {
+            let labels = self
+                .read_source(path)
+                .map_or_else(Vec::new, |text| scan_labels(&text));
+            self.sources.insert(path.to_string(), labels);
+        }
+
+        let mut ids = Vec::new();
+        let mut results = Vec::new();
+        for requested in args.get("breakpoints").as_array() {
+            let line = requested.get("line").as_i64().unwrap_or(0);
+            let label = self.sources[path]
+                .iter()
+                .find(|(label_line, _)| *label_line == line)
+                .and_then(|(_, name)| self.symbols.get_address(name));
+            let result = match (label, parse_condition(requested)) {
+                (Some((bank, addr)), Ok(condition)) => {
+                    let id = gb.gb_add_breakpoint(breakpoint(Some(bank), addr, condition));
+                    ids.push(id);
+                    object(vec![
+                        ("id", (id as i64).into()),
+                        ("verified", true.into()),
+                        ("line", line.into()),
+                    ])
+                }
+                (None, _) => unverified(
+                    "only a label from the symbols, or the first instruction after it, can break",
+                ),
+                (_, Err(message)) => unverified(&message),
+            };
+            results.push(result);
+        }
+        self.source_breakpoints.insert(path.to_string(), ids);
+        object(vec![("breakpoints", results.into())])
+    }
+
+    fn set_function_breakpoints<T>(&mut self, gb: &mut Gb<T>, args: &Json) -> Json {
+        for id in self.function_breakpoints.drain(..) {
+            gb.gb_remove_breakpoint(id);
+        }
+        let mut results = Vec::new();
+        for requested in args.get("breakpoints").as_array() {
+            let name = requested.get("name").as_str().unwrap_or("").trim();
+            let result = match (self.resolve(name), parse_condition(requested)) {
+                (Some((bank, addr)), Ok(condition)) => {
+                    let id = gb.gb_add_breakpoint(breakpoint(bank, addr, condition));
+                    self.function_breakpoints.push(id);
+                    object(vec![("id", (id as i64).into()), ("verified", true.into())])
+                }
+                (None, _) => unverified(&format!("{name} is not a label or address")),
+                (_, Err(message)) => unverified(&message),
+            };
+            results.push(result);
+        }
+        object(vec![("breakpoints", results.into())])
+    }
+
+    /// The current instruction, then every call site whose return address
+    /// is on the stack above SP.
+    fn stack_trace<T>(&self, gb: &Gb<T>) -> Json {
+        let registers = gb.get_registers();
+        let mut frames = vec![self.frame(gb, 0, registers.pc)];
+        let mut addr = registers.sp;
+        for _ in 0..STACK_WORDS {
+            let Some(high) = addr.checked_add(1) else {
+                break;
+            };
+            let word = u16::from_le_bytes([gb.gb_peek(addr), gb.gb_peek(high)]);
+            if let Some(call) = call_site(gb, word) {
+                frames.push(self.frame(gb, frames.len() as i64, call));
+            }
+            addr = addr.wrapping_add(2);
+        }
+        let total = frames.len() as i64;
+        object(vec![
+            ("stackFrames", frames.into()),
+            ("totalFrames", total.into()),
+        ])
+    }
+
+    fn frame<T>(&self, gb: &Gb<T>, id: i64, addr: u16) -> Json {
+        let mut fields = vec![
+            ("id", id.into()),
+            ("name", self.describe(gb, addr).into()),
+            ("instructionPointerReference", memory_reference(addr).into()),
+        ];
+        /* The line is the function's label, instructions have no lines. */
+        let source = self
+            .symbols
+            .get_nearest(gb.get_bank(addr), addr)
+            .and_then(|symbol| {
+                self.sources.iter().find_map(|(path, labels)| {
+                    let (line, _) = labels.iter().find(|(_, name)| *name == symbol.name)?;
+                    Some((path, *line))
+                })
+            });
+        match source {
+            Some((path, line)) => {
+                let name = Path::new(path)
+                    .file_name()
+                    .map_or(path.clone(), |name| name.to_string_lossy().into_owned());
+                fields.push((
+                    "source",
+                    object(vec![("name", name.into()), ("path", path.as_str().into())]),
+                ));
+                fields.push(("line", line.into()));
+                fields.push(("column", 1.into()));
+            }
+            None => {
+                fields.push(("line", 0.into()));
+                fields.push(("column", 0.into()));
+            }
+        }
+        object(fields)
+    }
+
+    fn registers<T>(&self, gb: &Gb<T>) -> Vec<Json> {
+        let registers = gb.get_registers();
+        let byte = |name: &str, value: u8| variable(name, format!("${value:02X}"), None);
+        let pair = |name: &str, hi: u8, lo: u8| {
+            let value = u16::from_be_bytes([hi, lo]);
+            variable(name, format!("${value:04X}"), Some(value))
+        };
+        let flag = |mask: u8, name: char| if registers.f & mask != 0 { name } else { '-' };
+        let flags: String = [
+            flag(0x80, 'Z'),
+            flag(0x40, 'N'),
+            flag(0x20, 'H'),
+            flag(0x10, 'C'),
+        ]
+        .iter()
+        .collect();
+        vec![
+            byte("A", registers.a),
+            variable("F", flags, None),
+            byte("B", registers.b),
+            byte("C", registers.c),
+            byte("D", registers.d),
+            byte("E", registers.e),
+            byte("H", registers.h),
+            byte("L", registers.l),
+            pair("BC", registers.b, registers.c),
+            pair("DE", registers.d, registers.e),
+            pair("HL", registers.h, registers.l),
+            variable("SP", format!("${:04X}", registers.sp), Some(registers.sp)),
+            variable("PC", self.describe(gb, registers.pc), Some(registers.pc)),
+            variable(
+                "ROM bank",
+                gb.get_rom_bank(ROM_BANK_SIZE as u16).to_string(),
+                None,
+            ),
+        ]
+    }
+
+    /// The words above SP, named by their address.
+    fn stack<T>(&self, gb: &Gb<T>) -> Vec<Json> {
+        let sp = gb.get_registers().sp;
+        (0..STACK_WORDS)
+            .map_while(|i| {
+                let addr = sp.checked_add(i * 2)?;
+                let word = u16::from_le_bytes([gb.gb_peek(addr), gb.gb_peek(addr.checked_add(1)?)]);
+                Some(variable(
+                    &format!("${addr:04X}"),
+                    self.describe(gb, word),
+                    Some(word),
+                ))
+            })
+            .collect()
+    }
+
+    fn evaluate<T>(&self, gb: &Gb<T>, expression: &str) -> Result<Json, String> {
+        let expression = expression.trim();
+        if let Some(register) = DebugRegister::parse(expression) {
+            let value = register.read(&gb.get_registers());
+            let result = if expression.len() == 1 {
+                format!("${value:02X}")
+            } else {
+                format!("${value:04X}")
+            };
+            return Ok(object(vec![
+                ("result", result.into()),
+                ("variablesReference", 0.into()),
+            ]));
+        }
+
+        let (location, count) = match expression.rsplit_once(',') {
+            Some((location, count)) => (
+                location.trim(),
+                count
+                    .trim()
+                    .parse::<usize>()
+                    .map_err(|_| format!("{count} is not a byte count"))?,
+            ),
+            None => (expression, 1),
+        };
+        if !(1..=MAX_WATCH_BYTES).contains(&count) {
+            return Err(format!("a watch reads 1 to {MAX_WATCH_BYTES} bytes"));
+        }
+        let location = location
+            .strip_prefix('[')
+            .and_then(|location| location.strip_suffix(']'))
+            .unwrap_or(location);
+        let (_, addr) = self
+            .resolve(location)
+            .ok_or_else(|| format!("{location} is not a register, address or label"))?;
+        let bytes = (0..count)
+            .map(|i| format!("${:02X}", gb.gb_peek(addr.wrapping_add(i as u16))))
+            .collect::<Vec<String>>();
+        Ok(object(vec![
+            ("result", bytes.join(" ").into()),
+            ("variablesReference", 0.into()),
+            ("memoryReference", memory_reference(addr).into()),
+        ]))
+    }
+
+    /// An address as `$XXXX`, or a label and its bank, for a ROM label.
+    fn resolve(&self, text: &str) -> Option<(Option<u16>, u16)> {
+        match parse_address(text) {
+            Some(addr) => Some((None, addr)),
+            None => self
+                .symbols
+                .get_address(text)
+                .map(|(bank, addr)| (Some(bank), addr)),
+        }
+    }
+
+    /// An address with the label it's in, if there is one.
+    fn describe<T>(&self, gb: &Gb<T>, addr: u16) -> String {
+        match self.symbols.get_nearest(gb.get_bank(addr), addr) {
+            Some(symbol) if symbol.addr == addr => format!("${addr:04X} {}", symbol.name),
+            Some(symbol) => format!("${addr:04X} {}+{}", symbol.name, addr - symbol.addr),
+            None => format!("${addr:04X}"),
+        }
+    }
+
+    /// The file at `path`, or with its name under the source root.
+    fn read_source(&self, path: &str) -> Option<String> {
+        fs::read_to_string(path).ok().or_else(|| {
+            let root = self.source_root.as_ref()?;
+            fs::read_to_string(Path::new(root).join(Path::new(path).file_name()?)).ok()
+        })
+    }
+
+    fn stopped(&mut self, reason: StopReason) -> io::Result<()> {
+        let body = match reason {
+            StopReason::Breakpoint { id } => stopped("breakpoint", Some(id)),
+            StopReason::Watchpoint { id, .. } => stopped("data breakpoint", Some(id)),
+            StopReason::Step | StopReason::Reached => stopped("step", None),
+        };
+        self.event("stopped", body)
+    }
+
+    fn respond(&mut self, request: &Json, body: Json) -> io::Result<()> {
+        let seq = request.get("seq").as_i64().unwrap_or(0);
+        let command = request.get("command").as_str().unwrap_or("").to_string();
+        self.send_response(seq, &command, Ok(()), body)
+    }
+
+    fn fail(&mut self, request: &Json, message: &str) -> io::Result<()> {
+        let seq = request.get("seq").as_i64().unwrap_or(0);
+        let command = request.get("command").as_str().unwrap_or("").to_string();
+        self.send_response(seq, &command, Err(message), Json::Null)
+    }
+
+    fn send_response(
+        &mut self,
+        request_seq: i64,
+        command: &str,
+        result: Result<(), &str>,
+        body: Json,
+    ) -> io::Result<()> {
+        let mut fields = vec![
+            ("type", "response".into()),
+            ("request_seq", request_seq.into()),
+            ("success", result.is_ok().into()),
+            ("command", command.into()),
+        ];
+        if let Err(message) = result {
+            fields.push(("message", message.into()));
+        }
+        if body != Json::Null {
+            fields.push(("body", body));
+        }
+        self.send(fields)
+    }
+
+    fn event(&mut self, event: &str, body: Json) -> io::Result<()> {
+        let mut fields = vec![("type", "event".into()), ("event", event.into())];
+        if body != Json::Null {
+            fields.push(("body", body));
+        }
+        self.send(fields)
+    }
+
+    fn output(&mut self, message: &str) -> io::Result<()> {
+        let body = object(vec![
+            ("category", "console".into()),
+            ("output", format!("{message}\n").into()),
+        ]);
+        self.event("output", body)
+    }
+
+    fn send(&mut self, mut fields: Vec<(&str, Json)>) -> io::Result<()> {
+        self.seq += 1;
+        fields.insert(0, ("seq", self.seq.into()));
+        write_message(&mut self.writer, &object(fields))
+    }
+}
+
+fn capabilities() -> Json {
+    object(vec![
+        ("supportsConfigurationDoneRequest", true.into()),
+        ("supportsFunctionBreakpoints", true.into()),
+        ("supportsConditionalBreakpoints", true.into()),
+        ("supportsEvaluateForHovers", true.into()),
+        ("supportsReadMemoryRequest", true.into()),
+        ("supportsWriteMemoryRequest", true.into()),
+        ("supportsTerminateRequest", true.into()),
+    ])
+}
+
+fn stopped(reason: &str, breakpoint: Option<usize>) -> Json {
+    let mut fields = vec![
+        ("reason", reason.into()),
+        ("threadId", THREAD_ID.into()),
+        ("allThreadsStopped", true.into()),
+    ];
+    if let Some(id) = breakpoint {
+        fields.push(("hitBreakpointIds", vec![(id as i64).into()].into()));
+    }
+    object(fields)
+}
+
+fn unverified(message: &str) -> Json {
+    object(vec![
+        ("verified", false.into()),
+        ("message", message.into()),
+    ])
+}
+
+fn variable(name: &str, value: String, memory: Option<u16>) -> Json {
+    let mut fields = vec![
+        ("name", name.into()),
+        ("value", value.into()),
+        ("variablesReference", 0.into()),
+    ];
+    if let Some(addr) = memory {
+        fields.push(("memoryReference", memory_reference(addr).into()));
+    }
+    object(fields)
+}
+
+/// The bank only applies to the switchable ROM bank.
+fn breakpoint(bank: Option<u16>, addr: u16, condition: Option<Condition>) -> Breakpoint {
+    let switchable = (ROM_BANK_SIZE..VRAM_ADDR).contains(&(addr as usize));
+    Breakpoint {
+        addr,
+        bank: bank.filter(|_| switchable),
+        condition,
+    }
+}
+
+fn parse_condition(requested: &Json) -> Result<Option<Condition>, String> {
+    match requested.get("condition").as_str().map(str::trim) {
+        None | Some("") => Ok(None),
+        Some(text) => Condition::parse(text)
+            .map(Some)
+            .ok_or_else(|| format!("{text} is not a condition like A == $10")),
+    }
+}
+
+/// `$C000` or `0xC000`.
+fn parse_address(text: &str) -> Option<u16> {
+    let hex = text.strip_prefix('$').or_else(|| text.strip_prefix("0x"))?;
+    u16::from_str_radix(hex, 16).ok()
+}
+
+fn memory_reference(addr: u16) -> String {
+    format!("0x{addr:04X}")
+}
+
+/// The address of a `readMemory` or `writeMemory`, with its offset.
+fn memory_range(args: &Json) -> Option<u16> {
+    let base = parse_address(args.get("memoryReference").as_str()?)? as i64;
+    let addr = base + args.get("offset").as_i64().unwrap_or(0);
+    u16::try_from(addr).ok()
+}
+
+/// Where the CALL or RST that pushed `ret` is, if one in ROM did.
+fn call_site<T>(gb: &Gb<T>, ret: u16) -> Option<u16> {
+    if ret as usize >= VRAM_ADDR {
+        return None;
+    }
+    let call = ret.checked_sub(CALL_SIZE)?;
+    let opcode = gb.gb_peek(call);
+    if opcode == OPCODE_CALL || OPCODE_CALL_CC.contains(&opcode) {
+        return Some(call);
+    }
+    let rst = ret.checked_sub(RST_SIZE)?;
+    (gb.gb_peek(rst) & OPCODE_RST_MASK == OPCODE_RST).then_some(rst)
+}
+
+/// Labels from a `.sym`, or a `.map` going by the extension.
+fn load_symbols(path: &str) -> Result<SymbolTable, String> {
+    let text = fs::read_to_string(path).map_err(|err| format!("{path}: {err}"))?;
+    let symbols = if path.to_ascii_lowercase().ends_with(".map") {
+        SymbolTable::parse_map(&text)
+    } else {
+        SymbolTable::parse(&text)
+    };
+    symbols.map_err(|err| format!("{path}: {err:?}"))
+}
+
+/// The labels an RGBDS source defines and their lines, local ones under the
+/// global label before them as `.sym` files have them. A label on a line of
+/// its own also has the lines up to the first instruction after it.
+fn scan_labels(text: &str) -> Vec<(i64, String)> {
+    let mut labels = Vec::new();
+    let mut scope = String::new();
+    /* A label on a line of its own, until the instruction it points at. */
+    let mut pending: Option<String> = None;
+    for (n, line) in text.lines().enumerate() {
+        let line_number = n as i64 + 1;
+        let code = line.split(';').next().unwrap_or("").trim();
+        let end = code
+            .find(|c: char| !(c.is_ascii_alphanumeric() || "_.@#".contains(c)))
+            .unwrap_or(code.len());
+        let (name, rest) = code.split_at(end);
+        if name.is_empty()
+            || name.starts_with(|c: char| c.is_ascii_digit())
+            || !rest.starts_with(':')
+        {
+            if let Some(label) = &pending {
+                labels.push((line_number, label.clone()));
+                if !code.is_empty() {
+                    pending = None;
+                }
+            }
+            continue;
+        }
+        let name = match name.strip_prefix('.') {
+            Some(local) => format!("{scope}.{local}"),
+            None => {
+                scope = name.split('.').next().unwrap_or(name).to_string();
+                name.to_string()
+            }
+        };
+        labels.push((line_number, name.clone()));
+        pending = if rest.trim_start_matches(':').trim().is_empty() {
+            Some(name)
+        } else {
+            None
+        };
+    }
+    labels
+}
+
+fn base64_encode(bytes: &[u8]) -> String {
+    let mut text = String::new();
+    for chunk in bytes.chunks(3) {
+        let block = chunk.iter().enumerate().fold(0u32, |block, (i, byte)| {
+            block | (*byte as u32) << (16 - i * 8)
+        });
+        for i in 0..4 {
+            if i <= chunk.len() {
+                text.push(BASE64[(block >> (18 - i * 6)) as usize & 0x3F] as char);
+            } else {
+                text.push('=');
+            }
+        }
+    }
+    text
+}
+
+fn base64_decode(text: &str) -> Option<Vec<u8>> {
+    let mut bytes = Vec::new();
+    let (mut block, mut bits) = (0u32, 0);
+    for c in text.bytes().filter(|c| *c != b'=') {
+        let value = BASE64.iter().position(|digit| *digit == c)? as u32;
+        block = block << 6 | value;
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            bytes.push((block >> bits) as u8);
+            block &= (1 << bits) - 1;
+        }
+    }
+    Some(bytes)
+}
@@ -0,0 +1,399 @@
+use std::cell::Cell;
+
+use super::{Gb, Registers, ROM_BANK_SIZE};
+
+/* Opcodes the step commands look for. */
+pub(super) const OPCODE_CALL: u8 = 0xCD;
+pub(super) const OPCODE_CALL_CC: [u8; 4] = [0xC4, 0xCC, 0xD4, 0xDC];
+const OPCODE_RET: [u8; 6] = [0xC9, 0xD9, 0xC0, 0xC8, 0xD0, 0xD8];
+pub(super) const OPCODE_RST_MASK: u8 = 0xC7;
+pub(super) const OPCODE_RST: u8 = 0xC7;
+pub(super) const CALL_SIZE: u16 = 3;
+pub(super) const RST_SIZE: u16 = 1;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DebugRegister {
+    A,
+    F,
+    B,
+    C,
+    D,
+    E,
+    H,
+    L,
+    AF,
+    BC,
+    DE,
+    HL,
+    SP,
+    PC,
+}
+impl DebugRegister {
+    pub fn parse(name: &str) -> Option<DebugRegister> {
+        let register = match name.to_ascii_uppercase().as_str() {
+            "A" => DebugRegister::A,
+            "F" => DebugRegister::F,
+            "B" => DebugRegister::B,
+            "C" => DebugRegister::C,
+            "D" => DebugRegister::D,
+            "E" => DebugRegister::E,
+            "H" => DebugRegister::H,
+            "L" => DebugRegister::L,
+            "AF" => DebugRegister::AF,
+            "BC" => DebugRegister::BC,
+            "DE" => DebugRegister::DE,
+            "HL" => DebugRegister::HL,
+            "SP" => DebugRegister::SP,
+            "PC" => DebugRegister::PC,
+            _ => return None,
+        };
+        Some(register)
+    }
+
+    pub fn read(self, registers: &Registers) -> u16 {
+        let pair = |hi: u8, lo: u8| u16::from_be_bytes([hi, lo]);
+        match self {
+            DebugRegister::A => registers.a as u16,
+            DebugRegister::F => registers.f as u16,
+            DebugRegister::B => registers.b as u16,
+            DebugRegister::C => registers.c as u16,
+            DebugRegister::D => registers.d as u16,
+            DebugRegister::E => registers.e as u16,
+            DebugRegister::H => registers.h as u16,
+            DebugRegister::L => registers.l as u16,
+            DebugRegister::AF => pair(registers.a, registers.f),
+            DebugRegister::BC => pair(registers.b, registers.c),
+            DebugRegister::DE => pair(registers.d, registers.e),
+            DebugRegister::HL => pair(registers.h, registers.l),
+            DebugRegister::SP => registers.sp,
+            DebugRegister::PC => registers.pc,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Comparison {
+    Equal,
+    NotEqual,
+    Less,
+    LessOrEqual,
+    Greater,
+    GreaterOrEqual,
+}
+
+/// A register compared with a value, F in the layout `push af` gives.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Condition {
+    pub register: DebugRegister,
+    pub comparison: Comparison,
+    pub value: u16,
+}
+impl Condition {
+    /// `A == 0x10`, `hl >= $C000` or `b != 3`.
+    pub fn parse(text: &str) -> Option<Condition> {
+        let mut parts = text.split_whitespace();
+        let register = DebugRegister::parse(parts.next()?)?;
+        let comparison = match parts.next()? {
+            "==" => Comparison::Equal,
+            "!=" => Comparison::NotEqual,
+            "<" => Comparison::Less,
+            "<=" => Comparison::LessOrEqual,
+            ">" => Comparison::Greater,
+            ">=" => Comparison::GreaterOrEqual,
+            _ => return None,
+        };
+        let value = parts.next()?;
+        let value = match value.strip_prefix("0x").or_else(|| value.strip_prefix('$')) {
+            Some(hex) => u16::from_str_radix(hex, 16).ok()?,
+            None => value.parse().ok()?,
+        };
+        if parts.next().is_some() {
+            return None;
+        }
+        Some(Condition {
+            register,
+            comparison,
+            value,
+        })
+    }
+
+    fn matches(&self, value: u16) -> bool {
+        match self.comparison {
+            Comparison::Equal => value == self.value,
+            Comparison::NotEqual => value != self.value,
+            Comparison::Less => value < self.value,
+            Comparison::LessOrEqual => value <= self.value,
+            Comparison::Greater => value > self.value,
+            Comparison::GreaterOrEqual => value >= self.value,
+        }
+    }
+}
+
+/// Stops before the instruction at `addr` runs.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Breakpoint {
+    pub addr: u16,
+    /// Only while this ROM bank is mapped at `addr`, for 0x0000-0x7FFF.
+    pub bank: Option<u16>,
+    pub condition: Option<Condition>,
+}
+impl Breakpoint {
+    pub fn new(addr: u16) -> Breakpoint {
+        Breakpoint {
+            addr,
+            bank: None,
+            condition: None,
+        }
+    }
+}
+
+/// Stops after the instruction that reads or writes `start..=end`.
+/// Opcode and operand fetches aren't reads.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Watchpoint {
+    pub start: u16,
+    pub end: u16,
+    pub read: bool,
+    pub write: bool,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum StopReason {
+    Breakpoint {
+        id: usize,
+    },
+    /// The first access that hit, `value` is what was read or written.
+    Watchpoint {
+        id: usize,
+        addr: u16,
+        value: u8,
+        write: bool,
+    },
+    /// A step finished.
+    Step,
+    /// What `gb_debug_run` was told to run until.
+    Reached,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RunUntil {
+    /// The end of the frame, at vblank.
+    Frame,
+    /// `Gb::get_clock` reaching this.
+    Clock(u64),
+    /// The instruction at this address, about to run.
+    Pc(u16),
+}
+
+/// Breakpoints and watchpoints on a `Gb`. They only stop the `gb_debug_*`
+/// calls, `run_frame` runs straight through.
+#[derive(Default)]
+pub(super) struct Debugger {
+    breakpoints: Vec<(usize, Breakpoint)>,
+    watchpoints: Vec<(usize, Watchpoint)>,
+    next_id: usize,
+    /// While a `gb_debug_*` call is running.
+    active: bool,
+    /// The breakpoint the CPU stopped on is passed on the way out.
+    resume_pc: Option<u16>,
+    stop: Cell<Option<StopReason>>,
+}
+impl Debugger {
+    /// Called by `_read` and `_write`, keeps the first hit.
+    pub(super) fn watch(&self, addr: usize, value: u8, write: bool) {
+        if !self.active || self.stop.get().is_some() {
+            return;
+        }
+        let addr = addr as u16;
+        let hit = self.watchpoints.iter().find(|(_, watchpoint)| {
+            (watchpoint.start..=watchpoint.end).contains(&addr)
+                && if write {
+                    watchpoint.write
+                } else {
+                    watchpoint.read
+                }
+        });
+        if let Some((id, _)) = hit {
+            self.stop.set(Some(StopReason::Watchpoint {
+                id: *id,
+                addr,
+                value,
+                write,
+            }));
+        }
+    }
+}
+
+impl<'a, T> Gb<'a, T> {
+    /// Returns the new breakpoint's ID.
+    pub fn gb_add_breakpoint(&mut self, breakpoint: Breakpoint) -> usize {
+        let debugger = self.debugger.get_or_insert_with(Default::default);
+        let id = debugger.next_id;
+        debugger.next_id += 1;
+        debugger.breakpoints.push((id, breakpoint));
+        id
+    }
+
+    pub fn gb_remove_breakpoint(&mut self, id: usize) -> bool {
+        let Some(debugger) = &mut self.debugger else {
+            return false;
+        };
+        let count = debugger.breakpoints.len();
+        debugger.breakpoints.retain(|(existing, _)| *existing != id);
+        debugger.breakpoints.len() != count
+    }
+
+    /// Returns the new watchpoint's ID, breakpoints and watchpoints share IDs.
+    pub fn gb_add_watchpoint(&mut self, watchpoint: Watchpoint) -> usize {
+        let debugger = self.debugger.get_or_insert_with(Default::default);
+        let id = debugger.next_id;
+        debugger.next_id += 1;
+        debugger.watchpoints.push((id, watchpoint));
+        id
+    }
+
+    pub fn gb_remove_watchpoint(&mut self, id: usize) -> bool {
+        let Some(debugger) = &mut self.debugger else {
+            return false;
+        };
+        let count = debugger.watchpoints.len();
+        debugger.watchpoints.retain(|(existing, _)| *existing != id);
+        debugger.watchpoints.len() != count
+    }
+
+    pub fn get_breakpoints(&self) -> &[(usize, Breakpoint)] {
+        self.debugger
+            .as_ref()
+            .map_or(&[], |debugger| &debugger.breakpoints)
+    }
+
+    pub fn get_watchpoints(&self) -> &[(usize, Watchpoint)] {
+        self.debugger
+            .as_ref()
+            .map_or(&[], |debugger| &debugger.watchpoints)
+    }
+
+    /// Drops every breakpoint and watchpoint.
+    pub fn gb_clear_debugger(&mut self) -> () {
+        self.debugger = None;
+    }
+
+    /// Where the CPU stopped, the next instruction to run.
+    pub fn get_pc(&self) -> u16 {
+        self.cpu_reg.pc.bytes
+    }
+
+    /// The ROM bank mapped at `addr`, bank 0 below 0x4000.
+    pub fn get_rom_bank(&self, addr: u16) -> u16 {
+        if (addr as usize) < ROM_BANK_SIZE {
+            0
+        } else if self.mbc == 1 && self.cart_mode_select != 0 {
+            self.selected_rom_bank & 0x1F
+        } else {
+            self.selected_rom_bank
+        }
+    }
+
+    /// Runs one instruction, an interrupt being taken first counts as part
+    /// of it.
+    pub fn gb_debug_step(&mut self) -> StopReason {
+        self._debug_run(|_, _| true)
+    }
+
+    /// Steps, running a whole CALL or RST until it returns.
+    pub fn gb_debug_step_over(&mut self) -> StopReason {
+        let pc = self.cpu_reg.pc.bytes;
+        let sp = self.cpu_reg.sp.bytes;
+        let opcode = self._read_memory(pc as usize);
+        let size = if opcode == OPCODE_CALL || OPCODE_CALL_CC.contains(&opcode) {
+            CALL_SIZE
+        } else if opcode & OPCODE_RST_MASK == OPCODE_RST {
+            RST_SIZE
+        } else {
+            return self.gb_debug_step();
+        };
+        let next = pc.wrapping_add(size);
+        /* A conditional call not taken is already there after one step. */
+        self._debug_run(|gb, _| gb.cpu_reg.pc.bytes == next && gb.cpu_reg.sp.bytes >= sp)
+    }
+
+    /// Runs until the current function returns.
+    pub fn gb_debug_step_out(&mut self) -> StopReason {
+        let sp = self.cpu_reg.sp.bytes;
+        self._debug_run(|gb, opcode| OPCODE_RET.contains(&opcode) && gb.cpu_reg.sp.bytes > sp)
+    }
+
+    /// Runs until `until`, a breakpoint or a watchpoint, whichever is first.
+    pub fn gb_debug_run(&mut self, until: RunUntil) -> StopReason {
+        if until == RunUntil::Frame {
+            self.gb_frame = false;
+        }
+        let reason = self._debug_run(|gb, _| match until {
+            RunUntil::Frame => gb.gb_frame,
+            RunUntil::Clock(clock) => gb.get_clock() >= clock,
+            RunUntil::Pc(pc) => gb.cpu_reg.pc.bytes == pc,
+        });
+        if reason == StopReason::Step {
+            StopReason::Reached
+        } else {
+            reason
+        }
+    }
+
+    /// Steps until `done`, given the opcode just run, says so.
+    fn _debug_run(&mut self, mut done: impl FnMut(&Self, u8) -> bool) -> StopReason {
+        let pc = self.cpu_reg.pc.bytes;
+        let debugger = self.debugger.get_or_insert_with(Default::default);
+        debugger.active = true;
+        debugger.resume_pc = Some(pc);
+
+        let reason = loop {
+            let opcode = self._read_memory(self.cpu_reg.pc.bytes as usize);
+            self._step_cpu();
+            if let Some(reason) = self
+                .debugger
+                .as_ref()
+                .and_then(|debugger| debugger.stop.take())
+            {
+                break reason;
+            }
+            if done(self, opcode) {
+                break StopReason::Step;
+            }
+        };
+        if let Some(debugger) = &mut self.debugger {
+            debugger.active = false;
+            debugger.resume_pc = None;
+        }
+        reason
+    }
+
+    /// Checked by `_step_cpu` before each opcode fetch, true to stop there.
+    pub(super) fn _debug_break(&mut self) -> bool {
+        let pc = self.cpu_reg.pc.bytes;
+        let bank = self.get_rom_bank(pc);
+        let registers = self.get_registers();
+        let Some(debugger) = &mut self.debugger else {
+            return false;
+        };
+        if !debugger.active || debugger.resume_pc.take() == Some(pc) {
+            return false;
+        }
+        let hit = debugger.breakpoints.iter().find(|(_, breakpoint)| {
+            breakpoint.addr == pc
+                && breakpoint
+                    .bank
+                    .map_or(true, |wanted| pc >= 0x8000 || wanted == bank)
+                && breakpoint.condition.map_or(true, |condition| {
+                    condition.matches(condition.register.read(&registers))
+                })
+        });
+        match hit {
+            Some((id, _)) => {
+                debugger.stop.set(Some(StopReason::Breakpoint { id: *id }));
+                true
+            }
+            None => false,
+        }
+    }
+}
@@ -0,0 +1,511 @@
+use std::fmt;
+
+use super::Gb;
+#[cfg(feature = "gbc")]
+use super::{VRAM_ADDR, VRAM_BANK_SIZE, WRAM_1_ADDR, WRAM_BANK_SIZE};
+
+const OPCODE_PREFIX_CB: u8 = 0xCB;
+const JR_SIZE: u16 = 2;
+
+/// Operands are written in the text as `n8` and `n16` for immediates, `a8`
+/// and `a16` for addresses, `e8` for jump offsets and `s8` for signed bytes.
+const OPCODES: [&str; 0x100] = [
+    "nop",
+    "ld bc, n16",
+    "ld [bc], a",
+    "inc bc",
+    "inc b",
+    "dec b",
+    "ld b, n8",
+    "rlca",
+    "ld [a16], sp",
+    "add hl, bc",
+    "ld a, [bc]",
+    "dec bc",
+    "inc c",
+    "dec c",
+    "ld c, n8",
+    "rrca",
+    "stop",
+    "ld de, n16",
+    "ld [de], a",
+    "inc de",
+    "inc d",
+    "dec d",
+    "ld d, n8",
+    "rla",
+    "jr e8",
+    "add hl, de",
+    "ld a, [de]",
+    "dec de",
+    "inc e",
+    "dec e",
+    "ld e, n8",
+    "rra",
+    "jr nz, e8",
+    "ld hl, n16",
+    "ld [hli], a",
+    "inc hl",
+    "inc h",
+    "dec h",
+    "ld h, n8",
+    "daa",
+    "jr z, e8",
+    "add hl, hl",
+    "ld a, [hli]",
+    "dec hl",
+    "inc l",
+    "dec l",
+    "ld l, n8",
+    "cpl",
+    "jr nc, e8",
+    "ld sp, n16",
+    "ld [hld], a",
+    "inc sp",
+    "inc [hl]",
+    "dec [hl]",
+    "ld [hl], n8",
+    "scf",
+    "jr c, e8",
+    "add hl, sp",
+    "ld a, [hld]",
+    "dec sp",
+    "inc a",
+    "dec a",
+    "ld a, n8",
+    "ccf",
+    "ld b, b",
+    "ld b, c",
+    "ld b, d",
+    "ld b, e",
+    "ld b, h",
+    "ld b, l",
+    "ld b, [hl]",
+    "ld b, a",
+    "ld c, b",
+    "ld c, c",
+    "ld c, d",
+    "ld c, e",
+    "ld c, h",
+    "ld c, l",
+    "ld c, [hl]",
+    "ld c, a",
+    "ld d, b",
+    "ld d, c",
+    "ld d, d",
+    "ld d, e",
+    "ld d, h",
+    "ld d, l",
+    "ld d, [hl]",
+    "ld d, a",
+    "ld e, b",
+    "ld e, c",
+    "ld e, d",
+    "ld e, e",
+    "ld e, h",
+    "ld e, l",
+    "ld e, [hl]",
+    "ld e, a",
+    "ld h, b",
+    "ld h, c",
+    "ld h, d",
+    "ld h, e",
+    "ld h, h",
+    "ld h, l",
+    "ld h, [hl]",
+    "ld h, a",
+    "ld l, b",
+    "ld l, c",
+    "ld l, d",
+    "ld l, e",
+    "ld l, h",
+    "ld l, l",
+    "ld l, [hl]",
+    "ld l, a",
+    "ld [hl], b",
+    "ld [hl], c",
+    "ld [hl], d",
+    "ld [hl], e",
+    "ld [hl], h",
+    "ld [hl], l",
+    "halt",
+    "ld [hl], a",
+    "ld a, b",
+    "ld a, c",
+    "ld a, d",
+    "ld a, e",
+    "ld a, h",
+    "ld a, l",
+    "ld a, [hl]",
+    "ld a, a",
+    "add a, b",
+    "add a, c",
+    "add a, d",
+    "add a, e",
+    "add a, h",
+    "add a, l",
+    "add a, [hl]",
+    "add a, a",
+    "adc a, b",
+    "adc a, c",
+    "adc a, d",
+    "adc a, e",
+    "adc a, h",
+    "adc a, l",
+    "adc a, [hl]",
+    "adc a, a",
+    "sub a, b",
+    "sub a, c",
+    "sub a, d",
+    "sub a, e",
+    "sub a, h",
+    "sub a, l",
+    "sub a, [hl]",
+    "sub a, a",
+    "sbc a, b",
+    "sbc a, c",
+    "sbc a, d",
+    "sbc a, e",
+    "sbc a, h",
+    "sbc a, l",
+    "sbc a, [hl]",
+    "sbc a, a",
+    "and a, b",
+    "and a, c",
+    "and a, d",
+    "and a, e",
+    "and a, h",
+    "and a, l",
+    "and a, [hl]",
+    "and a, a",
+    "xor a, b",
+    "xor a, c",
+    "xor a, d",
+    "xor a, e",
+    "xor a, h",
+    "xor a, l",
+    "xor a, [hl]",
+    "xor a, a",
+    "or a, b",
+    "or a, c",
+    "or a, d",
+    "or a, e",
+    "or a, h",
+    "or a, l",
+    "or a, [hl]",
+    "or a, a",
+    "cp a, b",
+    "cp a, c",
+    "cp a, d",
+    "cp a, e",
+    "cp a, h",
+    "cp a, l",
+    "cp a, [hl]",
+    "cp a, a",
+    "ret nz",
+    "pop bc",
+    "jp nz, a16",
+    "jp a16",
+    "call nz, a16",
+    "push bc",
+    "add a, n8",
+    "rst $00",
+    "ret z",
+    "ret",
+    "jp z, a16",
+    "prefix",
+    "call z, a16",
+    "call a16",
+    "adc a, n8",
+    "rst $08",
+    "ret nc",
+    "pop de",
+    "jp nc, a16",
+    "",
+    "call nc, a16",
+    "push de",
+    "sub a, n8",
+    "rst $10",
+    "ret c",
+    "reti",
+    "jp c, a16",
+    "",
+    "call c, a16",
+    "",
+    "sbc a, n8",
+    "rst $18",
+    "ldh [a8], a",
+    "pop hl",
+    "ldh [c], a",
+    "",
+    "",
+    "push hl",
+    "and a, n8",
+    "rst $20",
+    "add sp, s8",
+    "jp hl",
+    "ld [a16], a",
+    "",
+    "",
+    "",
+    "xor a, n8",
+    "rst $28",
+    "ldh a, [a8]",
+    "pop af",
+    "ldh a, [c]",
+    "di",
+    "",
+    "push af",
+    "or a, n8",
+    "rst $30",
+    "ld hl, sp + s8",
+    "ld sp, hl",
+    "ld a, [a16]",
+    "ei",
+    "",
+    "",
+    "cp a, n8",
+    "rst $38",
+];
+const CB_SHIFTS: [&str; 8] = ["rlc", "rrc", "rl", "rr", "sla", "sra", "swap", "srl"];
+const CB_BITS: [&str; 4] = ["", "bit", "res", "set"];
+const REGISTERS: [&str; 8] = ["b", "c", "d", "e", "h", "l", "[hl]", "a"];
+
+#[derive(Debug, PartialEq)]
+pub enum GbSymbolError {
+    GbSymbolSyntax { line: usize },
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Symbol {
+    pub bank: u16,
+    pub addr: u16,
+    pub name: String,
+}
+
+/// Labels from the `.sym` files RGBDS and GBDK write, a `bank:addr label`
+/// per line in hex with `;` comments.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SymbolTable {
+    /// Sorted by bank then address.
+    symbols: Vec<Symbol>,
+}
+impl SymbolTable {
+    pub fn new() -> SymbolTable {
+        SymbolTable::default()
+    }
+
+    pub fn parse(text: &str) -> Result<SymbolTable, GbSymbolError> {
+        let mut symbols = Vec::new();
+        for (n, raw_line) in text.lines().enumerate() {
+            let line = n + 1;
+            let text = raw_line.split(';').next().unwrap_or("").trim();
+            if text.is_empty() {
+                continue;
+            }
+            let symbol = text
+                .split_once(char::is_whitespace)
+                .and_then(|(location, name)| {
+                    let (bank, addr) = location.split_once(':')?;
+                    Some(Symbol {
+                        bank: u16::from_str_radix(bank, 16).ok()?,
+                        addr: u16::from_str_radix(addr, 16).ok()?,
+                        name: name.trim().to_string(),
+                    })
+                })
+                .ok_or(GbSymbolError::GbSymbolSyntax { line })?;
+            symbols.push(symbol);
+        }
+        Ok(SymbolTable::from_symbols(symbols))
+    }
+
+    /// Labels from the `.map` files RGBLINK writes, a `$addr = label` line
+    /// per label under a `ROMX bank #1:` style header for its bank.
+    pub fn parse_map(text: &str) -> Result<SymbolTable, GbSymbolError> {
+        let mut symbols = Vec::new();
+        let mut bank = None;
+        for (n, raw_line) in text.lines().enumerate() {
+            let line = n + 1;
+            let text = raw_line.trim();
+            if let Some(header) = text.strip_suffix(':') {
+                /* Anything else, like SUMMARY, ends the bank's labels. */
+                bank = match header.to_ascii_lowercase().split_once(" bank #") {
+                    Some((_, number)) => {
+                        let digits = number
+                            .split(|c: char| !c.is_ascii_digit())
+                            .next()
+                            .unwrap_or("");
+                        Some(
+                            digits
+                                .parse::<u16>()
+                                .map_err(|_| GbSymbolError::GbSymbolSyntax { line })?,
+                        )
+                    }
+                    None => None,
+                };
+                continue;
+            }
+            let (Some(bank), Some((addr, name))) = (
+                bank,
+                text.strip_prefix('$')
+                    .and_then(|text| text.split_once(" = ")),
+            ) else {
+                continue;
+            };
+            symbols.push(Symbol {
+                bank,
+                addr: u16::from_str_radix(addr, 16)
+                    .map_err(|_| GbSymbolError::GbSymbolSyntax { line })?,
+                name: name.trim().to_string(),
+            });
+        }
+        Ok(SymbolTable::from_symbols(symbols))
+    }
+
+    fn from_symbols(mut symbols: Vec<Symbol>) -> SymbolTable {
+        /* Stable, so the first of several labels on an address wins. */
+        symbols.sort_by_key(|symbol| (symbol.bank, symbol.addr));
+        SymbolTable { symbols }
+    }
+
+    pub fn get_symbols(&self) -> &[Symbol] {
+        &self.symbols
+    }
+
+    pub fn get_label(&self, bank: u16, addr: u16) -> Option<&str> {
+        let index = self
+            .symbols
+            .partition_point(|symbol| (symbol.bank, symbol.addr) < (bank, addr));
+        self.symbols
+            .get(index)
+            .filter(|symbol| symbol.bank == bank && symbol.addr == addr)
+            .map(|symbol| symbol.name.as_str())
+    }
+
+    /// The last label at or before `addr` in its bank, naming an address
+    /// inside a function.
+    pub fn get_nearest(&self, bank: u16, addr: u16) -> Option<&Symbol> {
+        let index = self
+            .symbols
+            .partition_point(|symbol| (symbol.bank, symbol.addr) <= (bank, addr));
+        let nearest = self.symbols[..index]
+            .last()
+            .filter(|symbol| symbol.bank == bank)?;
+        let first = self
+            .symbols
+            .partition_point(|symbol| (symbol.bank, symbol.addr) < (bank, nearest.addr));
+        Some(&self.symbols[first])
+    }
+
+    /// The bank and address of a label.
+    pub fn get_address(&self, name: &str) -> Option<(u16, u16)> {
+        self.symbols
+            .iter()
+            .find(|symbol| symbol.name == name)
+            .map(|symbol| (symbol.bank, symbol.addr))
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Instruction {
+    pub addr: u16,
+    pub bytes: Vec<u8>,
+    /// RGBDS syntax, with labels for addresses that have one.
+    pub text: String,
+}
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.text)
+    }
+}
+
+/// Decodes the instruction at `addr` from its first bytes, whatever follows
+/// a shorter one is ignored. `label` names the address a jump, call or load
+/// goes to.
+pub fn disassemble<'s>(
+    addr: u16,
+    bytes: [u8; 3],
+    label: impl Fn(u16) -> Option<&'s str>,
+) -> Instruction {
+    let opcode = bytes[0];
+    if opcode == OPCODE_PREFIX_CB {
+        let cbop = bytes[1];
+        let r = REGISTERS[(cbop & 0x07) as usize];
+        let b = (cbop >> 3) & 0x07;
+        let text = match cbop >> 6 {
+            0 => format!("{} {r}", CB_SHIFTS[b as usize]),
+            op => format!("{} {b}, {r}", CB_BITS[op as usize]),
+        };
+        return Instruction {
+            addr,
+            bytes: bytes[..2].to_vec(),
+            text,
+        };
+    }
+
+    let format = OPCODES[opcode as usize];
+    if format.is_empty() {
+        return Instruction {
+            addr,
+            bytes: vec![opcode],
+            text: format!("db ${opcode:02X}"),
+        };
+    }
+    let n8 = bytes[1];
+    let n16 = u16::from_le_bytes([bytes[1], bytes[2]]);
+    let name = |target: u16| label(target).map_or(format!("${target:04X}"), str::to_string);
+
+    let (placeholder, operand, size) = if format.contains("n16") {
+        ("n16", format!("${n16:04X}"), 2)
+    } else if format.contains("a16") {
+        ("a16", name(n16), 2)
+    } else if format.contains("n8") {
+        ("n8", format!("${n8:02X}"), 1)
+    } else if format.contains("a8") {
+        ("a8", name(0xFF00 | n8 as u16), 1)
+    } else if format.contains("e8") {
+        let target = addr.wrapping_add(JR_SIZE).wrapping_add(n8 as i8 as u16);
+        ("e8", name(target), 1)
+    } else if format.contains("s8") {
+        ("s8", (n8 as i8).to_string(), 1)
+    } else {
+        ("", String::new(), 0)
+    };
+    let text = if size == 0 {
+        format.to_string()
+    } else {
+        format.replace(placeholder, &operand)
+    };
+    Instruction {
+        addr,
+        bytes: bytes[..1 + size].to_vec(),
+        text,
+    }
+}
+
+impl<'a, T> Gb<'a, T> {
+    /// The bank mapped at `addr`, as `.sym` files number them: ROM, VRAM, cart
+    /// RAM or WRAM, and 0 elsewhere.
+    pub fn get_bank(&self, addr: u16) -> u16 {
+        match addr >> 12 {
+            0x0..=0x7 => self.get_rom_bank(addr),
+            #[cfg(feature = "gbc")]
+            0x8 | 0x9 => ((VRAM_ADDR - self.cgb.vram_bank_offset) / VRAM_BANK_SIZE) as u16,
+            0xA | 0xB => self.cart_ram_bank as u16,
+            #[cfg(feature = "gbc")]
+            0xD => ((WRAM_1_ADDR - self.cgb.wram_bank_offset) / WRAM_BANK_SIZE) as u16,
+            #[cfg(not(feature = "gbc"))]
+            0xD => 1,
+            _ => 0,
+        }
+    }
+
+    /// Decodes the instruction at `addr` without side effects, with labels
+    /// for the banks mapped now.
+    pub fn gb_disassemble(&self, addr: u16, symbols: Option<&SymbolTable>) -> Instruction {
+        let bytes = [0, 1, 2].map(|i| self._read_memory(addr.wrapping_add(i) as usize));
+        disassemble(addr, bytes, |target| {
+            symbols.and_then(|symbols| symbols.get_label(self.get_bank(target), target))
+        })
+    }
+}
@@ -0,0 +1,219 @@
+use super::link::LinkTransport;
+use super::Gb;
+
+pub const DMG07_PLAYERS: usize = 4;
+pub const DMG07_MAX_PACKET_SIZE: usize = 16;
+
+const PING_SIZE: usize = 4;
+const PING_HEADER: u8 = 0xFE;
+const PING_ACK: u8 = 0x88;
+/// Player 1 sends a whole ping of these to start transmitting.
+const PING_START: u8 = 0xAA;
+const START_ACK: u8 = 0xCC;
+/// Player 1 sends a whole packet of these to go back to pinging.
+const TRANSMISSION_RESTART: u8 = 0xFF;
+const STATUS_CONNECTED_SHIFT: u8 = 4;
+
+/// Cycles between bytes at RATE 0, each step of RATE's low nibble adds
+/// `BYTE_CYCLES_STEP`. Slow enough for a console to re-arm its transfer.
+const BYTE_CYCLES: u64 = 0x2000;
+const BYTE_CYCLES_STEP: u64 = 0x400;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Dmg07Phase {
+    Ping,
+    Start,
+    Transmission,
+}
+
+/// The DMG-07 four player adapter. It drives the clock, each player is the
+/// other end of a `LinkTransport`: a `LinkCable` for a console in the same
+/// process or a `TcpLink` for one elsewhere. Consoles over TCP have to be
+/// emulated at an even pace, one that runs a frame and sleeps misses bytes.
+pub struct Dmg07 {
+    players: [Option<Box<dyn LinkTransport>>; DMG07_PLAYERS],
+    connected: [bool; DMG07_PLAYERS],
+    phase: Dmg07Phase,
+    index: usize,
+    responses: [[u8; PING_SIZE]; DMG07_PLAYERS],
+    rate: u8,
+    packet_size: usize,
+    /// Sent during this round, every player's packet from the last one.
+    outgoing: Vec<u8>,
+    incoming: Vec<u8>,
+    next_clock: u64,
+}
+impl Dmg07 {
+    pub fn new() -> Dmg07 {
+        Dmg07 {
+            players: Default::default(),
+            connected: [false; DMG07_PLAYERS],
+            phase: Dmg07Phase::Ping,
+            index: 0,
+            responses: [[0; PING_SIZE]; DMG07_PLAYERS],
+            rate: 0,
+            packet_size: 1,
+            outgoing: Vec::new(),
+            incoming: Vec::new(),
+            next_clock: 0,
+        }
+    }
+
+    pub fn plug(&mut self, player: usize, transport: Box<dyn LinkTransport>) {
+        self.players[player] = Some(transport);
+    }
+
+    pub fn unplug(&mut self, player: usize) -> Option<Box<dyn LinkTransport>> {
+        self.connected[player] = false;
+        self.players[player].take()
+    }
+
+    pub fn get_phase(&self) -> Dmg07Phase {
+        self.phase
+    }
+
+    /// Players that answered the last ping.
+    pub fn is_connected(&self, player: usize) -> bool {
+        self.connected[player]
+    }
+
+    pub fn get_packet_size(&self) -> usize {
+        self.packet_size
+    }
+
+    /// The RATE byte player 1 sent, its low nibble slows the bytes down.
+    pub fn get_rate(&self) -> u8 {
+        self.rate
+    }
+
+    /// Clocks out every byte due by `clock`, in the consoles' `Gb::get_clock`
+    /// cycles. Over sockets it can follow wall time instead.
+    pub fn run_until(&mut self, clock: u64) {
+        while self.next_clock <= clock {
+            self.next_clock += BYTE_CYCLES + (self.rate & 0x0F) as u64 * BYTE_CYCLES_STEP;
+            self.clock_byte();
+        }
+    }
+
+    fn clock_byte(&mut self) {
+        let outgoing: [u8; DMG07_PLAYERS] =
+            std::array::from_fn(|player| self.outgoing_byte(player));
+
+        /* Every cable is clocked before any is waited on. */
+        for (player, transport) in self.players.iter_mut().enumerate() {
+            if let Some(transport) = transport {
+                transport.start_transfer(outgoing[player]);
+            }
+        }
+        let received: [u8; DMG07_PLAYERS] = std::array::from_fn(|player| {
+            self.players[player]
+                .as_mut()
+                .and_then(|transport| transport.finish_transfer())
+                .unwrap_or(0xFF)
+        });
+
+        match self.phase {
+            Dmg07Phase::Ping => self.ping(received),
+            Dmg07Phase::Start => {
+                self.index += 1;
+                if self.index == PING_SIZE {
+                    self.start_transmission();
+                }
+            }
+            Dmg07Phase::Transmission => self.transmit(received),
+        }
+    }
+
+    fn outgoing_byte(&self, player: usize) -> u8 {
+        match self.phase {
+            Dmg07Phase::Ping if self.index == 0 => PING_HEADER,
+            Dmg07Phase::Ping => {
+                let connected = self
+                    .connected
+                    .iter()
+                    .enumerate()
+                    .fold(0, |mask, (i, &on)| mask | ((on as u8) << i));
+                (connected << STATUS_CONNECTED_SHIFT) | (player as u8 + 1)
+            }
+            Dmg07Phase::Start => START_ACK,
+            Dmg07Phase::Transmission => self.outgoing[self.index],
+        }
+    }
+
+    fn ping(&mut self, received: [u8; DMG07_PLAYERS]) {
+        for (responses, byte) in self.responses.iter_mut().zip(received) {
+            responses[self.index] = byte;
+        }
+        self.index += 1;
+        if self.index < PING_SIZE {
+            return;
+        }
+        self.index = 0;
+
+        if self.responses[0] == [PING_START; PING_SIZE] {
+            self.phase = Dmg07Phase::Start;
+            return;
+        }
+        for (connected, responses) in self.connected.iter_mut().zip(&self.responses) {
+            *connected = responses[0] == PING_ACK && responses[1] == PING_ACK;
+        }
+        /* Player 1 picks the speed and the packet size. */
+        if self.connected[0] {
+            self.rate = self.responses[0][2];
+            self.packet_size = (self.responses[0][3] as usize).clamp(1, DMG07_MAX_PACKET_SIZE);
+        }
+    }
+
+    fn start_transmission(&mut self) {
+        self.phase = Dmg07Phase::Transmission;
+        self.index = 0;
+        self.outgoing = vec![0; self.packet_size * DMG07_PLAYERS];
+        self.incoming = vec![0; self.packet_size * DMG07_PLAYERS];
+    }
+
+    fn transmit(&mut self, received: [u8; DMG07_PLAYERS]) {
+        if self.index < self.packet_size {
+            for (player, byte) in received.into_iter().enumerate() {
+                if self.connected[player] {
+                    self.incoming[player * self.packet_size + self.index] = byte;
+                }
+            }
+        }
+        self.index += 1;
+        if self.index < self.outgoing.len() {
+            return;
+        }
+        self.index = 0;
+
+        let restart = self.incoming[..self.packet_size]
+            .iter()
+            .all(|&byte| byte == TRANSMISSION_RESTART);
+        if restart {
+            self.phase = Dmg07Phase::Ping;
+            return;
+        }
+        std::mem::swap(&mut self.outgoing, &mut self.incoming);
+        self.incoming.fill(0);
+    }
+}
+impl Default for Dmg07 {
+    fn default() -> Dmg07 {
+        Dmg07::new()
+    }
+}
+
+/// Steps whichever console is furthest behind, with the adapter clocking bytes
+/// as their time comes, until every console has finished a frame.
+pub fn run_dmg07_frame<T>(adapter: &mut Dmg07, gbs: &mut [Gb<T>]) {
+    for gb in gbs.iter_mut() {
+        gb.gb_frame = false;
+    }
+    while let Some(gb) = gbs
+        .iter_mut()
+        .filter(|gb| !gb.gb_frame)
+        .min_by_key(|gb| gb.get_clock())
+    {
+        adapter.run_until(gb.get_clock());
+        gb._step_cpu();
+    }
+}
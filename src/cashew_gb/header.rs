@@ -0,0 +1,225 @@
+pub const HEADER_SIZE: usize = 0x0150;
+
+const LOGO_ADDR: usize = 0x0104;
+const TITLE_ADDR: usize = 0x0134;
+const TITLE_END_ADDR: usize = 0x0143;
+const MANUFACTURER_ADDR: usize = 0x013F;
+const MANUFACTURER_END_ADDR: usize = 0x0142;
+const CGB_FLAG_ADDR: usize = 0x0143;
+const NEW_LICENSEE_ADDR: usize = 0x0144;
+const SGB_FLAG_ADDR: usize = 0x0146;
+const CART_TYPE_ADDR: usize = 0x0147;
+const ROM_SIZE_ADDR: usize = 0x0148;
+const RAM_SIZE_ADDR: usize = 0x0149;
+const DESTINATION_ADDR: usize = 0x014A;
+const OLD_LICENSEE_ADDR: usize = 0x014B;
+const VERSION_ADDR: usize = 0x014C;
+const HEADER_CHECKSUM_ADDR: usize = 0x014D;
+const GLOBAL_CHECKSUM_ADDR: usize = 0x014E;
+
+const CGB_FLAG_SUPPORTED: u8 = 0x80;
+const CGB_FLAG_ONLY: u8 = 0xC0;
+const SGB_FLAG_SUPPORTED: u8 = 0x03;
+const OLD_LICENSEE_USE_NEW: u8 = 0x33;
+
+const NINTENDO_LOGO: [u8; 0x30] = [
+    0xCE, 0xED, 0x66, 0x66, 0xCC, 0x0D, 0x00, 0x0B, 0x03, 0x73, 0x00, 0x83, 0x00, 0x0C, 0x00, 0x0D,
+    0x00, 0x08, 0x11, 0x1F, 0x88, 0x89, 0x00, 0x0E, 0xDC, 0xCC, 0x6E, 0xE6, 0xDD, 0xDD, 0xD9, 0x99,
+    0xBB, 0xBB, 0x67, 0x63, 0x6E, 0x0E, 0xEC, 0xCC, 0xDD, 0xDC, 0x99, 0x9F, 0xBB, 0xB9, 0x33, 0x3E,
+];
+
+const CART_MBC: [i8; 32] = [
+    0, 1, 1, 1, -1, 2, 2, -1, 0, 0, -1, 0, 0, 0, -1, 3, 3, 3, 3, 3, -1, -1, -1, -1, -1, 5, 5, 5, 5,
+    5, 5, -1,
+];
+const CART_RAM: [u8; 32] = [
+    0, 0, 1, 1, 0, 1, 1, 0, 1, 1, 0, 0, 0, 0, 0, 0, 1, 0, 1, 1, 0, 0, 0, 0, 0, 0, 1, 1, 0, 0, 0, 0,
+];
+const CART_BATTERY: [u8; 11] = [
+    0x03, 0x06, 0x09, 0x0D, 0x0F, 0x10, 0x13, 0x1B, 0x1E, 0x22, 0xFF,
+];
+const CART_RTC: [u8; 2] = [0x0F, 0x10];
+const NUM_ROM_BANKS: [u16; 9] = [2, 4, 8, 16, 32, 64, 128, 256, 512];
+const NUM_RAM_BANKS: [u8; 6] = [0, 1, 1, 4, 16, 8];
+const RAM_SIZES: [usize; 6] = [0x00, 0x800, 0x2000, 0x8000, 0x20000, 0x10000];
+const MBC2_RAM_SIZE: usize = 0x200;
+
+/// The cartridge header at 0x0100-0x014F, readable before a `Gb` exists.
+#[derive(Clone)]
+pub struct CartridgeHeader {
+    bytes: [u8; HEADER_SIZE],
+}
+impl CartridgeHeader {
+    /// Parses the first 0x150 bytes of a ROM, `None` if it is shorter.
+    pub fn new(rom: &[u8]) -> Option<CartridgeHeader> {
+        let mut bytes = [0; HEADER_SIZE];
+        bytes.copy_from_slice(rom.get(..HEADER_SIZE)?);
+        Some(CartridgeHeader { bytes })
+    }
+    pub fn read(rom_read: impl Fn(usize) -> u8) -> CartridgeHeader {
+        let mut bytes = [0; HEADER_SIZE];
+        for (addr, byte) in bytes.iter_mut().enumerate() {
+            *byte = rom_read(addr);
+        }
+        CartridgeHeader { bytes }
+    }
+
+    /// The printable start of the title. Old cartridges use all 16 bytes,
+    /// CGB ones end it before the CGB flag, or before the manufacturer code
+    /// when one is there, so it isn't read as part of the name.
+    pub fn get_title(&self) -> String {
+        let title_end = if self.get_manufacturer_code().is_some() {
+            MANUFACTURER_ADDR - 1
+        } else if self.is_cgb_supported() {
+            CGB_FLAG_ADDR - 1
+        } else {
+            TITLE_END_ADDR
+        };
+        self.bytes[TITLE_ADDR..=title_end]
+            .iter()
+            .take_while(|c| (b' '..=b'_').contains(c))
+            .map(|&c| c as char)
+            .collect()
+    }
+    /// The raw title area, including any manufacturer code and CGB flag.
+    pub fn get_title_bytes(&self) -> &[u8] {
+        &self.bytes[TITLE_ADDR..=TITLE_END_ADDR]
+    }
+    /// Sum of the 16 title bytes, the CGB boot ROM picks DMG palettes with it.
+    pub fn get_title_checksum(&self) -> u8 {
+        self.bytes[TITLE_ADDR..=TITLE_END_ADDR]
+            .iter()
+            .fold(0, |x, &c| x.wrapping_add(c))
+    }
+    /// Only present on later CGB cartridges, where it shortens the title.
+    pub fn get_manufacturer_code(&self) -> Option<String> {
+        let code = &self.bytes[MANUFACTURER_ADDR..=MANUFACTURER_END_ADDR];
+        if !self.is_cgb_supported()
+            || !code
+                .iter()
+                .all(|c| c.is_ascii_uppercase() || c.is_ascii_digit())
+        {
+            return None;
+        }
+        Some(code.iter().map(|&c| c as char).collect())
+    }
+
+    pub fn get_cgb_flag(&self) -> u8 {
+        self.bytes[CGB_FLAG_ADDR]
+    }
+    pub fn is_cgb_supported(&self) -> bool {
+        self.bytes[CGB_FLAG_ADDR] & CGB_FLAG_SUPPORTED != 0
+    }
+    pub fn is_cgb_only(&self) -> bool {
+        self.bytes[CGB_FLAG_ADDR] & CGB_FLAG_ONLY == CGB_FLAG_ONLY
+    }
+    pub fn get_sgb_flag(&self) -> u8 {
+        self.bytes[SGB_FLAG_ADDR]
+    }
+    /// The SGB ignores its flag unless the old licensee code defers to the new one.
+    pub fn is_sgb_supported(&self) -> bool {
+        self.bytes[SGB_FLAG_ADDR] == SGB_FLAG_SUPPORTED
+            && self.bytes[OLD_LICENSEE_ADDR] == OLD_LICENSEE_USE_NEW
+    }
+
+    pub fn get_old_licensee(&self) -> u8 {
+        self.bytes[OLD_LICENSEE_ADDR]
+    }
+    pub fn get_new_licensee(&self) -> [u8; 2] {
+        [
+            self.bytes[NEW_LICENSEE_ADDR],
+            self.bytes[NEW_LICENSEE_ADDR + 1],
+        ]
+    }
+    /// The two character licensee code, from whichever field is in use.
+    pub fn get_licensee(&self) -> String {
+        if self.bytes[OLD_LICENSEE_ADDR] == OLD_LICENSEE_USE_NEW {
+            self.get_new_licensee().iter().map(|&c| c as char).collect()
+        } else {
+            format!("{:02X}", self.bytes[OLD_LICENSEE_ADDR])
+        }
+    }
+    pub fn get_destination(&self) -> u8 {
+        self.bytes[DESTINATION_ADDR]
+    }
+    pub fn get_version(&self) -> u8 {
+        self.bytes[VERSION_ADDR]
+    }
+
+    pub fn get_cart_type(&self) -> u8 {
+        self.bytes[CART_TYPE_ADDR]
+    }
+    /// The MBC the core emulates for this cartridge, `None` if unsupported.
+    pub fn get_mbc(&self) -> Option<i8> {
+        CART_MBC
+            .get(self.get_cart_type() as usize)
+            .copied()
+            .filter(|&mbc| mbc != -1)
+    }
+    pub fn has_cart_ram(&self) -> bool {
+        CART_RAM.get(self.get_cart_type() as usize) == Some(&1)
+    }
+    pub fn has_battery(&self) -> bool {
+        CART_BATTERY.contains(&self.get_cart_type())
+    }
+    pub fn has_rtc(&self) -> bool {
+        CART_RTC.contains(&self.get_cart_type())
+    }
+
+    pub fn get_rom_size_code(&self) -> u8 {
+        self.bytes[ROM_SIZE_ADDR]
+    }
+    pub fn get_rom_banks(&self) -> Option<u16> {
+        NUM_ROM_BANKS
+            .get(self.get_rom_size_code() as usize)
+            .copied()
+    }
+    pub fn get_rom_size(&self) -> Option<usize> {
+        self.get_rom_banks()
+            .map(|banks| banks as usize * super::ROM_BANK_SIZE)
+    }
+    pub fn get_ram_size_code(&self) -> u8 {
+        self.bytes[RAM_SIZE_ADDR]
+    }
+    pub fn get_ram_banks(&self) -> Option<u8> {
+        NUM_RAM_BANKS
+            .get(self.get_ram_size_code() as usize)
+            .copied()
+    }
+    pub fn get_ram_size(&self) -> Option<usize> {
+        RAM_SIZES.get(self.get_ram_size_code() as usize).copied()
+    }
+    /// Bytes of cartridge RAM the frontend has to back, MBC2 has it built in.
+    pub fn get_save_size(&self) -> usize {
+        if self.get_mbc() == Some(2) {
+            return MBC2_RAM_SIZE;
+        }
+        self.get_ram_size().unwrap_or(0)
+    }
+
+    pub fn get_header_checksum(&self) -> u8 {
+        self.bytes[HEADER_CHECKSUM_ADDR]
+    }
+    pub fn compute_header_checksum(&self) -> u8 {
+        self.bytes[TITLE_ADDR..HEADER_CHECKSUM_ADDR]
+            .iter()
+            .fold(0_u8, |x, &c| x.wrapping_sub(c).wrapping_sub(1))
+    }
+    pub fn is_header_checksum_valid(&self) -> bool {
+        self.compute_header_checksum() == self.get_header_checksum()
+    }
+    pub fn get_global_checksum(&self) -> u16 {
+        ((self.bytes[GLOBAL_CHECKSUM_ADDR] as u16) << 8)
+            | self.bytes[GLOBAL_CHECKSUM_ADDR + 1] as u16
+    }
+    /// Sum of every ROM byte but the checksum itself, no hardware checks it.
+    pub fn compute_global_checksum(rom: &[u8]) -> u16 {
+        rom.iter()
+            .enumerate()
+            .filter(|(addr, _)| *addr != GLOBAL_CHECKSUM_ADDR && *addr != GLOBAL_CHECKSUM_ADDR + 1)
+            .fold(0_u16, |x, (_, &c)| x.wrapping_add(c as u16))
+    }
+    pub fn is_logo_valid(&self) -> bool {
+        self.bytes[LOGO_ADDR..LOGO_ADDR + NINTENDO_LOGO.len()] == NINTENDO_LOGO
+    }
+}
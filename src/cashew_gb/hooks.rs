@@ -0,0 +1,198 @@
+use std::cell::RefCell;
+use std::ops::RangeInclusive;
+
+use super::{Gb, IO_LY, ROM_BANK_SIZE};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Access {
+    /// Read by an instruction, OAM DMA or HDMA. Opcode and operand fetches
+    /// aren't reads.
+    Read,
+    /// About to be written, called with the value being written.
+    Write,
+    /// About to run as an instruction, called with its opcode.
+    Execute,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Event {
+    /// The LCD reached line 144, the frame is done.
+    VBlank,
+    /// LY changed to this, by the LCD moving on a line or being turned off.
+    Ly(u8),
+    /// An interrupt was taken, with PC at this vector.
+    Interrupt(u16),
+    /// The bank at 0x4000-0x7FFF changed to this.
+    RomBank(u16),
+    /// Cart RAM bank, or MBC3 RTC register, selected.
+    CartRamBank(u8),
+    #[cfg(feature = "gbc")]
+    WramBank(u8),
+    #[cfg(feature = "gbc")]
+    VramBank(u8),
+}
+
+type MemoryHook<T> = Box<dyn FnMut(&Gb<T>, u16, u8)>;
+type EventHook<T> = Box<dyn FnMut(&Gb<T>, Event)>;
+
+struct MemoryHookEntry<T> {
+    id: usize,
+    access: Access,
+    range: RangeInclusive<u16>,
+    hook: MemoryHook<T>,
+}
+
+/// Callbacks on a `Gb`. Without any, `hooks` is `None` and each place they
+/// are called from only checks that. Hooks get the `Gb` as it is mid
+/// instruction and can't be reentered, accesses made from inside one, say
+/// through `gb_peek`, aren't hooked.
+pub(super) struct Hooks<T> {
+    memory: RefCell<Vec<MemoryHookEntry<T>>>,
+    events: RefCell<Vec<(usize, EventHook<T>)>>,
+    next_id: usize,
+}
+impl<T> Default for Hooks<T> {
+    fn default() -> Hooks<T> {
+        Hooks {
+            memory: RefCell::new(Vec::new()),
+            events: RefCell::new(Vec::new()),
+            next_id: 0,
+        }
+    }
+}
+
+/// The banks whose changes are events.
+#[derive(Clone, Copy, PartialEq)]
+struct Banks {
+    rom: u16,
+    cart_ram: u8,
+    #[cfg(feature = "gbc")]
+    wram: u8,
+    #[cfg(feature = "gbc")]
+    vram: u8,
+}
+
+impl<'a, T> Gb<'a, T> {
+    /// Calls `hook` with the address and value of every `access` to
+    /// `range`. Returns the new hook's ID. Hooks only get the `Gb` to look
+    /// at, what they find goes out through what they capture, an
+    /// `Rc<Cell<_>>` or a channel.
+    pub fn gb_add_memory_hook(
+        &mut self,
+        access: Access,
+        range: RangeInclusive<u16>,
+        hook: impl FnMut(&Gb<T>, u16, u8) + 'static,
+    ) -> usize {
+        let hooks = self.hooks.get_or_insert_with(Default::default);
+        let id = hooks.next_id;
+        hooks.next_id += 1;
+        hooks.memory.get_mut().push(MemoryHookEntry {
+            id,
+            access,
+            range,
+            hook: Box::new(hook),
+        });
+        id
+    }
+
+    /// Calls `hook` on every `Event`. Returns the new hook's ID, memory and
+    /// event hooks share IDs.
+    pub fn gb_add_event_hook(&mut self, hook: impl FnMut(&Gb<T>, Event) + 'static) -> usize {
+        let hooks = self.hooks.get_or_insert_with(Default::default);
+        let id = hooks.next_id;
+        hooks.next_id += 1;
+        hooks.events.get_mut().push((id, Box::new(hook)));
+        id
+    }
+
+    pub fn gb_remove_hook(&mut self, id: usize) -> bool {
+        let Some(hooks) = &mut self.hooks else {
+            return false;
+        };
+        let memory = hooks.memory.get_mut();
+        let events = hooks.events.get_mut();
+        let count = memory.len() + events.len();
+        memory.retain(|entry| entry.id != id);
+        events.retain(|(existing, _)| *existing != id);
+        let removed = memory.len() + events.len() != count;
+        if memory.is_empty() && events.is_empty() {
+            self.hooks = None;
+        }
+        removed
+    }
+
+    /// Drops every hook.
+    pub fn gb_clear_hooks(&mut self) -> () {
+        self.hooks = None;
+    }
+
+    /// Called for every access hooks can be on.
+    pub(super) fn _hook_memory(&self, access: Access, addr: usize, val: u8) -> () {
+        let Some(hooks) = &self.hooks else {
+            return;
+        };
+        let Ok(mut memory) = hooks.memory.try_borrow_mut() else {
+            return;
+        };
+        let addr = addr as u16;
+        for entry in memory.iter_mut() {
+            if entry.access == access && entry.range.contains(&addr) {
+                (entry.hook)(self, addr, val);
+            }
+        }
+    }
+
+    pub(super) fn _hook_event(&self, event: Event) -> () {
+        let Some(hooks) = &self.hooks else {
+            return;
+        };
+        let Ok(mut events) = hooks.events.try_borrow_mut() else {
+            return;
+        };
+        for (_, hook) in events.iter_mut() {
+            hook(self, event);
+        }
+    }
+
+    /// `_write` with hooks, the write hooks and then whichever banks or LY
+    /// the write changed.
+    pub(super) fn _write_hooked(&mut self, addr: usize, val: u8) -> () {
+        self._hook_memory(Access::Write, addr, val);
+        let banks = self._hooked_banks();
+        let ly = self.hram_io[IO_LY];
+        self._write_memory(addr, val);
+
+        let changed = self._hooked_banks();
+        if changed != banks {
+            if changed.rom != banks.rom {
+                self._hook_event(Event::RomBank(changed.rom));
+            }
+            if changed.cart_ram != banks.cart_ram {
+                self._hook_event(Event::CartRamBank(changed.cart_ram));
+            }
+            #[cfg(feature = "gbc")]
+            {
+                if changed.wram != banks.wram {
+                    self._hook_event(Event::WramBank(changed.wram));
+                }
+                if changed.vram != banks.vram {
+                    self._hook_event(Event::VramBank(changed.vram));
+                }
+            }
+        }
+        if self.hram_io[IO_LY] != ly {
+            self._hook_event(Event::Ly(self.hram_io[IO_LY]));
+        }
+    }
+
+    fn _hooked_banks(&self) -> Banks {
+        Banks {
+            rom: self.get_rom_bank(ROM_BANK_SIZE as u16),
+            cart_ram: self.cart_ram_bank,
+            #[cfg(feature = "gbc")]
+            wram: self.cgb.wram_bank,
+            #[cfg(feature = "gbc")]
+            vram: self.cgb.vram_bank,
+        }
+    }
+}
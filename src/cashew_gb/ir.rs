@@ -0,0 +1,232 @@
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::io::{self, Read, Write};
+use std::net::{Shutdown, TcpListener, TcpStream, ToSocketAddrs};
+use std::rc::Rc;
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use super::Gb;
+
+/// Darkness longer than this, in clock cycles, ends a burst from a socket.
+const IR_BURST_GAP: u64 = 70224;
+
+/* Socket messages are the LED state then the sender's clock, little endian. */
+const IR_MESSAGE_SIZE: usize = 9;
+
+/// The other side of the infrared port, see `Gb::gb_init_ir`.
+///
+/// Times are `Gb::get_clock` cycles of the console plugged in.
+pub trait IrTransport {
+    fn set_led(&mut self, clock: u64, on: bool);
+    /// Whether light from the other side reaches the receiver at `clock`.
+    fn is_receiving(&mut self, clock: u64) -> bool;
+}
+
+/// The infrared port of a console, with or without anything pointed at it.
+pub struct IrPort {
+    transport: RefCell<Option<Box<dyn IrTransport>>>,
+}
+impl IrPort {
+    pub fn new() -> IrPort {
+        IrPort {
+            transport: RefCell::new(None),
+        }
+    }
+
+    pub fn plug(&self, transport: Box<dyn IrTransport>) {
+        *self.transport.borrow_mut() = Some(transport);
+    }
+
+    pub fn unplug(&self) -> Option<Box<dyn IrTransport>> {
+        self.transport.borrow_mut().take()
+    }
+
+    pub fn is_plugged(&self) -> bool {
+        self.transport.borrow().is_some()
+    }
+}
+impl Default for IrPort {
+    fn default() -> IrPort {
+        IrPort::new()
+    }
+}
+
+/// Contexts that own an `IrPort`, for `gb_init_ir_port`.
+pub trait GbIrContext {
+    fn get_ir_port(&self) -> &IrPort;
+}
+
+impl<'a, T: GbIrContext> Gb<'a, T> {
+    /// Routes the infrared port through the context's `IrPort`.
+    pub fn gb_init_ir_port(&mut self) {
+        self.gb_init_ir(ir_tx, ir_rx);
+    }
+}
+
+fn ir_tx<T: GbIrContext>(gb: &Gb<T>, on: bool) {
+    if let Some(transport) = gb
+        .get_context()
+        .get_ir_port()
+        .transport
+        .borrow_mut()
+        .as_mut()
+    {
+        transport.set_led(gb.get_clock(), on);
+    }
+}
+
+fn ir_rx<T: GbIrContext>(gb: &Gb<T>) -> bool {
+    gb.get_context()
+        .get_ir_port()
+        .transport
+        .borrow_mut()
+        .as_mut()
+        .is_some_and(|transport| transport.is_receiving(gb.get_clock()))
+}
+
+/// LED switches not yet seen, after `on` which is the state before them.
+#[derive(Default)]
+struct Signal {
+    on: bool,
+    edges: VecDeque<(u64, bool)>,
+}
+impl Signal {
+    fn at(&mut self, clock: u64) -> bool {
+        while let Some(&(time, on)) = self.edges.front() {
+            if time > clock {
+                break;
+            }
+            self.on = on;
+            self.edges.pop_front();
+        }
+        self.on
+    }
+}
+
+/// Two consoles facing each other in the same process, run with
+/// `link::run_linked_frame` so their clocks stay together.
+pub struct IrPair {
+    signals: Rc<RefCell<[Signal; 2]>>,
+    side: usize,
+}
+impl IrPair {
+    pub fn pair() -> (IrPair, IrPair) {
+        let signals = Rc::new(RefCell::new(Default::default()));
+        (
+            IrPair {
+                signals: signals.clone(),
+                side: 0,
+            },
+            IrPair { signals, side: 1 },
+        )
+    }
+}
+impl IrTransport for IrPair {
+    fn set_led(&mut self, clock: u64, on: bool) {
+        self.signals.borrow_mut()[self.side]
+            .edges
+            .push_back((clock, on));
+    }
+
+    fn is_receiving(&mut self, clock: u64) -> bool {
+        self.signals.borrow_mut()[1 - self.side].at(clock)
+    }
+}
+
+#[derive(Default)]
+struct IrSocketState {
+    received: VecDeque<(u64, bool)>,
+    connected: bool,
+}
+
+/// Infrared over a socket, one side listening and the other connecting.
+///
+/// The two clocks aren't in step, so each burst of light is replayed from
+/// when it is first seen, keeping the spacing the sender gave it. Protocols
+/// that answer within a burst need `IrPair` instead.
+pub struct IrSocket {
+    stream: TcpStream,
+    shared: Arc<Mutex<IrSocketState>>,
+    signal: Signal,
+    offset: Option<i64>,
+    last_remote: u64,
+}
+impl IrSocket {
+    pub fn connect(addr: impl ToSocketAddrs) -> io::Result<IrSocket> {
+        IrSocket::from_stream(TcpStream::connect(addr)?)
+    }
+
+    /// Waits for the other side to connect.
+    pub fn accept(listener: &TcpListener) -> io::Result<IrSocket> {
+        let (stream, _) = listener.accept()?;
+        IrSocket::from_stream(stream)
+    }
+
+    pub fn from_stream(stream: TcpStream) -> io::Result<IrSocket> {
+        stream.set_nodelay(true)?;
+        let reader = stream.try_clone()?;
+        let shared = Arc::new(Mutex::new(IrSocketState {
+            connected: true,
+            ..Default::default()
+        }));
+
+        let reader_shared = shared.clone();
+        thread::Builder::new()
+            .name("ir".to_string())
+            .spawn(move || ir_reader(reader, &reader_shared))?;
+
+        Ok(IrSocket {
+            stream,
+            shared,
+            signal: Signal::default(),
+            offset: None,
+            last_remote: 0,
+        })
+    }
+
+    pub fn is_connected(&self) -> bool {
+        self.shared.lock().unwrap().connected
+    }
+}
+impl IrTransport for IrSocket {
+    fn set_led(&mut self, clock: u64, on: bool) {
+        let mut message = [0; IR_MESSAGE_SIZE];
+        message[0] = on as u8;
+        message[1..].copy_from_slice(&clock.to_le_bytes());
+        if self.stream.write_all(&message).is_err() {
+            self.shared.lock().unwrap().connected = false;
+        }
+    }
+
+    fn is_receiving(&mut self, clock: u64) -> bool {
+        for (remote, on) in self.shared.lock().unwrap().received.drain(..) {
+            if self.offset.is_none() || remote.saturating_sub(self.last_remote) > IR_BURST_GAP {
+                self.offset = Some(clock as i64 - remote as i64);
+            }
+            self.last_remote = remote;
+            let local = (remote as i64 + self.offset.unwrap()).max(0) as u64;
+            self.signal.edges.push_back((local, on));
+        }
+        self.signal.at(clock)
+    }
+}
+impl Drop for IrSocket {
+    fn drop(&mut self) {
+        /* Wakes the reader thread up so it can exit. */
+        let _ = self.stream.shutdown(Shutdown::Both);
+    }
+}
+
+fn ir_reader(mut reader: TcpStream, shared: &Mutex<IrSocketState>) {
+    let mut message = [0; IR_MESSAGE_SIZE];
+    while reader.read_exact(&mut message).is_ok() {
+        let clock = u64::from_le_bytes(message[1..].try_into().unwrap());
+        shared
+            .lock()
+            .unwrap()
+            .received
+            .push_back((clock, message[0] != 0));
+    }
+    shared.lock().unwrap().connected = false;
+}
@@ -0,0 +1,324 @@
+use std::cell::RefCell;
+use std::io::{self, Read, Write};
+use std::net::{Shutdown, TcpListener, TcpStream, ToSocketAddrs};
+use std::rc::Rc;
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use super::{Gb, GbSerialRxRet, IO_SC, SERIAL_SC_CLOCK_SRC};
+
+/// How long a transfer clocked over TCP waits for the other console's byte.
+pub const LINK_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// What clocking a console that hasn't started its side of the transfer reads.
+const LINK_NOT_READY: u8 = 0xFF;
+
+/* TCP messages are [kind, sequence, byte], a reply echoes the transfer's
+ * sequence so one arriving after its timeout isn't taken for the next. */
+const LINK_TRANSFER: u8 = 0x01;
+const LINK_REPLY: u8 = 0x02;
+const LINK_MESSAGE_SIZE: usize = 3;
+
+/// One end of a cable, as seen by the console plugged into it.
+///
+/// A console with the internal clock calls `start_transfer` when the transfer
+/// starts and `finish_transfer` a byte's time later. With the external clock it
+/// offers SB through `set_ready` and polls `take_received` until the other side
+/// has clocked it.
+pub trait LinkTransport {
+    fn start_transfer(&mut self, byte: u8);
+    /// The byte shifted in, `None` if nothing answered.
+    fn finish_transfer(&mut self) -> Option<u8>;
+    /// Ignored while a received byte hasn't been taken yet.
+    fn set_ready(&mut self, byte: u8);
+    fn take_received(&mut self) -> Option<u8>;
+}
+
+/// The serial port of a console, with or without a cable in it.
+pub struct LinkPort {
+    transport: RefCell<Option<Box<dyn LinkTransport>>>,
+}
+impl LinkPort {
+    pub fn new() -> LinkPort {
+        LinkPort {
+            transport: RefCell::new(None),
+        }
+    }
+
+    pub fn plug(&self, transport: Box<dyn LinkTransport>) {
+        *self.transport.borrow_mut() = Some(transport);
+    }
+
+    pub fn unplug(&self) -> Option<Box<dyn LinkTransport>> {
+        self.transport.borrow_mut().take()
+    }
+
+    pub fn is_plugged(&self) -> bool {
+        self.transport.borrow().is_some()
+    }
+}
+impl Default for LinkPort {
+    fn default() -> LinkPort {
+        LinkPort::new()
+    }
+}
+
+/// Contexts that own a `LinkPort`, for `gb_init_link`.
+pub trait GbLinkContext {
+    fn get_link_port(&self) -> &LinkPort;
+}
+
+impl<'a, T: GbLinkContext> Gb<'a, T> {
+    /// Routes the serial port through the context's `LinkPort`.
+    pub fn gb_init_link(&mut self) {
+        self.gb_init_serial(link_tx, link_rx);
+    }
+}
+
+fn link_tx<T: GbLinkContext>(gb: &Gb<T>, byte: u8) {
+    let mut transport = gb.get_context().get_link_port().transport.borrow_mut();
+    let Some(transport) = transport.as_mut() else {
+        return;
+    };
+    if (gb.hram_io[IO_SC] & SERIAL_SC_CLOCK_SRC) != 0 {
+        transport.start_transfer(byte);
+    } else {
+        transport.set_ready(byte);
+    }
+}
+
+fn link_rx<T: GbLinkContext>(gb: &Gb<T>, rx: &mut u8) -> GbSerialRxRet {
+    let mut transport = gb.get_context().get_link_port().transport.borrow_mut();
+    let received = transport.as_mut().and_then(|transport| {
+        if (gb.hram_io[IO_SC] & SERIAL_SC_CLOCK_SRC) != 0 {
+            transport.finish_transfer()
+        } else {
+            transport.take_received()
+        }
+    });
+    match received {
+        Some(byte) => {
+            *rx = byte;
+            GbSerialRxRet::GbSerialRxSuccess
+        }
+        None => GbSerialRxRet::GbSerialRxNoConnection,
+    }
+}
+
+/// Steps whichever of two linked consoles is behind until both have finished
+/// a frame, so neither runs ahead of the other on the cable.
+pub fn run_linked_frame<A, B>(a: &mut Gb<A>, b: &mut Gb<B>) {
+    a.gb_frame = false;
+    b.gb_frame = false;
+    while !a.gb_frame || !b.gb_frame {
+        if b.gb_frame || (!a.gb_frame && a.get_clock() <= b.get_clock()) {
+            a._step_cpu();
+        } else {
+            b._step_cpu();
+        }
+    }
+}
+
+#[derive(Default)]
+struct CableEnd {
+    ready: Option<u8>,
+    received: Option<u8>,
+    sending: Option<u8>,
+}
+
+/// A cable between two consoles in the same process, run with `run_linked_frame`.
+///
+/// Bytes are swapped when the clocking side's transfer finishes, so the other
+/// side has until then to offer its own.
+pub struct LinkCable {
+    ends: Rc<RefCell<[CableEnd; 2]>>,
+    side: usize,
+}
+impl LinkCable {
+    pub fn pair() -> (LinkCable, LinkCable) {
+        let ends = Rc::new(RefCell::new(Default::default()));
+        (
+            LinkCable {
+                ends: ends.clone(),
+                side: 0,
+            },
+            LinkCable { ends, side: 1 },
+        )
+    }
+}
+impl LinkTransport for LinkCable {
+    fn start_transfer(&mut self, byte: u8) {
+        let end = &mut self.ends.borrow_mut()[self.side];
+        end.ready = None;
+        end.sending = Some(byte);
+    }
+
+    fn finish_transfer(&mut self) -> Option<u8> {
+        let mut ends = self.ends.borrow_mut();
+        let byte = ends[self.side].sending.take()?;
+        let other = &mut ends[1 - self.side];
+        Some(match other.ready.take() {
+            Some(reply) => {
+                other.received = Some(byte);
+                reply
+            }
+            None => LINK_NOT_READY,
+        })
+    }
+
+    fn set_ready(&mut self, byte: u8) {
+        let end = &mut self.ends.borrow_mut()[self.side];
+        if end.received.is_none() {
+            end.ready = Some(byte);
+        }
+    }
+
+    fn take_received(&mut self) -> Option<u8> {
+        self.ends.borrow_mut()[self.side].received.take()
+    }
+}
+
+#[derive(Default)]
+struct TcpState {
+    ready: Option<u8>,
+    received: Option<u8>,
+    reply: Option<u8>,
+    sequence: u8,
+    connected: bool,
+}
+
+/// A cable to a console on another device, one side listening and the other
+/// connecting. Either side can clock a transfer, the other's reader thread
+/// answers it straight away with the byte its console offered.
+pub struct TcpLink {
+    stream: Arc<Mutex<TcpStream>>,
+    shared: Arc<(Mutex<TcpState>, Condvar)>,
+    timeout: Duration,
+}
+impl TcpLink {
+    pub fn connect(addr: impl ToSocketAddrs) -> io::Result<TcpLink> {
+        TcpLink::from_stream(TcpStream::connect(addr)?)
+    }
+
+    /// Waits for the other side to connect.
+    pub fn accept(listener: &TcpListener) -> io::Result<TcpLink> {
+        let (stream, _) = listener.accept()?;
+        TcpLink::from_stream(stream)
+    }
+
+    pub fn from_stream(stream: TcpStream) -> io::Result<TcpLink> {
+        stream.set_nodelay(true)?;
+        let reader = stream.try_clone()?;
+        let stream = Arc::new(Mutex::new(stream));
+        let shared = Arc::new((
+            Mutex::new(TcpState {
+                connected: true,
+                ..Default::default()
+            }),
+            Condvar::new(),
+        ));
+
+        let writer = stream.clone();
+        let reader_shared = shared.clone();
+        thread::Builder::new()
+            .name("link".to_string())
+            .spawn(move || tcp_reader(reader, &writer, &reader_shared))?;
+
+        Ok(TcpLink {
+            stream,
+            shared,
+            timeout: LINK_TIMEOUT,
+        })
+    }
+
+    pub fn set_timeout(&mut self, timeout: Duration) {
+        self.timeout = timeout;
+    }
+
+    pub fn is_connected(&self) -> bool {
+        self.shared.0.lock().unwrap().connected
+    }
+}
+impl LinkTransport for TcpLink {
+    fn start_transfer(&mut self, byte: u8) {
+        let sequence = {
+            let mut state = self.shared.0.lock().unwrap();
+            state.ready = None;
+            state.reply = None;
+            state.sequence = state.sequence.wrapping_add(1);
+            state.sequence
+        };
+        let message = [LINK_TRANSFER, sequence, byte];
+        if self.stream.lock().unwrap().write_all(&message).is_err() {
+            self.shared.0.lock().unwrap().connected = false;
+        }
+    }
+
+    fn finish_transfer(&mut self) -> Option<u8> {
+        let (state, changed) = &*self.shared;
+        let (mut state, _) = changed
+            .wait_timeout_while(state.lock().unwrap(), self.timeout, |state| {
+                state.reply.is_none() && state.connected
+            })
+            .unwrap();
+        state.reply.take()
+    }
+
+    fn set_ready(&mut self, byte: u8) {
+        let mut state = self.shared.0.lock().unwrap();
+        if state.received.is_none() {
+            state.ready = Some(byte);
+        }
+    }
+
+    fn take_received(&mut self) -> Option<u8> {
+        self.shared.0.lock().unwrap().received.take()
+    }
+}
+impl Drop for TcpLink {
+    fn drop(&mut self) {
+        /* Wakes the reader thread up so it can exit. */
+        let _ = self.stream.lock().unwrap().shutdown(Shutdown::Both);
+    }
+}
+
+fn tcp_reader(
+    mut reader: TcpStream,
+    writer: &Mutex<TcpStream>,
+    shared: &(Mutex<TcpState>, Condvar),
+) {
+    let (state, changed) = shared;
+    let mut message = [0; LINK_MESSAGE_SIZE];
+    while reader.read_exact(&mut message).is_ok() {
+        let [kind, sequence, byte] = message;
+        match kind {
+            LINK_TRANSFER => {
+                let reply = {
+                    let mut state = state.lock().unwrap();
+                    match state.ready.take() {
+                        Some(reply) => {
+                            state.received = Some(byte);
+                            reply
+                        }
+                        None => LINK_NOT_READY,
+                    }
+                };
+                let message = [LINK_REPLY, sequence, reply];
+                if writer.lock().unwrap().write_all(&message).is_err() {
+                    break;
+                }
+            }
+            LINK_REPLY => {
+                let mut state = state.lock().unwrap();
+                if sequence == state.sequence {
+                    state.reply = Some(byte);
+                    changed.notify_all();
+                }
+            }
+            _ => {}
+        }
+    }
+    state.lock().unwrap().connected = false;
+    changed.notify_all();
+}
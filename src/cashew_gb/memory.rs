@@ -0,0 +1,212 @@
+#[cfg(not(feature = "gbc"))]
+use super::WRAM_0_ADDR;
+#[cfg(feature = "gbc")]
+use super::WRAM_1_ADDR;
+use super::{
+    Gb, CART_RAM_ADDR, CRAM_BANK_SIZE, ECHO_ADDR, IO_ADDR, OAM_ADDR, OAM_SIZE, UNUSED_ADDR,
+    VRAM_ADDR, VRAM_BANK_SIZE, WRAM_BANK_SIZE,
+};
+
+/// The CPU registers, F in the layout `push af` gives.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Registers {
+    pub a: u8,
+    pub f: u8,
+    pub b: u8,
+    pub c: u8,
+    pub d: u8,
+    pub e: u8,
+    pub h: u8,
+    pub l: u8,
+    pub sp: u16,
+    pub pc: u16,
+}
+
+impl<'a, T> Gb<'a, T> {
+    pub fn get_registers(&self) -> Registers {
+        let reg = &self.cpu_reg;
+        let [b, c] = reg.bc.bytes.to_be_bytes();
+        let [d, e] = reg.de.bytes.to_be_bytes();
+        let [h, l] = reg.hl.bytes.to_be_bytes();
+        Registers {
+            a: reg.a,
+            f: reg.f.byte << 4,
+            b,
+            c,
+            d,
+            e,
+            h,
+            l,
+            sp: reg.sp.bytes,
+            pc: reg.pc.bytes,
+        }
+    }
+
+    /// The low nibble of F is always 0, as on hardware.
+    pub fn gb_set_registers(&mut self, registers: &Registers) -> () {
+        let reg = &mut self.cpu_reg;
+        reg.a = registers.a;
+        reg.f.byte = registers.f >> 4;
+        reg.bc.bytes = u16::from_be_bytes([registers.b, registers.c]);
+        reg.de.bytes = u16::from_be_bytes([registers.d, registers.e]);
+        reg.hl.bytes = u16::from_be_bytes([registers.h, registers.l]);
+        reg.sp.bytes = registers.sp;
+        reg.pc.bytes = registers.pc;
+    }
+
+    /// What the CPU would read at `addr`, without tripping watchpoints.
+    pub fn gb_peek(&self, addr: u16) -> u8 {
+        self._read_memory(addr as usize)
+    }
+
+    /// Stores `val` where the CPU would read it back, without what writing
+    /// there does: no MBC banking, OAM DMA, DIV reset or palette increment.
+    /// ROM can't be poked. The CGB's VRAM and WRAM bank and palette
+    /// registers are read back from where the CPU keeps them, so poking
+    /// VBK or SVBK does switch banks.
+    pub fn gb_poke(&mut self, addr: u16, val: u8) -> () {
+        let addr = addr as usize;
+        if let Some(flat) = &mut self.flat_memory {
+            flat.memory[addr] = val;
+            return;
+        }
+        match addr >> 12 {
+            0x0..=0x7 => (),
+            0x8 | 0x9 => {
+                #[cfg(feature = "gbc")]
+                let offset = addr - self.cgb.vram_bank_offset;
+                #[cfg(not(feature = "gbc"))]
+                let offset = addr - VRAM_ADDR;
+                self.vram[offset] = val;
+            }
+            0xA | 0xB => {
+                if self.mbc == 3 && self.cart_ram_bank >= 0x08 {
+                    self.rtc_latched.bytes[self.cart_ram_bank as usize - 0x08] = val;
+                } else if self.mbc == 2 {
+                    self.gb_poke_cart_ram(0, (addr & 0x1FF) as u16, val);
+                } else {
+                    let bank = if self.cart_mode_select != 0 || self.mbc != 1 {
+                        self.cart_ram_bank
+                    } else {
+                        0
+                    };
+                    self.gb_poke_cart_ram(bank, (addr - CART_RAM_ADDR) as u16, val);
+                }
+            }
+            _ if addr < OAM_ADDR => {
+                /* Echo RAM mirrors 0xC000-0xDDFF. */
+                let addr = if addr >= ECHO_ADDR {
+                    addr - 0x2000
+                } else {
+                    addr
+                };
+                #[cfg(feature = "gbc")]
+                let offset = addr - self.cgb.wram_bank_offset;
+                #[cfg(not(feature = "gbc"))]
+                let offset = addr - WRAM_0_ADDR;
+                self.wram[offset] = val;
+            }
+            _ if addr < UNUSED_ADDR => self.oam[addr - OAM_ADDR] = val,
+            _ if addr < IO_ADDR => (),
+            _ => self._poke_io(addr, val),
+        }
+    }
+
+    fn _poke_io(&mut self, addr: usize, val: u8) -> () {
+        #[cfg(feature = "gbc")]
+        {
+            let cgb = &mut self.cgb;
+            match addr & 0xFF {
+                0x4F => {
+                    cgb.vram_bank = val & 0x01;
+                    if cgb.mode != 0 {
+                        cgb.vram_bank_offset = VRAM_ADDR - ((cgb.vram_bank as usize) << 13);
+                    }
+                    return;
+                }
+                0x68 => {
+                    cgb.bg_palette_id = val & 0x3F;
+                    cgb.bg_palette_inc = val >> 7;
+                    return;
+                }
+                0x69 => {
+                    let id = cgb.bg_palette_id as usize & 0x3F;
+                    cgb.bg_palette[id] = val;
+                    self._update_fix_colour(id >> 1, false);
+                    return;
+                }
+                0x6A => {
+                    cgb.oam_palette_id = val & 0x3F;
+                    cgb.oam_palette_inc = val >> 7;
+                    return;
+                }
+                0x6B => {
+                    let id = cgb.oam_palette_id as usize & 0x3F;
+                    cgb.oam_palette[id] = val;
+                    self._update_fix_colour(id >> 1, true);
+                    return;
+                }
+                0x70 => {
+                    cgb.wram_bank = val;
+                    cgb.wram_bank_offset = WRAM_1_ADDR - (1 << 12);
+                    if cgb.mode != 0 && (val & 7) > 0 {
+                        cgb.wram_bank_offset = WRAM_1_ADDR - ((val as usize & 7) << 12);
+                    }
+                    return;
+                }
+                _ => {}
+            }
+        }
+        self.hram_io[addr - IO_ADDR] = val;
+    }
+
+    /// A VRAM bank, `None` past the last one.
+    pub fn get_vram_bank(&self, bank: usize) -> Option<&[u8]> {
+        self.vram
+            .get(bank * VRAM_BANK_SIZE..(bank + 1) * VRAM_BANK_SIZE)
+    }
+
+    pub fn get_vram_bank_mut(&mut self, bank: usize) -> Option<&mut [u8]> {
+        self.vram
+            .get_mut(bank * VRAM_BANK_SIZE..(bank + 1) * VRAM_BANK_SIZE)
+    }
+
+    /// A WRAM bank, bank 0 is 0xC000-0xCFFF. `None` past the last one.
+    pub fn get_wram_bank(&self, bank: usize) -> Option<&[u8]> {
+        self.wram
+            .get(bank * WRAM_BANK_SIZE..(bank + 1) * WRAM_BANK_SIZE)
+    }
+
+    pub fn get_wram_bank_mut(&mut self, bank: usize) -> Option<&mut [u8]> {
+        self.wram
+            .get_mut(bank * WRAM_BANK_SIZE..(bank + 1) * WRAM_BANK_SIZE)
+    }
+
+    pub fn get_oam(&self) -> &[u8; OAM_SIZE] {
+        &self.oam
+    }
+
+    pub fn get_oam_mut(&mut self) -> &mut [u8; OAM_SIZE] {
+        &mut self.oam
+    }
+
+    /// A byte of cart RAM by bank, whether or not the game has enabled it.
+    /// 0xFF past the end.
+    pub fn gb_peek_cart_ram(&self, bank: u8, offset: u16) -> u8 {
+        match self._cart_ram_offset(bank, offset) {
+            Some(offset) => (self.gb_cart_ram_read)(self, offset),
+            None => 0xFF,
+        }
+    }
+
+    pub fn gb_poke_cart_ram(&mut self, bank: u8, offset: u16, val: u8) -> () {
+        if let Some(offset) = self._cart_ram_offset(bank, offset) {
+            (self.gb_cart_ram_write)(self, offset, val);
+        }
+    }
+
+    fn _cart_ram_offset(&self, bank: u8, offset: u16) -> Option<usize> {
+        let offset = bank as usize * CRAM_BANK_SIZE + offset as usize;
+        (self.cart_ram != 0 && offset < self.get_save_size()).then_some(offset)
+    }
+}
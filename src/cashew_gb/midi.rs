@@ -0,0 +1,360 @@
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::io::{self, Write};
+use std::rc::Rc;
+
+use super::{Gb, GbSerialRxRet, DMG_CLOCK_FREQ, IO_SC, SERIAL_SC_CLOCK_SRC};
+
+pub const MIDI_CLOCK: u8 = 0xF8;
+pub const MIDI_START: u8 = 0xFA;
+pub const MIDI_STOP: u8 = 0xFC;
+pub const MIDI_NOTE_OFF: u8 = 0x80;
+pub const MIDI_NOTE_ON: u8 = 0x90;
+pub const MIDI_CONTROL_CHANGE: u8 = 0xB0;
+pub const MIDI_PROGRAM_CHANGE: u8 = 0xC0;
+
+/// Master sync with no byte from the console for this long, in clock cycles,
+/// has stopped.
+const MASTER_SYNC_TIMEOUT: u64 = 1 << 20;
+
+/// What a tick is clocked into the console as, the data line held low.
+const SYNC_TICK: u8 = 0x00;
+const MIDI_VELOCITY: u8 = 0x7F;
+
+/* LSDj MIDI out sends a command byte, 0x70 plus the action, then its value. */
+const MIDI_OUT_COMMAND: u8 = 0x70;
+const MIDI_OUT_CLOCK: u8 = 0x7F;
+const MIDI_OUT_STOP: u8 = 0x7E;
+const MIDI_OUT_START: u8 = 0x7D;
+const MIDI_OUT_CHANNELS: usize = 4;
+/// The CC a MIDI out value selects with bits 4-6, per LSDj channel.
+const MIDI_OUT_CC_NUMBERS: [u8; 8] = [1, 2, 3, 7, 10, 11, 12, 13];
+
+/* LSDj keyboard mode reads PS/2 scan codes, shifted in LSB first. */
+const KEYBOARD_NOTES: [u8; 12] = [
+    0x1A, 0x1B, 0x22, 0x23, 0x21, 0x2A, 0x34, 0x32, 0x33, 0x31, 0x3B, 0x3A,
+];
+const KEYBOARD_OCTAVE_DOWN: u8 = 0x05;
+const KEYBOARD_OCTAVE_UP: u8 = 0x06;
+const KEYBOARD_RELEASE: u8 = 0xF0;
+/// The MIDI octave LSDj's keyboard starts on.
+const KEYBOARD_FIRST_OCTAVE: u8 = 3;
+
+/* Standard MIDI File timing, 120 BPM at 480 ticks a quarter note. */
+const SMF_DIVISION: u16 = 480;
+const SMF_TEMPO: u32 = 500000;
+const SMF_TICKS_PER_SECOND: u64 = 960;
+
+/// A MIDI connection on the host, one whole message at a time.
+///
+/// Clocks are `Gb::get_clock` cycles of the console the bridge is plugged into.
+pub trait MidiPort {
+    fn send(&mut self, clock: u64, message: &[u8]);
+    /// The next message that arrived by `clock`, if any.
+    fn receive(&mut self, clock: u64) -> Option<Vec<u8>>;
+}
+
+/// Lets the caller keep a port, an `SmfWriter` say, that the bridge owns too.
+impl<P: MidiPort> MidiPort for Rc<RefCell<P>> {
+    fn send(&mut self, clock: u64, message: &[u8]) {
+        self.borrow_mut().send(clock, message)
+    }
+
+    fn receive(&mut self, clock: u64) -> Option<Vec<u8>> {
+        self.borrow_mut().receive(clock)
+    }
+}
+
+/// What the bridge does, after the modes of an Arduinoboy.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum MidiMode {
+    /// MIDI clock in, a byte clocked into the console each tick. For LSDj in
+    /// MIDI sync and Nanoloop as slave.
+    SlaveSync,
+    /// The console clocks bytes out, each sent on as a MIDI clock tick.
+    MasterSync,
+    /// LSDj's MI.OUT, its commands played as notes, CCs and program changes
+    /// on one MIDI channel per LSDj channel.
+    LsdjMidiOut,
+    /// MIDI notes in, typed into LSDj's keyboard mode.
+    LsdjKeyboard,
+}
+
+struct Bridge {
+    mode: MidiMode,
+    port: Box<dyn MidiPort>,
+    /// Bytes waiting for the console to be ready to clock them in.
+    outgoing: VecDeque<u8>,
+    ready: Option<u8>,
+    playing: bool,
+    last_tick: u64,
+    command: Option<u8>,
+    channels: [u8; MIDI_OUT_CHANNELS],
+    notes: [Option<u8>; MIDI_OUT_CHANNELS],
+    octave: u8,
+}
+
+/// An Arduinoboy style bridge between the serial port and MIDI.
+pub struct MidiBridge {
+    bridge: RefCell<Bridge>,
+}
+impl MidiBridge {
+    pub fn new(mode: MidiMode, port: Box<dyn MidiPort>) -> MidiBridge {
+        MidiBridge {
+            bridge: RefCell::new(Bridge {
+                mode,
+                port,
+                outgoing: VecDeque::new(),
+                ready: None,
+                playing: false,
+                last_tick: 0,
+                command: None,
+                channels: [0, 1, 2, 3],
+                notes: [None; MIDI_OUT_CHANNELS],
+                octave: KEYBOARD_FIRST_OCTAVE,
+            }),
+        }
+    }
+
+    pub fn get_mode(&self) -> MidiMode {
+        self.bridge.borrow().mode
+    }
+
+    pub fn set_mode(&self, mode: MidiMode) {
+        let mut bridge = self.bridge.borrow_mut();
+        bridge.mode = mode;
+        bridge.outgoing.clear();
+        bridge.command = None;
+    }
+
+    /// The MIDI channels, 0-15, LSDj MIDI out plays PU1, PU2, WAV and NOI on.
+    pub fn set_channels(&self, channels: [u8; MIDI_OUT_CHANNELS]) {
+        self.bridge.borrow_mut().channels = channels.map(|channel| channel & 0x0F);
+    }
+
+    /// Reads MIDI in and stops master sync once the console stops clocking.
+    /// Called once a frame, since the serial port is only busy while there
+    /// is something to transfer.
+    pub fn poll(&self, clock: u64) {
+        let mut bridge = self.bridge.borrow_mut();
+        bridge.receive(clock);
+        if bridge.mode == MidiMode::MasterSync
+            && bridge.playing
+            && clock.saturating_sub(bridge.last_tick) > MASTER_SYNC_TIMEOUT
+        {
+            bridge.playing = false;
+            bridge.port.send(clock, &[MIDI_STOP]);
+        }
+    }
+}
+
+impl Bridge {
+    fn receive(&mut self, clock: u64) {
+        while let Some(message) = self.port.receive(clock) {
+            match (self.mode, message.as_slice()) {
+                (MidiMode::SlaveSync, [MIDI_CLOCK]) => self.outgoing.push_back(SYNC_TICK),
+                (MidiMode::LsdjKeyboard, &[status, note, velocity])
+                    if (status & 0xF0) == MIDI_NOTE_ON && velocity != 0 =>
+                {
+                    self.press_key(note)
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn press_key(&mut self, note: u8) {
+        let octave = note / 12;
+        while self.octave != octave {
+            let key = if self.octave < octave {
+                self.octave += 1;
+                KEYBOARD_OCTAVE_UP
+            } else {
+                self.octave -= 1;
+                KEYBOARD_OCTAVE_DOWN
+            };
+            self.type_key(key);
+        }
+        self.type_key(KEYBOARD_NOTES[(note % 12) as usize]);
+    }
+
+    fn type_key(&mut self, key: u8) {
+        for byte in [key, KEYBOARD_RELEASE, key] {
+            self.outgoing.push_back(byte.reverse_bits());
+        }
+    }
+
+    fn master_tick(&mut self, clock: u64) {
+        if !self.playing {
+            self.playing = true;
+            self.port.send(clock, &[MIDI_START]);
+        }
+        self.last_tick = clock;
+        self.port.send(clock, &[MIDI_CLOCK]);
+    }
+
+    fn midi_out(&mut self, clock: u64, byte: u8) {
+        match byte {
+            MIDI_OUT_CLOCK => self.port.send(clock, &[MIDI_CLOCK]),
+            MIDI_OUT_STOP => {
+                self.port.send(clock, &[MIDI_STOP]);
+                for channel in 0..MIDI_OUT_CHANNELS {
+                    self.stop_note(clock, channel);
+                }
+            }
+            MIDI_OUT_START => self.port.send(clock, &[MIDI_START]),
+            MIDI_OUT_COMMAND.. => self.command = Some(byte - MIDI_OUT_COMMAND),
+            value => {
+                if let Some(command) = self.command.take() {
+                    self.midi_out_action(clock, command as usize, value);
+                }
+            }
+        }
+    }
+
+    /// Notes for 0-3, a CC for 4-7 and a program change for 8-11, by channel.
+    fn midi_out_action(&mut self, clock: u64, command: usize, value: u8) {
+        let index = command % MIDI_OUT_CHANNELS;
+        let channel = self.channels[index];
+        match command / MIDI_OUT_CHANNELS {
+            0 => {
+                self.stop_note(clock, index);
+                if value != 0 {
+                    self.notes[index] = Some(value);
+                    self.port
+                        .send(clock, &[MIDI_NOTE_ON | channel, value, MIDI_VELOCITY]);
+                }
+            }
+            1 => {
+                let number = MIDI_OUT_CC_NUMBERS[((value >> 4) & 0x07) as usize];
+                let amount = ((value & 0x0F) as u16 * 0x7F / 0x0F) as u8;
+                self.port
+                    .send(clock, &[MIDI_CONTROL_CHANGE | channel, number, amount]);
+            }
+            2 => self
+                .port
+                .send(clock, &[MIDI_PROGRAM_CHANGE | channel, value]),
+            _ => {}
+        }
+    }
+
+    fn stop_note(&mut self, clock: u64, index: usize) {
+        if let Some(note) = self.notes[index].take() {
+            let channel = self.channels[index];
+            self.port.send(clock, &[MIDI_NOTE_OFF | channel, note, 0]);
+        }
+    }
+}
+
+/// Contexts that own a `MidiBridge`, for `gb_init_midi`.
+pub trait GbMidiContext {
+    fn get_midi_bridge(&self) -> &MidiBridge;
+}
+
+impl<'a, T: GbMidiContext> Gb<'a, T> {
+    /// Routes the serial port through the context's `MidiBridge`.
+    pub fn gb_init_midi(&mut self) {
+        self.gb_init_serial(midi_tx, midi_rx);
+    }
+}
+
+fn midi_tx<T: GbMidiContext>(gb: &Gb<T>, byte: u8) {
+    let mut bridge = gb.get_context().get_midi_bridge().bridge.borrow_mut();
+    if (gb.hram_io[IO_SC] & SERIAL_SC_CLOCK_SRC) == 0 {
+        bridge.ready = Some(byte);
+    } else if bridge.mode == MidiMode::MasterSync {
+        bridge.master_tick(gb.get_clock());
+    }
+}
+
+fn midi_rx<T: GbMidiContext>(gb: &Gb<T>, rx: &mut u8) -> GbSerialRxRet {
+    let mut bridge = gb.get_context().get_midi_bridge().bridge.borrow_mut();
+    let clock = gb.get_clock();
+    bridge.receive(clock);
+
+    /* Only with the external clock does the bridge decide when to shift. */
+    if (gb.hram_io[IO_SC] & SERIAL_SC_CLOCK_SRC) != 0 || bridge.ready.is_none() {
+        return GbSerialRxRet::GbSerialRxNoConnection;
+    }
+    if bridge.mode == MidiMode::LsdjMidiOut {
+        let byte = bridge.ready.take().unwrap();
+        bridge.midi_out(clock, byte);
+        *rx = 0x00;
+        return GbSerialRxRet::GbSerialRxSuccess;
+    }
+    match bridge.outgoing.pop_front() {
+        Some(byte) => {
+            bridge.ready = None;
+            *rx = byte;
+            GbSerialRxRet::GbSerialRxSuccess
+        }
+        None => GbSerialRxRet::GbSerialRxNoConnection,
+    }
+}
+
+/// A port that records what is sent as a Standard MIDI File, for listening to
+/// or checking a session on the host. Nothing is ever received.
+pub struct SmfWriter {
+    events: Vec<(u64, Vec<u8>)>,
+}
+impl SmfWriter {
+    pub fn new() -> SmfWriter {
+        SmfWriter { events: Vec::new() }
+    }
+
+    /// A format 0 file with one track, timed from the first message sent.
+    pub fn write(&self, out: &mut impl Write) -> io::Result<()> {
+        let mut track = Vec::new();
+        /* Tempo meta event. */
+        track.extend_from_slice(&[0x00, 0xFF, 0x51, 0x03]);
+        track.extend_from_slice(&SMF_TEMPO.to_be_bytes()[1..]);
+
+        let start = self.events.first().map_or(0, |(clock, _)| *clock);
+        let mut last_tick = 0;
+        for (clock, message) in &self.events {
+            let tick = (clock - start) * SMF_TICKS_PER_SECOND / DMG_CLOCK_FREQ as u64;
+            write_variable_length(&mut track, (tick - last_tick) as u32);
+            last_tick = tick;
+            /* System messages only fit a file escaped. */
+            if message[0] >= 0xF0 {
+                track.push(0xF7);
+                write_variable_length(&mut track, message.len() as u32);
+            }
+            track.extend_from_slice(message);
+        }
+        track.extend_from_slice(&[0x00, 0xFF, 0x2F, 0x00]);
+
+        out.write_all(b"MThd")?;
+        out.write_all(&6_u32.to_be_bytes())?;
+        out.write_all(&0_u16.to_be_bytes())?;
+        out.write_all(&1_u16.to_be_bytes())?;
+        out.write_all(&SMF_DIVISION.to_be_bytes())?;
+        out.write_all(b"MTrk")?;
+        out.write_all(&(track.len() as u32).to_be_bytes())?;
+        out.write_all(&track)
+    }
+}
+impl Default for SmfWriter {
+    fn default() -> SmfWriter {
+        SmfWriter::new()
+    }
+}
+impl MidiPort for SmfWriter {
+    fn send(&mut self, clock: u64, message: &[u8]) {
+        self.events.push((clock, message.to_vec()));
+    }
+
+    fn receive(&mut self, _clock: u64) -> Option<Vec<u8>> {
+        None
+    }
+}
+
+fn write_variable_length(out: &mut Vec<u8>, mut value: u32) {
+    let mut bytes = vec![(value & 0x7F) as u8];
+    value >>= 7;
+    while value != 0 {
+        bytes.push(0x80 | (value & 0x7F) as u8);
+        value >>= 7;
+    }
+    out.extend(bytes.iter().rev());
+}
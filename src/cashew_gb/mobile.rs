@@ -0,0 +1,591 @@
+use std::io::{self, ErrorKind, Read, Write};
+use std::net::{IpAddr, Ipv4Addr, SocketAddr, TcpStream};
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::thread;
+use std::time::Duration;
+
+use super::link::LinkTransport;
+
+pub const MOBILE_CONFIG_SIZE: usize = 0xC0;
+/// Data bytes in one TRANSFER_DATA, after the connection ID.
+pub const MOBILE_MAX_TRANSFER: usize = 254;
+/// Connection ID of the telephone line in TRANSFER_DATA.
+pub const MOBILE_PHONE_CONNECTION: u8 = 0xFF;
+
+const MOBILE_CONNECTIONS: usize = 2;
+const MOBILE_CONNECT_TIMEOUT: Duration = Duration::from_secs(2);
+const MOBILE_SESSION_NAME: &[u8] = b"NINTENDO";
+
+const MAGIC_1: u8 = 0x99;
+const MAGIC_2: u8 = 0x66;
+/// The blue PDC adapter, with the bit set that marks the adapter side.
+const DEVICE_ID: u8 = 0x88;
+const ADAPTER_IDLE: u8 = 0xD2;
+const ACK_FLAG: u8 = 0x80;
+const ERROR_UNKNOWN_COMMAND: u8 = 0xF0;
+const ERROR_CHECKSUM: u8 = 0xF1;
+
+const COMMAND_BEGIN_SESSION: u8 = 0x10;
+const COMMAND_END_SESSION: u8 = 0x11;
+const COMMAND_DIAL: u8 = 0x12;
+const COMMAND_HANG_UP: u8 = 0x13;
+const COMMAND_WAIT_FOR_CALL: u8 = 0x14;
+const COMMAND_TRANSFER_DATA: u8 = 0x15;
+const COMMAND_RESET: u8 = 0x16;
+const COMMAND_TELEPHONE_STATUS: u8 = 0x17;
+const COMMAND_SIO32: u8 = 0x18;
+const COMMAND_READ_CONFIG: u8 = 0x19;
+const COMMAND_WRITE_CONFIG: u8 = 0x1A;
+const COMMAND_CONNECTION_CLOSED: u8 = 0x1F;
+const COMMAND_ISP_LOGIN: u8 = 0x21;
+const COMMAND_ISP_LOGOUT: u8 = 0x22;
+const COMMAND_OPEN_TCP: u8 = 0x23;
+const COMMAND_CLOSE_TCP: u8 = 0x24;
+const COMMAND_DNS_QUERY: u8 = 0x28;
+const COMMAND_ERROR: u8 = 0x6E;
+
+/* Error codes sent back with COMMAND_ERROR. */
+const ERROR_INVALID_STATE: u8 = 0x01;
+const ERROR_INVALID_DATA: u8 = 0x02;
+const ERROR_CONNECTION_FAILED: u8 = 0x03;
+
+const TELEPHONE_IDLE: u8 = 0x00;
+const TELEPHONE_CONNECTED: u8 = 0x04;
+const TELEPHONE_STATUS_MARKER: u8 = 0x4D;
+
+/// Where the adapter's calls, lookups and connections really go, so that
+/// stand-ins can answer for the services that are gone.
+#[derive(Clone, Debug, Default)]
+pub struct MobileEndpoints {
+    names: Vec<(String, Ipv4Addr)>,
+    routes: Vec<((Ipv4Addr, u16), SocketAddr)>,
+    numbers: Vec<(String, SocketAddr)>,
+    fallback: Option<IpAddr>,
+    address: Option<Ipv4Addr>,
+}
+
+impl MobileEndpoints {
+    pub fn new() -> MobileEndpoints {
+        MobileEndpoints::default()
+    }
+
+    /// Answers DNS queries for `name`.
+    pub fn resolve(mut self, name: &str, ip: Ipv4Addr) -> MobileEndpoints {
+        self.names.push((name.to_string(), ip));
+        self
+    }
+
+    /// Sends connections to `ip:port` to `to` instead.
+    pub fn route(mut self, ip: Ipv4Addr, port: u16, to: SocketAddr) -> MobileEndpoints {
+        self.routes.push(((ip, port), to));
+        self
+    }
+
+    /// Sends every other connection to `host`, keeping its port.
+    pub fn route_all(mut self, host: IpAddr) -> MobileEndpoints {
+        self.fallback = Some(host);
+        self
+    }
+
+    /// Connects calls to `number` to `to`.
+    pub fn phone(mut self, number: &str, to: SocketAddr) -> MobileEndpoints {
+        self.numbers.push((number.to_string(), to));
+        self
+    }
+
+    /// The address ISP login hands out, 0.0.0.0 when left unset.
+    pub fn address(mut self, ip: Ipv4Addr) -> MobileEndpoints {
+        self.address = Some(ip);
+        self
+    }
+
+    fn lookup(&self, name: &str) -> Option<Ipv4Addr> {
+        self.names
+            .iter()
+            .find(|(known, _)| known.eq_ignore_ascii_case(name))
+            .map(|(_, ip)| *ip)
+    }
+
+    fn target(&self, ip: Ipv4Addr, port: u16) -> Option<SocketAddr> {
+        self.routes
+            .iter()
+            .find(|(key, _)| *key == (ip, port))
+            .map(|(_, to)| *to)
+            .or_else(|| self.fallback.map(|host| SocketAddr::new(host, port)))
+    }
+
+    fn call(&self, number: &str) -> Option<SocketAddr> {
+        self.numbers
+            .iter()
+            .find(|(known, _)| known == number)
+            .map(|(_, to)| *to)
+    }
+}
+
+/// A call or TCP connection, with what the game sent that it hasn't taken yet.
+struct MobileStream {
+    stream: TcpStream,
+    unsent: Vec<u8>,
+}
+impl MobileStream {
+    fn new(stream: TcpStream) -> io::Result<MobileStream> {
+        stream.set_nonblocking(true)?;
+        stream.set_nodelay(true)?;
+        Ok(MobileStream {
+            stream,
+            unsent: Vec::new(),
+        })
+    }
+
+    /// Sends as much of `data` as the socket takes, after what is left from
+    /// before, then reads what has arrived. None once the other side hung up.
+    fn exchange(&mut self, data: &[u8], buffer: &mut [u8]) -> Option<usize> {
+        self.unsent.extend_from_slice(data);
+        while !self.unsent.is_empty() {
+            match self.stream.write(&self.unsent) {
+                Ok(0) => return None,
+                Ok(size) => {
+                    self.unsent.drain(..size);
+                }
+                Err(err) if err.kind() == ErrorKind::WouldBlock => break,
+                Err(err) if err.kind() == ErrorKind::Interrupted => {}
+                Err(_) => return None,
+            }
+        }
+        match self.stream.read(buffer) {
+            Ok(0) => None,
+            Ok(size) => Some(size),
+            Err(err) if err.kind() == ErrorKind::WouldBlock => Some(0),
+            Err(_) => None,
+        }
+    }
+}
+
+/// Where a connection being made goes once it is up.
+#[derive(Clone, Copy)]
+enum Connecting {
+    Phone,
+    Tcp(usize),
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum PacketState {
+    Magic1,
+    Magic2,
+    Command,
+    Unused,
+    LengthHi,
+    LengthLo,
+    Data,
+    ChecksumHi,
+    ChecksumLo,
+    Device,
+    Ack,
+}
+
+/// The Mobile Adapter GB, plugged into a `LinkPort`. The console clocks every
+/// byte: its packets come in, then the adapter's reply goes out.
+pub struct MobileAdapter {
+    endpoints: MobileEndpoints,
+    config: [u8; MOBILE_CONFIG_SIZE],
+    state: PacketState,
+    command: u8,
+    length: usize,
+    packet: Vec<u8>,
+    checksum: u16,
+    received_checksum: u16,
+    /// The reply being clocked out, then back to reading packets.
+    outgoing: Vec<u8>,
+    sent: usize,
+    reply: Option<u8>,
+    session: bool,
+    phone: Option<MobileStream>,
+    connections: [Option<MobileStream>; MOBILE_CONNECTIONS],
+    /// A connection made on another thread, its reply held back until then.
+    connecting: Option<(Connecting, Receiver<io::Result<TcpStream>>)>,
+}
+impl MobileAdapter {
+    pub fn new(endpoints: MobileEndpoints) -> MobileAdapter {
+        MobileAdapter {
+            endpoints,
+            config: [0; MOBILE_CONFIG_SIZE],
+            state: PacketState::Magic1,
+            command: 0,
+            length: 0,
+            packet: Vec::new(),
+            checksum: 0,
+            received_checksum: 0,
+            outgoing: Vec::new(),
+            sent: 0,
+            reply: None,
+            session: false,
+            phone: None,
+            connections: Default::default(),
+            connecting: None,
+        }
+    }
+
+    /// The configuration area games write to, for saving between runs.
+    pub fn get_config(&self) -> &[u8; MOBILE_CONFIG_SIZE] {
+        &self.config
+    }
+
+    pub fn set_config(&mut self, config: &[u8; MOBILE_CONFIG_SIZE]) {
+        self.config = *config;
+    }
+
+    /// Takes one byte from the console, returns the byte shifted back out.
+    fn receive(&mut self, byte: u8) -> u8 {
+        if !self.finish_connecting() {
+            return ADAPTER_IDLE;
+        }
+        if self.sent < self.outgoing.len() {
+            let reply = self.outgoing[self.sent];
+            self.sent += 1;
+            return reply;
+        }
+
+        /* The checksum covers the header and the data. */
+        if matches!(
+            self.state,
+            PacketState::Command
+                | PacketState::Unused
+                | PacketState::LengthHi
+                | PacketState::LengthLo
+                | PacketState::Data
+        ) {
+            self.checksum = self.checksum.wrapping_add(byte as u16);
+        }
+
+        let mut reply = ADAPTER_IDLE;
+        self.state = match self.state {
+            PacketState::Magic1 if byte == MAGIC_1 => PacketState::Magic2,
+            PacketState::Magic1 => PacketState::Magic1,
+            PacketState::Magic2 if byte == MAGIC_2 => {
+                self.checksum = 0;
+                PacketState::Command
+            }
+            PacketState::Magic2 if byte == MAGIC_1 => PacketState::Magic2,
+            PacketState::Magic2 => PacketState::Magic1,
+            PacketState::Command => {
+                self.command = byte;
+                PacketState::Unused
+            }
+            PacketState::Unused => PacketState::LengthHi,
+            PacketState::LengthHi => {
+                self.length = (byte as usize) << 8;
+                PacketState::LengthLo
+            }
+            PacketState::LengthLo => {
+                self.length |= byte as usize;
+                self.packet.clear();
+                if self.length == 0 {
+                    PacketState::ChecksumHi
+                } else {
+                    PacketState::Data
+                }
+            }
+            PacketState::Data => {
+                self.packet.push(byte);
+                if self.packet.len() == self.length {
+                    PacketState::ChecksumHi
+                } else {
+                    PacketState::Data
+                }
+            }
+            PacketState::ChecksumHi => {
+                self.received_checksum = (byte as u16) << 8;
+                PacketState::ChecksumLo
+            }
+            PacketState::ChecksumLo => {
+                self.received_checksum |= byte as u16;
+                PacketState::Device
+            }
+            PacketState::Device => {
+                reply = DEVICE_ID;
+                PacketState::Ack
+            }
+            PacketState::Ack => {
+                reply = self.finish_packet();
+                PacketState::Magic1
+            }
+        };
+        reply
+    }
+
+    /// Acknowledges the packet just read and queues the reply to it.
+    fn finish_packet(&mut self) -> u8 {
+        if self.checksum != self.received_checksum {
+            return ERROR_CHECKSUM;
+        }
+        let Some((command, data)) = self.run_command() else {
+            return ERROR_UNKNOWN_COMMAND;
+        };
+        self.outgoing = if self.connecting.is_none() {
+            reply_packet(command, &data)
+        } else {
+            Vec::new()
+        };
+        self.sent = 0;
+        self.command ^ ACK_FLAG
+    }
+
+    /// Connects to `addr` on another thread, the console reads idle bytes
+    /// until it's done.
+    fn start_connecting(&mut self, to: Connecting, addr: SocketAddr) {
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || {
+            let _ = sender.send(TcpStream::connect_timeout(&addr, MOBILE_CONNECT_TIMEOUT));
+        });
+        self.connecting = Some((to, receiver));
+    }
+
+    /// Queues the reply to the command that started connecting once it is
+    /// done, returns whether it is.
+    fn finish_connecting(&mut self) -> bool {
+        let Some((to, receiver)) = &self.connecting else {
+            return true;
+        };
+        let stream = match receiver.try_recv() {
+            Err(TryRecvError::Empty) => return false,
+            Ok(result) => result.and_then(MobileStream::new).ok(),
+            Err(TryRecvError::Disconnected) => None,
+        };
+        let (command, data) = match (*to, stream) {
+            (Connecting::Phone, Some(stream)) => {
+                self.phone = Some(stream);
+                (COMMAND_DIAL, Vec::new())
+            }
+            (Connecting::Tcp(id), Some(stream)) => {
+                self.connections[id] = Some(stream);
+                (COMMAND_OPEN_TCP, vec![id as u8])
+            }
+            (Connecting::Phone, None) => error(COMMAND_DIAL, ERROR_CONNECTION_FAILED),
+            (Connecting::Tcp(_), None) => error(COMMAND_OPEN_TCP, ERROR_CONNECTION_FAILED),
+        };
+        self.connecting = None;
+        self.outgoing = reply_packet(command, &data);
+        self.sent = 0;
+        true
+    }
+
+    fn run_command(&mut self) -> Option<(u8, Vec<u8>)> {
+        let command = self.command;
+        let data = std::mem::take(&mut self.packet);
+        if !self.session && command != COMMAND_BEGIN_SESSION {
+            return Some(error(command, ERROR_INVALID_STATE));
+        }
+        /* Packets too short for their command. */
+        let invalid = || Some(error(command, ERROR_INVALID_DATA));
+
+        let reply = match command {
+            COMMAND_BEGIN_SESSION if data == MOBILE_SESSION_NAME => {
+                self.session = true;
+                data
+            }
+            COMMAND_BEGIN_SESSION => return Some(error(command, ERROR_INVALID_DATA)),
+            COMMAND_END_SESSION => {
+                self.hang_up();
+                self.session = false;
+                Vec::new()
+            }
+            COMMAND_DIAL => {
+                /* The first byte is the dialling mode. */
+                let number = String::from_utf8_lossy(data.get(1..).unwrap_or_default());
+                match self.endpoints.call(&number) {
+                    Some(addr) => {
+                        self.start_connecting(Connecting::Phone, addr);
+                        Vec::new()
+                    }
+                    None => return Some(error(command, ERROR_CONNECTION_FAILED)),
+                }
+            }
+            COMMAND_HANG_UP => {
+                self.hang_up();
+                Vec::new()
+            }
+            /* Nobody ever calls back. */
+            COMMAND_WAIT_FOR_CALL => return Some(error(command, ERROR_CONNECTION_FAILED)),
+            COMMAND_TRANSFER_DATA => return Some(self.transfer(data)),
+            COMMAND_RESET => {
+                self.hang_up();
+                Vec::new()
+            }
+            COMMAND_TELEPHONE_STATUS => {
+                let status = if self.phone.is_some() {
+                    TELEPHONE_CONNECTED
+                } else {
+                    TELEPHONE_IDLE
+                };
+                vec![status, TELEPHONE_STATUS_MARKER, 0x00]
+            }
+            /* Only 8 bit transfers are emulated, the mode is acknowledged as is. */
+            COMMAND_SIO32 => data,
+            COMMAND_READ_CONFIG => {
+                let [offset, size] = data[..] else {
+                    return invalid();
+                };
+                let (offset, size) = (offset as usize, size as usize);
+                if offset + size > MOBILE_CONFIG_SIZE {
+                    return Some(error(command, ERROR_INVALID_DATA));
+                }
+                let mut reply = vec![offset as u8];
+                reply.extend_from_slice(&self.config[offset..offset + size]);
+                reply
+            }
+            COMMAND_WRITE_CONFIG => {
+                let Some((&offset, bytes)) = data.split_first() else {
+                    return invalid();
+                };
+                let offset = offset as usize;
+                if offset + bytes.len() > MOBILE_CONFIG_SIZE {
+                    return Some(error(command, ERROR_INVALID_DATA));
+                }
+                self.config[offset..offset + bytes.len()].copy_from_slice(bytes);
+                vec![offset as u8, bytes.len() as u8]
+            }
+            COMMAND_ISP_LOGIN if self.phone.is_some() => {
+                let Some(dns) = login_dns(&data) else {
+                    return invalid();
+                };
+                let address = self.endpoints.address.unwrap_or(Ipv4Addr::UNSPECIFIED);
+                let mut reply = address.octets().to_vec();
+                reply.extend_from_slice(dns);
+                reply
+            }
+            COMMAND_ISP_LOGIN => return Some(error(command, ERROR_INVALID_STATE)),
+            COMMAND_ISP_LOGOUT => {
+                self.close_connections();
+                Vec::new()
+            }
+            COMMAND_OPEN_TCP => {
+                let [a, b, c, d, port_hi, port_lo] = data[..] else {
+                    return invalid();
+                };
+                let (ip, port) = (
+                    Ipv4Addr::new(a, b, c, d),
+                    u16::from_be_bytes([port_hi, port_lo]),
+                );
+                let Some(id) = self.connections.iter().position(Option::is_none) else {
+                    return Some(error(command, ERROR_CONNECTION_FAILED));
+                };
+                match self.endpoints.target(ip, port) {
+                    Some(addr) => {
+                        self.start_connecting(Connecting::Tcp(id), addr);
+                        Vec::new()
+                    }
+                    None => return Some(error(command, ERROR_CONNECTION_FAILED)),
+                }
+            }
+            COMMAND_CLOSE_TCP => {
+                let [id] = data[..] else {
+                    return invalid();
+                };
+                match self.connections.get_mut(id as usize) {
+                    Some(connection) if connection.is_some() => {
+                        *connection = None;
+                        vec![id]
+                    }
+                    _ => return Some(error(command, ERROR_INVALID_DATA)),
+                }
+            }
+            COMMAND_DNS_QUERY => {
+                let name = String::from_utf8_lossy(&data);
+                match self.endpoints.lookup(&name) {
+                    Some(ip) => ip.octets().to_vec(),
+                    None => return Some(error(command, ERROR_CONNECTION_FAILED)),
+                }
+            }
+            _ => return None,
+        };
+        Some((command, reply))
+    }
+
+    /// Sends what the game gave and answers with whatever has arrived, which
+    /// may be nothing yet.
+    fn transfer(&mut self, data: Vec<u8>) -> (u8, Vec<u8>) {
+        let Some(&id) = data.first() else {
+            return error(COMMAND_TRANSFER_DATA, ERROR_INVALID_DATA);
+        };
+        let stream = match id {
+            MOBILE_PHONE_CONNECTION => self.phone.as_mut(),
+            id => self
+                .connections
+                .get_mut(id as usize)
+                .and_then(Option::as_mut),
+        };
+        let Some(stream) = stream else {
+            return error(COMMAND_TRANSFER_DATA, ERROR_INVALID_STATE);
+        };
+
+        let mut buffer = [0; MOBILE_MAX_TRANSFER];
+        match stream.exchange(&data[1..], &mut buffer) {
+            Some(size) => {
+                let mut reply = vec![id];
+                reply.extend_from_slice(&buffer[..size]);
+                (COMMAND_TRANSFER_DATA, reply)
+            }
+            /* The other side hung up. */
+            None => {
+                if id == MOBILE_PHONE_CONNECTION {
+                    self.hang_up();
+                } else {
+                    self.connections[id as usize] = None;
+                }
+                (COMMAND_CONNECTION_CLOSED, vec![id])
+            }
+        }
+    }
+
+    fn hang_up(&mut self) {
+        self.phone = None;
+        self.close_connections();
+    }
+
+    fn close_connections(&mut self) {
+        self.connections = Default::default();
+    }
+}
+impl LinkTransport for MobileAdapter {
+    fn start_transfer(&mut self, byte: u8) {
+        self.reply = Some(self.receive(byte));
+    }
+
+    fn finish_transfer(&mut self) -> Option<u8> {
+        self.reply.take()
+    }
+
+    /* The adapter never drives the clock. */
+    fn set_ready(&mut self, _byte: u8) {}
+
+    fn take_received(&mut self) -> Option<u8> {
+        None
+    }
+}
+
+/// The two DNS servers after the length prefixed ID and password of an ISP
+/// login.
+fn login_dns(data: &[u8]) -> Option<&[u8]> {
+    let id_size = *data.first()? as usize;
+    let password_size = *data.get(1 + id_size)? as usize;
+    data.get(2 + id_size + password_size..)?.get(..8)
+}
+
+fn error(command: u8, code: u8) -> (u8, Vec<u8>) {
+    (COMMAND_ERROR, vec![command, code])
+}
+
+/// A reply with its header, checksum and the two bytes the console answers
+/// with its device ID and acknowledgement.
+fn reply_packet(command: u8, data: &[u8]) -> Vec<u8> {
+    let length = data.len() as u16;
+    let mut packet = vec![MAGIC_1, MAGIC_2, command | ACK_FLAG, 0x00];
+    packet.extend_from_slice(&length.to_be_bytes());
+    packet.extend_from_slice(data);
+    let checksum = packet[2..]
+        .iter()
+        .fold(0_u16, |sum, &byte| sum.wrapping_add(byte as u16));
+    packet.extend_from_slice(&checksum.to_be_bytes());
+    packet.extend_from_slice(&[DEVICE_ID, 0x00]);
+    packet
+}
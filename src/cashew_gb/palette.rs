@@ -0,0 +1,341 @@
+use super::CartridgeHeader;
+
+pub const PALETTE_FILE: &str = "palettes.toml";
+
+const SECTION_GAMES: &str = "games";
+const SECTION_PALETTE: &str = "palettes.";
+const KEY_DEFAULT: &str = "default";
+const KEY_BG: &str = "bg";
+const KEY_OBJ0: &str = "obj0";
+const KEY_OBJ1: &str = "obj1";
+
+pub const DMG_GREEN: DmgPalette = DmgPalette::uniform([
+    rgb(0x9B, 0xBC, 0x0F),
+    rgb(0x8B, 0xAC, 0x0F),
+    rgb(0x30, 0x62, 0x30),
+    rgb(0x0F, 0x38, 0x0F),
+]);
+pub const POCKET_GREY: DmgPalette = DmgPalette::uniform([
+    rgb(0xC4, 0xCF, 0xA1),
+    rgb(0x8B, 0x95, 0x6D),
+    rgb(0x4D, 0x53, 0x3C),
+    rgb(0x1F, 0x1F, 0x1F),
+]);
+pub const LIGHT_TEAL: DmgPalette = DmgPalette::uniform([
+    rgb(0x00, 0xB5, 0x81),
+    rgb(0x00, 0x9A, 0x71),
+    rgb(0x00, 0x69, 0x4A),
+    rgb(0x00, 0x4F, 0x3B),
+]);
+pub const HIGH_CONTRAST: DmgPalette = DmgPalette::uniform([
+    rgb(0xFF, 0xFF, 0xFF),
+    rgb(0xAA, 0xAA, 0xAA),
+    rgb(0x55, 0x55, 0x55),
+    rgb(0x00, 0x00, 0x00),
+]);
+
+pub const PRESETS: [(&str, DmgPalette); 4] = [
+    ("dmg", DMG_GREEN),
+    ("pocket", POCKET_GREY),
+    ("light", LIGHT_TEAL),
+    ("contrast", HIGH_CONTRAST),
+];
+
+/// Four CGB colours (BGR555) for each of the DMG palettes, darkest last.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DmgPalette {
+    pub obj0: [u16; 4],
+    pub obj1: [u16; 4],
+    pub bg: [u16; 4],
+}
+impl DmgPalette {
+    pub const fn uniform(colours: [u16; 4]) -> DmgPalette {
+        DmgPalette {
+            obj0: colours,
+            obj1: colours,
+            bg: colours,
+        }
+    }
+}
+
+/// 8 bits per channel down to the CGB's 5.
+pub const fn rgb(r: u8, g: u8, b: u8) -> u16 {
+    ((b as u16 >> 3) << 10) | ((g as u16 >> 3) << 5) | (r as u16 >> 3)
+}
+
+/// CGB and SGB colours have red in the low bits, the LCD wants it high.
+pub const fn to_rgb555(colour: u16) -> u16 {
+    ((colour & 0x7C00) >> 10) | (colour & 0x03E0) | ((colour & 0x001F) << 10)
+}
+
+#[derive(Debug, PartialEq)]
+pub enum GbPaletteError {
+    GbPaletteSyntax { line: usize },
+    GbPaletteUnknownSection { line: usize },
+    GbPaletteUnknownKey { line: usize },
+    GbPaletteColour { line: usize },
+    GbPaletteUnknownPalette { line: usize },
+    GbPaletteMissingBg { line: usize },
+}
+
+/// A `[palettes.name]` section being read, obj0, obj1 and bg.
+struct CustomPalette {
+    line: usize,
+    name: String,
+    colours: [Option<[u16; 4]>; 3],
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum GameKey {
+    Title(String),
+    GlobalChecksum(u16),
+}
+
+/// Named palettes, the presets first, and which one each game starts with.
+///
+/// Read from a TOML subset:
+///
+/// ```toml
+/// default = "pocket"
+///
+/// [palettes.mine]
+/// bg = ["#E0F8D0", "#88C070", "#346856", "#081820"]
+/// obj0 = ["#FFFFFF", "#FF8484", "#943A3A", "#000000"]  # bg if left out
+///
+/// [games]
+/// "TETRIS" = "contrast"  # by title
+/// 0x91E6 = "mine"        # by global checksum
+/// ```
+#[derive(Clone, Debug, PartialEq)]
+pub struct PaletteConfig {
+    palettes: Vec<(String, DmgPalette)>,
+    games: Vec<(GameKey, String)>,
+    default: Option<String>,
+}
+impl PaletteConfig {
+    pub fn new() -> PaletteConfig {
+        PaletteConfig {
+            palettes: PRESETS
+                .iter()
+                .map(|(name, palette)| (name.to_string(), *palette))
+                .collect(),
+            games: Vec::new(),
+            default: None,
+        }
+    }
+
+    pub fn parse(text: &str) -> Result<PaletteConfig, GbPaletteError> {
+        let mut config = PaletteConfig::new();
+        let mut section = String::new();
+        let mut custom: Option<CustomPalette> = None;
+        let mut references = Vec::new();
+
+        for (n, raw_line) in text.lines().enumerate() {
+            let line = n + 1;
+            let text = strip_comment(raw_line).trim();
+            if text.is_empty() {
+                continue;
+            }
+
+            if let Some(name) = text.strip_prefix('[').and_then(|t| t.strip_suffix(']')) {
+                config.finish_palette(custom.take())?;
+                section = name.trim().to_string();
+                if let Some(name) = section.strip_prefix(SECTION_PALETTE) {
+                    let name =
+                        unquote(name.trim()).ok_or(GbPaletteError::GbPaletteSyntax { line })?;
+                    custom = Some(CustomPalette {
+                        line,
+                        name,
+                        colours: [None; 3],
+                    });
+                } else if section != SECTION_GAMES {
+                    return Err(GbPaletteError::GbPaletteUnknownSection { line });
+                }
+                continue;
+            }
+
+            let (key, value) =
+                split_key_value(text).ok_or(GbPaletteError::GbPaletteSyntax { line })?;
+            match (&mut custom, section.as_str()) {
+                (Some(CustomPalette { colours, .. }), _) => {
+                    let slot = match key.as_str() {
+                        KEY_OBJ0 => 0,
+                        KEY_OBJ1 => 1,
+                        KEY_BG => 2,
+                        _ => return Err(GbPaletteError::GbPaletteUnknownKey { line }),
+                    };
+                    colours[slot] = Some(parse_colours(value, line)?);
+                }
+                (None, SECTION_GAMES) => {
+                    let name = unquote(value).ok_or(GbPaletteError::GbPaletteSyntax { line })?;
+                    let game = match key
+                        .strip_prefix("0x")
+                        .map(|hex| u16::from_str_radix(hex, 16))
+                    {
+                        Some(Ok(checksum)) => GameKey::GlobalChecksum(checksum),
+                        _ => GameKey::Title(key),
+                    };
+                    references.push((line, name.clone()));
+                    config.games.push((game, name));
+                }
+                (None, "") if key == KEY_DEFAULT => {
+                    let name = unquote(value).ok_or(GbPaletteError::GbPaletteSyntax { line })?;
+                    references.push((line, name.clone()));
+                    config.default = Some(name);
+                }
+                _ => return Err(GbPaletteError::GbPaletteUnknownKey { line }),
+            }
+        }
+        config.finish_palette(custom)?;
+
+        /* Palettes can be used before the section that defines them. */
+        if let Some((line, _)) = references
+            .iter()
+            .find(|(_, name)| config.get(name).is_none())
+        {
+            return Err(GbPaletteError::GbPaletteUnknownPalette { line: *line });
+        }
+        Ok(config)
+    }
+
+    fn finish_palette(&mut self, custom: Option<CustomPalette>) -> Result<(), GbPaletteError> {
+        let Some(CustomPalette {
+            line,
+            name,
+            colours: [obj0, obj1, bg],
+        }) = custom
+        else {
+            return Ok(());
+        };
+        let bg = bg.ok_or(GbPaletteError::GbPaletteMissingBg { line })?;
+        let palette = DmgPalette {
+            obj0: obj0.unwrap_or(bg),
+            obj1: obj1.unwrap_or(bg),
+            bg,
+        };
+        match self
+            .palettes
+            .iter_mut()
+            .find(|(existing, _)| *existing == name)
+        {
+            Some((_, existing)) => *existing = palette,
+            None => self.palettes.push((name, palette)),
+        }
+        Ok(())
+    }
+
+    pub fn get(&self, name: &str) -> Option<&DmgPalette> {
+        self.palettes
+            .iter()
+            .find(|(existing, _)| existing == name)
+            .map(|(_, palette)| palette)
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.palettes.iter().map(|(name, _)| name.as_str())
+    }
+
+    /// The palette a game starts with, `None` to keep the CGB colourisation.
+    pub fn select(&self, header: &CartridgeHeader) -> Option<&str> {
+        let title = header.get_title();
+        let checksum = header.get_global_checksum();
+        self.games
+            .iter()
+            .find(|(game, _)| *game == GameKey::GlobalChecksum(checksum))
+            .or_else(|| {
+                self.games
+                    .iter()
+                    .find(|(game, _)| *game == GameKey::Title(title.clone()))
+            })
+            .map(|(_, name)| name.as_str())
+            .or(self.default.as_deref())
+    }
+
+    /// The palette after `current`, wrapping around, for switching at runtime.
+    pub fn next(&self, current: Option<&str>) -> &str {
+        let index = current
+            .and_then(|current| self.names().position(|name| name == current))
+            .map_or(0, |index| (index + 1) % self.palettes.len());
+        &self.palettes[index].0
+    }
+
+    /// The palette before `current`, wrapping around.
+    pub fn previous(&self, current: Option<&str>) -> &str {
+        let index = current
+            .and_then(|current| self.names().position(|name| name == current))
+            .map_or(0, |index| {
+                (index + self.palettes.len() - 1) % self.palettes.len()
+            });
+        &self.palettes[index].0
+    }
+}
+impl Default for PaletteConfig {
+    fn default() -> PaletteConfig {
+        PaletteConfig::new()
+    }
+}
+
+pub(super) fn strip_comment(line: &str) -> &str {
+    let mut quoted = false;
+    for (i, c) in line.char_indices() {
+        match c {
+            '"' => quoted = !quoted,
+            '#' if !quoted => return &line[..i],
+            _ => {}
+        }
+    }
+    line
+}
+
+pub(super) fn unquote(text: &str) -> Option<String> {
+    if text.starts_with('"') {
+        let inner = text.strip_prefix('"')?.strip_suffix('"')?;
+        return (!inner.contains('"')).then(|| inner.to_string());
+    }
+    let bare = text
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-');
+    (bare && !text.is_empty()).then(|| text.to_string())
+}
+
+/// Titles can contain `=`, so a quoted key is read to its closing quote first.
+pub(super) fn split_key_value(text: &str) -> Option<(String, &str)> {
+    let key_end = if let Some(quoted) = text.strip_prefix('"') {
+        quoted.find('"')? + 2
+    } else {
+        text.find('=')?
+    };
+    let key = unquote(text[..key_end].trim())?;
+    let value = text[key_end..].trim_start().strip_prefix('=')?.trim();
+    Some((key, value))
+}
+
+fn parse_colours(value: &str, line: usize) -> Result<[u16; 4], GbPaletteError> {
+    let list = value
+        .strip_prefix('[')
+        .and_then(|value| value.strip_suffix(']'))
+        .ok_or(GbPaletteError::GbPaletteSyntax { line })?;
+    let mut colours = [0; 4];
+    let mut count = 0;
+    for item in list
+        .split(',')
+        .map(str::trim)
+        .filter(|item| !item.is_empty())
+    {
+        let hex = unquote(item)
+            .and_then(|item| item.strip_prefix('#').map(str::to_string))
+            .filter(|hex| hex.len() == 6)
+            .ok_or(GbPaletteError::GbPaletteColour { line })?;
+        let value =
+            u32::from_str_radix(&hex, 16).map_err(|_| GbPaletteError::GbPaletteColour { line })?;
+        if count == colours.len() {
+            return Err(GbPaletteError::GbPaletteColour { line });
+        }
+        colours[count] = rgb((value >> 16) as u8, (value >> 8) as u8, value as u8);
+        count += 1;
+    }
+    if count != colours.len() {
+        return Err(GbPaletteError::GbPaletteColour { line });
+    }
+    Ok(colours)
+}
@@ -0,0 +1,410 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use super::crc32;
+use super::link::LinkTransport;
+
+pub const PRINTER_WIDTH: usize = 160;
+/// Each packet of image data is a band of 2 rows of 20 tiles.
+pub const PRINTER_BAND_HEIGHT: usize = 16;
+const PRINTER_BAND_SIZE: usize = PRINTER_WIDTH / 8 * PRINTER_BAND_HEIGHT / 8 * TILE_SIZE;
+/// The printer's RAM holds 9 bands, 160x144 pixels.
+const PRINTER_MAX_BANDS: usize = 9;
+/// Blank rows fed per unit of the margins in the PRINT command.
+const PRINTER_MARGIN_ROWS: usize = PRINTER_BAND_HEIGHT;
+const TILE_SIZE: usize = 16;
+
+const MAGIC_1: u8 = 0x88;
+const MAGIC_2: u8 = 0x33;
+const PRINTER_ALIVE: u8 = 0x81;
+const PRINTER_IDLE: u8 = 0x00;
+
+const PRINTER_INIT: u8 = 0x01;
+const PRINTER_PRINT: u8 = 0x02;
+const PRINTER_DATA: u8 = 0x04;
+const PRINTER_BREAK: u8 = 0x08;
+const PRINTER_STATUS: u8 = 0x0F;
+
+pub const PRINTER_STATUS_CHECKSUM: u8 = 0x01;
+pub const PRINTER_STATUS_PRINTING: u8 = 0x02;
+pub const PRINTER_STATUS_FULL: u8 = 0x04;
+pub const PRINTER_STATUS_UNPROCESSED: u8 = 0x08;
+pub const PRINTER_STATUS_PACKET_ERROR: u8 = 0x10;
+/// Set when the sink fails, the game shows its printer error.
+pub const PRINTER_STATUS_OTHER_ERROR: u8 = 0x40;
+
+/// STATUS packets answered with the printing bit set after a PRINT.
+const PRINTER_BUSY_POLLS: u8 = 4;
+/// What a palette of 0 prints as, the identity mapping.
+const PRINTER_DEFAULT_PALETTE: u8 = 0xE4;
+
+const PNG_SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A];
+const PNG_BIT_DEPTH: u8 = 2;
+const PNG_GREYSCALE: u8 = 0;
+const ZLIB_HEADER: [u8; 2] = [0x78, 0x01];
+const DEFLATE_STORED_MAX: usize = 0xFFFF;
+
+#[derive(Clone, Copy, PartialEq)]
+enum PacketState {
+    Magic1,
+    Magic2,
+    Command,
+    Compression,
+    LengthLo,
+    LengthHi,
+    Data,
+    ChecksumLo,
+    ChecksumHi,
+    Alive,
+    Status,
+}
+
+/// A printed piece of paper, shades 0 (white) to 3 (black), margins included.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PrintStrip {
+    pub height: usize,
+    pub pixels: Vec<u8>,
+}
+impl PrintStrip {
+    /// A 2 bit greyscale PNG, stored without compression.
+    pub fn to_png(&self) -> Vec<u8> {
+        let row_size = 1 + PRINTER_WIDTH / 4;
+        let mut raw = Vec::with_capacity(row_size * self.height);
+        for row in self.pixels.chunks(PRINTER_WIDTH) {
+            raw.push(0);
+            for pixels in row.chunks(4) {
+                raw.push(
+                    pixels
+                        .iter()
+                        .fold(0, |byte, shade| (byte << 2) | (3 - shade)),
+                );
+            }
+        }
+
+        let mut header = Vec::with_capacity(13);
+        header.extend_from_slice(&(PRINTER_WIDTH as u32).to_be_bytes());
+        header.extend_from_slice(&(self.height as u32).to_be_bytes());
+        header.extend_from_slice(&[PNG_BIT_DEPTH, PNG_GREYSCALE, 0, 0, 0]);
+
+        let mut png = PNG_SIGNATURE.to_vec();
+        png_chunk(&mut png, b"IHDR", &header);
+        png_chunk(&mut png, b"IDAT", &zlib_stored(&raw));
+        png_chunk(&mut png, b"IEND", &[]);
+        png
+    }
+}
+
+fn png_chunk(png: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    png.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    let start = png.len();
+    png.extend_from_slice(kind);
+    png.extend_from_slice(data);
+    let crc = crc32(&png[start..]);
+    png.extend_from_slice(&crc.to_be_bytes());
+}
+
+fn zlib_stored(data: &[u8]) -> Vec<u8> {
+    let mut zlib = ZLIB_HEADER.to_vec();
+    let mut blocks = data.chunks(DEFLATE_STORED_MAX).peekable();
+    if blocks.peek().is_none() {
+        zlib.extend_from_slice(&[0x01, 0x00, 0x00, 0xFF, 0xFF]);
+    }
+    while let Some(block) = blocks.next() {
+        zlib.push(blocks.peek().is_none() as u8);
+        zlib.extend_from_slice(&(block.len() as u16).to_le_bytes());
+        zlib.extend_from_slice(&(!(block.len() as u16)).to_le_bytes());
+        zlib.extend_from_slice(block);
+    }
+    let (mut a, mut b) = (1_u32, 0_u32);
+    for &byte in data {
+        a = (a + byte as u32) % 65521;
+        b = (b + a) % 65521;
+    }
+    zlib.extend_from_slice(&((b << 16) | a).to_be_bytes());
+    zlib
+}
+
+/// Where finished prints go.
+pub trait PrintSink {
+    fn print(&mut self, strip: PrintStrip) -> io::Result<()>;
+}
+impl<F: FnMut(PrintStrip) -> io::Result<()>> PrintSink for F {
+    fn print(&mut self, strip: PrintStrip) -> io::Result<()> {
+        self(strip)
+    }
+}
+
+/// Saves prints as numbered PNG files in a directory, after any already there.
+pub struct PrintDirectory {
+    path: PathBuf,
+    next: usize,
+}
+impl PrintDirectory {
+    pub fn new(path: &Path) -> io::Result<PrintDirectory> {
+        fs::create_dir_all(path)?;
+        let next = fs::read_dir(path)?
+            .filter_map(|entry| print_number(&entry.ok()?.file_name().to_string_lossy()))
+            .max()
+            .map_or(0, |last| last + 1);
+        Ok(PrintDirectory {
+            path: path.to_path_buf(),
+            next,
+        })
+    }
+}
+impl PrintSink for PrintDirectory {
+    fn print(&mut self, strip: PrintStrip) -> io::Result<()> {
+        let path = self.path.join(format!("print_{:04}.png", self.next));
+        self.next += 1;
+        fs::write(path, strip.to_png())
+    }
+}
+
+fn print_number(name: &str) -> Option<usize> {
+    name.strip_prefix("print_")?
+        .strip_suffix(".png")?
+        .parse()
+        .ok()
+}
+
+/// The Game Boy Printer, plugged into a `LinkPort`. The console always clocks it.
+pub struct Printer {
+    sink: Box<dyn PrintSink>,
+    state: PacketState,
+    command: u8,
+    compressed: bool,
+    length: usize,
+    packet: Vec<u8>,
+    checksum: u16,
+    received_checksum: u16,
+    image: Vec<u8>,
+    status: u8,
+    busy: u8,
+    reply: Option<u8>,
+}
+impl Printer {
+    pub fn new(sink: Box<dyn PrintSink>) -> Printer {
+        Printer {
+            sink,
+            state: PacketState::Magic1,
+            command: 0,
+            compressed: false,
+            length: 0,
+            packet: Vec::new(),
+            checksum: 0,
+            received_checksum: 0,
+            image: Vec::new(),
+            status: 0,
+            busy: 0,
+            reply: None,
+        }
+    }
+
+    pub fn get_status(&self) -> u8 {
+        let printing = if self.busy > 0 {
+            PRINTER_STATUS_PRINTING
+        } else {
+            0
+        };
+        self.status | printing
+    }
+
+    /// Takes one byte of a packet, returns the byte shifted back out.
+    fn receive(&mut self, byte: u8) -> u8 {
+        let mut reply = PRINTER_IDLE;
+        self.state = match self.state {
+            PacketState::Magic1 if byte == MAGIC_1 => PacketState::Magic2,
+            PacketState::Magic1 => PacketState::Magic1,
+            PacketState::Magic2 if byte == MAGIC_2 => {
+                self.checksum = 0;
+                PacketState::Command
+            }
+            PacketState::Magic2 if byte == MAGIC_1 => PacketState::Magic2,
+            PacketState::Magic2 => PacketState::Magic1,
+            PacketState::Command => {
+                self.command = byte;
+                PacketState::Compression
+            }
+            PacketState::Compression => {
+                self.compressed = (byte & 0x01) != 0;
+                PacketState::LengthLo
+            }
+            PacketState::LengthLo => {
+                self.length = byte as usize;
+                PacketState::LengthHi
+            }
+            PacketState::LengthHi => {
+                self.length |= (byte as usize) << 8;
+                self.packet.clear();
+                if self.length == 0 {
+                    PacketState::ChecksumLo
+                } else {
+                    PacketState::Data
+                }
+            }
+            PacketState::Data => {
+                self.packet.push(byte);
+                if self.packet.len() == self.length {
+                    PacketState::ChecksumLo
+                } else {
+                    PacketState::Data
+                }
+            }
+            PacketState::ChecksumLo => {
+                self.received_checksum = byte as u16;
+                PacketState::ChecksumHi
+            }
+            PacketState::ChecksumHi => {
+                self.received_checksum |= (byte as u16) << 8;
+                PacketState::Alive
+            }
+            PacketState::Alive => {
+                reply = PRINTER_ALIVE;
+                self.run_command();
+                PacketState::Status
+            }
+            PacketState::Status => {
+                reply = self.get_status();
+                if self.command == PRINTER_STATUS {
+                    self.busy = self.busy.saturating_sub(1);
+                }
+                PacketState::Magic1
+            }
+        };
+
+        /* The checksum covers everything from the command to the data. */
+        if matches!(
+            self.state,
+            PacketState::Compression
+                | PacketState::LengthLo
+                | PacketState::LengthHi
+                | PacketState::Data
+                | PacketState::ChecksumLo
+        ) {
+            self.checksum = self.checksum.wrapping_add(byte as u16);
+        }
+        reply
+    }
+
+    fn run_command(&mut self) {
+        if self.checksum != self.received_checksum {
+            self.status |= PRINTER_STATUS_CHECKSUM;
+            return;
+        }
+        self.status &= !(PRINTER_STATUS_CHECKSUM | PRINTER_STATUS_PACKET_ERROR);
+
+        match self.command {
+            PRINTER_INIT => {
+                self.image.clear();
+                self.status = 0;
+                self.busy = 0;
+            }
+            PRINTER_DATA if !self.packet.is_empty() => {
+                let data = if self.compressed {
+                    decompress(&self.packet)
+                } else {
+                    std::mem::take(&mut self.packet)
+                };
+                let free = PRINTER_MAX_BANDS * PRINTER_BAND_SIZE - self.image.len();
+                self.image.extend_from_slice(&data[..data.len().min(free)]);
+                self.status |= PRINTER_STATUS_UNPROCESSED;
+                if self.image.len() == PRINTER_MAX_BANDS * PRINTER_BAND_SIZE {
+                    self.status |= PRINTER_STATUS_FULL;
+                }
+            }
+            PRINTER_DATA => {}
+            PRINTER_PRINT if self.packet.len() >= 4 => {
+                let (sheets, margins, palette) = (self.packet[0], self.packet[1], self.packet[2]);
+                if sheets > 0 {
+                    let strip = self.render(sheets as usize, margins, palette);
+                    if self.sink.print(strip).is_err() {
+                        self.status |= PRINTER_STATUS_OTHER_ERROR;
+                    }
+                }
+                self.image.clear();
+                self.status &= !(PRINTER_STATUS_UNPROCESSED | PRINTER_STATUS_FULL);
+                self.busy = PRINTER_BUSY_POLLS;
+            }
+            PRINTER_BREAK => self.busy = 0,
+            PRINTER_STATUS => {}
+            _ => self.status |= PRINTER_STATUS_PACKET_ERROR,
+        }
+    }
+
+    fn render(&self, sheets: usize, margins: u8, palette: u8) -> PrintStrip {
+        let palette = if palette == 0 {
+            PRINTER_DEFAULT_PALETTE
+        } else {
+            palette
+        };
+        let tiles_per_row = PRINTER_WIDTH / 8;
+        /* Only whole bands print, the rest of a short DATA packet is dropped. */
+        let bands = self.image.len() / PRINTER_BAND_SIZE;
+        let mut image = vec![0; PRINTER_WIDTH * bands * PRINTER_BAND_HEIGHT];
+        for (index, tile) in self.image[..bands * PRINTER_BAND_SIZE]
+            .chunks_exact(TILE_SIZE)
+            .enumerate()
+        {
+            let (tile_x, tile_y) = (index % tiles_per_row * 8, index / tiles_per_row * 8);
+            for (row, bytes) in tile.chunks_exact(2).enumerate() {
+                for bit in 0..8 {
+                    let colour =
+                        ((bytes[0] >> (7 - bit)) & 1) | (((bytes[1] >> (7 - bit)) & 1) << 1);
+                    image[(tile_y + row) * PRINTER_WIDTH + tile_x + bit] =
+                        (palette >> (colour * 2)) & 3;
+                }
+            }
+        }
+
+        let before = (margins >> 4) as usize * PRINTER_MARGIN_ROWS * PRINTER_WIDTH;
+        let after = (margins & 0x0F) as usize * PRINTER_MARGIN_ROWS * PRINTER_WIDTH;
+        let mut pixels = vec![0; before];
+        for _ in 0..sheets {
+            pixels.extend_from_slice(&image);
+        }
+        pixels.resize(pixels.len() + after, 0);
+        PrintStrip {
+            height: pixels.len() / PRINTER_WIDTH,
+            pixels,
+        }
+    }
+}
+impl LinkTransport for Printer {
+    fn start_transfer(&mut self, byte: u8) {
+        self.reply = Some(self.receive(byte));
+    }
+
+    fn finish_transfer(&mut self) -> Option<u8> {
+        self.reply.take()
+    }
+
+    /* The printer never drives the clock. */
+    fn set_ready(&mut self, _byte: u8) {}
+
+    fn take_received(&mut self) -> Option<u8> {
+        None
+    }
+}
+
+/// Runs of `(control & 0x7F) + 2` copies of the next byte when bit 7 is set,
+/// otherwise `control + 1` bytes copied as they are.
+fn decompress(packet: &[u8]) -> Vec<u8> {
+    let mut data = Vec::with_capacity(PRINTER_BAND_SIZE);
+    let mut pos = 0;
+    while let Some(&control) = packet.get(pos) {
+        pos += 1;
+        if (control & 0x80) != 0 {
+            let Some(&byte) = packet.get(pos) else {
+                break;
+            };
+            pos += 1;
+            data.resize(data.len() + (control & 0x7F) as usize + 2, byte);
+        } else {
+            let end = (pos + control as usize + 1).min(packet.len());
+            data.extend_from_slice(&packet[pos..end]);
+            pos = end;
+        }
+    }
+    data
+}
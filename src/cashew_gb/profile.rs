@@ -0,0 +1,454 @@
+use std::collections::HashMap;
+use std::io::{self, Write};
+
+use super::debug::{OPCODE_CALL, OPCODE_CALL_CC, OPCODE_RST, OPCODE_RST_MASK};
+use super::disasm::SymbolTable;
+use super::{
+    Gb, CONTROL_INTR_ADDR, LCDC_INTR_ADDR, SERIAL_INTR_ADDR, TIMER_INTR_ADDR, VBLANK_INTR_ADDR,
+};
+
+const OPCODE_HALT: u8 = 0x76;
+const ROOT: usize = 0;
+
+/// The interrupt vectors in priority order, with the names the report uses.
+const INTERRUPTS: [(u8, &str); 5] = [
+    (VBLANK_INTR_ADDR, "vblank"),
+    (LCDC_INTR_ADDR, "stat"),
+    (TIMER_INTR_ADDR, "timer"),
+    (SERIAL_INTR_ADDR, "serial"),
+    (CONTROL_INTR_ADDR, "joypad"),
+];
+
+/// How a frame of the call stack was entered.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+enum Entry {
+    /// Whatever ran from reset, or after the stack pointer was moved above
+    /// every frame.
+    Root,
+    Call {
+        bank: u16,
+        addr: u16,
+    },
+    /// Index into `INTERRUPTS`.
+    Interrupt(usize),
+}
+
+/// A call stack seen so far, a node of the tree every stack is a path of.
+struct Node {
+    parent: usize,
+    entry: Entry,
+    children: HashMap<Entry, usize>,
+    calls: u64,
+    /* Only meaningful while the node is on the stack. */
+    sp: u16,
+    start: u64,
+}
+
+/// Cycles spent at one address, over every stack it ran under.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Hotspot {
+    pub bank: u16,
+    pub addr: u16,
+    pub cycles: u64,
+}
+
+/// Cycles spent in a function, by the nearest label before each address.
+/// `total` includes what it called and the interrupts taken meanwhile.
+#[derive(Clone, Debug, PartialEq)]
+pub struct FunctionTime {
+    pub name: String,
+    pub own: u64,
+    pub total: u64,
+    pub calls: u64,
+}
+
+/// Cycles from an interrupt being taken to its handler returning, nested
+/// interrupts included.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct InterruptTime {
+    pub name: &'static str,
+    pub count: u64,
+    pub cycles: u64,
+}
+
+/// Counts the cycles of every instruction run, as `_step_cpu` clocks them
+/// from `OP_CYCLES`, against its bank and address and the call stack it
+/// ran under.
+///
+/// Frames are pushed by CALL, RST and interrupts and popped once the stack
+/// pointer goes above where the return address was pushed, so code that
+/// drops its return address or moves the stack still unwinds. Cycles are
+/// the ones `get_clock` counts, waiting in HALT included but kept apart
+/// from the address of the HALT.
+pub struct Profiler {
+    nodes: Vec<Node>,
+    current: usize,
+    cycles: HashMap<(usize, u16, u16), u64>,
+    halt: HashMap<usize, u64>,
+    interrupts: [InterruptTime; 5],
+    total: u64,
+}
+impl Profiler {
+    pub fn new() -> Profiler {
+        let mut interrupts = [InterruptTime::default(); 5];
+        for (time, (_, name)) in interrupts.iter_mut().zip(INTERRUPTS) {
+            time.name = name;
+        }
+        Profiler {
+            nodes: vec![Node {
+                parent: ROOT,
+                entry: Entry::Root,
+                children: HashMap::new(),
+                calls: 1,
+                sp: 0,
+                start: 0,
+            }],
+            current: ROOT,
+            cycles: HashMap::new(),
+            halt: HashMap::new(),
+            interrupts,
+            total: 0,
+        }
+    }
+
+    /// Every cycle counted.
+    pub fn get_total(&self) -> u64 {
+        self.total
+    }
+
+    /// Cycles spent in HALT, the instruction and waiting for an interrupt.
+    pub fn get_halt(&self) -> u64 {
+        self.halt.values().sum()
+    }
+
+    /// Addresses by cycles spent on the instruction there, most first. HALT's
+    /// wait isn't included.
+    pub fn get_hotspots(&self) -> Vec<Hotspot> {
+        let mut cycles = HashMap::<(u16, u16), u64>::new();
+        for (&(_, bank, addr), &count) in &self.cycles {
+            *cycles.entry((bank, addr)).or_default() += count;
+        }
+        let mut hotspots: Vec<Hotspot> = cycles
+            .into_iter()
+            .map(|((bank, addr), cycles)| Hotspot { bank, addr, cycles })
+            .collect();
+        hotspots.sort_by_key(|hotspot| (u64::MAX - hotspot.cycles, hotspot.bank, hotspot.addr));
+        hotspots
+    }
+
+    /// Functions by their own cycles, most first. Code before the first
+    /// label of its bank is named by its bank. HALT's wait isn't included.
+    pub fn get_functions(&self, symbols: &SymbolTable) -> Vec<FunctionTime> {
+        let mut functions = HashMap::<String, FunctionTime>::new();
+        /* Each function on a stack once, however deep it recursed. */
+        let mut stack = Vec::new();
+        for (&(node, bank, addr), &cycles) in &self.cycles {
+            stack.clear();
+            stack.push(function_name(symbols, bank, addr));
+            let mut index = node;
+            while index != ROOT {
+                if let Entry::Call { bank, addr } = self.nodes[index].entry {
+                    let name = function_name(symbols, bank, addr);
+                    if !stack.contains(&name) {
+                        stack.push(name);
+                    }
+                }
+                index = self.nodes[index].parent;
+            }
+            for (i, name) in stack.drain(..).enumerate() {
+                let function = function_time(&mut functions, name);
+                if i == 0 {
+                    function.own += cycles;
+                }
+                function.total += cycles;
+            }
+        }
+        for node in &self.nodes {
+            if let Entry::Call { bank, addr } = node.entry {
+                let name = function_name(symbols, bank, addr);
+                function_time(&mut functions, name).calls += node.calls;
+            }
+        }
+        let mut functions: Vec<FunctionTime> = functions.into_values().collect();
+        functions.sort_by(|a, b| b.own.cmp(&a.own).then_with(|| a.name.cmp(&b.name)));
+        functions
+    }
+
+    pub fn get_interrupts(&self) -> &[InterruptTime; 5] {
+        &self.interrupts
+    }
+
+    /// Forgets everything counted, the stack being profiled is kept.
+    pub fn clear(&mut self) {
+        for node in &mut self.nodes {
+            node.calls = 0;
+            node.start = 0;
+        }
+        for time in &mut self.interrupts {
+            time.count = 0;
+            time.cycles = 0;
+        }
+        self.cycles.clear();
+        self.halt.clear();
+        self.total = 0;
+    }
+
+    /// The report, totals then the `limit` hottest addresses and, with
+    /// `symbols`, functions.
+    pub fn write_report(
+        &self,
+        writer: &mut impl Write,
+        symbols: Option<&SymbolTable>,
+        limit: usize,
+    ) -> io::Result<()> {
+        let total = self.total.max(1);
+        let percent = |cycles: u64| cycles as f64 * 100.0 / total as f64;
+        let halt = self.get_halt();
+        writeln!(writer, "{:>12} cycles", self.total)?;
+        writeln!(writer, "{halt:>12} in HALT ({:.2}%)", percent(halt))?;
+
+        writeln!(writer, "\ninterrupt        count       cycles")?;
+        for time in &self.interrupts {
+            writeln!(
+                writer,
+                "{:10} {:>11} {:>12} ({:.2}%)",
+                time.name,
+                time.count,
+                time.cycles,
+                percent(time.cycles)
+            )?;
+        }
+
+        if let Some(symbols) = symbols {
+            writeln!(writer, "\n         own        total      calls  function")?;
+            for function in self.get_functions(symbols).iter().take(limit) {
+                writeln!(
+                    writer,
+                    "{:>12} {:>12} {:>10}  {} ({:.2}%)",
+                    function.own,
+                    function.total,
+                    function.calls,
+                    function.name,
+                    percent(function.own)
+                )?;
+            }
+        }
+
+        writeln!(writer, "\n      cycles  address")?;
+        for hotspot in self.get_hotspots().iter().take(limit) {
+            let label = symbols.map_or(String::new(), |symbols| {
+                format!(" {}", address_name(symbols, hotspot.bank, hotspot.addr))
+            });
+            writeln!(
+                writer,
+                "{:>12}  {:02X}:{:04X}{label} ({:.2}%)",
+                hotspot.cycles,
+                hotspot.bank,
+                hotspot.addr,
+                percent(hotspot.cycles)
+            )?;
+        }
+        Ok(())
+    }
+
+    /// A `frame;frame;frame cycles` line per stack, the format
+    /// `flamegraph.pl` and speedscope read. Frames are named by label, or
+    /// `bank:addr` without one, interrupts as `[vblank]` and the like and
+    /// HALT's wait as `[halt]`. With `symbols` the function each address is
+    /// in ends its stack.
+    pub fn write_collapsed(
+        &self,
+        writer: &mut impl Write,
+        symbols: Option<&SymbolTable>,
+    ) -> io::Result<()> {
+        let mut stacks = HashMap::<String, u64>::new();
+        let mut add = |node: usize, leaf: Option<String>, cycles: u64| {
+            let mut frames = vec![];
+            let mut index = node;
+            loop {
+                frames.push(self.frame_name(index, symbols));
+                if index == ROOT {
+                    break;
+                }
+                index = self.nodes[index].parent;
+            }
+            frames.reverse();
+            if let Some(leaf) = leaf {
+                if frames.last() != Some(&leaf) {
+                    frames.push(leaf);
+                }
+            }
+            *stacks.entry(frames.join(";")).or_default() += cycles;
+        };
+        for (&(node, bank, addr), &cycles) in &self.cycles {
+            add(
+                node,
+                symbols.map(|symbols| function_name(symbols, bank, addr)),
+                cycles,
+            );
+        }
+        for (&node, &cycles) in &self.halt {
+            add(node, Some(String::from("[halt]")), cycles);
+        }
+
+        let mut stacks: Vec<(String, u64)> = stacks.into_iter().collect();
+        stacks.sort();
+        for (stack, cycles) in stacks {
+            writeln!(writer, "{stack} {cycles}")?;
+        }
+        Ok(())
+    }
+
+    fn frame_name(&self, index: usize, symbols: Option<&SymbolTable>) -> String {
+        match self.nodes[index].entry {
+            Entry::Root => String::from("[reset]"),
+            Entry::Call { bank, addr } => symbols.map_or_else(
+                || format!("{bank:02X}:{addr:04X}"),
+                |symbols| address_name(symbols, bank, addr),
+            ),
+            Entry::Interrupt(index) => format!("[{}]", INTERRUPTS[index].1),
+        }
+    }
+
+    fn push(&mut self, entry: Entry, sp: u16) {
+        let next = self.nodes.len();
+        let index = *self.nodes[self.current]
+            .children
+            .entry(entry)
+            .or_insert(next);
+        if index == next {
+            self.nodes.push(Node {
+                parent: self.current,
+                entry,
+                children: HashMap::new(),
+                calls: 0,
+                sp: 0,
+                start: 0,
+            });
+        }
+        let node = &mut self.nodes[index];
+        node.calls += 1;
+        node.sp = sp;
+        node.start = self.total;
+        self.current = index;
+    }
+
+    /// Pops every frame whose return address is below `sp`.
+    fn unwind(&mut self, sp: u16) {
+        while self.current != ROOT && sp > self.nodes[self.current].sp {
+            let node = &self.nodes[self.current];
+            if let Entry::Interrupt(index) = node.entry {
+                self.interrupts[index].cycles += self.total - node.start;
+            }
+            self.current = node.parent;
+        }
+    }
+}
+
+impl Default for Profiler {
+    fn default() -> Profiler {
+        Profiler::new()
+    }
+}
+
+/// The label at or before `addr`, or the bank before its first label.
+fn function_name(symbols: &SymbolTable, bank: u16, addr: u16) -> String {
+    symbols
+        .get_nearest(bank, addr)
+        .map_or_else(|| format!("bank {bank:02X}"), |symbol| symbol.name.clone())
+}
+
+/// `function_name` with how far past the label `addr` is.
+fn address_name(symbols: &SymbolTable, bank: u16, addr: u16) -> String {
+    match symbols.get_nearest(bank, addr) {
+        Some(symbol) if symbol.addr != addr => format!("{}+{}", symbol.name, addr - symbol.addr),
+        _ => function_name(symbols, bank, addr),
+    }
+}
+
+fn function_time(functions: &mut HashMap<String, FunctionTime>, name: String) -> &mut FunctionTime {
+    functions.entry(name.clone()).or_insert(FunctionTime {
+        name,
+        own: 0,
+        total: 0,
+        calls: 0,
+    })
+}
+
+/// Where an instruction started, for `_profile_step` once it has run.
+pub(super) struct ProfileStart {
+    bank: u16,
+    pc: u16,
+    sp: u16,
+    clock: u64,
+}
+
+impl<'a, T> Gb<'a, T> {
+    /// Starts profiling with `profiler`, or stops with `None`. Returns the
+    /// profiler that was in use.
+    pub fn gb_set_profiler(&mut self, profiler: Option<Profiler>) -> Option<Profiler> {
+        std::mem::replace(&mut self.profiler, profiler.map(Box::new)).map(|profiler| *profiler)
+    }
+
+    pub fn get_profiler(&self) -> Option<&Profiler> {
+        self.profiler.as_deref()
+    }
+
+    /// Called by `_step_cpu` once an interrupt has been taken, with PC at
+    /// its vector.
+    pub(super) fn _profile_interrupt(&mut self) -> () {
+        let pc = self.cpu_reg.pc.bytes;
+        let sp = self.cpu_reg.sp.bytes;
+        let Some(profiler) = &mut self.profiler else {
+            return;
+        };
+        if let Some(index) = INTERRUPTS.iter().position(|(addr, _)| *addr as u16 == pc) {
+            profiler.interrupts[index].count += 1;
+            profiler.push(Entry::Interrupt(index), sp);
+        }
+    }
+
+    /// Called by `_step_cpu` before each opcode fetch.
+    pub(super) fn _profile_start(&self) -> ProfileStart {
+        let pc = self.cpu_reg.pc.bytes;
+        ProfileStart {
+            bank: self.get_bank(pc),
+            pc,
+            sp: self.cpu_reg.sp.bytes,
+            clock: self.counter.clock,
+        }
+    }
+
+    /// Called by `_step_cpu` once the instruction fetched as `opcode` has
+    /// been clocked.
+    pub(super) fn _profile_step(&mut self, opcode: u8, start: ProfileStart) -> () {
+        let pc = self.cpu_reg.pc.bytes;
+        let sp = self.cpu_reg.sp.bytes;
+        let bank = self.get_bank(pc);
+        let cycles = self.counter.clock - start.clock;
+        let Some(profiler) = &mut self.profiler else {
+            return;
+        };
+
+        let node = profiler.current;
+        profiler.total += cycles;
+        if opcode == OPCODE_HALT {
+            *profiler.halt.entry(node).or_default() += cycles;
+        } else {
+            *profiler
+                .cycles
+                .entry((node, start.bank, start.pc))
+                .or_default() += cycles;
+        }
+
+        /* A conditional call not taken leaves SP alone. */
+        let call = opcode == OPCODE_CALL
+            || OPCODE_CALL_CC.contains(&opcode)
+            || opcode & OPCODE_RST_MASK == OPCODE_RST;
+        if call && sp == start.sp.wrapping_sub(2) {
+            profiler.push(Entry::Call { bank, addr: pc }, sp);
+        } else {
+            profiler.unwind(sp);
+        }
+    }
+}
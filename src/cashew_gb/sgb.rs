@@ -0,0 +1,518 @@
+use super::palette::to_rgb555;
+use super::{LCD_HEIGHT, LCD_WIDTH};
+
+pub const SGB_WIDTH: usize = 256;
+pub const SGB_HEIGHT: usize = 224;
+/// Where the Game Boy screen sits inside the border.
+pub const SGB_SCREEN_X: usize = 48;
+pub const SGB_SCREEN_Y: usize = 40;
+pub const SGB_MAX_PLAYERS: usize = 4;
+
+const PACKET_SIZE: usize = 16;
+const PACKET_BITS: u8 = PACKET_SIZE as u8 * 8;
+
+const JOYP_SELECT_MASK: u8 = 0x30;
+const JOYP_RESET: u8 = 0x00;
+const JOYP_BIT_1: u8 = 0x10;
+const JOYP_BIT_0: u8 = 0x20;
+const JOYP_IDLE: u8 = 0x30;
+const JOYP_P15: u8 = 0x20;
+
+const ATTR_WIDTH: usize = LCD_WIDTH as usize / 8;
+const ATTR_HEIGHT: usize = LCD_HEIGHT as usize / 8;
+const ATTR_CELLS: usize = ATTR_WIDTH * ATTR_HEIGHT;
+const ATF_SIZE: usize = ATTR_CELLS / 4;
+const ATF_COUNT: usize = 45;
+const SYSTEM_PALETTES: usize = 512;
+
+/* VRAM transfers read back what the game shows on the LCD, as tiles. */
+const TRANSFER_SIZE: usize = 0x1000;
+const SCREEN_TILE_SIZE: usize = 16;
+const SCREEN_SIZE: usize = ATTR_CELLS * SCREEN_TILE_SIZE;
+
+const BORDER_TILE_SIZE: usize = 32;
+const BORDER_TILES_SIZE: usize = 256 * BORDER_TILE_SIZE;
+const BORDER_MAP_WIDTH: usize = 32;
+const BORDER_MAP_SIZE: usize = 0x800;
+const BORDER_PALETTES: usize = 4;
+const BORDER_PALETTE_FIRST: usize = 4;
+
+/// Pixel values `get_palette` reserves, next to the 4 attribute palettes.
+pub const SGB_PIXEL_BLACK: u8 = 0x10;
+
+const SGB_DEFAULT_PALETTE: [u16; 4] = [0x67BF, 0x265B, 0x10B5, 0x2866];
+
+const SGB_PAL01: u8 = 0x00;
+const SGB_PAL23: u8 = 0x01;
+const SGB_PAL03: u8 = 0x02;
+const SGB_PAL12: u8 = 0x03;
+const SGB_ATTR_BLK: u8 = 0x04;
+const SGB_ATTR_LIN: u8 = 0x05;
+const SGB_ATTR_DIV: u8 = 0x06;
+const SGB_ATTR_CHR: u8 = 0x07;
+const SGB_PAL_SET: u8 = 0x0A;
+const SGB_PAL_TRN: u8 = 0x0B;
+const SGB_MLT_REQ: u8 = 0x11;
+const SGB_CHR_TRN: u8 = 0x13;
+const SGB_PCT_TRN: u8 = 0x14;
+const SGB_ATTR_TRN: u8 = 0x15;
+const SGB_ATTR_SET: u8 = 0x16;
+const SGB_MASK_EN: u8 = 0x17;
+
+#[derive(Clone, Copy, PartialEq)]
+enum Mask {
+    None,
+    Freeze,
+    Black,
+    Colour0,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Transfer {
+    Palettes,
+    BorderTiles(usize),
+    BorderMap,
+    Attributes,
+}
+
+/// Super Game Boy state, only allocated when SGB mode is on.
+pub struct Sgb {
+    packet: [u8; PACKET_SIZE],
+    packet_bit: u8,
+    receiving: bool,
+    pulse_ready: bool,
+    command: Vec<u8>,
+
+    palettes: [[u16; 4]; 4],
+    system_palettes: Vec<[u16; 4]>,
+    attributes: [u8; ATTR_CELLS],
+    attribute_files: Vec<u8>,
+    mask: Mask,
+
+    screen: Vec<u8>,
+    transfer: Option<Transfer>,
+    transfer_frame_started: bool,
+
+    border_tiles: Vec<u8>,
+    border_map: Vec<u8>,
+    border_palettes: [[u16; 16]; BORDER_PALETTES],
+    border_loaded: bool,
+    border_changed: bool,
+
+    joypads: [u8; SGB_MAX_PLAYERS],
+    player_count: usize,
+    player: usize,
+    last_joyp: u8,
+}
+
+impl Sgb {
+    pub fn new() -> Sgb {
+        Sgb {
+            packet: [0; PACKET_SIZE],
+            packet_bit: 0,
+            receiving: false,
+            pulse_ready: false,
+            command: Vec::new(),
+            palettes: [SGB_DEFAULT_PALETTE; 4],
+            system_palettes: vec![[0; 4]; SYSTEM_PALETTES],
+            attributes: [0; ATTR_CELLS],
+            attribute_files: vec![0; ATF_COUNT * ATF_SIZE],
+            mask: Mask::None,
+            screen: vec![0; SCREEN_SIZE],
+            transfer: None,
+            transfer_frame_started: false,
+            border_tiles: vec![0; BORDER_TILES_SIZE],
+            border_map: vec![0; BORDER_MAP_SIZE],
+            border_palettes: [[0; 16]; BORDER_PALETTES],
+            border_loaded: false,
+            border_changed: false,
+            joypads: [0xFF; SGB_MAX_PLAYERS],
+            player_count: 1,
+            player: 0,
+            last_joyp: JOYP_IDLE,
+        }
+    }
+
+    /// Feeds a JOYP write to the packet receiver. Returns the joypad ID to
+    /// show in the low nibble when a multiplayer game reads with both lines high.
+    pub fn write_joyp(&mut self, val: u8) -> Option<u8> {
+        let lines = val & JOYP_SELECT_MASK;
+        match lines {
+            JOYP_RESET => {
+                self.receiving = true;
+                self.pulse_ready = false;
+                self.packet_bit = 0;
+                self.packet = [0; PACKET_SIZE];
+            }
+            JOYP_IDLE => {
+                self.pulse_ready = true;
+                /* The next joypad is selected when P15 goes back high. */
+                if !self.receiving && self.last_joyp & JOYP_P15 == 0 && self.player_count > 1 {
+                    self.player = (self.player + 1) % self.player_count;
+                }
+            }
+            JOYP_BIT_0 | JOYP_BIT_1 if self.receiving && self.pulse_ready => {
+                self.pulse_ready = false;
+                if lines == JOYP_BIT_1 {
+                    self.packet[self.packet_bit as usize >> 3] |= 1 << (self.packet_bit & 7);
+                }
+                self.packet_bit += 1;
+                if self.packet_bit == PACKET_BITS {
+                    /* The stop bit that follows is ignored. */
+                    self.receiving = false;
+                    self.receive_packet();
+                }
+            }
+            _ => {}
+        }
+        self.last_joyp = lines;
+
+        (lines == JOYP_IDLE && self.player_count > 1).then(|| 0x0F - self.player as u8)
+    }
+
+    fn receive_packet(&mut self) {
+        self.command.extend_from_slice(&self.packet);
+        let packets = (self.command[0] & 0x07).max(1) as usize;
+        if self.command.len() >= packets * PACKET_SIZE {
+            let command = std::mem::take(&mut self.command);
+            self.execute(&command);
+        }
+    }
+
+    fn execute(&mut self, data: &[u8]) {
+        let byte = |i: usize| data.get(i).copied().unwrap_or(0);
+        let word = |i: usize| u16::from_le_bytes([byte(i), byte(i + 1)]);
+
+        match data[0] >> 3 {
+            command @ (SGB_PAL01 | SGB_PAL23 | SGB_PAL03 | SGB_PAL12) => {
+                let (a, b) = match command {
+                    SGB_PAL01 => (0, 1),
+                    SGB_PAL23 => (2, 3),
+                    SGB_PAL03 => (0, 3),
+                    _ => (1, 2),
+                };
+                for i in 0..3 {
+                    self.palettes[a][i + 1] = word(3 + i * 2);
+                    self.palettes[b][i + 1] = word(9 + i * 2);
+                }
+                self.set_colour_0(word(1));
+            }
+            SGB_ATTR_BLK => {
+                for set in 0..(byte(1) & 0x1F) as usize {
+                    let base = 2 + set * 6;
+                    self.attr_block(
+                        byte(base) & 0x07,
+                        byte(base + 1),
+                        [
+                            byte(base + 2),
+                            byte(base + 3),
+                            byte(base + 4),
+                            byte(base + 5),
+                        ]
+                        .map(|c| (c & 0x1F) as usize),
+                    );
+                }
+            }
+            SGB_ATTR_LIN => {
+                for set in 0..byte(1) as usize {
+                    let line = byte(2 + set);
+                    let index = (line & 0x1F) as usize;
+                    let palette = (line >> 5) & 0x03;
+                    for (cell, attribute) in self.attributes.iter_mut().enumerate() {
+                        let (x, y) = (cell % ATTR_WIDTH, cell / ATTR_WIDTH);
+                        if (line & 0x80 != 0 && y == index) || (line & 0x80 == 0 && x == index) {
+                            *attribute = palette;
+                        }
+                    }
+                }
+            }
+            SGB_ATTR_DIV => {
+                let division = byte(1);
+                let at = (byte(2) & 0x1F) as usize;
+                for (cell, attribute) in self.attributes.iter_mut().enumerate() {
+                    let position = if division & 0x40 != 0 {
+                        cell / ATTR_WIDTH
+                    } else {
+                        cell % ATTR_WIDTH
+                    };
+                    *attribute = match position.cmp(&at) {
+                        std::cmp::Ordering::Less => (division >> 2) & 0x03,
+                        std::cmp::Ordering::Equal => (division >> 4) & 0x03,
+                        std::cmp::Ordering::Greater => division & 0x03,
+                    };
+                }
+            }
+            SGB_ATTR_CHR => {
+                let (mut x, mut y) = ((byte(1) & 0x1F) as usize, (byte(2) & 0x1F) as usize);
+                let count = (word(3) as usize).min(ATTR_CELLS);
+                for i in 0..count {
+                    if x >= ATTR_WIDTH || y >= ATTR_HEIGHT {
+                        break;
+                    }
+                    self.attributes[y * ATTR_WIDTH + x] =
+                        (byte(6 + i / 4) >> (6 - 2 * (i % 4))) & 0x03;
+                    if byte(5) == 0 {
+                        x += 1;
+                        if x == ATTR_WIDTH {
+                            x = 0;
+                            y += 1;
+                        }
+                    } else {
+                        y += 1;
+                        if y == ATTR_HEIGHT {
+                            y = 0;
+                            x += 1;
+                        }
+                    }
+                }
+            }
+            SGB_PAL_SET => {
+                for i in 0..4 {
+                    self.palettes[i] = self.system_palettes[(word(1 + i * 2) & 0x1FF) as usize];
+                }
+                self.set_colour_0(self.palettes[0][0]);
+                let flags = byte(9);
+                if flags & 0x80 != 0 {
+                    self.apply_attribute_file((flags & 0x3F) as usize);
+                }
+                if flags & 0x40 != 0 {
+                    self.mask = Mask::None;
+                }
+            }
+            SGB_ATTR_SET => {
+                self.apply_attribute_file((byte(1) & 0x3F) as usize);
+                if byte(1) & 0x40 != 0 {
+                    self.mask = Mask::None;
+                }
+            }
+            SGB_MLT_REQ => {
+                self.player_count = match byte(1) & 0x03 {
+                    1 => 2,
+                    3 => 4,
+                    _ => 1,
+                };
+                self.player = 0;
+            }
+            SGB_MASK_EN => {
+                self.mask = match byte(1) & 0x03 {
+                    1 => Mask::Freeze,
+                    2 => Mask::Black,
+                    3 => Mask::Colour0,
+                    _ => Mask::None,
+                };
+            }
+            SGB_PAL_TRN => self.start_transfer(Transfer::Palettes),
+            SGB_CHR_TRN => self.start_transfer(Transfer::BorderTiles((byte(1) & 0x01) as usize)),
+            SGB_PCT_TRN => self.start_transfer(Transfer::BorderMap),
+            SGB_ATTR_TRN => self.start_transfer(Transfer::Attributes),
+            /* Sound, SNES code upload and the rest have nothing to drive. */
+            _ => {}
+        }
+    }
+
+    /// Colour 0 is shared by all four palettes.
+    fn set_colour_0(&mut self, colour: u16) {
+        for palette in self.palettes.iter_mut() {
+            palette[0] = colour;
+        }
+    }
+
+    /// `control` picks inside, border and outside, `area` is x1, y1, x2, y2.
+    fn attr_block(&mut self, control: u8, palettes: u8, area: [usize; 4]) {
+        let [x1, y1, x2, y2] = area;
+        let inside = palettes & 0x03;
+        let outside = (palettes >> 4) & 0x03;
+        /* A block with only its inside or outside set colours the border too. */
+        let (control, border) = match control {
+            0x01 => (0x03, inside),
+            0x04 => (0x06, outside),
+            _ => (control, (palettes >> 2) & 0x03),
+        };
+
+        for (cell, attribute) in self.attributes.iter_mut().enumerate() {
+            let (x, y) = (cell % ATTR_WIDTH, cell / ATTR_WIDTH);
+            let within = (x1..=x2).contains(&x) && (y1..=y2).contains(&y);
+            let on_border = within && (x == x1 || x == x2 || y == y1 || y == y2);
+            if within && !on_border && control & 0x01 != 0 {
+                *attribute = inside;
+            } else if on_border && control & 0x02 != 0 {
+                *attribute = border;
+            } else if !within && control & 0x04 != 0 {
+                *attribute = outside;
+            }
+        }
+    }
+
+    fn apply_attribute_file(&mut self, file: usize) {
+        if file >= ATF_COUNT {
+            return;
+        }
+        let atf = &self.attribute_files[file * ATF_SIZE..][..ATF_SIZE];
+        for (cell, attribute) in self.attributes.iter_mut().enumerate() {
+            *attribute = (atf[cell / 4] >> (6 - 2 * (cell % 4))) & 0x03;
+        }
+    }
+
+    fn start_transfer(&mut self, transfer: Transfer) {
+        self.transfer = Some(transfer);
+        self.transfer_frame_started = false;
+    }
+
+    fn finish_transfer(&mut self, transfer: Transfer) {
+        let data = &self.screen[..TRANSFER_SIZE];
+        match transfer {
+            Transfer::Palettes => {
+                for (palette, colours) in self.system_palettes.iter_mut().zip(data.chunks(8)) {
+                    for (colour, bytes) in palette.iter_mut().zip(colours.chunks(2)) {
+                        *colour = u16::from_le_bytes([bytes[0], bytes[1]]);
+                    }
+                }
+            }
+            Transfer::BorderTiles(bank) => {
+                self.border_tiles[bank * TRANSFER_SIZE..][..TRANSFER_SIZE].copy_from_slice(data);
+                self.border_changed = true;
+            }
+            Transfer::BorderMap => {
+                self.border_map.copy_from_slice(&data[..BORDER_MAP_SIZE]);
+                for (palette, colours) in self
+                    .border_palettes
+                    .iter_mut()
+                    .zip(data[BORDER_MAP_SIZE..].chunks(32))
+                {
+                    for (colour, bytes) in palette.iter_mut().zip(colours.chunks(2)) {
+                        *colour = u16::from_le_bytes([bytes[0], bytes[1]]);
+                    }
+                }
+                self.border_loaded = true;
+                self.border_changed = true;
+            }
+            Transfer::Attributes => {
+                self.attribute_files
+                    .copy_from_slice(&data[..ATF_COUNT * ATF_SIZE]);
+            }
+        }
+    }
+
+    /// Records the finished line for VRAM transfers and recolours it in place,
+    /// `false` if the screen is frozen and the line should not be shown.
+    pub fn draw_line(&mut self, line: u8, pixels: &mut [u8; LCD_WIDTH as usize]) -> bool {
+        let line = line as usize;
+        let row = (line / 8) * ATTR_WIDTH * SCREEN_TILE_SIZE + (line % 8) * 2;
+        for (tile, shades) in pixels.chunks(8).enumerate() {
+            let (mut low, mut high) = (0, 0);
+            for (x, shade) in shades.iter().enumerate() {
+                low |= (shade & 0x01) << (7 - x);
+                high |= ((shade >> 1) & 0x01) << (7 - x);
+            }
+            self.screen[row + tile * SCREEN_TILE_SIZE] = low;
+            self.screen[row + tile * SCREEN_TILE_SIZE + 1] = high;
+        }
+
+        if let Some(transfer) = self.transfer {
+            /* Wait for a whole frame drawn after the command. */
+            if line == 0 {
+                self.transfer_frame_started = true;
+            } else if line == LCD_HEIGHT as usize - 1 && self.transfer_frame_started {
+                self.transfer = None;
+                self.finish_transfer(transfer);
+            }
+        }
+
+        match self.mask {
+            Mask::Freeze => return false,
+            Mask::Black => pixels.fill(SGB_PIXEL_BLACK),
+            Mask::Colour0 => pixels.fill(0),
+            Mask::None => {
+                let attributes = &self.attributes[(line / 8) * ATTR_WIDTH..][..ATTR_WIDTH];
+                for (x, pixel) in pixels.iter_mut().enumerate() {
+                    *pixel = (attributes[x / 8] << 2) | (*pixel & 0x03);
+                }
+            }
+        }
+        true
+    }
+
+    /// RGB555 colours for the pixels `draw_line` produces.
+    pub fn get_palette(&self) -> [u16; 0x40] {
+        let mut palette = [0; 0x40];
+        for (i, colours) in self.palettes.iter().enumerate() {
+            for (j, &colour) in colours.iter().enumerate() {
+                palette[i * 4 + j] = to_rgb555(colour);
+            }
+        }
+        palette[SGB_PIXEL_BLACK as usize] = 0;
+        palette
+    }
+
+    /// Draws the border into a `SGB_WIDTH` by `SGB_HEIGHT` RGB555 frame, with
+    /// colour 0 where the game screen and transparent tiles are.
+    /// `false` if the game has not sent a border.
+    pub fn render_border(&self, frame: &mut [u16]) -> bool {
+        if !self.border_loaded || frame.len() < SGB_WIDTH * SGB_HEIGHT {
+            return false;
+        }
+        let backdrop = to_rgb555(self.palettes[0][0]);
+        for ty in 0..SGB_HEIGHT / 8 {
+            for tx in 0..SGB_WIDTH / 8 {
+                let entry = (ty * BORDER_MAP_WIDTH + tx) * 2;
+                let tile = &self.border_tiles[self.border_map[entry] as usize * BORDER_TILE_SIZE..]
+                    [..BORDER_TILE_SIZE];
+                let attributes = self.border_map[entry + 1];
+                let palette = &self.border_palettes
+                    [((attributes >> 2) & 0x07) as usize % BORDER_PALETTE_FIRST];
+
+                for y in 0..8 {
+                    let row = if attributes & 0x80 != 0 { 7 - y } else { y };
+                    for x in 0..8 {
+                        let bit = if attributes & 0x40 != 0 { x } else { 7 - x };
+                        let index = [
+                            tile[row * 2],
+                            tile[row * 2 + 1],
+                            tile[16 + row * 2],
+                            tile[17 + row * 2],
+                        ]
+                        .iter()
+                        .enumerate()
+                        .fold(0, |index, (plane, byte)| {
+                            index | (((byte >> bit) & 1) << plane)
+                        });
+                        frame[(ty * 8 + y) * SGB_WIDTH + tx * 8 + x] = if index == 0 {
+                            backdrop
+                        } else {
+                            to_rgb555(palette[index as usize])
+                        };
+                    }
+                }
+            }
+        }
+        true
+    }
+
+    /// Whether a new border arrived since the last call.
+    pub fn take_border_changed(&mut self) -> bool {
+        std::mem::take(&mut self.border_changed)
+    }
+
+    pub fn get_player_count(&self) -> usize {
+        self.player_count
+    }
+    pub fn get_player(&self) -> usize {
+        self.player
+    }
+
+    /// Players 2 to 4, player 1 is the `Gb` joypad. Same layout as `set_joypad`.
+    pub fn set_joypad(&mut self, player: usize, joypad: u8) {
+        if let Some(state) = self.joypads.get_mut(player) {
+            *state = joypad;
+        }
+    }
+    pub fn get_joypad(&self, player: usize) -> u8 {
+        self.joypads[player]
+    }
+}
+
+impl Default for Sgb {
+    fn default() -> Sgb {
+        Sgb::new()
+    }
+}
@@ -0,0 +1,129 @@
+use std::cell::RefCell;
+
+use super::{Gb, Registers, IO_IE, IO_IF};
+
+/// The whole address space, for flat memory.
+pub const FLAT_MEMORY_SIZE: usize = 0x10000;
+
+const M_CYCLE: u64 = 4;
+
+/// The registers and IME of a SingleStepTests vector, F in the layout
+/// `push af` gives.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct CpuState {
+    pub a: u8,
+    pub f: u8,
+    pub b: u8,
+    pub c: u8,
+    pub d: u8,
+    pub e: u8,
+    pub h: u8,
+    pub l: u8,
+    pub sp: u16,
+    pub pc: u16,
+    pub ime: bool,
+}
+
+/// A read or write the CPU made on flat memory, the way SingleStepTests
+/// vectors list their M-cycles.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct BusAccess {
+    pub addr: u16,
+    pub val: u8,
+    pub write: bool,
+}
+
+/// Flat memory and the CPU's accesses to it since they were last taken.
+pub(super) struct FlatMemory {
+    pub(super) memory: Box<[u8]>,
+    accesses: RefCell<Vec<BusAccess>>,
+}
+impl FlatMemory {
+    fn new(memory: Box<[u8]>) -> FlatMemory {
+        assert_eq!(memory.len(), FLAT_MEMORY_SIZE);
+        FlatMemory {
+            memory,
+            accesses: RefCell::new(Vec::new()),
+        }
+    }
+}
+
+impl<'a, T> Gb<'a, T> {
+    /// Puts 64KB of plain memory in place of everything `_read` and `_write`
+    /// decode, the MBC and IO included. `None` goes back to the cartridge.
+    pub fn gb_set_flat_memory(&mut self, memory: Option<Box<[u8]>>) -> () {
+        self.flat_memory = memory.map(|memory| Box::new(FlatMemory::new(memory)));
+    }
+
+    pub fn get_flat_memory(&self) -> Option<&[u8]> {
+        self.flat_memory.as_ref().map(|flat| &*flat.memory)
+    }
+
+    /// The CPU's reads and writes on flat memory since the last call, in
+    /// order. Peeks and pokes aren't included.
+    pub fn take_bus_accesses(&mut self) -> Vec<BusAccess> {
+        self.flat_memory
+            .as_ref()
+            .map_or_else(Vec::new, |flat| flat.accesses.take())
+    }
+
+    pub(super) fn _log_bus(&self, addr: usize, val: u8, write: bool) -> () {
+        if let Some(flat) = &self.flat_memory {
+            flat.accesses.borrow_mut().push(BusAccess {
+                addr: addr as u16,
+                val,
+                write,
+            });
+        }
+    }
+
+    /// Runs the single instruction at `initial.pc` on flat memory holding
+    /// `ram`, zero elsewhere. Returns the state after it and the M-cycles it
+    /// took, `take_bus_accesses` has what it read and wrote. HALT waits for
+    /// the timer or LCD to raise an interrupt.
+    pub fn gb_run_cpu_test(&mut self, initial: &CpuState, ram: &[(u16, u8)]) -> (CpuState, u64) {
+        let mut memory = vec![0; FLAT_MEMORY_SIZE].into_boxed_slice();
+        for (addr, val) in ram {
+            memory[*addr as usize] = *val;
+        }
+        self.flat_memory = Some(Box::new(FlatMemory::new(memory)));
+
+        self.gb_set_registers(&Registers {
+            a: initial.a,
+            f: initial.f,
+            b: initial.b,
+            c: initial.c,
+            d: initial.d,
+            e: initial.e,
+            h: initial.h,
+            l: initial.l,
+            sp: initial.sp,
+            pc: initial.pc,
+        });
+        self.gb_ime = initial.ime;
+        self.gb_halt = false;
+        /* The vector's IE and IF are in flat memory, these only end HALT. */
+        self.hram_io[IO_IF] = 0;
+        self.hram_io[IO_IE] = 0xFF;
+
+        let clock = self.counter.clock;
+        self._step_cpu();
+        let cycles = (self.counter.clock - clock) / M_CYCLE;
+
+        let reg = self.get_registers();
+        let state = CpuState {
+            a: reg.a,
+            f: reg.f,
+            b: reg.b,
+            c: reg.c,
+            d: reg.d,
+            e: reg.e,
+            h: reg.h,
+            l: reg.l,
+            sp: reg.sp,
+            pc: reg.pc,
+            ime: self.gb_ime,
+        };
+        (state, cycles)
+    }
+}
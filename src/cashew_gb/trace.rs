@@ -0,0 +1,111 @@
+use std::io::Write;
+use std::ops::{Range, RangeInclusive};
+
+use super::{Gb, LOG_CYCLE, LOG_EVERY, MAX_CYCLE};
+
+/// Bytes from PC on each line.
+const PCMEM_SIZE: u16 = 4;
+
+/// Writes a line per instruction, before it runs, in the format Gameboy
+/// Doctor compares:
+///
+/// `A:01 F:B0 B:00 C:13 D:00 E:D8 H:01 L:4D SP:FFFE PC:0100 PCMEM:00,C3,13,02`
+///
+/// Instructions are counted as `Gb::cycle` counts them. Tracing stops if the
+/// writer fails.
+pub struct Tracer {
+    writer: Box<dyn Write>,
+    cycles: Range<u32>,
+    every: u32,
+    pcs: Option<RangeInclusive<u16>>,
+}
+impl Tracer {
+    /// Every instruction.
+    pub fn new(writer: Box<dyn Write>) -> Tracer {
+        Tracer {
+            writer,
+            cycles: 0..u32::MAX,
+            every: 1,
+            pcs: None,
+        }
+    }
+
+    /// Every `LOG_EVERY`th instruction from `LOG_CYCLE`, `LOG_SIZE` lines.
+    pub fn sampled(writer: Box<dyn Write>) -> Tracer {
+        Tracer::new(writer)
+            .cycles(LOG_CYCLE..MAX_CYCLE)
+            .every(LOG_EVERY)
+    }
+
+    /// Only the instructions counted in `cycles`.
+    pub fn cycles(mut self, cycles: Range<u32>) -> Tracer {
+        self.cycles = cycles;
+        self
+    }
+
+    /// One in `every` of them, from the start of the window.
+    pub fn every(mut self, every: u32) -> Tracer {
+        self.every = every.max(1);
+        self
+    }
+
+    /// Only instructions at these addresses.
+    pub fn pc_range(mut self, pcs: RangeInclusive<u16>) -> Tracer {
+        self.pcs = Some(pcs);
+        self
+    }
+
+    pub fn into_writer(self) -> Box<dyn Write> {
+        self.writer
+    }
+}
+
+impl<'a, T> Gb<'a, T> {
+    /// Returns the tracer it replaces, `None` turns tracing off.
+    pub fn gb_set_tracer(&mut self, tracer: Option<Tracer>) -> Option<Tracer> {
+        let previous = std::mem::replace(&mut self.tracer, tracer.map(Box::new));
+        previous.map(|tracer| *tracer)
+    }
+
+    /// Called by `_step_cpu` before each opcode fetch.
+    pub(super) fn _trace(&mut self) -> () {
+        let Some(tracer) = &self.tracer else {
+            return;
+        };
+        let cycle = self.cycle;
+        let pc = self.cpu_reg.pc.bytes;
+        let traced = tracer.cycles.contains(&cycle)
+            && (cycle - tracer.cycles.start) % tracer.every == 0
+            && tracer.pcs.as_ref().map_or(true, |pcs| pcs.contains(&pc));
+        if !traced {
+            return;
+        }
+
+        let reg = &self.cpu_reg;
+        let pcmem = (0..PCMEM_SIZE)
+            .map(|i| format!("{:02X}", self._read_memory(pc.wrapping_add(i) as usize)))
+            .collect::<Vec<String>>()
+            .join(",");
+        let line = format!(
+            "A:{:02X} F:{:02X} B:{:02X} C:{:02X} D:{:02X} E:{:02X} H:{:02X} L:{:02X} SP:{:04X} PC:{:04X} PCMEM:{}",
+            reg.a,
+            reg.f.byte << 4,
+            reg.bc.get_hi(),
+            reg.bc.get_lo(),
+            reg.de.get_hi(),
+            reg.de.get_lo(),
+            reg.hl.get_hi(),
+            reg.hl.get_lo(),
+            reg.sp.bytes,
+            pc,
+            pcmem
+        );
+        let written = self
+            .tracer
+            .as_mut()
+            .is_some_and(|tracer| writeln!(tracer.writer, "{line}").is_ok());
+        if !written {
+            self.tracer = None;
+        }
+    }
+}
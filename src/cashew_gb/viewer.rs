@@ -0,0 +1,370 @@
+use std::fmt;
+
+use super::palette;
+use super::{
+    Gb, IO_LCDC, IO_SCX, IO_SCY, LCDC_OBJ_SIZE, LCDC_TILE_SELECT, NUM_SPRITES, OBJ_FLIP_X,
+    OBJ_FLIP_Y, OBJ_PALETTE, OBJ_PRIORITY, VRAM_BANK_SIZE, VRAM_BMAP_1, VRAM_BMAP_2, VRAM_TILES_1,
+    VRAM_TILES_2,
+};
+#[cfg(feature = "12-colour")]
+use super::{LCD_PALETTE_BG, LCD_PALETTE_OBJ};
+#[cfg(feature = "gbc")]
+use super::{OBJ_BANK, OBJ_CGB_PALETTE};
+
+const TILE_SIZE: usize = 8;
+const TILE_BYTES: usize = 16;
+const TILES_PER_BANK: usize = 384;
+const SHEET_COLUMNS: usize = 16;
+const MAP_TILES: usize = 32;
+const MAP_SIZE: usize = MAP_TILES * TILE_SIZE;
+const LCD_WIDTH: usize = 160;
+const LCD_HEIGHT: usize = 144;
+/* OAM Y and X are offset so sprites can sit partly off screen. */
+const OAM_Y_OFFSET: u8 = 16;
+const OAM_X_OFFSET: u8 = 8;
+
+/* The OAM table is a grid of cells, each with a sprite in the middle. */
+const OAM_COLUMNS: usize = 8;
+const OAM_CELL_WIDTH: usize = 16;
+const OAM_CELL_HEIGHT: usize = 24;
+
+/* Palette swatches, a row per palette with BG then OBJ colours. */
+const SWATCH_SIZE: usize = 8;
+const SWATCH_GAP: usize = 8;
+const PALETTES: usize = 8;
+const PALETTE_COLOURS: usize = 4;
+
+const COLOUR_VIEWPORT: u16 = palette::to_rgb555(palette::rgb(0xFF, 0x20, 0x20));
+const COLOUR_EMPTY: u16 = palette::to_rgb555(palette::rgb(0x40, 0x40, 0x48));
+const COLOUR_TRANSPARENT: u16 = palette::to_rgb555(palette::rgb(0xA0, 0x80, 0xA0));
+
+/// Pixels in the LCD's RGB555, as `Gb::get_palette` returns them.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Image {
+    pub width: usize,
+    pub height: usize,
+    pub pixels: Vec<u16>,
+}
+impl Image {
+    fn new(width: usize, height: usize, colour: u16) -> Image {
+        Image {
+            width,
+            height,
+            pixels: vec![colour; width * height],
+        }
+    }
+
+    pub fn get(&self, x: usize, y: usize) -> u16 {
+        self.pixels[y * self.width + x]
+    }
+
+    fn set(&mut self, x: usize, y: usize, colour: u16) {
+        self.pixels[y * self.width + x] = colour;
+    }
+
+    /// 8 bits per channel, red first.
+    pub fn to_rgb888(&self) -> Vec<u8> {
+        self.pixels
+            .iter()
+            .flat_map(|colour| {
+                let channel = |shift: u16| {
+                    let value = ((colour >> shift) & 0x1F) as u8;
+                    (value << 3) | (value >> 2)
+                };
+                [channel(10), channel(5), channel(0)]
+            })
+            .collect()
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TileMap {
+    Map9800,
+    Map9C00,
+}
+
+/// An OAM entry, the position as the sprite appears on the LCD.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Sprite {
+    pub index: u8,
+    pub y: i16,
+    pub x: i16,
+    pub tile: u8,
+    /// OBP0 or OBP1 on DMG, 0-7 on CGB.
+    pub palette: u8,
+    pub bank: u8,
+    pub flip_x: bool,
+    pub flip_y: bool,
+    /// Behind BG colours 1-3.
+    pub priority: bool,
+}
+impl fmt::Display for Sprite {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{:02} {:4},{:4} T{:02X} P{} B{} {}{}{}",
+            self.index,
+            self.x,
+            self.y,
+            self.tile,
+            self.palette,
+            self.bank,
+            if self.flip_x { 'X' } else { '-' },
+            if self.flip_y { 'Y' } else { '-' },
+            if self.priority { 'P' } else { '-' },
+        )
+    }
+}
+
+impl<'a, T> Gb<'a, T> {
+    /// All 384 tiles of a VRAM bank, 16 to a row, in BG palette 0. `None`
+    /// for bank 1 outside CGB builds.
+    pub fn get_tile_sheet(&self, bank: usize) -> Option<Image> {
+        let vram = self.get_vram_bank(bank)?;
+        let colours = self._bg_colours(0);
+        let rows = TILES_PER_BANK / SHEET_COLUMNS;
+        let mut image = Image::new(SHEET_COLUMNS * TILE_SIZE, rows * TILE_SIZE, 0);
+        for tile in 0..TILES_PER_BANK {
+            let x = (tile % SHEET_COLUMNS) * TILE_SIZE;
+            let y = (tile / SHEET_COLUMNS) * TILE_SIZE;
+            draw_tile(
+                &mut image,
+                x,
+                y,
+                &vram[tile * TILE_BYTES..],
+                false,
+                false,
+                |c| colours[c as usize],
+            );
+        }
+        Some(image)
+    }
+
+    /// The whole 256x256 map with the SCX/SCY viewport outlined, tiles
+    /// addressed as LCDC has them now.
+    pub fn get_tile_map(&self, map: TileMap) -> Image {
+        let map_offset = match map {
+            TileMap::Map9800 => VRAM_BMAP_1,
+            TileMap::Map9C00 => VRAM_BMAP_2,
+        } as usize;
+        let unsigned = self.hram_io[IO_LCDC] & LCDC_TILE_SELECT != 0;
+        let mut image = Image::new(MAP_SIZE, MAP_SIZE, 0);
+
+        for entry in 0..MAP_TILES * MAP_TILES {
+            let index = self.vram[map_offset + entry];
+            #[allow(unused_mut)]
+            let (mut attributes, mut palette, mut bank) = (0, 0, 0);
+            #[cfg(feature = "gbc")]
+            if self.cgb.mode != 0 {
+                attributes = self.vram[VRAM_BANK_SIZE + map_offset + entry];
+                palette = attributes & OBJ_CGB_PALETTE;
+                bank = ((attributes >> OBJ_BANK) & 1) as usize;
+            }
+            let tile = if unsigned {
+                VRAM_TILES_1 as usize + index as usize * TILE_BYTES
+            } else {
+                VRAM_TILES_2 as usize + ((index as usize + 0x80) % 0x100) * TILE_BYTES
+            };
+            let colours = self._bg_colours(palette);
+            let x = (entry % MAP_TILES) * TILE_SIZE;
+            let y = (entry / MAP_TILES) * TILE_SIZE;
+            draw_tile(
+                &mut image,
+                x,
+                y,
+                &self.vram[bank * VRAM_BANK_SIZE + tile..],
+                attributes & OBJ_FLIP_X != 0,
+                attributes & OBJ_FLIP_Y != 0,
+                |c| colours[c as usize],
+            );
+        }
+
+        /* The viewport wraps around the map's edges. */
+        let scx = self.hram_io[IO_SCX] as usize;
+        let scy = self.hram_io[IO_SCY] as usize;
+        for x in 0..LCD_WIDTH {
+            image.set((scx + x) % MAP_SIZE, scy, COLOUR_VIEWPORT);
+            image.set(
+                (scx + x) % MAP_SIZE,
+                (scy + LCD_HEIGHT - 1) % MAP_SIZE,
+                COLOUR_VIEWPORT,
+            );
+        }
+        for y in 0..LCD_HEIGHT {
+            image.set(scx, (scy + y) % MAP_SIZE, COLOUR_VIEWPORT);
+            image.set(
+                (scx + LCD_WIDTH - 1) % MAP_SIZE,
+                (scy + y) % MAP_SIZE,
+                COLOUR_VIEWPORT,
+            );
+        }
+        image
+    }
+
+    /// The 40 OAM entries.
+    pub fn get_sprites(&self) -> Vec<Sprite> {
+        (0..NUM_SPRITES)
+            .map(|index| {
+                let entry = &self.oam[index as usize * 4..][..4];
+                let flags = entry[3];
+                #[allow(unused_mut)]
+                let (mut palette, mut bank) = ((flags & OBJ_PALETTE != 0) as u8, 0);
+                #[cfg(feature = "gbc")]
+                if self.cgb.mode != 0 {
+                    palette = flags & OBJ_CGB_PALETTE;
+                    bank = (flags >> OBJ_BANK) & 1;
+                }
+                Sprite {
+                    index,
+                    y: entry[0] as i16 - OAM_Y_OFFSET as i16,
+                    x: entry[1] as i16 - OAM_X_OFFSET as i16,
+                    tile: entry[2],
+                    palette,
+                    bank,
+                    flip_x: flags & OBJ_FLIP_X != 0,
+                    flip_y: flags & OBJ_FLIP_Y != 0,
+                    priority: flags & OBJ_PRIORITY != 0,
+                }
+            })
+            .collect()
+    }
+
+    /// Every sprite in its own cell, 8 to a row in OAM order, 8x16 when
+    /// LCDC has tall sprites. Transparent pixels are drawn pink.
+    pub fn get_oam_table(&self) -> Image {
+        let rows = NUM_SPRITES as usize / OAM_COLUMNS;
+        let mut image = Image::new(
+            OAM_COLUMNS * OAM_CELL_WIDTH,
+            rows * OAM_CELL_HEIGHT,
+            COLOUR_EMPTY,
+        );
+        let tall = self.hram_io[IO_LCDC] & LCDC_OBJ_SIZE != 0;
+        for sprite in self.get_sprites() {
+            let colours = self._obj_colours(sprite.palette);
+            let x = (sprite.index as usize % OAM_COLUMNS) * OAM_CELL_WIDTH + TILE_SIZE / 2;
+            let y = (sprite.index as usize / OAM_COLUMNS) * OAM_CELL_HEIGHT + TILE_SIZE / 2;
+            let tiles: &[u8] = if tall {
+                /* Flipping a tall sprite swaps its halves. */
+                let top = sprite.tile & 0xFE;
+                if sprite.flip_y {
+                    &[top + 1, top]
+                } else {
+                    &[top, top + 1]
+                }
+            } else {
+                &[sprite.tile]
+            };
+            for (half, tile) in tiles.iter().enumerate() {
+                let data = sprite.bank as usize * VRAM_BANK_SIZE + *tile as usize * TILE_BYTES;
+                draw_tile(
+                    &mut image,
+                    x,
+                    y + half * TILE_SIZE,
+                    &self.vram[data..],
+                    sprite.flip_x,
+                    sprite.flip_y,
+                    |c| {
+                        if c == 0 {
+                            COLOUR_TRANSPARENT
+                        } else {
+                            colours[c as usize]
+                        }
+                    },
+                );
+            }
+        }
+        image
+    }
+
+    /// A row per palette, the four BG colours then the four OBJ colours. On
+    /// DMG the rows are BGP, OBP0 and OBP1 after the game's shade mapping.
+    pub fn get_palette_swatches(&self) -> Image {
+        let width = PALETTE_COLOURS * 2 * SWATCH_SIZE + SWATCH_GAP;
+        let mut image = Image::new(width, PALETTES * SWATCH_SIZE, COLOUR_EMPTY);
+        #[cfg(feature = "gbc")]
+        let cgb = self.cgb.mode != 0;
+        #[cfg(not(feature = "gbc"))]
+        let cgb = false;
+
+        for palette in 0..PALETTES as u8 {
+            let mut swatches = Vec::new();
+            if cgb || palette == 0 {
+                swatches.extend(self._bg_colours(palette).into_iter().enumerate());
+            }
+            if cgb || palette < 2 {
+                let obj = self._obj_colours(palette).into_iter().enumerate();
+                swatches.extend(obj.map(|(i, colour)| (PALETTE_COLOURS + i, colour)));
+            }
+            for (column, colour) in swatches {
+                let x = column * SWATCH_SIZE + (column / PALETTE_COLOURS) * SWATCH_GAP;
+                for y in 0..SWATCH_SIZE {
+                    for dx in 0..SWATCH_SIZE {
+                        image.set(x + dx, palette as usize * SWATCH_SIZE + y, colour);
+                    }
+                }
+            }
+        }
+        image
+    }
+
+    /// DMG has the one BG palette, `palette` only picks on CGB.
+    #[cfg_attr(not(feature = "gbc"), allow(unused_variables))]
+    fn _bg_colours(&self, palette: u8) -> [u16; PALETTE_COLOURS] {
+        let colours = self.get_palette();
+        #[cfg(feature = "gbc")]
+        if self.cgb.mode != 0 {
+            let start = palette as usize * PALETTE_COLOURS;
+            return colours[start..start + PALETTE_COLOURS].try_into().unwrap();
+        }
+        #[cfg(feature = "12-colour")]
+        let offset = LCD_PALETTE_BG;
+        #[cfg(not(feature = "12-colour"))]
+        let offset = 0;
+        self.display
+            .bg_palette
+            .map(|shade| colours[(shade | offset) as usize])
+    }
+
+    fn _obj_colours(&self, palette: u8) -> [u16; PALETTE_COLOURS] {
+        let colours = self.get_palette();
+        #[cfg(feature = "gbc")]
+        if self.cgb.mode != 0 {
+            let start = 0x20 + palette as usize * PALETTE_COLOURS;
+            return colours[start..start + PALETTE_COLOURS].try_into().unwrap();
+        }
+        #[cfg(feature = "12-colour")]
+        let offset = palette * LCD_PALETTE_OBJ;
+        #[cfg(not(feature = "12-colour"))]
+        let offset = 0;
+        let start = palette as usize * PALETTE_COLOURS;
+        let shades: [u8; PALETTE_COLOURS] = self.display.sp_palette[start..start + PALETTE_COLOURS]
+            .try_into()
+            .unwrap();
+        shades.map(|shade| colours[(shade | offset) as usize])
+    }
+}
+
+/// Draws the 2bpp tile at the start of `data` with its top left at `x`, `y`.
+fn draw_tile(
+    image: &mut Image,
+    x: usize,
+    y: usize,
+    data: &[u8],
+    flip_x: bool,
+    flip_y: bool,
+    colour: impl Fn(u8) -> u16,
+) {
+    for row in 0..TILE_SIZE {
+        let line = if flip_y { TILE_SIZE - 1 - row } else { row };
+        let (lo, hi) = (data[line * 2], data[line * 2 + 1]);
+        for column in 0..TILE_SIZE {
+            let bit = if flip_x {
+                column
+            } else {
+                TILE_SIZE - 1 - column
+            };
+            let c = ((lo >> bit) & 1) | (((hi >> bit) & 1) << 1);
+            image.set(x + column, y + row, colour(c));
+        }
+    }
+}
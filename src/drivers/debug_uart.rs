@@ -0,0 +1,59 @@
+use std::io::{self, Read, Write};
+
+use svc::hal::delay::{TickType, BLOCK};
+use svc::hal::gpio::{AnyIOPin, InputPin, OutputPin};
+use svc::hal::peripheral::Peripheral;
+use svc::hal::uart::{config::Config, Uart, UartDriver, UartRxDriver, UartTxDriver};
+use svc::hal::units::Hertz;
+use svc::sys::EspError;
+
+pub const DEBUG_UART_BAUD_RATE: u32 = 115_200;
+const READ_POLL_MS: u64 = 10;
+
+/// The receiving half of the UART the debug adapter is served on, apart
+/// from the console UART so log lines can't garble the protocol.
+pub struct DebugUartRx(UartRxDriver<'static>);
+
+pub struct DebugUartTx(UartTxDriver<'static>);
+
+pub fn debug_uart(
+    uart: impl Peripheral<P = impl Uart> + 'static,
+    tx: impl Peripheral<P = impl OutputPin> + 'static,
+    rx: impl Peripheral<P = impl InputPin> + 'static,
+) -> Result<(DebugUartRx, DebugUartTx), EspError> {
+    let config = Config::new().baudrate(Hertz(DEBUG_UART_BAUD_RATE));
+    let driver = UartDriver::new(
+        uart,
+        tx,
+        rx,
+        Option::<AnyIOPin>::None,
+        Option::<AnyIOPin>::None,
+        &config,
+    )?;
+    let (tx, rx) = driver.into_split();
+    Ok((DebugUartRx(rx), DebugUartTx(tx)))
+}
+
+impl Read for DebugUartRx {
+    /// Waits for at least one byte, a read of the whole buffer would wait
+    /// for it to fill.
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            let timeout = TickType::new_millis(READ_POLL_MS).ticks();
+            let count = self.0.read(buf, timeout).map_err(io::Error::other)?;
+            if count > 0 || buf.is_empty() {
+                return Ok(count);
+            }
+        }
+    }
+}
+
+impl Write for DebugUartTx {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.write(buf).map_err(io::Error::other)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.0.wait_done(BLOCK).map_err(io::Error::other)
+    }
+}
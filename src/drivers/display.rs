@@ -11,6 +11,7 @@ use svc::hal::{
     units::MegaHertz,
 };
 
+use crate::cashew_gb::palette::{self, DmgPalette};
 use crate::cashew_gb::{LCD_HEIGHT, LCD_PALETTE_ALL, LCD_WIDTH};
 
 pub struct DisplayPins<CS, DC, RST>
@@ -48,7 +49,7 @@ where
         PinDriver<'p, RST, Output>,
     >,
     buffer: Vec<Rgb565>,
-    palette: [[u16; 4]; 3],
+    palette: [[Rgb565; 4]; 3],
     area: Rectangle,
 }
 impl<'p, DC, RST> Display<'p, DC, RST>
@@ -89,11 +90,7 @@ where
         Display {
             driver,
             buffer: vec![Rgb565::WHITE; LCD_WIDTH as usize * LCD_HEIGHT as usize],
-            palette: [
-                [0x7FFF, 0x03E0, 0x1A00, 0x0120], /* OBJ0 */
-                [0x7FFF, 0x329F, 0x001F, 0x001F], /* OBJ1 */
-                [0x7FFF, 0x7E10, 0x48E7, 0x0000], /* BG */
-            ],
+            palette: lcd_colours(&palette::DMG_GREEN),
             area: Rectangle::new(Point::new(0, 0), Size::new(LCD_WIDTH as u32, 128)),
         }
     }
@@ -106,11 +103,14 @@ where
 {
     pub fn buffer_line_gb(&mut self, pixels: [u8; 160], line: u8) -> () {
         for x in 0..LCD_WIDTH as usize {
-            self.buffer[x + (LCD_WIDTH as usize * line as usize)] = Rgb565::from(RawU16::new(
-                self.palette[((pixels[x] & LCD_PALETTE_ALL) as usize) >> 4][pixels[x] as usize & 3],
-            ));
+            self.buffer[x + (LCD_WIDTH as usize * line as usize)] =
+                self.palette[((pixels[x] & LCD_PALETTE_ALL) as usize) >> 4][pixels[x] as usize & 3];
         }
     }
+    /// The colours `buffer_line_gb` draws DMG lines with, from the next line.
+    pub fn set_dmg_palette(&mut self, palette: &DmgPalette) -> () {
+        self.palette = lcd_colours(palette);
+    }
     pub fn buffer_line_gbc(&mut self, pixels: [u8; 160], line: u8, palette: [u16; 0x40]) -> () {
         for x in 0..LCD_WIDTH as usize {
             self.buffer[x + (LCD_WIDTH as usize * line as usize)] =
@@ -123,3 +123,10 @@ where
             .unwrap();
     }
 }
+
+/// OBJ0, OBJ1 and BG, in the order of the pixels' palette bits.
+fn lcd_colours(palette: &DmgPalette) -> [[Rgb565; 4]; 3] {
+    [palette.obj0, palette.obj1, palette.bg].map(|colours| {
+        colours.map(|colour| Rgb565::from(Rgb555::from(RawU16::new(palette::to_rgb555(colour)))))
+    })
+}
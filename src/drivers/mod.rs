@@ -1,7 +1,15 @@
 mod audio;
+#[cfg(feature = "debug")]
+mod debug_uart;
 mod display;
+mod rom_partition;
 mod snes_controller;
+mod storage;
 
+#[cfg(feature = "debug")]
+pub use debug_uart::debug_uart;
 pub use display::Display;
 pub use display::DisplayPins;
-pub use snes_controller::SNESController;
+pub use rom_partition::RomPartition;
+pub use snes_controller::{SNESController, SNES_L, SNES_R};
+pub use storage::Storage;
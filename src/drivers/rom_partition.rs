@@ -0,0 +1,75 @@
+use std::{
+    ffi::{c_void, CString},
+    ptr, slice,
+};
+
+use svc::sys::{self, esp, EspError};
+
+const ROM_SIZE_LOCATION: usize = 0x0148;
+const ROM_MIN_SIZE: usize = 0x8000;
+const ROM_MAX_SIZE_SHIFT: u8 = 8;
+
+pub struct RomPartition {
+    data: &'static [u8],
+    handle: sys::esp_partition_mmap_handle_t,
+}
+
+impl RomPartition {
+    /// Maps the data partition called `label` into the address space, so
+    /// ROM banks are read straight from flash. The ROM length is taken from
+    /// the cartridge header, the rest of the partition is ignored.
+    pub fn new(label: &str) -> Result<RomPartition, EspError> {
+        let label = CString::new(label).unwrap();
+        let partition = unsafe {
+            sys::esp_partition_find_first(
+                sys::esp_partition_type_t_ESP_PARTITION_TYPE_DATA,
+                sys::esp_partition_subtype_t_ESP_PARTITION_SUBTYPE_ANY,
+                label.as_ptr(),
+            )
+        };
+        if partition.is_null() {
+            return Err(EspError::from_infallible::<{ sys::ESP_ERR_NOT_FOUND as i32 }>());
+        }
+
+        let partition_size = unsafe { (*partition).size } as usize;
+        let mut mapped: *const c_void = ptr::null();
+        let mut handle: sys::esp_partition_mmap_handle_t = 0;
+        esp!(unsafe {
+            sys::esp_partition_mmap(
+                partition,
+                0,
+                partition_size,
+                sys::esp_partition_mmap_memory_t_ESP_PARTITION_MMAP_DATA,
+                &mut mapped,
+                &mut handle,
+            )
+        })?;
+        let mut rom_partition = RomPartition {
+            data: unsafe { slice::from_raw_parts(mapped as *const u8, partition_size) },
+            handle,
+        };
+
+        if partition_size < ROM_MIN_SIZE
+            || rom_partition.data[ROM_SIZE_LOCATION] > ROM_MAX_SIZE_SHIFT
+            || ROM_MIN_SIZE << rom_partition.data[ROM_SIZE_LOCATION] > partition_size
+        {
+            return Err(EspError::from_infallible::<
+                { sys::ESP_ERR_INVALID_SIZE as i32 },
+            >());
+        }
+        rom_partition.data =
+            &rom_partition.data[..ROM_MIN_SIZE << rom_partition.data[ROM_SIZE_LOCATION]];
+
+        Ok(rom_partition)
+    }
+
+    pub fn data(&self) -> &[u8] {
+        self.data
+    }
+}
+
+impl Drop for RomPartition {
+    fn drop(&mut self) {
+        unsafe { sys::esp_partition_munmap(self.handle) }
+    }
+}
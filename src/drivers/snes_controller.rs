@@ -9,6 +9,9 @@ use svc::hal::{
     gpio::{Input, InputPin, Output, OutputPin, PinDriver},
 };
 
+pub const SNES_L: u16 = 0x20;
+pub const SNES_R: u16 = 0x10;
+
 pub struct SNESController<'a, CLK, LATCH, DATA>
 where
     CLK: OutputPin,
@@ -18,6 +21,7 @@ where
     clock: PinDriver<'a, CLK, Output>,
     latch: PinDriver<'a, LATCH, Output>,
     data: PinDriver<'a, DATA, Input>,
+    last_state: u16,
 }
 
 impl<'a, CLK, LATCH, DATA> SNESController<'a, CLK, LATCH, DATA>
@@ -31,6 +35,7 @@ where
             clock: PinDriver::output(clock).unwrap(),
             latch: PinDriver::output(latch).unwrap(),
             data: PinDriver::input(data).unwrap(),
+            last_state: 0,
         }
     }
     pub fn read(&mut self) -> u16 {
@@ -47,8 +52,13 @@ where
             self.clock.set_low().unwrap();
             Ets::delay_us(6);
         }
+        self.last_state = state;
         state
     }
+    /// Buttons from the last read that have no Game Boy equivalent.
+    pub fn get_extra_buttons(&self) -> u16 {
+        self.last_state & (SNES_L | SNES_R)
+    }
     pub fn read_gb(&mut self) -> u8 {
         let input = self.read();
         let mut state = 0;
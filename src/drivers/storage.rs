@@ -0,0 +1,46 @@
+use std::ffi::CString;
+
+use svc::sys::{self, esp, EspError};
+
+const MAX_OPEN_FILES: i32 = 4;
+
+pub struct Storage {
+    base_path: CString,
+    label: CString,
+}
+
+impl Storage {
+    /// Mounts the FAT image written by tools/pack-storage at `base_path`.
+    /// The image has no wear levelling layer, so it is mounted read-only.
+    pub fn new(label: &str, base_path: &str) -> Result<Storage, EspError> {
+        let storage = Storage {
+            base_path: CString::new(base_path).unwrap(),
+            label: CString::new(label).unwrap(),
+        };
+        let mount_config = sys::esp_vfs_fat_mount_config_t {
+            format_if_mount_failed: false,
+            max_files: MAX_OPEN_FILES,
+            ..Default::default()
+        };
+        esp!(unsafe {
+            sys::esp_vfs_fat_spiflash_mount_ro(
+                storage.base_path.as_ptr(),
+                storage.label.as_ptr(),
+                &mount_config,
+            )
+        })?;
+        Ok(storage)
+    }
+
+    pub fn path(&self, file: &str) -> String {
+        format!("{}/{}", self.base_path.to_str().unwrap(), file)
+    }
+}
+
+impl Drop for Storage {
+    fn drop(&mut self) {
+        unsafe {
+            sys::esp_vfs_fat_spiflash_unmount_ro(self.base_path.as_ptr(), self.label.as_ptr());
+        }
+    }
+}
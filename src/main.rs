@@ -1,23 +1,67 @@
-use cashew_gb::{Gb, GbError, GbInitError};
+use cashew_gb::cheat::{self, CheatConfig};
+#[cfg(feature = "debug")]
+use cashew_gb::dap::DapServer;
+use cashew_gb::ir::{GbIrContext, IrPort};
+use cashew_gb::link::{GbLinkContext, LinkPort};
+use cashew_gb::printer::{PrintDirectory, Printer};
+use cashew_gb::{palette, DmgPalette, Gb, GbError, GbInitError, PaletteConfig, JOYPAD_SELECT};
 use std::cell::RefCell;
+use std::fs;
+use std::path::Path;
 use std::sync::mpsc::{channel, Receiver, RecvError, Sender};
 use std::thread;
+use svc::fs::fatfs::Fatfs;
 use svc::hal;
 use svc::hal::delay::FreeRtos;
+use svc::hal::gpio::AnyIOPin;
 use svc::hal::gpio::Gpio4;
 use svc::hal::gpio::Gpio5;
+use svc::hal::sd::{spi::SdSpiHostDriver, SdCardConfiguration, SdCardDriver};
 use svc::hal::spi::{config::DriverConfig, Dma, SpiDriver};
+use svc::io::vfs::MountedFatfs;
 use svc::sys;
 
 mod cashew_gb;
 mod drivers;
+mod menu;
 
 const KB: usize = 1024;
+const ROM_PARTITION_LABEL: &str = "rom";
+const STORAGE_PARTITION_LABEL: &str = "storage";
+const STORAGE_BASE_PATH: &str = "/storage";
+const SD_CARD_BASE_PATH: &str = "/sdcard";
+/// The storage partition is read-only, prints go to the SD card.
+const PRINTS_PATH: &str = "/sdcard/prints";
 
 struct Context {
-    rom: Box<Vec<u8>>,
+    rom: drivers::RomPartition,
     ram: RefCell<Vec<u8>>,
-    display_channel_sender: Sender<Option<([u8; 160], u8, [u16; 0x40])>>,
+    display_channel_sender: Sender<DisplayMessage>,
+    link: LinkPort,
+    ir: IrPort,
+}
+impl GbLinkContext for Context {
+    fn get_link_port(&self) -> &LinkPort {
+        &self.link
+    }
+}
+impl GbIrContext for Context {
+    fn get_ir_port(&self) -> &IrPort {
+        &self.ir
+    }
+}
+
+/// What the emulation thread has the display thread do.
+enum DisplayMessage {
+    /// Buffers a line whose pixels index the colours sent with it
+    Line([u8; 160], u8, [u16; 0x40]),
+    /// Buffers a DMG line in the last `DmgPalette` sent, without CGB colours
+    #[cfg(not(feature = "gbc"))]
+    DmgLine([u8; 160], u8),
+    #[cfg(not(feature = "gbc"))]
+    DmgPalette(DmgPalette),
+    /// Draws the buffered frame
+    Draw,
 }
 
 fn main() -> () {
@@ -54,7 +98,8 @@ fn main() -> () {
             unsafe { svc::sys::uxTaskGetStackHighWaterMark(core::ptr::null_mut()) }
         );
 
-        let rom = Box::new(Vec::from(include_bytes!("../rom.gbc")));
+        let rom = drivers::RomPartition::new(ROM_PARTITION_LABEL)
+            .expect("No ROM in the rom partition, write one with tools/flash-rom");
 
         println!(
             "OK - rom - HEAP: {}B, STACK: {}B",
@@ -62,36 +107,38 @@ fn main() -> () {
             unsafe { svc::sys::uxTaskGetStackHighWaterMark(core::ptr::null_mut()) }
         );
 
-        // let mut cfg = SdCardConfiguration::new();
-        // cfg.speed_khz = 16 * KB as u32;
-
-        // let sd_card_driver = SdCardDriver::new_spi(
-        //     SdSpiHostDriver::new(
-        //         &spi_driver,
-        //         Some(peripherals.pins.gpio2),
-        //         AnyIOPin::none(),
-        //         AnyIOPin::none(),
-        //         AnyIOPin::none(),
-        //         None,
-        //     )
-        //     .unwrap(),
-        //     &cfg,
-        // )
-        // .unwrap();
-        // println!(
-        //     "OK - sd_card_driver - HEAP: {}B, STACK: {}B",
-        //     unsafe { sys::esp_get_free_heap_size() },
-        //     unsafe { svc::sys::uxTaskGetStackHighWaterMark(core::ptr::null_mut()) }
-        // );
+        let storage = drivers::Storage::new(STORAGE_PARTITION_LABEL, STORAGE_BASE_PATH)
+            .map_err(|err| log::warn!("Storage partition not mounted: {}", err))
+            .ok();
+        let palettes = load_palettes(storage.as_ref());
+        let cheats = load_cheats(storage.as_ref());
+        println!(
+            "OK - storage - HEAP: {}B, STACK: {}B",
+            unsafe { sys::esp_get_free_heap_size() },
+            unsafe { svc::sys::uxTaskGetStackHighWaterMark(core::ptr::null_mut()) }
+        );
 
-        // let _mounted_fatfs =
-        //     MountedFatfs::mount(Fatfs::new_sdcard(0, sd_card_driver).unwrap(), "/sdcard", 4)
-        //         .unwrap();
-        // println!(
-        //     "OK - _mounted_fatfs - HEAP: {}B, STACK: {}B",
-        //     unsafe { sys::esp_get_free_heap_size() },
-        //     unsafe { svc::sys::uxTaskGetStackHighWaterMark(core::ptr::null_mut()) }
-        // );
+        // Prints are saved to the SD card, without one the game still runs
+        let mut sd_card_config = SdCardConfiguration::new();
+        sd_card_config.speed_khz = 16 * KB as u32;
+        let sd_card = SdSpiHostDriver::new(
+            &spi_driver,
+            Some(peripherals.pins.gpio2),
+            AnyIOPin::none(),
+            AnyIOPin::none(),
+            AnyIOPin::none(),
+            None,
+        )
+        .and_then(|host| SdCardDriver::new_spi(host, &sd_card_config))
+        .and_then(|driver| Fatfs::new_sdcard(0, driver))
+        .and_then(|fatfs| MountedFatfs::mount(fatfs, SD_CARD_BASE_PATH, 4))
+        .map_err(|err| log::warn!("SD card not mounted: {}", err))
+        .ok();
+        println!(
+            "OK - sd_card - HEAP: {}B, STACK: {}B",
+            unsafe { sys::esp_get_free_heap_size() },
+            unsafe { svc::sys::uxTaskGetStackHighWaterMark(core::ptr::null_mut()) }
+        );
 
         // let mut rom_file = OpenOptions::new()
         //     .read(true)
@@ -135,7 +182,16 @@ fn main() -> () {
             rom,
             ram: RefCell::new(vec![]),
             display_channel_sender,
+            link: LinkPort::new(),
+            ir: IrPort::new(),
         };
+        // Without a card to save to, the serial port is left unplugged
+        if sd_card.is_some() {
+            match PrintDirectory::new(Path::new(PRINTS_PATH)) {
+                Ok(prints) => context.link.plug(Box::new(Printer::new(Box::new(prints)))),
+                Err(err) => log::warn!("No printer, {} not writable: {}", PRINTS_PATH, err),
+            }
+        }
 
         let mut controller = drivers::SNESController::new(
             peripherals.pins.gpio15,
@@ -156,26 +212,168 @@ fn main() -> () {
                         .borrow_mut()
                         .append(&mut vec![0; gb.get_save_size()]);
                     gb.gb_init_lcd(draw_line);
+                    gb.gb_init_link();
+                    gb.gb_init_ir_port();
+                    let header = gb.get_header();
+                    if header.is_sgb_supported() && !header.is_cgb_supported() {
+                        // The border doesn't fit the LCD, only the colours are shown
+                        gb.gb_set_sgb(true);
+                        gb.gb_reset();
+                    } else if !header.is_cgb_supported() {
+                        #[cfg(feature = "gbc")]
+                        gb.gb_colourise(!controller.read_gb());
+                    }
                     gb
                 }
                 _ => {
                     panic!("Failed to create Gameboy instance")
                 }
             };
+        let mut palette_name = palettes.select(gb.get_header()).map(str::to_string);
+        if let Some(name) = &palette_name {
+            set_dmg_palette(&mut gb, palettes.get(name).unwrap());
+        }
+        gb.gb_set_cheats(cheats.select(gb.get_header()));
+
+        // Built for debugging, the second UART serves the debug adapter and the
+        // game plays on until a client launches
+        #[cfg(feature = "debug")]
+        let mut dap_server = match drivers::debug_uart(
+            peripherals.uart1,
+            peripherals.pins.gpio17,
+            peripherals.pins.gpio18,
+        ) {
+            Ok((rx, tx)) => DapServer::new(rx, tx)
+                .map_err(|err| log::warn!("No debug adapter: {}", err))
+                .ok(),
+            Err(err) => {
+                log::warn!("No debug adapter, UART not set up: {}", err);
+                None
+            }
+        };
+
         let max_frame = 60 * 60 * 10;
+        let mut extra_buttons = 0;
+        let mut buttons = 0;
+        let mut cheat_menu: Option<menu::CheatMenu> = None;
+        let mut viewer_menu: Option<menu::ViewerMenu> = None;
+        let menu_palette = menu::menu_palette();
         for _ in 0..max_frame {
+            #[cfg(feature = "debug")]
+            if let Some(server) = &mut dap_server {
+                // The flashed ROM is debugged whatever the launch names
+                let served = server.poll_launch().and_then(|launch| match launch {
+                    Some(launch) => server.serve(&mut gb, &launch, |gb| {
+                        gb.get_context()
+                            .display_channel_sender
+                            .send(DisplayMessage::Draw)
+                            .unwrap()
+                    }),
+                    None => Ok(()),
+                });
+                if let Err(err) = served {
+                    log::warn!("Debug adapter stopped: {}", err);
+                    dap_server = None;
+                }
+            }
+
             let input = controller.read_gb();
+            let pressed = controller.get_extra_buttons() & !extra_buttons;
+            let pressed_buttons = input & !buttons;
+            extra_buttons = controller.get_extra_buttons();
+            buttons = input;
+
+            // The game is paused while the cheat menu is open
+            if let Some(open_menu) = &mut cheat_menu {
+                if open_menu.update(&mut gb, pressed_buttons) {
+                    open_menu.draw(gb.get_cheats(), |pixels, line| {
+                        context
+                            .display_channel_sender
+                            .send(DisplayMessage::Line(pixels, line, menu_palette))
+                            .unwrap()
+                    });
+                    context
+                        .display_channel_sender
+                        .send(DisplayMessage::Draw)
+                        .unwrap();
+                    continue;
+                }
+                cheat_menu = None;
+            }
+            // and so is it while the VRAM viewer is
+            if let Some(open_menu) = &mut viewer_menu {
+                if open_menu.update(pressed_buttons) {
+                    open_menu.draw(&gb, |pixels, line, colours| {
+                        context
+                            .display_channel_sender
+                            .send(DisplayMessage::Line(pixels, line, colours))
+                            .unwrap()
+                    });
+                    context
+                        .display_channel_sender
+                        .send(DisplayMessage::Draw)
+                        .unwrap();
+                    continue;
+                }
+                viewer_menu = None;
+            }
+            // Select and L open the cheat menu, Select and R the VRAM viewer
+            if pressed & drivers::SNES_L != 0 && input & JOYPAD_SELECT != 0 {
+                cheat_menu = Some(menu::CheatMenu::new());
+                continue;
+            }
+            if pressed & drivers::SNES_R != 0 && input & JOYPAD_SELECT != 0 {
+                viewer_menu = Some(menu::ViewerMenu::new());
+                continue;
+            }
+            if pressed != 0 {
+                // L and R cycle through the DMG palettes
+                let name = if pressed & drivers::SNES_R != 0 {
+                    palettes.next(palette_name.as_deref())
+                } else {
+                    palettes.previous(palette_name.as_deref())
+                };
+                set_dmg_palette(&mut gb, palettes.get(name).unwrap());
+                palette_name = Some(name.to_string());
+            }
             gb.set_joypad(!input);
             gb.run_frame();
-            gb.get_context().display_channel_sender.send(None).unwrap();
+            gb.get_context()
+                .display_channel_sender
+                .send(DisplayMessage::Draw)
+                .unwrap();
         }
     });
 
     log::info!("DONE!");
 }
 
+fn load_palettes(storage: Option<&drivers::Storage>) -> PaletteConfig {
+    let Some(text) =
+        storage.and_then(|storage| fs::read_to_string(storage.path(palette::PALETTE_FILE)).ok())
+    else {
+        return PaletteConfig::new();
+    };
+    PaletteConfig::parse(&text).unwrap_or_else(|err| {
+        log::warn!("Ignoring {}: {:?}", palette::PALETTE_FILE, err);
+        PaletteConfig::new()
+    })
+}
+
+fn load_cheats(storage: Option<&drivers::Storage>) -> CheatConfig {
+    let Some(text) =
+        storage.and_then(|storage| fs::read_to_string(storage.path(cheat::CHEAT_FILE)).ok())
+    else {
+        return CheatConfig::new();
+    };
+    CheatConfig::parse(&text).unwrap_or_else(|err| {
+        log::warn!("Ignoring {}: {:?}", cheat::CHEAT_FILE, err);
+        CheatConfig::new()
+    })
+}
+
 fn rom_read(gb: &Gb<Context>, addr: usize) -> u8 {
-    gb.get_context().rom[addr]
+    gb.get_context().rom.data()[addr]
 }
 
 fn ram_read(gb: &Gb<Context>, addr: usize) -> u8 {
@@ -200,22 +398,51 @@ fn gb_error(_gb: &Gb<Context>, gb_error: GbError, addr: u16) -> () {
 }
 
 fn draw_line(gb: &Gb<Context>, pixels: [u8; 160], line: u8) -> () {
+    // Without CGB colours, DMG lines are coloured by the display
+    #[cfg(not(feature = "gbc"))]
+    if gb.get_sgb().is_none() {
+        return gb
+            .get_context()
+            .display_channel_sender
+            .send(DisplayMessage::DmgLine(pixels, line))
+            .unwrap();
+    }
     gb.get_context()
         .display_channel_sender
-        .send(Some((pixels, line, gb.get_palette().clone())))
+        .send(DisplayMessage::Line(pixels, line, gb.get_palette()))
         .unwrap()
 }
 
+/// Colours DMG games with `palette`, through the CGB palettes when there are
+/// any and by the display otherwise.
+fn set_dmg_palette(gb: &mut Gb<Context>, palette: &DmgPalette) -> () {
+    #[cfg(feature = "gbc")]
+    gb.gb_set_dmg_palette(palette);
+    #[cfg(not(feature = "gbc"))]
+    gb.get_context()
+        .display_channel_sender
+        .send(DisplayMessage::DmgPalette(*palette))
+        .unwrap();
+}
+
 fn display_channel_listener(
-    display_channel_receiver: Receiver<Option<([u8; 160], u8, [u16; 0x40])>>,
+    display_channel_receiver: Receiver<DisplayMessage>,
     mut display: drivers::Display<Gpio4, Gpio5>,
 ) -> () {
     loop {
         match display_channel_receiver.recv() {
-            Ok(Some((pixels, line, palette))) => {
+            Ok(DisplayMessage::Line(pixels, line, palette)) => {
                 display.buffer_line_gbc(pixels, line, palette);
             }
-            Ok(None) => {
+            #[cfg(not(feature = "gbc"))]
+            Ok(DisplayMessage::DmgLine(pixels, line)) => {
+                display.buffer_line_gb(pixels, line);
+            }
+            #[cfg(not(feature = "gbc"))]
+            Ok(DisplayMessage::DmgPalette(palette)) => {
+                display.set_dmg_palette(&palette);
+            }
+            Ok(DisplayMessage::Draw) => {
                 display.draw();
             }
             Err(RecvError) => {
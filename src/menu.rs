@@ -0,0 +1,348 @@
+use crate::cashew_gb::{
+    cheat::Cheat,
+    palette,
+    viewer::{Image, TileMap},
+    Gb, JOYPAD_A, JOYPAD_B, JOYPAD_DOWN, JOYPAD_LEFT, JOYPAD_RIGHT, JOYPAD_UP,
+};
+
+const LINE_WIDTH: usize = 160;
+/// The panel only shows the top 128 of the LCD's lines.
+const VISIBLE_LINES: u8 = 128;
+const ROW_HEIGHT: u8 = 8;
+const ROWS: usize = (VISIBLE_LINES / ROW_HEIGHT) as usize;
+const GLYPH_WIDTH: usize = 6;
+const COLUMNS: usize = LINE_WIDTH / GLYPH_WIDTH;
+/// Lines under the title row.
+const IMAGE_LINES: usize = (VISIBLE_LINES - ROW_HEIGHT) as usize;
+/// How far A pans an image wider than the LCD.
+const PAN_WIDTH: usize = 64;
+
+const COLOUR_BACKGROUND: u8 = 0;
+const COLOUR_TEXT: u8 = 1;
+const COLOUR_SELECTED: u8 = 2;
+const COLOUR_SELECTED_TEXT: u8 = 3;
+
+/// 5x7 glyphs from space to underscore, a byte per column with the top row in
+/// bit 0. Lowercase is drawn as uppercase.
+const FONT: [[u8; 5]; 64] = [
+    [0x00, 0x00, 0x00, 0x00, 0x00],
+    [0x00, 0x00, 0x5F, 0x00, 0x00],
+    [0x00, 0x07, 0x00, 0x07, 0x00],
+    [0x14, 0x7F, 0x14, 0x7F, 0x14],
+    [0x24, 0x2A, 0x7F, 0x2A, 0x12],
+    [0x23, 0x13, 0x08, 0x64, 0x62],
+    [0x36, 0x49, 0x55, 0x22, 0x50],
+    [0x00, 0x05, 0x03, 0x00, 0x00],
+    [0x00, 0x1C, 0x22, 0x41, 0x00],
+    [0x00, 0x41, 0x22, 0x1C, 0x00],
+    [0x08, 0x2A, 0x1C, 0x2A, 0x08],
+    [0x08, 0x08, 0x3E, 0x08, 0x08],
+    [0x00, 0x50, 0x30, 0x00, 0x00],
+    [0x08, 0x08, 0x08, 0x08, 0x08],
+    [0x00, 0x60, 0x60, 0x00, 0x00],
+    [0x20, 0x10, 0x08, 0x04, 0x02],
+    [0x3E, 0x51, 0x49, 0x45, 0x3E],
+    [0x00, 0x42, 0x7F, 0x40, 0x00],
+    [0x42, 0x61, 0x51, 0x49, 0x46],
+    [0x21, 0x41, 0x45, 0x4B, 0x31],
+    [0x18, 0x14, 0x12, 0x7F, 0x10],
+    [0x27, 0x45, 0x45, 0x45, 0x39],
+    [0x3C, 0x4A, 0x49, 0x49, 0x30],
+    [0x01, 0x71, 0x09, 0x05, 0x03],
+    [0x36, 0x49, 0x49, 0x49, 0x36],
+    [0x06, 0x49, 0x49, 0x29, 0x1E],
+    [0x00, 0x36, 0x36, 0x00, 0x00],
+    [0x00, 0x56, 0x36, 0x00, 0x00],
+    [0x08, 0x14, 0x22, 0x41, 0x00],
+    [0x14, 0x14, 0x14, 0x14, 0x14],
+    [0x00, 0x41, 0x22, 0x14, 0x08],
+    [0x02, 0x01, 0x51, 0x09, 0x06],
+    [0x32, 0x49, 0x79, 0x41, 0x3E],
+    [0x7E, 0x11, 0x11, 0x11, 0x7E],
+    [0x7F, 0x49, 0x49, 0x49, 0x36],
+    [0x3E, 0x41, 0x41, 0x41, 0x22],
+    [0x7F, 0x41, 0x41, 0x22, 0x1C],
+    [0x7F, 0x49, 0x49, 0x49, 0x41],
+    [0x7F, 0x09, 0x09, 0x01, 0x01],
+    [0x3E, 0x41, 0x41, 0x51, 0x32],
+    [0x7F, 0x08, 0x08, 0x08, 0x7F],
+    [0x00, 0x41, 0x7F, 0x41, 0x00],
+    [0x20, 0x40, 0x41, 0x3F, 0x01],
+    [0x7F, 0x08, 0x14, 0x22, 0x41],
+    [0x7F, 0x40, 0x40, 0x40, 0x40],
+    [0x7F, 0x02, 0x04, 0x02, 0x7F],
+    [0x7F, 0x04, 0x08, 0x10, 0x7F],
+    [0x3E, 0x41, 0x41, 0x41, 0x3E],
+    [0x7F, 0x09, 0x09, 0x09, 0x06],
+    [0x3E, 0x41, 0x51, 0x21, 0x5E],
+    [0x7F, 0x09, 0x19, 0x29, 0x46],
+    [0x46, 0x49, 0x49, 0x49, 0x31],
+    [0x01, 0x01, 0x7F, 0x01, 0x01],
+    [0x3F, 0x40, 0x40, 0x40, 0x3F],
+    [0x1F, 0x20, 0x40, 0x20, 0x1F],
+    [0x7F, 0x20, 0x18, 0x20, 0x7F],
+    [0x63, 0x14, 0x08, 0x14, 0x63],
+    [0x03, 0x04, 0x78, 0x04, 0x03],
+    [0x61, 0x51, 0x49, 0x45, 0x43],
+    [0x00, 0x7F, 0x41, 0x41, 0x00],
+    [0x02, 0x04, 0x08, 0x10, 0x20],
+    [0x00, 0x41, 0x41, 0x7F, 0x00],
+    [0x04, 0x02, 0x01, 0x02, 0x04],
+    [0x40, 0x40, 0x40, 0x40, 0x40],
+];
+
+/// Colours for the menu's lines, in the layout `Gb::get_palette` returns.
+pub fn menu_palette() -> [u16; 0x40] {
+    let mut colours = [0; 0x40];
+    colours[COLOUR_BACKGROUND as usize] = palette::to_rgb555(palette::rgb(0x10, 0x10, 0x18));
+    colours[COLOUR_TEXT as usize] = palette::to_rgb555(palette::rgb(0xE0, 0xE0, 0xE0));
+    colours[COLOUR_SELECTED as usize] = palette::to_rgb555(palette::rgb(0xE0, 0xE0, 0xE0));
+    colours[COLOUR_SELECTED_TEXT as usize] = palette::to_rgb555(palette::rgb(0x10, 0x10, 0x18));
+    colours
+}
+
+/// The on-device list of the game's cheats, drawn over the LCD while the game
+/// is paused.
+pub struct CheatMenu {
+    selected: usize,
+    top: usize,
+}
+impl CheatMenu {
+    pub fn new() -> CheatMenu {
+        CheatMenu {
+            selected: 0,
+            top: 0,
+        }
+    }
+
+    /// Up and down move, A turns the selected cheat on or off. False once B
+    /// has closed the menu.
+    pub fn update<T>(&mut self, gb: &mut Gb<T>, pressed: u8) -> bool {
+        let count = gb.get_cheats().len();
+        if pressed & JOYPAD_B != 0 {
+            return false;
+        }
+        if count == 0 {
+            return true;
+        }
+        if pressed & JOYPAD_UP != 0 {
+            self.selected = (self.selected + count - 1) % count;
+        }
+        if pressed & JOYPAD_DOWN != 0 {
+            self.selected = (self.selected + 1) % count;
+        }
+        if pressed & JOYPAD_A != 0 {
+            let enabled = gb.get_cheats()[self.selected].enabled;
+            gb.gb_set_cheat_enabled(self.selected, !enabled);
+        }
+
+        /* The title takes the first row. */
+        let rows = ROWS - 1;
+        if self.selected < self.top {
+            self.top = self.selected;
+        } else if self.selected >= self.top + rows {
+            self.top = self.selected + 1 - rows;
+        }
+        true
+    }
+
+    pub fn draw(&self, cheats: &[Cheat], mut draw_line: impl FnMut([u8; LINE_WIDTH], u8)) {
+        let mut rows = vec![("CHEATS  A:TOGGLE B:BACK".to_string(), false)];
+        if cheats.is_empty() {
+            rows.push(("NONE FOR THIS GAME".to_string(), false));
+        }
+        for (index, cheat) in cheats.iter().enumerate().skip(self.top).take(ROWS - 1) {
+            let mark = if cheat.enabled { '*' } else { ' ' };
+            rows.push((format!("{mark} {}", cheat.name), index == self.selected));
+        }
+
+        for line in 0..VISIBLE_LINES {
+            let (text, selected) = rows
+                .get((line / ROW_HEIGHT) as usize)
+                .map_or(("", false), |(text, selected)| (text.as_str(), *selected));
+            draw_line(text_line(text, selected, line % ROW_HEIGHT), line);
+        }
+    }
+}
+
+impl Default for CheatMenu {
+    fn default() -> CheatMenu {
+        CheatMenu::new()
+    }
+}
+
+/// The viewer's pages, in the order left and right go through them.
+const VIEWER_PAGES: [&str; 7] = [
+    "TILES 0", "TILES 1", "MAP 9800", "MAP 9C00", "OAM", "PALETTES", "SPRITES",
+];
+const SPRITES_PAGE: usize = 6;
+
+/// The on-device VRAM viewer, drawn over the LCD while the game is paused.
+pub struct ViewerMenu {
+    page: usize,
+    top: usize,
+    left: usize,
+}
+impl ViewerMenu {
+    pub fn new() -> ViewerMenu {
+        ViewerMenu {
+            page: 0,
+            top: 0,
+            left: 0,
+        }
+    }
+
+    /// Left and right change the page, up and down scroll it and A pans
+    /// across the maps. False once B has closed the viewer.
+    pub fn update(&mut self, pressed: u8) -> bool {
+        if pressed & JOYPAD_B != 0 {
+            return false;
+        }
+        let pages = VIEWER_PAGES.len();
+        if pressed & (JOYPAD_LEFT | JOYPAD_RIGHT) != 0 {
+            self.page = if pressed & JOYPAD_RIGHT != 0 {
+                (self.page + 1) % pages
+            } else {
+                (self.page + pages - 1) % pages
+            };
+            self.top = 0;
+            self.left = 0;
+        }
+        /* The sprite list scrolls by rows, the images by a row's lines. */
+        let step = if self.page == SPRITES_PAGE {
+            1
+        } else {
+            ROW_HEIGHT as usize
+        };
+        if pressed & JOYPAD_UP != 0 {
+            self.top = self.top.saturating_sub(step);
+        }
+        if pressed & JOYPAD_DOWN != 0 {
+            self.top += step;
+        }
+        if pressed & JOYPAD_A != 0 {
+            self.left += PAN_WIDTH;
+        }
+        true
+    }
+
+    /// Each line comes with its own palette, the images have more colours
+    /// than one palette holds.
+    pub fn draw<T>(
+        &mut self,
+        gb: &Gb<T>,
+        mut draw_line: impl FnMut([u8; LINE_WIDTH], u8, [u16; 0x40]),
+    ) {
+        let title = format!(
+            "{}/{} {}  B:BACK",
+            self.page + 1,
+            VIEWER_PAGES.len(),
+            VIEWER_PAGES[self.page]
+        );
+        let colours = menu_palette();
+        for line in 0..ROW_HEIGHT {
+            draw_line(text_line(&title, false, line), line, colours);
+        }
+
+        let image = match self.page {
+            0 | 1 => gb.get_tile_sheet(self.page),
+            2 => Some(gb.get_tile_map(TileMap::Map9800)),
+            3 => Some(gb.get_tile_map(TileMap::Map9C00)),
+            4 => Some(gb.get_oam_table()),
+            5 => Some(gb.get_palette_swatches()),
+            _ => None,
+        };
+        let Some(image) = image else {
+            self.draw_sprites(gb, &mut draw_line);
+            return;
+        };
+
+        self.top = self.top.min(image.height.saturating_sub(IMAGE_LINES));
+        /* Panning stops at the right edge once, then wraps back. */
+        let max_left = image.width.saturating_sub(LINE_WIDTH);
+        if self.left > max_left {
+            self.left = if self.left - PAN_WIDTH >= max_left {
+                0
+            } else {
+                max_left
+            };
+        }
+        for line in ROW_HEIGHT..VISIBLE_LINES {
+            let y = self.top + (line - ROW_HEIGHT) as usize;
+            let (pixels, colours) = image_line(&image, self.left, y);
+            draw_line(pixels, line, colours);
+        }
+    }
+
+    fn draw_sprites<T>(
+        &mut self,
+        gb: &Gb<T>,
+        draw_line: &mut impl FnMut([u8; LINE_WIDTH], u8, [u16; 0x40]),
+    ) {
+        let sprites = gb.get_sprites();
+        let rows = ROWS - 1;
+        self.top = self.top.min(sprites.len().saturating_sub(rows));
+        let colours = menu_palette();
+        for line in ROW_HEIGHT..VISIBLE_LINES {
+            let row = self.top + ((line - ROW_HEIGHT) / ROW_HEIGHT) as usize;
+            let text = sprites.get(row).map(|sprite| sprite.to_string());
+            let pixels = text_line(text.as_deref().unwrap_or(""), false, line % ROW_HEIGHT);
+            draw_line(pixels, line, colours);
+        }
+    }
+}
+
+impl Default for ViewerMenu {
+    fn default() -> ViewerMenu {
+        ViewerMenu::new()
+    }
+}
+
+/// A line of the image from `left`, with the palette of its colours after the
+/// menu's. Past the 64th colour, and off the image, pixels are background.
+fn image_line(image: &Image, left: usize, y: usize) -> ([u8; LINE_WIDTH], [u16; 0x40]) {
+    let mut colours = menu_palette();
+    let mut used = COLOUR_SELECTED_TEXT as usize + 1;
+    let mut pixels = [COLOUR_BACKGROUND; LINE_WIDTH];
+    if y >= image.height {
+        return (pixels, colours);
+    }
+    for (x, pixel) in pixels.iter_mut().enumerate() {
+        if left + x >= image.width {
+            break;
+        }
+        let colour = image.get(left + x, y);
+        let index = match colours[..used].iter().rposition(|c| *c == colour) {
+            Some(index) if index > COLOUR_SELECTED_TEXT as usize => index,
+            _ if used < colours.len() => {
+                colours[used] = colour;
+                used += 1;
+                used - 1
+            }
+            _ => continue,
+        };
+        *pixel = index as u8;
+    }
+    (pixels, colours)
+}
+
+fn text_line(text: &str, selected: bool, y: u8) -> [u8; LINE_WIDTH] {
+    let (background, foreground) = if selected {
+        (COLOUR_SELECTED, COLOUR_SELECTED_TEXT)
+    } else {
+        (COLOUR_BACKGROUND, COLOUR_TEXT)
+    };
+    let mut pixels = [background; LINE_WIDTH];
+    for (column, c) in text.chars().take(COLUMNS).enumerate() {
+        let c = c.to_ascii_uppercase() as usize;
+        let glyph = FONT
+            .get(c.wrapping_sub(0x20))
+            .unwrap_or(&FONT[b'?' as usize - 0x20]);
+        for (x, bits) in glyph.iter().enumerate() {
+            if (bits >> y) & 1 != 0 {
+                pixels[column * GLYPH_WIDTH + x] = foreground;
+            }
+        }
+    }
+    pixels
+}
@@ -0,0 +1,73 @@
+//! Runs a ROM for some frames logging how every byte of the ROM and cart RAM
+//! is read, writes the log as a Mesen `.cdl` and prints each bank's coverage.
+//!
+//! cdl <rom> <cdl> [--frames N] [--raw FILE]
+//!
+//! `--raw` also writes the log's own flags, a byte per ROM byte and then per
+//! cart RAM byte: 0x01 opcode, 0x02 operand, 0x04 data, 0x08 OAM DMA or HDMA.
+//! Nothing presses the buttons, so only what runs unattended is covered.
+
+use anyhow::{bail, Context, Result};
+use cashew_tools::{cashew_gb::cdl::Coverage, load_rom};
+use std::{env, fs, path::PathBuf};
+
+const DEFAULT_FRAMES: u32 = 60 * 60;
+
+fn main() -> Result<()> {
+    let mut paths = Vec::new();
+    let mut frames = DEFAULT_FRAMES;
+    let mut raw = None;
+
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--frames" => {
+                frames = args
+                    .next()
+                    .context("missing frame count")?
+                    .parse()
+                    .context("frame count")?
+            }
+            "--raw" => raw = Some(PathBuf::from(args.next().context("missing path")?)),
+            _ => paths.push(PathBuf::from(arg)),
+        }
+    }
+    let [rom_path, cdl_path] = &paths[..] else {
+        bail!("usage: cdl <rom> <cdl> [--frames N] [--raw FILE]");
+    };
+
+    let rom = fs::read(rom_path).with_context(|| format!("reading {}", rom_path.display()))?;
+    let mut gb = load_rom(&rom)?;
+    gb.gb_set_code_data_log(Some(gb.gb_new_code_data_log()));
+    for _ in 0..frames {
+        gb.run_frame();
+    }
+    let log = gb.get_code_data_log().unwrap();
+
+    fs::write(cdl_path, log.to_mesen(&rom))
+        .with_context(|| format!("writing {}", cdl_path.display()))?;
+    if let Some(path) = &raw {
+        let mut flags = log.get_rom();
+        flags.extend(log.get_cart_ram());
+        fs::write(path, flags).with_context(|| format!("writing {}", path.display()))?;
+    }
+
+    println!("bank   opcode  operand     data      dma   unused");
+    let mut bank = 0;
+    while let Some(coverage) = log.get_rom_coverage(bank) {
+        print_coverage(&format!("{bank:4}"), &coverage);
+        bank += 1;
+    }
+    if !log.get_cart_ram().is_empty() {
+        print_coverage(" RAM", &log.get_cart_ram_coverage());
+    }
+    println!("wrote {} after {frames} frames", cdl_path.display());
+    Ok(())
+}
+
+fn print_coverage(name: &str, coverage: &Coverage) {
+    println!(
+        "{name} {:8} {:8} {:8} {:8} {:8}",
+        coverage.opcode, coverage.operand, coverage.data, coverage.dma, coverage.unused
+    );
+}
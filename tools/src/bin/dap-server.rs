@@ -0,0 +1,145 @@
+//! Serves the Debug Adapter Protocol to VS Code or another editor over TCP,
+//! one client at a time, each launching the ROM at the `program` of its
+//! launch request with a `.sym` or `.map` at `symbols`.
+//!
+//! dap-server [--listen ADDR] [--serial DEVICE]
+//!
+//! With `--serial` the client is passed through to a board built with the
+//! `debug` feature instead, which serves the flashed ROM on its second UART,
+//! TX on GPIO17 and RX on GPIO18 at 115200 baud, while the game plays. The
+//! device is opened as a file, set its baud rate with `stty` first. The
+//! launch's paths are read on the board then, `sourceRoot` can point at a
+//! directory of sources on its storage.
+//!
+//! Point the editor's `debugServer` at the address. The launch arguments are
+//! `program`, `symbols`, `sourceRoot` and `stopOnEntry`.
+
+use anyhow::{bail, Context, Result};
+use cashew_tools::{cashew_gb::dap::DapServer, load_rom};
+use std::{
+    env,
+    fs::{self, File, OpenOptions},
+    io::{self, Read, Write},
+    net::{TcpListener, TcpStream},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    thread,
+};
+
+const DEFAULT_LISTEN: &str = "127.0.0.1:4711";
+/// Where the noise from the board starting up ends and the protocol starts.
+const HEADER: &[u8] = b"Content-Length";
+
+fn main() -> Result<()> {
+    let mut listen = String::from(DEFAULT_LISTEN);
+    let mut serial = None;
+
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--listen" => listen = args.next().context("missing address")?,
+            "--serial" => serial = Some(PathBuf::from(args.next().context("missing device")?)),
+            _ => bail!("usage: dap-server [--listen ADDR] [--serial DEVICE]"),
+        }
+    }
+
+    let mut board = serial.as_deref().map(Board::open).transpose()?;
+    let listener = TcpListener::bind(&listen).with_context(|| format!("listening on {listen}"))?;
+    println!("waiting for a client on {}", listener.local_addr()?);
+    for stream in listener.incoming() {
+        let stream = stream?;
+        println!("client connected from {}", stream.peer_addr()?);
+        let result = match &mut board {
+            Some(board) => board.forward(stream),
+            None => debug(stream),
+        };
+        if let Err(err) = result {
+            println!("session ended: {err:#}");
+        } else {
+            println!("client disconnected");
+        }
+    }
+    Ok(())
+}
+
+fn debug(stream: TcpStream) -> Result<()> {
+    stream.set_nodelay(true)?;
+    let mut server = DapServer::new(stream.try_clone()?, stream)?;
+    while let Some(launch) = server.wait_for_launch()? {
+        let rom = match fs::read(&launch.program) {
+            Ok(rom) => rom,
+            Err(err) => {
+                server.fail_launch(&launch, &format!("{}: {err}", launch.program))?;
+                continue;
+            }
+        };
+        match load_rom(&rom) {
+            Ok(mut gb) => {
+                println!("launched {}", launch.program);
+                return Ok(server.serve(&mut gb, &launch, |_| {})?);
+            }
+            Err(err) => server.fail_launch(&launch, &format!("{}: {err}", launch.program))?,
+        }
+    }
+    Ok(())
+}
+
+/// The board, with whichever client is connected getting what it sends.
+struct Board {
+    device: File,
+    client: Arc<Mutex<Option<TcpStream>>>,
+}
+impl Board {
+    fn open(path: &Path) -> Result<Board> {
+        let device = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(path)
+            .with_context(|| format!("opening {}", path.display()))?;
+        let client = Arc::new(Mutex::new(None));
+        let mut reader = device.try_clone()?;
+        let reader_client = client.clone();
+        thread::spawn(move || board_reader(&mut reader, &reader_client));
+        Ok(Board { device, client })
+    }
+
+    /// Copies the client to the board until it disconnects.
+    fn forward(&mut self, stream: TcpStream) -> Result<()> {
+        *self.client.lock().unwrap() = Some(stream.try_clone()?);
+        let result = io::copy(&mut &stream, &mut self.device);
+        *self.client.lock().unwrap() = None;
+        result?;
+        Ok(())
+    }
+}
+
+fn board_reader(board: &mut File, client: &Mutex<Option<TcpStream>>) -> io::Result<()> {
+    /* Whatever the line picked up first is dropped, the protocol starts at
+     * its first header. */
+    let mut log = Vec::new();
+    let mut synced = false;
+    let mut buffer = [0; 512];
+    loop {
+        let count = board.read(&mut buffer)?;
+        if count == 0 {
+            return Ok(());
+        }
+        let data = if synced {
+            &buffer[..count]
+        } else {
+            log.extend_from_slice(&buffer[..count]);
+            let Some(start) = log
+                .windows(HEADER.len())
+                .position(|window| window == HEADER)
+            else {
+                continue;
+            };
+            synced = true;
+            &log[start..]
+        };
+        /* Nobody to send it to after a client has gone. */
+        if let Some(stream) = client.lock().unwrap().as_mut() {
+            let _ = stream.write_all(data);
+        }
+    }
+}
@@ -0,0 +1,59 @@
+//! Hosts a DMG-07 four player adapter that consoles join over TCP, each one
+//! connecting a `TcpLink` to it. Players take the free ports in the order they
+//! connect.
+//!
+//! dmg07 [--listen ADDR]
+
+use anyhow::{bail, Context, Result};
+use cashew_tools::cashew_gb::{
+    dmg07::{Dmg07, DMG07_PLAYERS},
+    link::TcpLink,
+    DMG_CLOCK_FREQ,
+};
+use std::{
+    env,
+    io::ErrorKind,
+    net::TcpListener,
+    thread,
+    time::{Duration, Instant},
+};
+
+const DEFAULT_LISTEN: &str = "0.0.0.0:5707";
+const POLL_INTERVAL: Duration = Duration::from_millis(1);
+
+fn main() -> Result<()> {
+    let mut listen = String::from(DEFAULT_LISTEN);
+
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--listen" => listen = args.next().context("missing address")?,
+            _ => bail!("usage: dmg07 [--listen ADDR]"),
+        }
+    }
+
+    let listener = TcpListener::bind(&listen).with_context(|| format!("listening on {listen}"))?;
+    listener.set_nonblocking(true)?;
+    println!("waiting for players on {}", listener.local_addr()?);
+
+    let mut adapter = Dmg07::new();
+    let mut players = 0;
+    let start = Instant::now();
+    loop {
+        match listener.accept() {
+            Ok((stream, addr)) if players < DMG07_PLAYERS => {
+                stream.set_nonblocking(false)?;
+                adapter.plug(players, Box::new(TcpLink::from_stream(stream)?));
+                players += 1;
+                println!("player {players} joined from {addr}");
+            }
+            Ok((_, addr)) => println!("turned {addr} away, all ports are taken"),
+            Err(err) if err.kind() == ErrorKind::WouldBlock => {}
+            Err(err) => return Err(err).context("accepting a player"),
+        }
+
+        let clock = start.elapsed().as_nanos() * DMG_CLOCK_FREQ as u128 / 1_000_000_000;
+        adapter.run_until(clock as u64);
+        thread::sleep(POLL_INTERVAL);
+    }
+}
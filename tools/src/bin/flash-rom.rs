@@ -0,0 +1,119 @@
+//! Writes a ROM image into the `rom` data partition, so the firmware can map
+//! it from flash without being rebuilt.
+//!
+//! flash-rom <rom.gb[c]> [--port PORT] [--partitions partitions.csv] [--espflash PATH]
+//!           [--patch PATH | --no-patch] [--fix-header-checksum]
+//!
+//! An IPS, UPS or BPS patch with the same name as the ROM is applied before
+//! writing, unless another one is given with `--patch`.
+
+use anyhow::{anyhow, bail, Context, Result};
+use cashew_tools::{cashew_gb::CartridgeHeader, load_rom, partitions, patch};
+use std::{env, fs, path::PathBuf, process::Command};
+
+const ROM_PARTITION_LABEL: &str = "rom";
+
+fn main() -> Result<()> {
+    let mut rom_path = None;
+    let mut port = None;
+    let mut partitions_path = PathBuf::from("partitions.csv");
+    let mut espflash = String::from("espflash");
+    let mut patch_path = None;
+    let mut find_patch = true;
+    let mut fix_header_checksum = false;
+
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--port" => port = args.next(),
+            "--partitions" => partitions_path = args.next().context("missing path")?.into(),
+            "--espflash" => espflash = args.next().context("missing path")?,
+            "--patch" => patch_path = Some(PathBuf::from(args.next().context("missing path")?)),
+            "--no-patch" => find_patch = false,
+            "--fix-header-checksum" => fix_header_checksum = true,
+            _ if rom_path.is_none() => rom_path = Some(PathBuf::from(arg)),
+            _ => bail!("unexpected argument {arg}"),
+        }
+    }
+    let Some(rom_path) = rom_path else {
+        bail!("usage: flash-rom <rom> [--port PORT] [--partitions CSV] [--espflash PATH]");
+    };
+
+    let mut rom = fs::read(&rom_path).with_context(|| format!("reading {}", rom_path.display()))?;
+    if find_patch && patch_path.is_none() {
+        patch_path = patch::find_patch(&rom_path);
+    }
+    let mut image_path = rom_path.clone();
+    if let Some(patch_path) = patch_path {
+        let patch_data =
+            fs::read(&patch_path).with_context(|| format!("reading {}", patch_path.display()))?;
+        rom = match patch::apply(&rom, &patch_data) {
+            Err(patch::GbPatchError::GbPatchHeaderChecksum { .. }) if fix_header_checksum => {
+                let mut patched = patch::apply_unchecked(&rom, &patch_data)
+                    .map_err(|err| anyhow!("{:?}", err))?;
+                patch::fix_header_checksum(&mut patched);
+                patched
+            }
+            result => {
+                result.map_err(|err| anyhow!("applying {}: {:?}", patch_path.display(), err))?
+            }
+        };
+        println!("patched with {}", patch_path.display());
+
+        image_path = env::temp_dir().join(rom_path.file_name().unwrap());
+        fs::write(&image_path, &rom)?;
+    }
+    let header = CartridgeHeader::new(&rom)
+        .with_context(|| format!("{} is too short for a ROM header", rom_path.display()))?;
+    // The firmware maps as much of the partition as the header says the ROM
+    // is, a file of another size is truncated or padded with whatever was
+    // flashed before it.
+    let Some(header_size) = header.get_rom_size() else {
+        bail!(
+            "unknown ROM size code {:#04x} in the header",
+            header.get_rom_size_code()
+        );
+    };
+    if rom.len() != header_size {
+        bail!(
+            "ROM is {} bytes but its header says {} bytes",
+            rom.len(),
+            header_size
+        );
+    }
+    load_rom(&rom).with_context(|| format!("{} is not a valid ROM", rom_path.display()))?;
+
+    let partitions = partitions::read(&partitions_path)?;
+    let partition = partitions::find(&partitions, ROM_PARTITION_LABEL)?;
+    if rom.len() > partition.size as usize {
+        bail!(
+            "ROM is {}KB but the {} partition only holds {}KB",
+            rom.len() / 1024,
+            ROM_PARTITION_LABEL,
+            partition.size / 1024
+        );
+    }
+
+    let mut command = Command::new(&espflash);
+    command.arg("write-bin");
+    if let Some(port) = port {
+        command.args(["--port", &port]);
+    }
+    command
+        .arg(format!("{:#x}", partition.offset))
+        .arg(&image_path);
+
+    println!(
+        "writing {} ({}KB) at {:#x}",
+        rom_path.display(),
+        rom.len() / 1024,
+        partition.offset
+    );
+    let status = command
+        .status()
+        .with_context(|| format!("running {espflash}"))?;
+    if !status.success() {
+        bail!("{espflash} exited with {status}");
+    }
+    Ok(())
+}
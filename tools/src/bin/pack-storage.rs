@@ -0,0 +1,244 @@
+//! Packs a directory of `.gb`, `.gbc` and `.sav` files into a FAT image for
+//! the `storage` partition, with an `index.csv` of the cartridge headers.
+//! A `palettes.toml` or `cheats.toml` in the directory is checked and copied
+//! to the root.
+//!
+//! pack-storage <dir> <image> [--partitions partitions.csv] [--check] [--no-patch]
+//!
+//! An IPS, UPS or BPS patch with the same name as a ROM is applied to it, the
+//! image only holds the patched ROM.
+//!
+//! `--check` mounts the written image again and compares every file and the
+//! index against the source directory, so the packer can be verified without
+//! a board.
+
+use anyhow::{anyhow, bail, ensure, Context, Result};
+use cashew_tools::{
+    cashew_gb::{
+        cheat::{self, CheatConfig},
+        palette, PaletteConfig,
+    },
+    partitions, patch,
+    storage::{self, IndexEntry},
+};
+use std::{
+    collections::BTreeMap,
+    env,
+    fs::{self, OpenOptions},
+    io::{Read, Write},
+    path::{Path, PathBuf},
+};
+
+const STORAGE_PARTITION_LABEL: &str = "storage";
+/// ESP-IDF mounts raw flash partitions with the flash sector size.
+const SECTOR_SIZE: u16 = 4096;
+const VOLUME_LABEL: [u8; 11] = *b"CASHEW     ";
+
+struct Source {
+    roms: BTreeMap<String, Vec<u8>>,
+    saves: BTreeMap<String, Vec<u8>>,
+    index: Vec<IndexEntry>,
+    /// Config files for the root, by name.
+    configs: BTreeMap<&'static str, Vec<u8>>,
+}
+
+fn main() -> Result<()> {
+    let mut paths = Vec::new();
+    let mut partitions_path = PathBuf::from("partitions.csv");
+    let mut check = false;
+    let mut apply_patches = true;
+
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--partitions" => partitions_path = args.next().context("missing path")?.into(),
+            "--check" => check = true,
+            "--no-patch" => apply_patches = false,
+            _ => paths.push(PathBuf::from(arg)),
+        }
+    }
+    let [source_dir, image_path] = &paths[..] else {
+        bail!("usage: pack-storage <dir> <image> [--partitions CSV] [--check] [--no-patch]");
+    };
+
+    let partitions = partitions::read(&partitions_path)?;
+    let partition = partitions::find(&partitions, STORAGE_PARTITION_LABEL)?;
+
+    let source = read_source(source_dir, apply_patches)?;
+    write_image(image_path, partition.size, &source)?;
+    print!("{}", storage::write_index(&source.index));
+    println!(
+        "wrote {} ({}KB), flash it at {:#x}",
+        image_path.display(),
+        partition.size / 1024,
+        partition.offset
+    );
+
+    if check {
+        check_image(image_path, &source)?;
+        println!(
+            "check passed: {} roms, {} saves",
+            source.roms.len(),
+            source.saves.len()
+        );
+    }
+    Ok(())
+}
+
+fn read_source(dir: &Path, apply_patches: bool) -> Result<Source> {
+    let mut source = Source {
+        roms: BTreeMap::new(),
+        saves: BTreeMap::new(),
+        index: Vec::new(),
+        configs: BTreeMap::new(),
+    };
+
+    for entry in fs::read_dir(dir).with_context(|| format!("reading {}", dir.display()))? {
+        let path = entry?.path();
+        let Some(name) = path.file_name().and_then(|name| name.to_str()) else {
+            continue;
+        };
+        if name == palette::PALETTE_FILE {
+            let text = fs::read_to_string(&path)?;
+            PaletteConfig::parse(&text).map_err(|err| anyhow!("{name}: {:?}", err))?;
+            source
+                .configs
+                .insert(palette::PALETTE_FILE, text.into_bytes());
+            continue;
+        }
+        if name == cheat::CHEAT_FILE {
+            let text = fs::read_to_string(&path)?;
+            CheatConfig::parse(&text).map_err(|err| anyhow!("{name}: {:?}", err))?;
+            source.configs.insert(cheat::CHEAT_FILE, text.into_bytes());
+            continue;
+        }
+        let extension = path
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .map(str::to_ascii_lowercase);
+        let (target, is_rom) = match extension.as_deref() {
+            Some("gb" | "gbc") => (&mut source.roms, true),
+            Some("sav") => (&mut source.saves, false),
+            _ => continue,
+        };
+        ensure!(
+            !name.contains(','),
+            "{name}: file names can't contain commas"
+        );
+        let mut data = fs::read(&path)?;
+        let patch_path = (is_rom && apply_patches)
+            .then(|| patch::find_patch(&path))
+            .flatten();
+        if let Some(patch_path) = patch_path {
+            let patch_data = fs::read(&patch_path)?;
+            data = patch::apply(&data, &patch_data)
+                .map_err(|err| anyhow!("applying {}: {:?}", patch_path.display(), err))?;
+            println!("patched {name} with {}", patch_path.display());
+        }
+        target.insert(name.to_string(), data);
+    }
+
+    for (name, rom) in &source.roms {
+        let stem = Path::new(name).file_stem().unwrap().to_str().unwrap();
+        let save = source
+            .saves
+            .keys()
+            .find(|save| Path::new(save).file_stem().unwrap() == stem)
+            .cloned();
+        let entry = IndexEntry::from_rom(name, rom, save).with_context(|| name.clone())?;
+        source.index.push(entry);
+    }
+    Ok(source)
+}
+
+fn write_image(path: &Path, size: u32, source: &Source) -> Result<()> {
+    let mut image = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .open(path)
+        .with_context(|| format!("creating {}", path.display()))?;
+    image.set_len(size as u64)?;
+
+    fatfs::format_volume(
+        &mut image,
+        fatfs::FormatVolumeOptions::new()
+            .bytes_per_sector(SECTOR_SIZE)
+            .volume_label(VOLUME_LABEL),
+    )?;
+    let fs = fatfs::FileSystem::new(&mut image, fatfs::FsOptions::new())?;
+    let root = fs.root_dir();
+
+    for (dir_name, files) in [
+        (storage::ROM_DIR, &source.roms),
+        (storage::SAVE_DIR, &source.saves),
+    ] {
+        let dir = root.create_dir(dir_name)?;
+        for (name, data) in files {
+            let mut file = dir.create_file(name)?;
+            file.truncate()?;
+            file.write_all(data)
+                .with_context(|| format!("{dir_name}/{name} does not fit the image"))?;
+        }
+    }
+
+    let mut index = root.create_file(storage::INDEX_FILE)?;
+    index.truncate()?;
+    index.write_all(storage::write_index(&source.index).as_bytes())?;
+
+    for (name, data) in &source.configs {
+        let mut file = root.create_file(name)?;
+        file.truncate()?;
+        file.write_all(data)?;
+    }
+    Ok(())
+}
+
+fn check_image(path: &Path, source: &Source) -> Result<()> {
+    let mut image = OpenOptions::new().read(true).write(true).open(path)?;
+    let fs = fatfs::FileSystem::new(&mut image, fatfs::FsOptions::new())?;
+    let root = fs.root_dir();
+
+    for (dir_name, files) in [
+        (storage::ROM_DIR, &source.roms),
+        (storage::SAVE_DIR, &source.saves),
+    ] {
+        let dir = root.open_dir(dir_name)?;
+        let mut found = 0;
+        for entry in dir.iter() {
+            let entry = entry?;
+            if !entry.is_file() {
+                continue;
+            }
+            let name = entry.file_name();
+            let Some(expected) = files.get(&name) else {
+                bail!("{dir_name}/{name} was not in the source directory");
+            };
+            let mut data = Vec::new();
+            entry.to_file().read_to_end(&mut data)?;
+            ensure!(
+                &data == expected,
+                "{dir_name}/{name} differs from the source"
+            );
+            found += 1;
+        }
+        ensure!(found == files.len(), "{dir_name} is missing files");
+    }
+
+    let mut csv = String::new();
+    root.open_file(storage::INDEX_FILE)?
+        .read_to_string(&mut csv)?;
+    ensure!(
+        storage::read_index(&csv)? == source.index,
+        "{} differs from the cartridge headers",
+        storage::INDEX_FILE
+    );
+
+    for (name, expected) in &source.configs {
+        let mut data = Vec::new();
+        root.open_file(name)?.read_to_end(&mut data)?;
+        ensure!(&data == expected, "{name} differs from the source");
+    }
+    Ok(())
+}
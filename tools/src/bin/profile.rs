@@ -0,0 +1,94 @@
+//! Runs a ROM for some frames counting the cycles of every instruction and
+//! prints where they went: HALT, each interrupt's handlers, the hottest
+//! addresses and, with a `.sym` or `.map`, the hottest functions.
+//!
+//! profile <rom> [--symbols FILE] [--frames N] [--top N] [--collapsed FILE]
+//!
+//! `--collapsed` also writes every call stack with its cycles, a line each,
+//! for `flamegraph.pl` or speedscope. Nothing presses the buttons, so only
+//! what runs unattended is profiled.
+
+use anyhow::{anyhow, bail, Context, Result};
+use cashew_tools::{
+    cashew_gb::{disasm::SymbolTable, profile::Profiler},
+    load_rom,
+};
+use std::{
+    env,
+    fs::{self, File},
+    io::{self, BufWriter, Write},
+    path::{Path, PathBuf},
+};
+
+const USAGE: &str =
+    "usage: profile <rom> [--symbols FILE] [--frames N] [--top N] [--collapsed FILE]";
+const DEFAULT_FRAMES: u32 = 60 * 60;
+const DEFAULT_TOP: usize = 20;
+
+fn main() -> Result<()> {
+    let mut rom_path = None;
+    let mut symbols_path = None;
+    let mut collapsed = None;
+    let mut frames = DEFAULT_FRAMES;
+    let mut top = DEFAULT_TOP;
+
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--symbols" => symbols_path = Some(PathBuf::from(args.next().context("missing path")?)),
+            "--collapsed" => collapsed = Some(PathBuf::from(args.next().context("missing path")?)),
+            "--frames" => {
+                frames = args
+                    .next()
+                    .context("missing frame count")?
+                    .parse()
+                    .context("frame count")?
+            }
+            "--top" => {
+                top = args
+                    .next()
+                    .context("missing count")?
+                    .parse()
+                    .context("count")?
+            }
+            _ if rom_path.is_none() => rom_path = Some(PathBuf::from(arg)),
+            _ => bail!(USAGE),
+        }
+    }
+    let Some(rom_path) = rom_path else {
+        bail!(USAGE);
+    };
+
+    let symbols = symbols_path.as_deref().map(load_symbols).transpose()?;
+    let rom = fs::read(&rom_path).with_context(|| format!("reading {}", rom_path.display()))?;
+    let mut gb = load_rom(&rom)?;
+    gb.gb_set_profiler(Some(Profiler::new()));
+    for _ in 0..frames {
+        gb.run_frame();
+    }
+    let profiler = gb.get_profiler().unwrap();
+
+    if let Some(path) = &collapsed {
+        let mut writer = BufWriter::new(
+            File::create(path).with_context(|| format!("creating {}", path.display()))?,
+        );
+        profiler.write_collapsed(&mut writer, symbols.as_ref())?;
+        writer.flush()?;
+    }
+    profiler.write_report(&mut io::stdout().lock(), symbols.as_ref(), top)?;
+    println!("\nprofiled {frames} frames");
+    Ok(())
+}
+
+fn load_symbols(path: &Path) -> Result<SymbolTable> {
+    let text = fs::read_to_string(path).with_context(|| format!("reading {}", path.display()))?;
+    let symbols = if path
+        .extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("map"))
+    {
+        SymbolTable::parse_map(&text)
+    } else {
+        SymbolTable::parse(&text)
+    };
+    symbols.map_err(|err| anyhow!("{}: {err:?}", path.display()))
+}
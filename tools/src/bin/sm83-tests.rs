@@ -0,0 +1,250 @@
+//! Runs the SM83 SingleStepTests vectors against the core, one instruction
+//! per case on flat memory, and compares the registers, memory, M-cycles and
+//! the reads and writes on the bus with the vector's.
+//!
+//! sm83-tests <dir or .json>... [--verbose]
+//!
+//! Every case of a file is run and the first failure of each is printed, or
+//! all of them with `--verbose`. Exits with an error if any case failed.
+//! HALT waits for the LCD or timer here, so its cases are skipped.
+
+use anyhow::{bail, Context, Result};
+use cashew_tools::{
+    cashew_gb::single_step::{BusAccess, CpuState},
+    load_rom,
+};
+use serde_json::Value;
+use std::{
+    env, fs,
+    panic::{self, AssertUnwindSafe},
+    path::{Path, PathBuf},
+};
+
+const ROM_SIZE: usize = 0x8000;
+const HEADER_CHECKSUM_START: usize = 0x134;
+const HEADER_CHECKSUM_ADDR: usize = 0x14D;
+const OPCODE_HALT: u8 = 0x76;
+
+struct Case {
+    name: String,
+    initial: CpuState,
+    initial_ram: Vec<(u16, u8)>,
+    expected: CpuState,
+    expected_ram: Vec<(u16, u8)>,
+    cycles: u64,
+    /// The M-cycles that read or write, internal ones left out.
+    bus: Vec<BusAccess>,
+}
+
+fn main() -> Result<()> {
+    let mut paths = Vec::new();
+    let mut verbose = false;
+    for arg in env::args().skip(1) {
+        match arg.as_str() {
+            "--verbose" => verbose = true,
+            _ => paths.push(PathBuf::from(arg)),
+        }
+    }
+    if paths.is_empty() {
+        bail!("usage: sm83-tests <dir or .json>... [--verbose]");
+    }
+
+    let mut files = Vec::new();
+    for path in paths {
+        if path.is_dir() {
+            let mut entries = fs::read_dir(&path)
+                .with_context(|| format!("reading {}", path.display()))?
+                .map(|entry| entry.map(|entry| entry.path()))
+                .collect::<Result<Vec<PathBuf>, _>>()?;
+            entries.retain(|entry| entry.extension().is_some_and(|ext| ext == "json"));
+            entries.sort();
+            files.extend(entries);
+        } else {
+            files.push(path);
+        }
+    }
+
+    /* The panics are reported as failures, not printed. */
+    panic::set_hook(Box::new(|_| {}));
+    let rom = blank_rom();
+    let mut gb = load_rom(&rom)?;
+    let (mut passed, mut failed, mut skipped) = (0, 0, 0);
+    for file in &files {
+        let mut cases = read_cases(file)?;
+        let total = cases.len();
+        cases.retain(|case| !case.initial_ram.contains(&(case.initial.pc, OPCODE_HALT)));
+        let file_skipped = total - cases.len();
+        let mut failures = Vec::new();
+        for case in &cases {
+            let result = panic::catch_unwind(AssertUnwindSafe(|| {
+                let (state, cycles) = gb.gb_run_cpu_test(&case.initial, &case.initial_ram);
+                let bus = gb.take_bus_accesses();
+                let memory = gb.get_flat_memory().unwrap();
+                let ram = case
+                    .expected_ram
+                    .iter()
+                    .map(|(addr, _)| (*addr, memory[*addr as usize]))
+                    .collect::<Vec<(u16, u8)>>();
+                (state, ram, cycles, bus)
+            }));
+            let panicked = result.is_err();
+            let error = match result {
+                Ok((state, ..)) if state != case.expected => {
+                    Some(format!("got {state:?}\n  expected {:?}", case.expected))
+                }
+                Ok((_, ram, ..)) if ram != case.expected_ram => Some(format!(
+                    "ram {ram:02X?}\n  expected {:02X?}",
+                    case.expected_ram
+                )),
+                Ok((_, _, cycles, _)) if cycles != case.cycles => {
+                    Some(format!("{cycles} M-cycles, expected {}", case.cycles))
+                }
+                Ok((_, _, _, bus)) if bus != case.bus => Some(format!(
+                    "bus {}\n  expected {}",
+                    format_bus(&bus),
+                    format_bus(&case.bus)
+                )),
+                Ok(_) => None,
+                Err(_) => Some("panicked".to_string()),
+            };
+            if let Some(error) = error {
+                failures.push(format!("{}: {error}", case.name));
+            }
+            if panicked {
+                /* A panic can leave the core mid-instruction. */
+                gb = load_rom(&rom)?;
+            }
+        }
+
+        let name = file.file_name().unwrap().to_string_lossy();
+        let skipped_note = if file_skipped > 0 {
+            format!(", {file_skipped} skipped")
+        } else {
+            String::new()
+        };
+        if failures.is_empty() {
+            println!("{name}: {} passed{skipped_note}", cases.len());
+        } else {
+            println!(
+                "{name}: {} of {} failed{skipped_note}",
+                failures.len(),
+                cases.len()
+            );
+            let shown = if verbose { failures.len() } else { 1 };
+            for failure in failures.iter().take(shown) {
+                println!("  {failure}");
+            }
+        }
+        passed += cases.len() - failures.len();
+        failed += failures.len();
+        skipped += file_skipped;
+    }
+
+    println!("{passed} passed, {failed} failed, {skipped} skipped");
+    if failed > 0 {
+        bail!("{failed} cases failed");
+    }
+    Ok(())
+}
+
+/// A ROM-only cartridge that passes `Gb::new`, flat memory replaces it.
+fn blank_rom() -> Vec<u8> {
+    let mut rom = vec![0; ROM_SIZE];
+    rom[HEADER_CHECKSUM_ADDR] = rom[HEADER_CHECKSUM_START..HEADER_CHECKSUM_ADDR]
+        .iter()
+        .fold(0u8, |checksum, byte| {
+            checksum.wrapping_sub(*byte).wrapping_sub(1)
+        });
+    rom
+}
+
+fn read_cases(path: &Path) -> Result<Vec<Case>> {
+    let text = fs::read_to_string(path).with_context(|| format!("reading {}", path.display()))?;
+    let json: Value =
+        serde_json::from_str(&text).with_context(|| format!("parsing {}", path.display()))?;
+    let Value::Array(cases) = json else {
+        bail!("{}: expected an array of cases", path.display());
+    };
+    cases
+        .iter()
+        .enumerate()
+        .map(|(i, case)| read_case(case).with_context(|| format!("{} case {i}", path.display())))
+        .collect()
+}
+
+fn read_case(case: &Value) -> Result<Case> {
+    let (initial, initial_ram) = read_state(&case["initial"])?;
+    let (expected, expected_ram) = read_state(&case["final"])?;
+    let cycles = case["cycles"].as_array().context("cycles")?;
+    Ok(Case {
+        name: case["name"].as_str().unwrap_or("?").to_string(),
+        initial,
+        initial_ram,
+        expected,
+        expected_ram,
+        cycles: cycles.len() as u64,
+        bus: cycles
+            .iter()
+            .filter_map(|cycle| read_cycle(cycle).transpose())
+            .collect::<Result<Vec<BusAccess>>>()?,
+    })
+}
+
+/// An `[address, value, pins]` M-cycle, None for the internal ones, which
+/// are null or have neither the read nor the write pin set.
+fn read_cycle(cycle: &Value) -> Result<Option<BusAccess>> {
+    if cycle.is_null() {
+        return Ok(None);
+    }
+    let pins = cycle[2].as_str().context("cycle pins")?.as_bytes();
+    let write = match pins {
+        [b'r', ..] => false,
+        [_, b'w', ..] => true,
+        _ => return Ok(None),
+    };
+    Ok(Some(BusAccess {
+        addr: cycle[0].as_u64().context("cycle address")? as u16,
+        val: cycle[1].as_u64().context("cycle value")? as u8,
+        write,
+    }))
+}
+
+fn format_bus(bus: &[BusAccess]) -> String {
+    let accesses: Vec<String> = bus
+        .iter()
+        .map(|access| {
+            let kind = if access.write { 'w' } else { 'r' };
+            format!("{kind} {:04X}:{:02X}", access.addr, access.val)
+        })
+        .collect();
+    format!("[{}]", accesses.join(", "))
+}
+
+fn read_state(state: &Value) -> Result<(CpuState, Vec<(u16, u8)>)> {
+    let number = |key: &str| state[key].as_u64().with_context(|| key.to_string());
+    let byte = |key: &str| number(key).map(|value| value as u8);
+    let cpu = CpuState {
+        a: byte("a")?,
+        f: byte("f")?,
+        b: byte("b")?,
+        c: byte("c")?,
+        d: byte("d")?,
+        e: byte("e")?,
+        h: byte("h")?,
+        l: byte("l")?,
+        sp: number("sp")? as u16,
+        pc: number("pc")? as u16,
+        ime: number("ime")? != 0,
+    };
+    let ram = state["ram"]
+        .as_array()
+        .context("ram")?
+        .iter()
+        .map(|entry| {
+            let addr = entry[0].as_u64().context("ram address")?;
+            let val = entry[1].as_u64().context("ram value")?;
+            Ok((addr as u16, val as u8))
+        })
+        .collect::<Result<Vec<(u16, u8)>>>()?;
+    Ok((cpu, ram))
+}
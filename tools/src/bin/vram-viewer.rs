@@ -0,0 +1,101 @@
+//! Runs a ROM for some frames and exports what its VRAM holds as PNGs: the
+//! tile sheets of both banks, the two background maps with the viewport
+//! outlined, the 40 OAM sprites and the palettes. The sprite table is printed.
+//! Super Game Boy games run in SGB mode, as on the board, and the border they
+//! send is written too.
+//!
+//! vram-viewer <rom> <dir> [--frames N]
+//!
+//! The second tile bank is only written when the core is built with `gbc`.
+
+use anyhow::{bail, Context, Result};
+use cashew_tools::{
+    cashew_gb::{
+        sgb::{SGB_HEIGHT, SGB_WIDTH},
+        viewer::{Image, TileMap},
+    },
+    load_rom,
+};
+use std::{
+    env,
+    fs::{self, File},
+    io::BufWriter,
+    path::{Path, PathBuf},
+};
+
+const DEFAULT_FRAMES: u32 = 60;
+
+fn main() -> Result<()> {
+    let mut paths = Vec::new();
+    let mut frames = DEFAULT_FRAMES;
+
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--frames" => {
+                frames = args
+                    .next()
+                    .context("missing frame count")?
+                    .parse()
+                    .context("frame count")?
+            }
+            _ => paths.push(PathBuf::from(arg)),
+        }
+    }
+    let [rom_path, dir] = &paths[..] else {
+        bail!("usage: vram-viewer <rom> <dir> [--frames N]");
+    };
+
+    let rom = fs::read(rom_path).with_context(|| format!("reading {}", rom_path.display()))?;
+    let mut gb = load_rom(&rom)?;
+    let header = gb.get_header();
+    if header.is_sgb_supported() && !header.is_cgb_supported() {
+        gb.gb_set_sgb(true);
+        gb.gb_reset();
+    }
+    for _ in 0..frames {
+        gb.run_frame();
+    }
+
+    fs::create_dir_all(dir).with_context(|| format!("creating {}", dir.display()))?;
+    for bank in 0..2 {
+        if let Some(sheet) = gb.get_tile_sheet(bank) {
+            write_png(&dir.join(format!("tiles{bank}.png")), &sheet)?;
+        }
+    }
+    write_png(&dir.join("map9800.png"), &gb.get_tile_map(TileMap::Map9800))?;
+    write_png(&dir.join("map9C00.png"), &gb.get_tile_map(TileMap::Map9C00))?;
+    write_png(&dir.join("oam.png"), &gb.get_oam_table())?;
+    write_png(&dir.join("palettes.png"), &gb.get_palette_swatches())?;
+    if let Some(sgb) = gb.get_sgb() {
+        let mut border = Image {
+            width: SGB_WIDTH,
+            height: SGB_HEIGHT,
+            pixels: vec![0; SGB_WIDTH * SGB_HEIGHT],
+        };
+        if sgb.render_border(&mut border.pixels) {
+            write_png(&dir.join("border.png"), &border)?;
+        }
+    }
+
+    for sprite in gb.get_sprites() {
+        println!("{sprite}");
+    }
+    println!("wrote {} after {frames} frames", dir.display());
+    Ok(())
+}
+
+fn write_png(path: &Path, image: &Image) -> Result<()> {
+    let file = File::create(path).with_context(|| format!("creating {}", path.display()))?;
+    let mut encoder = png::Encoder::new(
+        BufWriter::new(file),
+        image.width as u32,
+        image.height as u32,
+    );
+    encoder.set_color(png::ColorType::Rgb);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder
+        .write_header()?
+        .write_image_data(&image.to_rgb888())
+        .with_context(|| format!("writing {}", path.display()))
+}
@@ -0,0 +1,59 @@
+// The lints the baseline core already trips, anything else in it is linted.
+#[path = "../../src"]
+#[allow(
+    dead_code,
+    mismatched_lifetime_syntaxes,
+    unknown_lints,
+    unused_assignments,
+    unused_imports,
+    clippy::assign_op_pattern,
+    clippy::bad_bit_mask,
+    clippy::collapsible_if,
+    clippy::erasing_op,
+    clippy::identity_op,
+    clippy::implicit_saturating_sub,
+    clippy::large_enum_variant,
+    clippy::manual_range_contains,
+    clippy::manual_range_patterns,
+    clippy::manual_rotate,
+    clippy::mistyped_literal_suffixes,
+    clippy::needless_borrow,
+    clippy::needless_return,
+    clippy::never_loop,
+    clippy::new_ret_no_self,
+    clippy::nonminimal_bool,
+    clippy::redundant_closure,
+    clippy::type_complexity,
+    clippy::unnecessary_cast,
+    clippy::unnecessary_unwrap,
+    clippy::unused_unit
+)]
+mod firmware {
+    pub mod cashew_gb;
+}
+
+pub mod partitions;
+pub mod patch;
+pub mod storage;
+
+pub use firmware::cashew_gb;
+
+use anyhow::{bail, Result};
+use cashew_gb::{Gb, GbInitError};
+
+/// Runs the ROM through `Gb::new`, the same checks the firmware does at boot.
+pub fn load_rom(rom: &Vec<u8>) -> Result<Gb<'_, Vec<u8>>> {
+    fn rom_read(gb: &Gb<Vec<u8>>, addr: usize) -> u8 {
+        gb.get_context().get(addr).copied().unwrap_or(0xFF)
+    }
+    fn ram_read(_gb: &Gb<Vec<u8>>, _addr: usize) -> u8 {
+        0xFF
+    }
+    fn ram_write(_gb: &Gb<Vec<u8>>, _addr: usize, _val: u8) {}
+
+    match Gb::new(rom, rom_read, ram_read, ram_write, None) {
+        GbInitError::GbInitNoError(gb) => Ok(gb),
+        GbInitError::GbInitCartridgeUnsupported => bail!("cartridge type is not supported"),
+        GbInitError::GbInitInvalidChecksum => bail!("header checksum does not match"),
+    }
+}
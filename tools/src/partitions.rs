@@ -0,0 +1,83 @@
+use anyhow::{anyhow, bail, Context, Result};
+use std::{fs, path::Path};
+
+const PARTITION_TABLE_OFFSET: u32 = 0x8000;
+const PARTITION_TABLE_SIZE: u32 = 0x1000;
+const APP_ALIGNMENT: u32 = 0x10000;
+const DATA_ALIGNMENT: u32 = 0x1000;
+
+pub struct Partition {
+    pub name: String,
+    pub kind: String,
+    pub subtype: String,
+    pub offset: u32,
+    pub size: u32,
+}
+
+/// Reads an ESP-IDF partition table CSV, filling in the offsets left empty
+/// the same way the IDF partition tool does.
+pub fn read(path: &Path) -> Result<Vec<Partition>> {
+    let csv = fs::read_to_string(path).with_context(|| format!("reading {}", path.display()))?;
+    parse(&csv)
+}
+
+pub fn parse(csv: &str) -> Result<Vec<Partition>> {
+    let mut partitions: Vec<Partition> = Vec::new();
+    let mut next_offset = PARTITION_TABLE_OFFSET + PARTITION_TABLE_SIZE;
+
+    for (n, line) in csv.lines().enumerate() {
+        let line = line.split('#').next().unwrap().trim();
+        if line.is_empty() {
+            continue;
+        }
+        let fields: Vec<&str> = line.split(',').map(str::trim).collect();
+        if fields.len() < 5 {
+            bail!("line {}: expected name, type, subtype, offset, size", n + 1);
+        }
+
+        let kind = fields[1].to_string();
+        let alignment = if kind == "app" {
+            APP_ALIGNMENT
+        } else {
+            DATA_ALIGNMENT
+        };
+        let offset = if fields[3].is_empty() {
+            (next_offset + alignment - 1) & !(alignment - 1)
+        } else {
+            parse_size(fields[3]).with_context(|| format!("line {}: offset", n + 1))?
+        };
+        let size = parse_size(fields[4]).with_context(|| format!("line {}: size", n + 1))?;
+        next_offset = offset + size;
+
+        partitions.push(Partition {
+            name: fields[0].to_string(),
+            kind,
+            subtype: fields[2].to_string(),
+            offset,
+            size,
+        });
+    }
+
+    Ok(partitions)
+}
+
+pub fn find<'p>(partitions: &'p [Partition], name: &str) -> Result<&'p Partition> {
+    partitions
+        .iter()
+        .find(|partition| partition.name == name)
+        .ok_or_else(|| anyhow!("no partition named {name}"))
+}
+
+fn parse_size(value: &str) -> Result<u32> {
+    let (digits, multiplier) = match value.chars().last() {
+        Some('K' | 'k') => (&value[..value.len() - 1], 1024),
+        Some('M' | 'm') => (&value[..value.len() - 1], 1024 * 1024),
+        _ => (value, 1),
+    };
+    let number = match digits.strip_prefix("0x").or(digits.strip_prefix("0X")) {
+        Some(hex) => u32::from_str_radix(hex, 16),
+        None => digits.parse(),
+    }
+    .with_context(|| format!("invalid size {value}"))?;
+    Ok(number * multiplier)
+}
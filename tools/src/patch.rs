@@ -0,0 +1,361 @@
+use std::path::{Path, PathBuf};
+
+use crate::cashew_gb::{crc32, CartridgeHeader};
+
+pub const PATCH_EXTENSIONS: [&str; 3] = ["ips", "ups", "bps"];
+
+const IPS_MAGIC: &[u8] = b"PATCH";
+const IPS_EOF: usize = 0x454F46;
+const UPS_MAGIC: &[u8] = b"UPS1";
+const BPS_MAGIC: &[u8] = b"BPS1";
+const FOOTER_SIZE: usize = 12;
+/// The largest ROM a cartridge header can describe.
+const TARGET_MAX_SIZE: usize = 8 * 1024 * 1024;
+
+const HEADER_CHECKSUM_ADDR: usize = 0x014D;
+
+const BPS_SOURCE_READ: usize = 0;
+const BPS_TARGET_READ: usize = 1;
+const BPS_SOURCE_COPY: usize = 2;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PatchFormat {
+    Ips,
+    Ups,
+    Bps,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum GbPatchError {
+    GbPatchUnknownFormat,
+    GbPatchTruncated,
+    GbPatchOutOfBounds,
+    GbPatchSourceSize {
+        expected: usize,
+        actual: usize,
+    },
+    GbPatchSourceChecksum {
+        expected: u32,
+        actual: u32,
+    },
+    GbPatchTargetChecksum {
+        expected: u32,
+        actual: u32,
+    },
+    GbPatchPatchChecksum {
+        expected: u32,
+        actual: u32,
+    },
+    /// The ROM was already failing the header check before patching.
+    GbPatchSourceHeaderChecksum {
+        stored: u8,
+        computed: u8,
+    },
+    /// The patch changed header bytes without updating the checksum.
+    GbPatchHeaderChecksum {
+        stored: u8,
+        computed: u8,
+    },
+}
+
+pub fn detect(patch: &[u8]) -> Option<PatchFormat> {
+    if patch.starts_with(IPS_MAGIC) {
+        Some(PatchFormat::Ips)
+    } else if patch.starts_with(UPS_MAGIC) {
+        Some(PatchFormat::Ups)
+    } else if patch.starts_with(BPS_MAGIC) {
+        Some(PatchFormat::Bps)
+    } else {
+        None
+    }
+}
+
+/// Looks for `<rom name>.ips`, `.ups` or `.bps` next to the ROM.
+pub fn find_patch(rom_path: &Path) -> Option<PathBuf> {
+    PATCH_EXTENSIONS
+        .iter()
+        .flat_map(|extension| [extension.to_string(), extension.to_ascii_uppercase()])
+        .map(|extension| rom_path.with_extension(extension))
+        .find(|path| path.is_file())
+}
+
+/// Patches `rom` and checks the result would still pass `Gb::new`.
+pub fn apply(rom: &[u8], patch: &[u8]) -> Result<Vec<u8>, GbPatchError> {
+    let patched = apply_unchecked(rom, patch)?;
+
+    let header = CartridgeHeader::new(&patched).ok_or(GbPatchError::GbPatchTruncated)?;
+    if !header.is_header_checksum_valid() {
+        let source = CartridgeHeader::new(rom).ok_or(GbPatchError::GbPatchTruncated)?;
+        if !source.is_header_checksum_valid() {
+            return Err(GbPatchError::GbPatchSourceHeaderChecksum {
+                stored: source.get_header_checksum(),
+                computed: source.compute_header_checksum(),
+            });
+        }
+        return Err(GbPatchError::GbPatchHeaderChecksum {
+            stored: header.get_header_checksum(),
+            computed: header.compute_header_checksum(),
+        });
+    }
+    Ok(patched)
+}
+
+/// Patches `rom` without looking at the resulting header.
+pub fn apply_unchecked(rom: &[u8], patch: &[u8]) -> Result<Vec<u8>, GbPatchError> {
+    match detect(patch) {
+        Some(PatchFormat::Ips) => apply_ips(rom, patch),
+        Some(PatchFormat::Ups) => apply_ups(rom, patch),
+        Some(PatchFormat::Bps) => apply_bps(rom, patch),
+        None => Err(GbPatchError::GbPatchUnknownFormat),
+    }
+}
+
+/// Rewrites the header checksum, for hacks that forgot to.
+pub fn fix_header_checksum(rom: &mut [u8]) -> Option<()> {
+    rom[HEADER_CHECKSUM_ADDR] = CartridgeHeader::new(rom)?.compute_header_checksum();
+    Some(())
+}
+
+fn apply_ips(rom: &[u8], patch: &[u8]) -> Result<Vec<u8>, GbPatchError> {
+    let mut target = rom.to_vec();
+    let mut reader = PatchReader::new(patch, IPS_MAGIC.len());
+
+    loop {
+        let offset = reader.read_be(3)?;
+        if offset == IPS_EOF {
+            break;
+        }
+        let size = reader.read_be(2)?;
+        let (size, fill) = if size == 0 {
+            (reader.read_be(2)?, Some(reader.read()?))
+        } else {
+            (size, None)
+        };
+
+        if target.len() < offset + size {
+            target.resize(offset + size, 0);
+        }
+        match fill {
+            Some(fill) => target[offset..offset + size].fill(fill),
+            None => target[offset..offset + size].copy_from_slice(reader.read_slice(size)?),
+        }
+    }
+
+    /* Optional truncation extension after the EOF marker. */
+    if reader.remaining() == 3 {
+        target.truncate(reader.read_be(3)?);
+    }
+    Ok(target)
+}
+
+fn apply_ups(rom: &[u8], patch: &[u8]) -> Result<Vec<u8>, GbPatchError> {
+    let footer = Footer::read(patch)?;
+    let mut reader = PatchReader::new(&patch[..patch.len() - FOOTER_SIZE], UPS_MAGIC.len());
+
+    let source_size = reader.read_varint()?;
+    let target_size = reader.read_target_size()?;
+    footer.check_source(rom, source_size)?;
+
+    let mut target = rom.to_vec();
+    target.resize(target_size, 0);
+    let mut offset: usize = 0;
+    while reader.remaining() > 0 {
+        offset = offset
+            .checked_add(reader.read_varint()?)
+            .ok_or(GbPatchError::GbPatchOutOfBounds)?;
+        loop {
+            let xor = reader.read()?;
+            if offset < target_size {
+                target[offset] ^= xor;
+            }
+            offset = offset.saturating_add(1);
+            if xor == 0 {
+                break;
+            }
+        }
+    }
+
+    footer.check_target(&target)?;
+    Ok(target)
+}
+
+fn apply_bps(rom: &[u8], patch: &[u8]) -> Result<Vec<u8>, GbPatchError> {
+    let footer = Footer::read(patch)?;
+    let mut reader = PatchReader::new(&patch[..patch.len() - FOOTER_SIZE], BPS_MAGIC.len());
+
+    let source_size = reader.read_varint()?;
+    let target_size = reader.read_target_size()?;
+    let metadata_size = reader.read_varint()?;
+    reader.read_slice(metadata_size)?;
+    footer.check_source(rom, source_size)?;
+
+    let mut target = Vec::with_capacity(target_size);
+    let mut source_offset: usize = 0;
+    let mut target_offset: usize = 0;
+    while reader.remaining() > 0 {
+        let action = reader.read_varint()?;
+        let length = (action >> 2) + 1;
+        if length > target_size - target.len() {
+            return Err(GbPatchError::GbPatchOutOfBounds);
+        }
+        match action & 3 {
+            BPS_SOURCE_READ => {
+                let start = target.len();
+                target.extend_from_slice(
+                    rom.get(start..start + length)
+                        .ok_or(GbPatchError::GbPatchOutOfBounds)?,
+                );
+            }
+            BPS_TARGET_READ => {
+                target.extend_from_slice(reader.read_slice(length)?);
+            }
+            command => {
+                let relative = reader.read_varint()?;
+                let offset = if command == BPS_SOURCE_COPY {
+                    &mut source_offset
+                } else {
+                    &mut target_offset
+                };
+                *offset = if relative & 1 != 0 {
+                    offset.checked_sub(relative >> 1)
+                } else {
+                    offset.checked_add(relative >> 1)
+                }
+                .ok_or(GbPatchError::GbPatchOutOfBounds)?;
+
+                for _ in 0..length {
+                    /* Target copies may overlap the bytes they produce. */
+                    let byte = if command == BPS_SOURCE_COPY {
+                        rom.get(*offset)
+                    } else {
+                        target.get(*offset)
+                    };
+                    target.push(*byte.ok_or(GbPatchError::GbPatchOutOfBounds)?);
+                    *offset += 1;
+                }
+            }
+        }
+    }
+
+    if target.len() != target_size {
+        return Err(GbPatchError::GbPatchOutOfBounds);
+    }
+    footer.check_target(&target)?;
+    Ok(target)
+}
+
+struct Footer {
+    source_crc: u32,
+    target_crc: u32,
+}
+impl Footer {
+    fn read(patch: &[u8]) -> Result<Footer, GbPatchError> {
+        if patch.len() < 4 + FOOTER_SIZE {
+            return Err(GbPatchError::GbPatchTruncated);
+        }
+        let footer = &patch[patch.len() - FOOTER_SIZE..];
+        let word = |n: usize| u32::from_le_bytes(footer[n * 4..n * 4 + 4].try_into().unwrap());
+
+        let patch_crc = crc32(&patch[..patch.len() - 4]);
+        if patch_crc != word(2) {
+            return Err(GbPatchError::GbPatchPatchChecksum {
+                expected: word(2),
+                actual: patch_crc,
+            });
+        }
+        Ok(Footer {
+            source_crc: word(0),
+            target_crc: word(1),
+        })
+    }
+    fn check_source(&self, rom: &[u8], size: usize) -> Result<(), GbPatchError> {
+        if rom.len() != size {
+            return Err(GbPatchError::GbPatchSourceSize {
+                expected: size,
+                actual: rom.len(),
+            });
+        }
+        let crc = crc32(rom);
+        if crc != self.source_crc {
+            return Err(GbPatchError::GbPatchSourceChecksum {
+                expected: self.source_crc,
+                actual: crc,
+            });
+        }
+        Ok(())
+    }
+    fn check_target(&self, target: &[u8]) -> Result<(), GbPatchError> {
+        let crc = crc32(target);
+        if crc != self.target_crc {
+            return Err(GbPatchError::GbPatchTargetChecksum {
+                expected: self.target_crc,
+                actual: crc,
+            });
+        }
+        Ok(())
+    }
+}
+
+struct PatchReader<'p> {
+    patch: &'p [u8],
+    pos: usize,
+}
+impl<'p> PatchReader<'p> {
+    fn new(patch: &'p [u8], pos: usize) -> PatchReader<'p> {
+        PatchReader { patch, pos }
+    }
+    fn remaining(&self) -> usize {
+        self.patch.len().saturating_sub(self.pos)
+    }
+    fn read(&mut self) -> Result<u8, GbPatchError> {
+        let byte = *self
+            .patch
+            .get(self.pos)
+            .ok_or(GbPatchError::GbPatchTruncated)?;
+        self.pos += 1;
+        Ok(byte)
+    }
+    fn read_slice(&mut self, size: usize) -> Result<&'p [u8], GbPatchError> {
+        let slice = self
+            .patch
+            .get(self.pos..self.pos + size)
+            .ok_or(GbPatchError::GbPatchTruncated)?;
+        self.pos += size;
+        Ok(slice)
+    }
+    fn read_be(&mut self, size: usize) -> Result<usize, GbPatchError> {
+        let mut value = 0;
+        for _ in 0..size {
+            value = (value << 8) | self.read()? as usize;
+        }
+        Ok(value)
+    }
+    /// A UPS or BPS target size, refused past what a ROM can be.
+    fn read_target_size(&mut self) -> Result<usize, GbPatchError> {
+        match self.read_varint()? {
+            size if size > TARGET_MAX_SIZE => Err(GbPatchError::GbPatchOutOfBounds),
+            size => Ok(size),
+        }
+    }
+    /// UPS and BPS numbers, 7 bits per byte with the overlap removed.
+    fn read_varint(&mut self) -> Result<usize, GbPatchError> {
+        let mut value: usize = 0;
+        let mut shift: usize = 1;
+        loop {
+            let byte = self.read()?;
+            value = ((byte & 0x7F) as usize)
+                .checked_mul(shift)
+                .and_then(|bits| value.checked_add(bits))
+                .ok_or(GbPatchError::GbPatchOutOfBounds)?;
+            if byte & 0x80 != 0 {
+                return Ok(value);
+            }
+            shift = shift
+                .checked_mul(0x80)
+                .ok_or(GbPatchError::GbPatchOutOfBounds)?;
+            value = value
+                .checked_add(shift)
+                .ok_or(GbPatchError::GbPatchOutOfBounds)?;
+        }
+    }
+}
@@ -0,0 +1,80 @@
+use anyhow::{bail, Context, Result};
+
+use crate::load_rom;
+
+pub const ROM_DIR: &str = "roms";
+pub const SAVE_DIR: &str = "saves";
+pub const INDEX_FILE: &str = "index.csv";
+
+const INDEX_HEADER: &str = "file,title,cgb,mbc,ram_size,save";
+
+/// One line of `index.csv`, the header metadata the launcher lists games by.
+#[derive(Clone, Debug, PartialEq)]
+pub struct IndexEntry {
+    pub file: String,
+    pub title: String,
+    pub cgb: u8,
+    pub mbc: i8,
+    pub ram_size: usize,
+    pub save: Option<String>,
+}
+
+impl IndexEntry {
+    pub fn from_rom(file: &str, rom: &Vec<u8>, save: Option<String>) -> Result<IndexEntry> {
+        let gb = load_rom(rom)?;
+        let header = gb.get_header();
+        Ok(IndexEntry {
+            file: file.to_string(),
+            title: header.get_title().replace(',', " "),
+            cgb: header.get_cgb_flag(),
+            mbc: header.get_mbc().unwrap(),
+            ram_size: header.get_save_size(),
+            save,
+        })
+    }
+}
+
+pub fn write_index(entries: &[IndexEntry]) -> String {
+    let mut csv = String::from(INDEX_HEADER);
+    csv.push('\n');
+    for entry in entries {
+        csv += &format!(
+            "{},{},{:#04x},{},{:#x},{}\n",
+            entry.file,
+            entry.title,
+            entry.cgb,
+            entry.mbc,
+            entry.ram_size,
+            entry.save.as_deref().unwrap_or("")
+        );
+    }
+    csv
+}
+
+pub fn read_index(csv: &str) -> Result<Vec<IndexEntry>> {
+    let mut lines = csv.lines().enumerate();
+    if lines.next().map(|(_, header)| header) != Some(INDEX_HEADER) {
+        bail!("missing index header");
+    }
+
+    let mut entries = Vec::new();
+    for (n, line) in lines {
+        let fields: Vec<&str> = line.split(',').collect();
+        if fields.len() != 6 {
+            bail!("line {}: expected 6 fields", n + 1);
+        }
+        entries.push(IndexEntry {
+            file: fields[0].to_string(),
+            title: fields[1].to_string(),
+            cgb: u8::from_str_radix(fields[2].trim_start_matches("0x"), 16)
+                .with_context(|| format!("line {}: cgb", n + 1))?,
+            mbc: fields[3]
+                .parse()
+                .with_context(|| format!("line {}: mbc", n + 1))?,
+            ram_size: usize::from_str_radix(fields[4].trim_start_matches("0x"), 16)
+                .with_context(|| format!("line {}: ram_size", n + 1))?,
+            save: Some(fields[5].to_string()).filter(|save| !save.is_empty()),
+        });
+    }
+    Ok(entries)
+}
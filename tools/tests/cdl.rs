@@ -0,0 +1,107 @@
+mod common;
+
+use cashew_tools::cashew_gb::{
+    cdl::{Coverage, CDL_DATA, CDL_DMA, CDL_OPCODE, CDL_OPERAND},
+    crc32,
+    debug::RunUntil,
+};
+use common::Cart;
+
+const PROGRAM: [u8; 30] = [
+    0x3E, 0x0A, /* ld a, 0x0A */
+    0xEA, 0x00, 0x00, /* ld (0x0000), a */
+    0xFA, 0x10, 0xA0, /* ld a, (0xA010) */
+    0x3E, 0x02, /* ld a, 2 */
+    0xEA, 0x00, 0x20, /* ld (0x2000), a */
+    0xCD, 0x00, 0x40, /* call 0x4000 */
+    0x3E, 0x03, /* ld a, 3 */
+    0xEA, 0x00, 0x20, /* ld (0x2000), a */
+    0xCD, 0x00, 0x40, /* call 0x4000 */
+    0x3E, 0x40, /* ld a, 0x40 */
+    0xE0, 0x46, /* ldh (DMA), a */
+    0x18, 0xFE, /* 0x16C: jr -2 */
+];
+const BANKED: [u8; 4] = [
+    0xFA, 0x20, 0x40, /* ld a, (0x4020) */
+    0xC9, /* ret */
+];
+
+#[test]
+fn flags_by_bank_offset() {
+    let cart = Cart::banked(&PROGRAM, 4, &BANKED);
+    let mut gb = cart.gb();
+    let log = gb.gb_new_code_data_log();
+    assert_eq!(log.get_rom().len(), 0x10000);
+    gb.gb_set_code_data_log(Some(log));
+    gb.gb_debug_run(RunUntil::Pc(0x016C));
+    gb.gb_debug_run(RunUntil::Frame);
+    let log = gb.get_code_data_log().unwrap();
+
+    assert_eq!(log.get_rom_flags(0, 0x0150), CDL_OPCODE);
+    assert_eq!(log.get_rom_flags(0, 0x0151), CDL_OPERAND);
+    /* Bank 2 at 0x4000 is 0x8000 in the ROM. */
+    let rom = log.get_rom();
+    assert_eq!(rom[0x8000], CDL_OPCODE);
+    assert_eq!(rom[0x8001], CDL_OPERAND);
+    assert_eq!(rom[0x8003], CDL_OPCODE);
+    assert_eq!(rom[0x8020], CDL_DATA);
+    assert_eq!(log.get_rom_flags(2, 0x4020), CDL_DATA);
+    /* Bank 3 also had its first 0xA0 bytes copied to OAM. */
+    assert_eq!(log.get_rom_flags(3, 0x4000), CDL_OPCODE | CDL_DMA);
+    assert_eq!(log.get_rom_flags(3, 0x4020), CDL_DATA | CDL_DMA);
+    assert_eq!(log.get_rom_flags(3, 0x409F), CDL_DMA);
+    assert_eq!(log.get_rom_flags(3, 0x40A0), 0);
+    assert_eq!(
+        log.get_rom_coverage(1),
+        Some(Coverage {
+            unused: 0x4000,
+            ..Coverage::default()
+        })
+    );
+    assert_eq!(log.get_rom_coverage(3).unwrap().dma, 0xA0);
+    assert_eq!(log.get_rom_coverage(4), None);
+    assert_eq!(log.get_rom_flags(4, 0x4000), 0);
+
+    assert_eq!(log.get_cart_ram()[0x10], CDL_DATA);
+    assert_eq!(log.get_cart_ram_coverage().data, 1);
+
+    /* Peeks aren't reads. */
+    gb.gb_peek(0x4030);
+    let log = gb.get_code_data_log().unwrap();
+    assert_eq!(log.get_rom_flags(3, 0x4030), CDL_DMA);
+    log.clear();
+    assert_eq!(log.get_rom_coverage(0).unwrap().unused, 0x4000);
+}
+
+#[test]
+fn mesen_file() {
+    let cart = Cart::banked(&PROGRAM, 4, &BANKED);
+    let mut gb = cart.gb();
+    gb.gb_set_code_data_log(Some(gb.gb_new_code_data_log()));
+    gb.gb_debug_run(RunUntil::Pc(0x016C));
+    gb.gb_debug_run(RunUntil::Frame);
+    let mesen = gb.get_code_data_log().unwrap().to_mesen(&cart.rom);
+
+    assert_eq!(&mesen[..5], b"CDLv2");
+    assert_eq!(mesen[5..9], crc32(&cart.rom).to_le_bytes());
+    let flags = &mesen[9..];
+    assert_eq!(flags.len(), cart.rom.len());
+    /* Code 1, data 2, both 3. */
+    assert_eq!(flags[0x0150], 1);
+    assert_eq!(flags[0x0151], 1);
+    assert_eq!(flags[0x8020], 2);
+    assert_eq!(flags[0xC000], 3);
+    assert_eq!(flags[0xC09F], 2);
+    assert_eq!(flags[0x4000], 0);
+}
+
+#[test]
+fn stop_logging() {
+    let cart = Cart::banked(&PROGRAM, 4, &BANKED);
+    let mut gb = cart.gb();
+    gb.gb_set_code_data_log(Some(gb.gb_new_code_data_log()));
+    let log = gb.gb_set_code_data_log(None).unwrap();
+    gb.gb_debug_run(RunUntil::Pc(0x016C));
+    assert!(gb.get_code_data_log().is_none());
+    assert_eq!(log.get_rom_coverage(0).unwrap().unused, 0x4000);
+}
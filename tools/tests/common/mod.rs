@@ -0,0 +1,100 @@
+//! A cartridge running a program of the test's, with the ports tests plug
+//! things into.
+#![allow(dead_code)]
+
+use cashew_tools::cashew_gb::{
+    ir::{GbIrContext, IrPort},
+    link::{GbLinkContext, LinkPort},
+    CartridgeHeader, Gb, GbInitError,
+};
+use std::cell::RefCell;
+
+const PROGRAM_ADDR: usize = 0x0150;
+const CART_RAM_SIZE: usize = 0x2000;
+const ROM_BANK_SIZE: usize = 0x4000;
+/// Stands for the bank number in `Cart::banked` code.
+pub const BANK: u8 = 0xBB;
+
+pub struct Cart {
+    pub rom: Vec<u8>,
+    pub ram: RefCell<Vec<u8>>,
+    pub link: LinkPort,
+    pub ir: IrPort,
+}
+impl GbLinkContext for Cart {
+    fn get_link_port(&self) -> &LinkPort {
+        &self.link
+    }
+}
+impl GbIrContext for Cart {
+    fn get_ir_port(&self) -> &IrPort {
+        &self.ir
+    }
+}
+
+impl Cart {
+    /// An MBC1 cartridge with 8KB of RAM, starting `program` at 0x150.
+    pub fn new(program: &[u8], cgb: bool) -> Cart {
+        let mut rom = vec![0; 0x8000];
+        /* nop, jp 0x150 */
+        rom[0x100..0x104].copy_from_slice(&[0x00, 0xC3, 0x50, 0x01]);
+        rom[0x134..0x138].copy_from_slice(b"TEST");
+        if cgb {
+            rom[0x143] = 0x80;
+        }
+        rom[0x147] = 0x03;
+        rom[0x149] = 0x02;
+        rom[0x14D] = CartridgeHeader::new(&rom)
+            .unwrap()
+            .compute_header_checksum();
+        rom[PROGRAM_ADDR..PROGRAM_ADDR + program.len()].copy_from_slice(program);
+        Cart {
+            rom,
+            ram: RefCell::new(vec![0; CART_RAM_SIZE]),
+            link: LinkPort::new(),
+            ir: IrPort::new(),
+        }
+    }
+
+    /// Like `new`, with `banks` 16KB ROM banks each holding `banked` at
+    /// 0x4000 with the bank's number in place of any `BANK` byte.
+    pub fn banked(program: &[u8], banks: usize, banked: &[u8]) -> Cart {
+        let mut cart = Cart::new(program, false);
+        cart.rom.resize(banks * ROM_BANK_SIZE, 0);
+        cart.rom[0x148] = (banks / 2).trailing_zeros() as u8;
+        cart.rom[0x14D] = CartridgeHeader::new(&cart.rom)
+            .unwrap()
+            .compute_header_checksum();
+        for bank in 1..banks {
+            let code = banked
+                .iter()
+                .map(|&byte| if byte == BANK { bank as u8 } else { byte });
+            cart.rom.splice(
+                bank * ROM_BANK_SIZE..bank * ROM_BANK_SIZE + banked.len(),
+                code,
+            );
+        }
+        cart
+    }
+
+    pub fn gb(&self) -> Gb<'_, Cart> {
+        fn rom_read(gb: &Gb<Cart>, addr: usize) -> u8 {
+            gb.get_context().rom[addr]
+        }
+        fn ram_read(gb: &Gb<Cart>, addr: usize) -> u8 {
+            gb.get_context().ram.borrow()[addr]
+        }
+        fn ram_write(gb: &Gb<Cart>, addr: usize, val: u8) {
+            gb.get_context().ram.borrow_mut()[addr] = val;
+        }
+        match Gb::new(self, rom_read, ram_read, ram_write, None) {
+            GbInitError::GbInitNoError(gb) => gb,
+            _ => panic!("the test cartridge was refused"),
+        }
+    }
+
+    /// Byte `addr` of cart RAM, where the programs leave their results.
+    pub fn ram(&self, addr: usize) -> u8 {
+        self.ram.borrow()[addr]
+    }
+}
@@ -0,0 +1,229 @@
+use cashew_tools::{
+    cashew_gb::dap::{read_message, write_message, DapServer, Json},
+    load_rom,
+};
+use std::{
+    env, fs,
+    io::{self, BufReader},
+    os::unix::net::UnixStream,
+    path::PathBuf,
+    thread,
+    time::{Duration, Instant},
+};
+
+const SOURCE: &str = "\
+SECTION \"main\", ROM0[$150]
+Main:
+    call Sub
+    jr Main
+
+Sub:
+    ; stores A
+    ld a, $42
+    ld [$C000], a
+    ret
+";
+const SYMBOLS: &str = "00:0150 Main\n00:0160 Sub\n";
+
+/// The program `SOURCE` assembles to, in a ROM the core takes.
+fn rom() -> Vec<u8> {
+    let mut rom = vec![0; 0x8000];
+    /* nop, jp 0x150 */
+    rom[0x100..0x104].copy_from_slice(&[0x00, 0xC3, 0x50, 0x01]);
+    rom[0x14D] = rom[0x134..0x14D].iter().fold(0_u8, |checksum, byte| {
+        checksum.wrapping_sub(*byte).wrapping_sub(1)
+    });
+    rom[0x150..0x155].copy_from_slice(&[0xCD, 0x60, 0x01, 0x18, 0xFB]);
+    rom[0x160..0x166].copy_from_slice(&[0x3E, 0x42, 0xEA, 0x00, 0xC0, 0xC9]);
+    rom
+}
+
+/// The editor's end of a session.
+struct Client {
+    reader: BufReader<UnixStream>,
+    writer: UnixStream,
+    seq: i64,
+}
+impl Client {
+    fn send(&mut self, command: &str, args: &str) {
+        self.seq += 1;
+        let request = format!(
+            r#"{{"seq":{},"type":"request","command":"{command}","arguments":{args}}}"#,
+            self.seq
+        );
+        write_message(&mut self.writer, &Json::parse(&request).unwrap()).unwrap();
+    }
+
+    /// The response to the last request sent, skipping events.
+    fn response(&mut self) -> Json {
+        loop {
+            let message = read_message(&mut self.reader).unwrap().unwrap();
+            if message.get("type").as_str() == Some("response") {
+                assert_eq!(message.get("request_seq").as_i64(), Some(self.seq));
+                return message;
+            }
+        }
+    }
+
+    fn request(&mut self, command: &str, args: &str) -> Json {
+        self.send(command, args);
+        self.response()
+    }
+
+    fn event(&mut self, name: &str) -> Json {
+        loop {
+            let message = read_message(&mut self.reader).unwrap().unwrap();
+            if message.get("event").as_str() == Some(name) {
+                return message;
+            }
+        }
+    }
+}
+
+fn connect() -> (DapServer<UnixStream>, Client) {
+    let (server, client) = UnixStream::pair().unwrap();
+    let server = DapServer::new(server.try_clone().unwrap(), server).unwrap();
+    let client = Client {
+        reader: BufReader::new(client.try_clone().unwrap()),
+        writer: client,
+        seq: 0,
+    };
+    (server, client)
+}
+
+/// A directory holding `SOURCE` and `SYMBOLS`.
+fn project(name: &str) -> PathBuf {
+    let dir = env::temp_dir().join(format!("cashew-dap-{name}-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(dir.join("main.asm"), SOURCE).unwrap();
+    fs::write(dir.join("game.sym"), SYMBOLS).unwrap();
+    dir
+}
+
+#[test]
+fn breakpoints_only_on_label_lines() {
+    let dir = project("breakpoints");
+    let (mut server, mut client) = connect();
+    let session = thread::spawn(move || {
+        let rom = rom();
+        let mut gb = load_rom(&rom).unwrap();
+        let launch = server.wait_for_launch().unwrap().unwrap();
+        server.serve(&mut gb, &launch, |_| {}).unwrap();
+    });
+
+    client.request("initialize", "{}");
+    let symbols = dir.join("game.sym");
+    client.request(
+        "launch",
+        &format!(
+            r#"{{"program":"game.gb","symbols":"{}"}}"#,
+            symbols.display()
+        ),
+    );
+    let source = dir.join("main.asm");
+    let response = client.request(
+        "setBreakpoints",
+        &format!(
+            r#"{{"source":{{"path":"{}"}},"breakpoints":[{{"line":2}},{{"line":3}},{{"line":4}},{{"line":5}},{{"line":6}},{{"line":7}},{{"line":8}},{{"line":9}}]}}"#,
+            source.display()
+        ),
+    );
+    let breakpoints = response.get("body").get("breakpoints").as_array();
+    let verified: Vec<(Option<bool>, Option<i64>)> = breakpoints
+        .iter()
+        .map(|breakpoint| {
+            (
+                breakpoint.get("verified").as_bool(),
+                breakpoint.get("line").as_i64(),
+            )
+        })
+        .collect();
+    /* A label's line and the first instruction after it, nothing past
+     * that is moved onto the next label. */
+    assert_eq!(
+        verified,
+        [
+            (Some(true), Some(2)),
+            (Some(true), Some(3)),
+            (Some(false), None),
+            (Some(false), None),
+            (Some(true), Some(6)),
+            (Some(true), Some(7)),
+            (Some(true), Some(8)),
+            (Some(false), None),
+        ]
+    );
+
+    /* Only Sub is left to break at. */
+    client.request(
+        "setBreakpoints",
+        &format!(
+            r#"{{"source":{{"path":"{}"}},"breakpoints":[{{"line":8}}]}}"#,
+            source.display()
+        ),
+    );
+    client.request("configurationDone", "{}");
+    let stopped = client.event("stopped");
+    assert_eq!(
+        stopped.get("body").get("reason").as_str(),
+        Some("breakpoint")
+    );
+    let pc = client.request("evaluate", r#"{"expression":"pc"}"#);
+    assert_eq!(pc.get("body").get("result").as_str(), Some("$0160"));
+
+    client.request("disconnect", "{}");
+    session.join().unwrap();
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+/// Polls until the launch arrives, at most a second.
+fn poll_for_launch(server: &mut DapServer<UnixStream>) -> Option<String> {
+    let start = Instant::now();
+    while start.elapsed() < Duration::from_secs(1) {
+        if let Some(launch) = server.poll_launch().unwrap() {
+            return Some(launch.program);
+        }
+        thread::sleep(Duration::from_millis(5));
+    }
+    None
+}
+
+#[test]
+fn poll_launch_without_waiting() {
+    let (mut server, mut client) = connect();
+    /* Nobody there, the game carries on. */
+    let start = Instant::now();
+    assert!(server.poll_launch().unwrap().is_none());
+    assert!(start.elapsed() < Duration::from_millis(100));
+
+    /* Requests before the launch are answered while polling. */
+    client.send("initialize", "{}");
+    assert_eq!(poll_for_launch(&mut server), None);
+    assert_eq!(client.response().get("success").as_bool(), Some(true));
+
+    /* A client leaving before it launches leaves room for the next. */
+    client.send("disconnect", "{}");
+    assert_eq!(poll_for_launch(&mut server), None);
+    client.response();
+
+    client.send("launch", r#"{"program":"game.gb"}"#);
+    assert_eq!(poll_for_launch(&mut server).as_deref(), Some("game.gb"));
+}
+
+#[test]
+fn oversized_and_deep_messages_refused() {
+    /* Refused from the header, before the body is allocated. */
+    let mut huge = "Content-Length: 1073741824\r\n\r\n{}".as_bytes();
+    let err = read_message(&mut huge).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+
+    let nested = |depth: usize| "[".repeat(depth) + &"]".repeat(depth);
+    assert!(Json::parse(&nested(32)).is_some());
+    assert!(Json::parse(&nested(33)).is_none());
+    assert!(Json::parse(&nested(100_000)).is_none());
+
+    let body = format!(r#"{{"arguments":{}}}"#, nested(100_000));
+    let message = format!("Content-Length: {}\r\n\r\n{body}", body.len());
+    let err = read_message(&mut message.as_bytes()).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+}
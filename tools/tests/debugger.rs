@@ -0,0 +1,161 @@
+mod common;
+
+use cashew_tools::cashew_gb::debug::{
+    Breakpoint, Comparison, Condition, DebugRegister, RunUntil, StopReason, Watchpoint,
+};
+use common::{Cart, BANK};
+
+const PROGRAM: [u8; 27] = [
+    0x3E, 0x02, /* ld a, 2 */
+    0xEA, 0x00, 0x20, /* ld (0x2000), a */
+    0xCD, 0x00, 0x40, /* 0x155: call 0x4000 */
+    0x3E, 0x03, /* 0x158: ld a, 3 */
+    0xEA, 0x00, 0x20, /* ld (0x2000), a */
+    0xCD, 0x00, 0x40, /* 0x15D: call 0x4000 */
+    0xFF, /* 0x160: rst 0x38 */
+    0x06, 0x05, /* 0x161: ld b, 5 */
+    0x05, /* 0x163: dec b */
+    0x20, 0xFD, /* jr nz, -3 */
+    0xEA, 0x00, 0xC0, /* 0x166: ld (0xC000), a */
+    0x18, 0xFE, /* jr -2 */
+];
+/// At 0x4000 of every bank, leaves A at the bank number times 0x10.
+const BANKED: [u8; 0x15] = [
+    0xCD, 0x10, 0x40, /* call 0x4010 */
+    0xC9, /* 0x4003: ret */
+    0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, /* up to 0x4010 */
+    0x3E, BANK, /* 0x4010: ld a, BANK */
+    0xCB, 0x37, /* swap a */
+    0xC9, /* ret */
+];
+
+fn cart() -> Cart {
+    let mut cart = Cart::banked(&PROGRAM, 4, &BANKED);
+    /* rst 0x38: ret */
+    cart.rom[0x38] = 0xC9;
+    cart
+}
+
+#[test]
+fn breakpoint_in_one_bank() {
+    let cart = cart();
+    let mut gb = cart.gb();
+    let id = gb.gb_add_breakpoint(Breakpoint {
+        bank: Some(3),
+        ..Breakpoint::new(0x4000)
+    });
+
+    /* Passes 0x4000 in bank 2 first. */
+    assert_eq!(
+        gb.gb_debug_run(RunUntil::Frame),
+        StopReason::Breakpoint { id }
+    );
+    assert_eq!(gb.get_pc(), 0x4000);
+    assert_eq!(gb.get_rom_bank(0x4000), 3);
+    assert_eq!(gb.get_registers().a, 3);
+
+    /* Resuming passes the breakpoint it stopped on. */
+    assert_eq!(gb.gb_debug_run(RunUntil::Pc(0x0160)), StopReason::Reached);
+    assert_eq!(gb.get_registers().a, 0x30);
+}
+
+#[test]
+fn breakpoint_condition() {
+    let cart = cart();
+    let mut gb = cart.gb();
+    let condition = Condition::parse("b == 2").unwrap();
+    assert_eq!(
+        condition,
+        Condition {
+            register: DebugRegister::B,
+            comparison: Comparison::Equal,
+            value: 2,
+        }
+    );
+    let id = gb.gb_add_breakpoint(Breakpoint {
+        condition: Some(condition),
+        ..Breakpoint::new(0x0163)
+    });
+    assert_eq!(
+        gb.gb_debug_run(RunUntil::Frame),
+        StopReason::Breakpoint { id }
+    );
+    assert_eq!(gb.get_registers().b, 2);
+
+    assert_eq!(
+        Condition::parse("hl >= $C000").map(|condition| condition.value),
+        Some(0xC000)
+    );
+    assert_eq!(
+        Condition::parse("SP < 0x10").map(|condition| condition.register),
+        Some(DebugRegister::SP)
+    );
+    assert_eq!(Condition::parse("a = 1"), None);
+    assert_eq!(Condition::parse("q == 1"), None);
+    assert_eq!(Condition::parse("a == 1 2"), None);
+}
+
+#[test]
+fn step_over_and_out() {
+    let cart = cart();
+    let mut gb = cart.gb();
+    gb.gb_debug_run(RunUntil::Pc(0x0155));
+
+    /* Over the whole call, nested one included. */
+    assert_eq!(gb.gb_debug_step_over(), StopReason::Step);
+    assert_eq!(gb.get_pc(), 0x0158);
+    assert_eq!(gb.get_registers().a, 0x20);
+
+    /* Into the next one and out of the nested call, then the outer. */
+    gb.gb_debug_run(RunUntil::Pc(0x015D));
+    assert_eq!(gb.gb_debug_step(), StopReason::Step);
+    assert_eq!(gb.get_pc(), 0x4000);
+    gb.gb_debug_step();
+    assert_eq!(gb.get_pc(), 0x4010);
+    assert_eq!(gb.gb_debug_step_out(), StopReason::Step);
+    assert_eq!(gb.get_pc(), 0x4003);
+    assert_eq!(gb.gb_debug_step_out(), StopReason::Step);
+    assert_eq!(gb.get_pc(), 0x0160);
+
+    /* RST counts as a call. */
+    assert_eq!(gb.gb_debug_step_over(), StopReason::Step);
+    assert_eq!(gb.get_pc(), 0x0161);
+    /* Anything else is a single step. */
+    assert_eq!(gb.gb_debug_step_over(), StopReason::Step);
+    assert_eq!(gb.get_pc(), 0x0163);
+}
+
+#[test]
+fn step_over_stops_at_breakpoints_inside() {
+    let cart = cart();
+    let mut gb = cart.gb();
+    let id = gb.gb_add_breakpoint(Breakpoint::new(0x4010));
+    gb.gb_debug_run(RunUntil::Pc(0x0155));
+    assert_eq!(gb.gb_debug_step_over(), StopReason::Breakpoint { id });
+    assert_eq!(gb.get_pc(), 0x4010);
+    assert!(gb.gb_remove_breakpoint(id));
+    assert!(!gb.gb_remove_breakpoint(id));
+}
+
+#[test]
+fn watchpoint_on_write() {
+    let cart = cart();
+    let mut gb = cart.gb();
+    let id = gb.gb_add_watchpoint(Watchpoint {
+        start: 0xC000,
+        end: 0xC0FF,
+        read: false,
+        write: true,
+    });
+    assert_eq!(
+        gb.gb_debug_run(RunUntil::Frame),
+        StopReason::Watchpoint {
+            id,
+            addr: 0xC000,
+            value: 0x30,
+            write: true,
+        }
+    );
+    /* Stopped after the instruction. */
+    assert_eq!(gb.get_pc(), 0x0169);
+}
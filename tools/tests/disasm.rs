@@ -0,0 +1,106 @@
+mod common;
+
+use cashew_tools::cashew_gb::{
+    debug::RunUntil,
+    disasm::{disassemble, GbSymbolError, SymbolTable},
+};
+use common::Cart;
+
+fn text(addr: u16, bytes: [u8; 3]) -> String {
+    disassemble(addr, bytes, |_| None).text
+}
+
+#[test]
+fn cb_prefix() {
+    let swap = disassemble(0x0200, [0xCB, 0x37, 0xFF], |_| None);
+    assert_eq!(swap.text, "swap a");
+    /* The byte after the CB opcode isn't part of it. */
+    assert_eq!(swap.bytes, [0xCB, 0x37]);
+    assert_eq!(text(0, [0xCB, 0x00, 0]), "rlc b");
+    assert_eq!(text(0, [0xCB, 0x3E, 0]), "srl [hl]");
+    assert_eq!(text(0, [0xCB, 0x7C, 0]), "bit 7, h");
+    assert_eq!(text(0, [0xCB, 0x86, 0]), "res 0, [hl]");
+    assert_eq!(text(0, [0xCB, 0xFF, 0]), "set 7, a");
+}
+
+#[test]
+fn ldh_and_signed_operands() {
+    assert_eq!(text(0, [0xE0, 0x40, 0]), "ldh [$FF40], a");
+    assert_eq!(text(0, [0xF0, 0x44, 0]), "ldh a, [$FF44]");
+    assert_eq!(text(0, [0xE8, 0xFE, 0]), "add sp, -2");
+    assert_eq!(text(0, [0xF8, 0x05, 0]), "ld hl, sp + 5");
+    let labels = |addr: u16| (addr == 0xFF44).then_some("rLY");
+    assert_eq!(disassemble(0, [0xF0, 0x44, 0], labels).text, "ldh a, [rLY]");
+
+    let unused = disassemble(0, [0xD3, 0x12, 0x34], |_| None);
+    assert_eq!((unused.text.as_str(), unused.bytes.len()), ("db $D3", 1));
+}
+
+#[test]
+fn jr_targets() {
+    /* From the address after the two bytes, both ways and wrapping. */
+    assert_eq!(text(0x0150, [0x18, 0xFE, 0]), "jr $0150");
+    assert_eq!(text(0x0150, [0x20, 0x10, 0]), "jr nz, $0162");
+    assert_eq!(text(0x0150, [0x38, 0x80, 0]), "jr c, $00D2");
+    assert_eq!(text(0xFFFF, [0x18, 0x00, 0]), "jr $0001");
+    let labels = |addr: u16| (addr == 0x0140).then_some("Loop");
+    assert_eq!(
+        disassemble(0x0150, [0x28, 0xEE, 0], labels).text,
+        "jr z, Loop"
+    );
+}
+
+/// In every bank, a call to the bank's own function at 0x4010.
+const BANKED: [u8; 3] = [0xCD, 0x10, 0x40];
+const PROGRAM: [u8; 7] = [
+    0x3E, 0x02, /* ld a, 2 */
+    0xEA, 0x00, 0x20, /* ld (0x2000), a */
+    0x18, 0xFE, /* 0x155: jr -2 */
+];
+
+#[test]
+fn labels_of_the_mapped_bank() {
+    let cart = Cart::banked(&PROGRAM, 4, &BANKED);
+    let symbols =
+        SymbolTable::parse("; a comment\n00:0155 Main\n01:4010 OneFunc\n02:4010 TwoFunc\n")
+            .unwrap();
+    let mut gb = cart.gb();
+
+    assert_eq!(
+        gb.gb_disassemble(0x4000, Some(&symbols)).text,
+        "call OneFunc"
+    );
+    gb.gb_debug_run(RunUntil::Pc(0x0155));
+    assert_eq!(gb.get_bank(0x4000), 2);
+    assert_eq!(
+        gb.gb_disassemble(0x4000, Some(&symbols)).text,
+        "call TwoFunc"
+    );
+    assert_eq!(gb.gb_disassemble(0x0155, Some(&symbols)).text, "jr Main");
+    assert_eq!(gb.gb_disassemble(0x4000, None).text, "call $4010");
+}
+
+#[test]
+fn symbol_files() {
+    assert_eq!(
+        SymbolTable::parse("00:0150 Main\nnonsense\n"),
+        Err(GbSymbolError::GbSymbolSyntax { line: 2 })
+    );
+
+    let map = "\
+ROM0 bank #0:
+    SECTION: $0150-$0160 ($0011 bytes) [\"main\"]
+             $0150 = Main
+ROMX bank #3:
+             $4000 = Banked
+SUMMARY:
+             $5000 = NotALabel
+";
+    let symbols = SymbolTable::parse_map(map).unwrap();
+    assert_eq!(symbols.get_address("Main"), Some((0, 0x0150)));
+    assert_eq!(symbols.get_address("Banked"), Some((3, 0x4000)));
+    assert_eq!(symbols.get_address("NotALabel"), None);
+    let nearest = symbols.get_nearest(3, 0x4123).unwrap();
+    assert_eq!(nearest.name, "Banked");
+    assert!(symbols.get_nearest(2, 0x4123).is_none());
+}
@@ -0,0 +1,253 @@
+mod common;
+
+use cashew_tools::cashew_gb::{
+    dmg07::{run_dmg07_frame, Dmg07, Dmg07Phase},
+    link::LinkCable,
+    Gb,
+};
+use common::Cart;
+
+const SCRIPT_ADDR: usize = 0x0400;
+const LOG_ADDR: u16 = 0xA000;
+const FRAME_CYCLES: u64 = 70224;
+
+const PING_HEADER: u8 = 0xFE;
+const ACK: u8 = 0x88;
+const START: u8 = 0xAA;
+const START_ACK: u8 = 0xCC;
+
+/// Logs every byte the adapter clocks in at 0xA000. Sends 0 until the first
+/// ping header, then the script at 0x400 a byte at a time, so its first byte
+/// answers the ping's first status byte.
+const PLAYER: [u8; 50] = [
+    0x3E,
+    0x0A, /* ld a, 0x0A */
+    0xEA,
+    0x00,
+    0x00, /* ld (0x0000), a */
+    0x21,
+    0x00,
+    0xA0, /* ld hl, 0xA000 */
+    0x11,
+    0x00,
+    0x04, /* ld de, 0x0400 */
+    /* wait_header: */
+    0xAF, /* xor a */
+    0xE0,
+    0x01, /* ldh (SB), a */
+    0x3E,
+    0x80, /* ld a, 0x80 */
+    0xE0,
+    0x02, /* ldh (SC), a */
+    0xF0,
+    0x02, /* ldh a, (SC) */
+    0xCB,
+    0x7F, /* bit 7, a */
+    0x20,
+    0xFA, /* jr nz, -6 */
+    0xF0,
+    0x01, /* ldh a, (SB) */
+    0x22, /* ld (hl+), a */
+    0xFE,
+    PING_HEADER, /* cp 0xFE */
+    0x20,
+    0xEC, /* jr nz, wait_header */
+    /* script: */
+    0x1A, /* ld a, (de) */
+    0x13, /* inc de */
+    0xE0,
+    0x01, /* ldh (SB), a */
+    0x3E,
+    0x80, /* ld a, 0x80 */
+    0xE0,
+    0x02, /* ldh (SC), a */
+    0xF0,
+    0x02, /* ldh a, (SC) */
+    0xCB,
+    0x7F, /* bit 7, a */
+    0x20,
+    0xFA, /* jr nz, -6 */
+    0xF0,
+    0x01, /* ldh a, (SB) */
+    0x22, /* ld (hl+), a */
+    0x18,
+    0xED, /* jr script */
+];
+
+/// The bytes a player sends from the second ping on: the rest of an answer
+/// the header came too late for, two answers, then player 1 starts the
+/// transmission while the others answer once more. Past the start's four
+/// bytes every round opens with the player's packet.
+fn script(player: usize, rate: u8, size: u8, packets: &[Vec<u8>]) -> Vec<u8> {
+    let mut script = vec![ACK, rate, size];
+    for _ in 0..2 {
+        script.extend_from_slice(&[ACK, ACK, rate, size]);
+    }
+    if player == 0 {
+        script.extend_from_slice(&[START; 4]);
+    } else {
+        script.extend_from_slice(&[ACK, ACK, rate, size]);
+    }
+    script.extend_from_slice(&[0; 4]);
+    for packet in packets {
+        script.extend_from_slice(packet);
+        script.resize(script.len() + 3 * packet.len(), 0);
+    }
+    script
+}
+
+fn cart(script: &[u8]) -> Cart {
+    let mut cart = Cart::new(&PLAYER, false);
+    cart.rom[SCRIPT_ADDR..SCRIPT_ADDR + script.len()].copy_from_slice(script);
+    cart
+}
+
+/// How many bytes the console has logged.
+fn logged(gb: &Gb<Cart>) -> usize {
+    let registers = gb.get_registers();
+    (u16::from_be_bytes([registers.h, registers.l]) - LOG_ADDR) as usize
+}
+
+/// Plugs a console into each of the adapter's first ports.
+fn plug<'c>(adapter: &mut Dmg07, carts: &'c [Cart]) -> Vec<Gb<'c, Cart>> {
+    carts
+        .iter()
+        .enumerate()
+        .map(|(player, cart)| {
+            let (port, console) = LinkCable::pair();
+            adapter.plug(player, Box::new(port));
+            cart.link.plug(Box::new(console));
+            let mut gb = cart.gb();
+            gb.gb_init_link();
+            gb
+        })
+        .collect()
+}
+
+/// What player `player` of `players` hears up to the transmission, from the
+/// status byte after the header it missed while starting.
+fn pings(player: usize, players: usize) -> Vec<u8> {
+    let status = |connected: u8| connected << 4 | (player as u8 + 1);
+    let all = (1 << players) - 1;
+    let mut log = vec![status(0); 3];
+    for connected in [0, 0, all, all] {
+        log.push(PING_HEADER);
+        log.extend_from_slice(&[status(connected); 3]);
+    }
+    log.extend_from_slice(&[START_ACK; 4]);
+    log
+}
+
+fn relay(players: usize) {
+    let (rate, size) = (0x01, 3);
+    /* A round's packets, from one player after the other. */
+    let packets: Vec<Vec<Vec<u8>>> = (0..players)
+        .map(|player| {
+            (0..2)
+                .map(|round| vec![0x10 * (player as u8 + 1) + round; size])
+                .collect()
+        })
+        .collect();
+    let carts: Vec<Cart> = (0..players)
+        .map(|player| {
+            /* Only player 1's rate and size count. */
+            let (rate, size) = if player == 0 {
+                (rate, size as u8)
+            } else {
+                (0x0F, 1)
+            };
+            cart(&script(player, rate, size, &packets[player]))
+        })
+        .collect();
+
+    let mut adapter = Dmg07::new();
+    let mut gbs = plug(&mut adapter, &carts);
+    for _ in 0..12 {
+        run_dmg07_frame(&mut adapter, &mut gbs);
+    }
+
+    assert_eq!(adapter.get_phase(), Dmg07Phase::Transmission);
+    assert_eq!(adapter.get_rate(), rate);
+    assert_eq!(adapter.get_packet_size(), size);
+    for player in 0..4 {
+        assert_eq!(adapter.is_connected(player), player < players);
+    }
+
+    let round = 4 * size;
+    for (player, gb) in gbs.iter_mut().enumerate() {
+        let log: Vec<u8> = (0..logged(gb))
+            .map(|i| gb.gb_peek(LOG_ADDR + i as u16))
+            .collect();
+        let pings = pings(player, players);
+        assert_eq!(log[..pings.len()], pings, "player {}", player + 1);
+
+        /* Nothing to relay in the first round, then every player gets the
+         * packets of the one before, empty for unplugged ports. */
+        let rounds = &log[pings.len()..];
+        assert!(rounds.len() >= 3 * round, "player {}", player + 1);
+        assert_eq!(rounds[..round], vec![0; round]);
+        for (n, relayed) in rounds[round..3 * round].chunks(round).enumerate() {
+            let mut expected: Vec<u8> = packets
+                .iter()
+                .flat_map(|packets| packets[n].clone())
+                .collect();
+            expected.resize(round, 0);
+            assert_eq!(relayed, expected, "player {} round {}", player + 1, n + 2);
+        }
+    }
+}
+
+#[test]
+fn two_players() {
+    relay(2);
+}
+
+#[test]
+fn four_players() {
+    relay(4);
+}
+
+#[test]
+fn rate_slows_the_bytes() {
+    let mut counts = Vec::new();
+    for rate in [0x00, 0x0F] {
+        let carts = [cart(&script(0, rate, 1, &[]))];
+        let mut adapter = Dmg07::new();
+        let mut gbs = plug(&mut adapter, &carts);
+        for _ in 0..8 {
+            run_dmg07_frame(&mut adapter, &mut gbs);
+        }
+        assert_eq!(adapter.get_rate(), rate);
+        let before = logged(&gbs[0]);
+        run_dmg07_frame(&mut adapter, &mut gbs);
+        counts.push(logged(&gbs[0]) - before);
+    }
+    /* 0x2000 cycles a byte, and 0x400 more for each step of the rate. */
+    for (count, cycles) in counts.into_iter().zip([0x2000, 0x2000 + 15 * 0x400]) {
+        let expected = FRAME_CYCLES / cycles;
+        assert!(
+            (expected..=expected + 1).contains(&(count as u64)),
+            "{count} bytes a frame at {cycles} cycles a byte"
+        );
+    }
+}
+
+#[test]
+fn player_one_restarts_the_pings() {
+    let size = 2;
+    let mut script = script(0, 0, size, &[vec![0x42; 2]]);
+    /* A packet of 0xFF goes back to pinging. */
+    script.extend_from_slice(&[0xFF; 2]);
+    script.resize(script.len() + 3 * size as usize, 0);
+    let carts = [cart(&script)];
+    let mut adapter = Dmg07::new();
+    let mut gbs = plug(&mut adapter, &carts);
+
+    let mut phases = Vec::new();
+    for _ in 0..10 {
+        run_dmg07_frame(&mut adapter, &mut gbs);
+        phases.push(adapter.get_phase());
+    }
+    assert!(phases.contains(&Dmg07Phase::Transmission));
+    assert_eq!(adapter.get_phase(), Dmg07Phase::Ping);
+}
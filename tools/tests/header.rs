@@ -0,0 +1,101 @@
+use cashew_tools::cashew_gb::{CartridgeHeader, HEADER_SIZE};
+
+/// A header with `title` written from 0x134 and the bytes in `fields` set.
+fn header(title: &[u8], fields: &[(usize, u8)]) -> CartridgeHeader {
+    let mut rom = vec![0; HEADER_SIZE];
+    rom[0x134..0x134 + title.len()].copy_from_slice(title);
+    for &(addr, val) in fields {
+        rom[addr] = val;
+    }
+    CartridgeHeader::new(&rom).unwrap()
+}
+
+#[test]
+fn short_rom() {
+    assert!(CartridgeHeader::new(&[0; HEADER_SIZE - 1]).is_none());
+}
+
+#[test]
+fn dmg_title_uses_all_16_bytes() {
+    let header = header(b"ABCDEFGHIJKLMNOP", &[]);
+    assert_eq!(header.get_title(), "ABCDEFGHIJKLMNOP");
+    assert!(!header.is_cgb_supported());
+    assert_eq!(header.get_manufacturer_code(), None);
+}
+
+#[test]
+fn title_stops_at_padding() {
+    assert_eq!(header(b"TETRIS", &[]).get_title(), "TETRIS");
+}
+
+#[test]
+fn cgb_title_stops_before_the_flag() {
+    let header = header(b"ABCDEFGHIJK MNO", &[(0x143, 0x80)]);
+    assert_eq!(header.get_title(), "ABCDEFGHIJK MNO");
+    assert_eq!(header.get_manufacturer_code(), None);
+    assert_eq!(header.get_title_bytes().len(), 16);
+    assert!(header.is_cgb_supported());
+    assert!(!header.is_cgb_only());
+}
+
+#[test]
+fn cgb_title_stops_before_the_manufacturer_code() {
+    let header = header(b"POKEMON_SLVAAXE", &[(0x143, 0xC0)]);
+    assert_eq!(header.get_manufacturer_code().as_deref(), Some("AAXE"));
+    assert_eq!(header.get_title(), "POKEMON_SLV");
+    assert!(header.is_cgb_only());
+}
+
+#[test]
+fn lowercase_is_not_a_manufacturer_code() {
+    let header = header(b"ABCDEFGHIJKaaxe", &[(0x143, 0x80)]);
+    assert_eq!(header.get_manufacturer_code(), None);
+    // The title still ends at the first byte outside ' '..='_'.
+    assert_eq!(header.get_title(), "ABCDEFGHIJK");
+}
+
+#[test]
+fn manufacturer_code_needs_the_cgb_flag() {
+    let header = header(b"ABCDEFGHIJKAAXE", &[]);
+    assert_eq!(header.get_manufacturer_code(), None);
+    assert_eq!(header.get_title(), "ABCDEFGHIJKAAXE");
+}
+
+#[test]
+fn new_licensee() {
+    let header = header(b"", &[(0x144, b'0'), (0x145, b'1'), (0x14B, 0x33)]);
+    assert_eq!(header.get_new_licensee(), *b"01");
+    assert_eq!(header.get_licensee(), "01");
+
+    let header = header_with_old_licensee(0x01);
+    assert_eq!(header.get_licensee(), "01");
+    let header = header_with_old_licensee(0xA4);
+    assert_eq!(header.get_licensee(), "A4");
+}
+
+fn header_with_old_licensee(code: u8) -> CartridgeHeader {
+    header(b"", &[(0x144, b'9'), (0x145, b'9'), (0x14B, code)])
+}
+
+#[test]
+fn sgb_flag_needs_the_new_licensee() {
+    assert!(header(b"", &[(0x146, 0x03), (0x14B, 0x33)]).is_sgb_supported());
+    assert!(!header(b"", &[(0x146, 0x03), (0x14B, 0x01)]).is_sgb_supported());
+}
+
+#[test]
+fn header_checksum() {
+    let mut rom = vec![0; HEADER_SIZE];
+    rom[0x134..0x13A].copy_from_slice(b"TETRIS");
+    let checksum = CartridgeHeader::new(&rom)
+        .unwrap()
+        .compute_header_checksum();
+    rom[0x14D] = checksum;
+    assert!(CartridgeHeader::new(&rom)
+        .unwrap()
+        .is_header_checksum_valid());
+    rom[0x14D] = checksum.wrapping_add(1);
+    assert!(!CartridgeHeader::new(&rom)
+        .unwrap()
+        .is_header_checksum_valid());
+}
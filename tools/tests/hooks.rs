@@ -0,0 +1,152 @@
+mod common;
+
+use cashew_tools::cashew_gb::{
+    debug::RunUntil,
+    hooks::{Access, Event},
+    CartridgeHeader, Gb,
+};
+use common::Cart;
+use std::{cell::RefCell, rc::Rc};
+
+/// Every event but the LCD's, which come on their own.
+fn bank_events(gb: &mut Gb<Cart>) -> Rc<RefCell<Vec<Event>>> {
+    let events = Rc::new(RefCell::new(Vec::new()));
+    let log = events.clone();
+    gb.gb_add_event_hook(move |_, event| {
+        if !matches!(event, Event::Ly(_) | Event::VBlank) {
+            log.borrow_mut().push(event);
+        }
+    });
+    events
+}
+
+const BANKS: [u8; 31] = [
+    0x3E, 0x02, /* ld a, 2 */
+    0xEA, 0x00, 0x20, /* ld (0x2000), a */
+    0xCD, 0x00, 0x40, /* call 0x4000 */
+    0xEA, 0x00, 0x20, /* ld (0x2000), a */
+    0x3E, 0x03, /* ld a, 3 */
+    0xEA, 0x00, 0x20, /* ld (0x2000), a */
+    0xCD, 0x00, 0x40, /* call 0x4000 */
+    0x3E, 0x01, /* ld a, 1 */
+    0xEA, 0x00, 0x40, /* ld (0x4000), a */
+    0x3E, 0x08, /* ld a, 8 */
+    0xEA, 0x00, 0x40, /* ld (0x4000), a */
+    0x18, 0xFE, /* 0x16D: jr -2 */
+];
+
+/// An MBC3 cartridge, its banks returning from 0x4000.
+fn mbc3() -> Cart {
+    let mut cart = Cart::banked(&BANKS, 4, &[0xC9]);
+    cart.rom[0x147] = 0x13;
+    cart.rom[0x14D] = CartridgeHeader::new(&cart.rom)
+        .unwrap()
+        .compute_header_checksum();
+    cart
+}
+
+#[test]
+fn bank_switches() {
+    let cart = mbc3();
+    let mut gb = cart.gb();
+    let events = bank_events(&mut gb);
+    gb.gb_debug_run(RunUntil::Pc(0x016D));
+
+    /* Selecting the bank already there isn't an event. The RTC registers
+     * come as the cart RAM bank they're selected as. */
+    assert_eq!(
+        *events.borrow(),
+        [
+            Event::RomBank(2),
+            Event::RomBank(3),
+            Event::CartRamBank(1),
+            Event::CartRamBank(8),
+        ]
+    );
+}
+
+#[test]
+fn execute_hook_sees_the_bank() {
+    let cart = mbc3();
+    let mut gb = cart.gb();
+    let banks = Rc::new(RefCell::new(Vec::new()));
+    let log = banks.clone();
+    gb.gb_add_memory_hook(Access::Execute, 0x4000..=0x4000, move |gb, addr, opcode| {
+        log.borrow_mut().push((gb.get_rom_bank(addr), opcode));
+    });
+    let writes = Rc::new(RefCell::new(Vec::new()));
+    let log = writes.clone();
+    let id = gb.gb_add_memory_hook(Access::Write, 0x2000..=0x3FFF, move |_, addr, val| {
+        log.borrow_mut().push((addr, val));
+    });
+    gb.gb_debug_run(RunUntil::Pc(0x0160));
+
+    assert_eq!(*banks.borrow(), [(2, 0xC9)]);
+    assert_eq!(*writes.borrow(), [(0x2000, 2), (0x2000, 2), (0x2000, 3)]);
+    assert!(gb.gb_remove_hook(id));
+    assert!(!gb.gb_remove_hook(id));
+    gb.gb_debug_run(RunUntil::Pc(0x016D));
+    assert_eq!(*banks.borrow(), [(2, 0xC9), (3, 0xC9)]);
+    assert_eq!(writes.borrow().len(), 3);
+}
+
+#[test]
+#[cfg(feature = "gbc")]
+fn cgb_bank_switches() {
+    let program = [
+        0x3E, 0x03, /* ld a, 3 */
+        0xE0, 0x70, /* ldh (SVBK), a */
+        0x3E, 0x01, /* ld a, 1 */
+        0xE0, 0x4F, /* ldh (VBK), a */
+        0x18, 0xFE, /* 0x158: jr -2 */
+    ];
+    let cart = Cart::new(&program, true);
+    let mut gb = cart.gb();
+    let events = bank_events(&mut gb);
+    gb.gb_debug_run(RunUntil::Pc(0x0158));
+    assert_eq!(*events.borrow(), [Event::WramBank(3), Event::VramBank(1)]);
+}
+
+const LCD_OFF: [u8; 11] = [
+    0xF0, 0x44, /* ldh a, (LY) */
+    0xFE, 0x90, /* cp 144 */
+    0x20, 0xFA, /* jr nz, -6 */
+    0xAF, /* xor a */
+    0xE0, 0x40, /* ldh (LCDC), a */
+    0x18, 0xFE, /* 0x159: jr -2 */
+];
+
+#[test]
+fn ly_every_line() {
+    let cart = Cart::new(&LCD_OFF, false);
+    let mut gb = cart.gb();
+    let events = Rc::new(RefCell::new(Vec::new()));
+    let log = events.clone();
+    gb.gb_add_event_hook(move |gb, event| {
+        log.borrow_mut().push((event, gb.gb_peek(0xFF44)));
+    });
+    gb.gb_debug_run(RunUntil::Pc(0x0159));
+    let events = events.borrow();
+
+    /* Each line in turn, LY already moved on when the hook sees it, and
+     * vblank straight after line 144 starts. */
+    let (last, lines) = events.split_last().unwrap();
+    let mut ly = None;
+    for (i, (event, at)) in lines.iter().enumerate() {
+        match event {
+            Event::Ly(line) => {
+                assert_eq!(line, at);
+                if let Some(ly) = ly {
+                    assert_eq!(*line, (ly + 1) % 154);
+                }
+                ly = Some(*line);
+            }
+            Event::VBlank => assert_eq!(lines[i - 1].0, Event::Ly(144)),
+            other => panic!("{other:?}"),
+        }
+    }
+    assert_eq!(ly, Some(144));
+    assert_eq!(lines.last().unwrap().0, Event::VBlank);
+    /* Turning the LCD off takes LY back to 0. */
+    assert_eq!(*last, (Event::Ly(0), 0));
+}
@@ -0,0 +1,105 @@
+mod common;
+
+use cashew_tools::cashew_gb::{
+    ir::{IrPair, IrSocket, IrTransport},
+    link::run_linked_frame,
+};
+use common::Cart;
+use std::{net::TcpListener, thread, time::Duration};
+
+/// Waits a while, then switches the LED on for good.
+const SENDER: [u8; 11] = [
+    0x06, 0x00, /* ld b, 0 */
+    0x05, /* dec b */
+    0x20, 0xFD, /* jr nz, -3 */
+    0x3E, 0xC1, /* ld a, 0xC1 */
+    0xE0, 0x56, /* ldh (RP), a */
+    0x18, 0xFE, /* jr -2 */
+];
+
+/// Writes `rp` to RP, then polls it into 0xA001 until light is seen and
+/// stores the reading that saw it at 0xA000.
+fn receiver(rp: u8) -> Vec<u8> {
+    vec![
+        0x3E, 0x0A, /* ld a, 0x0A */
+        0xEA, 0x00, 0x00, /* ld (0x0000), a */
+        0x3E, rp, /* ld a, rp */
+        0xE0, 0x56, /* ldh (RP), a */
+        0xF0, 0x56, /* ldh a, (RP) */
+        0xEA, 0x01, 0xA0, /* ld (0xA001), a */
+        0xCB, 0x4F, /* bit 1, a */
+        0x20, 0xF7, /* jr nz, -9 */
+        0xEA, 0x00, 0xA0, /* ld (0xA000), a */
+        0x18, 0xFE, /* jr -2 */
+    ]
+}
+
+/// Runs the sender and a receiver enabling RP with `rp` facing each other.
+fn facing(rp: u8) -> Cart {
+    let (sender, receiver) = (Cart::new(&SENDER, true), Cart::new(&receiver(rp), true));
+    let (a, b) = IrPair::pair();
+    sender.ir.plug(Box::new(a));
+    receiver.ir.plug(Box::new(b));
+    let (mut a, mut b) = (sender.gb(), receiver.gb());
+    a.gb_init_ir_port();
+    b.gb_init_ir_port();
+    for _ in 0..2 {
+        run_linked_frame(&mut a, &mut b);
+    }
+    receiver
+}
+
+#[test]
+fn pair_sees_the_other_led() {
+    let receiver = facing(0xC0);
+    /* Bit 1 goes low while light is received. */
+    assert_eq!(receiver.ram(0), 0xFC);
+    assert_eq!(receiver.ram(1), 0xFC);
+}
+
+#[test]
+fn pair_read_disabled() {
+    let receiver = facing(0x00);
+    /* Without bits 6 and 7 the receiver always reads no light. */
+    assert_eq!(receiver.ram(0), 0x00);
+    assert_eq!(receiver.ram(1), 0x3E);
+}
+
+#[test]
+fn pair_timing() {
+    let (mut a, mut b) = IrPair::pair();
+    a.set_led(100, true);
+    a.set_led(180, false);
+    assert!(!b.is_receiving(99));
+    assert!(b.is_receiving(100));
+    assert!(b.is_receiving(179));
+    assert!(!b.is_receiving(180));
+    /* A console doesn't see its own LED. */
+    assert!(!a.is_receiving(200));
+}
+
+#[test]
+fn socket_replays_bursts() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let accepted = thread::spawn(move || IrSocket::accept(&listener).unwrap());
+    let mut a = IrSocket::connect(addr).unwrap();
+    let mut b = accepted.join().unwrap();
+
+    a.set_led(1000, true);
+    a.set_led(1500, false);
+    thread::sleep(Duration::from_millis(50));
+    /* The burst starts when it's first seen and keeps its length. */
+    assert!(b.is_receiving(5000));
+    assert!(b.is_receiving(5499));
+    assert!(!b.is_receiving(5500));
+
+    /* After a long gap the next burst starts over from when it's seen. */
+    a.set_led(1000 + 200_000, true);
+    thread::sleep(Duration::from_millis(50));
+    assert!(b.is_receiving(9000));
+
+    drop(a);
+    thread::sleep(Duration::from_millis(50));
+    assert!(!b.is_connected());
+}
@@ -0,0 +1,224 @@
+mod common;
+
+use cashew_tools::cashew_gb::link::{
+    run_linked_frame, LinkCable, LinkTransport, TcpLink, LINK_TIMEOUT,
+};
+use common::Cart;
+use std::{
+    net::TcpListener,
+    thread,
+    time::{Duration, Instant},
+};
+
+const SC_INTERNAL: u8 = 0x81;
+const SC_EXTERNAL: u8 = 0x80;
+const SC_FAST: u8 = 0x83;
+
+/// Enables cart RAM, sends `sb` with SC set to `sc`, then stores what was
+/// shifted in at 0xA000.
+fn transfer(sb: u8, sc: u8) -> Vec<u8> {
+    vec![
+        0x3E, 0x0A, /* ld a, 0x0A */
+        0xEA, 0x00, 0x00, /* ld (0x0000), a */
+        0x3E, sb, /* ld a, sb */
+        0xE0, 0x01, /* ldh (SB), a */
+        0x3E, sc, /* ld a, sc */
+        0xE0, 0x02, /* ldh (SC), a */
+        0xF0, 0x02, /* ldh a, (SC) */
+        0xCB, 0x7F, /* bit 7, a */
+        0x20, 0xFA, /* jr nz, -6 */
+        0xF0, 0x01, /* ldh a, (SB) */
+        0xEA, 0x00, 0xA0, /* ld (0xA000), a */
+        0x18, 0xFE, /* jr -2 */
+    ]
+}
+
+fn linked(sb: u8, sc: u8) -> Cart {
+    Cart::new(&transfer(sb, sc), false)
+}
+
+/// Runs frames of `cart` until the transfer is done, at most 5s of them.
+fn run_until_received(cart: &Cart) -> u8 {
+    let mut gb = cart.gb();
+    gb.gb_init_link();
+    let start = Instant::now();
+    while cart.ram(0) == 0 && start.elapsed() < Duration::from_secs(5) {
+        gb.run_frame();
+    }
+    cart.ram(0)
+}
+
+#[test]
+fn cable_internal_and_external_clock() {
+    let (master, slave) = (linked(0x42, SC_INTERNAL), linked(0x99, SC_EXTERNAL));
+    let (a, b) = LinkCable::pair();
+    master.link.plug(Box::new(a));
+    slave.link.plug(Box::new(b));
+    let (mut a, mut b) = (master.gb(), slave.gb());
+    a.gb_init_link();
+    b.gb_init_link();
+    for _ in 0..3 {
+        run_linked_frame(&mut a, &mut b);
+    }
+    assert_eq!(master.ram(0), 0x99);
+    assert_eq!(slave.ram(0), 0x42);
+}
+
+#[test]
+fn cable_unplugged() {
+    let (master, slave) = (linked(0x42, SC_INTERNAL), linked(0x99, SC_EXTERNAL));
+    let (mut a, mut b) = (master.gb(), slave.gb());
+    a.gb_init_link();
+    b.gb_init_link();
+    for _ in 0..3 {
+        run_linked_frame(&mut a, &mut b);
+    }
+    /* The clocking side reads a line pulled high, the other never finishes. */
+    assert_eq!(master.ram(0), 0xFF);
+    assert_eq!(slave.ram(0), 0x00);
+}
+
+#[test]
+fn cable_peer_not_ready() {
+    let (mut a, mut b) = LinkCable::pair();
+    a.start_transfer(0x42);
+    assert_eq!(a.finish_transfer(), Some(0xFF));
+    assert_eq!(b.take_received(), None);
+
+    b.set_ready(0x99);
+    a.start_transfer(0x42);
+    assert_eq!(a.finish_transfer(), Some(0x99));
+    assert_eq!(b.take_received(), Some(0x42));
+    assert_eq!(b.take_received(), None);
+}
+
+/// Answers every byte with its complement, as soon as it's sent.
+struct Complement(Option<u8>);
+impl LinkTransport for Complement {
+    fn start_transfer(&mut self, byte: u8) {
+        self.0 = Some(!byte);
+    }
+    fn finish_transfer(&mut self) -> Option<u8> {
+        self.0.take()
+    }
+    fn set_ready(&mut self, _byte: u8) {}
+    fn take_received(&mut self) -> Option<u8> {
+        None
+    }
+}
+
+/// Cycles until a CGB clocking with `sc` has its byte back.
+fn cycles_to_transfer(sc: u8) -> u64 {
+    let cart = Cart::new(&transfer(0x0F, sc), true);
+    cart.link.plug(Box::new(Complement(None)));
+    let mut gb = cart.gb();
+    gb.gb_init_link();
+    while cart.ram(0) == 0 {
+        gb._step_cpu();
+    }
+    assert_eq!(cart.ram(0), 0xF0);
+    gb.get_clock()
+}
+
+#[test]
+fn cgb_fast_clock() {
+    let (normal, fast) = (cycles_to_transfer(SC_INTERNAL), cycles_to_transfer(SC_FAST));
+    /* 8192Hz against 262144Hz, 32 times quicker less the setup. */
+    assert!(fast * 8 < normal, "fast {fast}, normal {normal}");
+}
+
+/// Runs the external clock side on its own thread, as it would be on
+/// another device.
+fn spawn_slave(link: impl FnOnce() -> TcpLink + Send + 'static) -> thread::JoinHandle<u8> {
+    thread::spawn(move || {
+        let slave = linked(0x99, SC_EXTERNAL);
+        slave.link.plug(Box::new(link()));
+        run_until_received(&slave)
+    })
+}
+
+#[test]
+fn tcp_connecting_side_clocks() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let slave = spawn_slave(move || TcpLink::accept(&listener).unwrap());
+
+    let master = linked(0x42, SC_INTERNAL);
+    master.link.plug(Box::new(TcpLink::connect(addr).unwrap()));
+    /* Gives the slave time to offer SB before it's clocked. */
+    thread::sleep(Duration::from_millis(50));
+    assert_eq!(run_until_received(&master), 0x99);
+    assert_eq!(slave.join().unwrap(), 0x42);
+}
+
+#[test]
+fn tcp_listening_side_clocks() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let slave = spawn_slave(move || TcpLink::connect(addr).unwrap());
+
+    let master = linked(0x42, SC_INTERNAL);
+    master
+        .link
+        .plug(Box::new(TcpLink::accept(&listener).unwrap()));
+    thread::sleep(Duration::from_millis(50));
+    assert_eq!(run_until_received(&master), 0x99);
+    assert_eq!(slave.join().unwrap(), 0x42);
+}
+
+#[test]
+fn tcp_timeout_on_a_silent_peer() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let mut link = TcpLink::connect(listener.local_addr().unwrap()).unwrap();
+    /* Connected, but never answers. */
+    let (_peer, _) = listener.accept().unwrap();
+
+    link.start_transfer(0x42);
+    let start = Instant::now();
+    assert_eq!(link.finish_transfer(), None);
+    assert!(start.elapsed() >= LINK_TIMEOUT);
+    assert!(link.is_connected());
+
+    link.set_timeout(Duration::from_millis(20));
+    link.start_transfer(0x42);
+    let start = Instant::now();
+    assert_eq!(link.finish_transfer(), None);
+    assert!(start.elapsed() < LINK_TIMEOUT);
+}
+
+#[test]
+fn tcp_peer_gone() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let link = TcpLink::connect(listener.local_addr().unwrap()).unwrap();
+    let (peer, _) = listener.accept().unwrap();
+    drop(peer);
+    let start = Instant::now();
+    while link.is_connected() && start.elapsed() < Duration::from_secs(1) {
+        thread::sleep(Duration::from_millis(5));
+    }
+    assert!(!link.is_connected());
+
+    /* A game clocking a dead link reads 0xFF without waiting out the timeout. */
+    let master = linked(0x42, SC_INTERNAL);
+    master.link.plug(Box::new(link));
+    let start = Instant::now();
+    assert_eq!(run_until_received(&master), 0xFF);
+    assert!(start.elapsed() < LINK_TIMEOUT);
+}
+
+#[test]
+fn tcp_peer_gone_mid_transfer() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let mut link = TcpLink::connect(listener.local_addr().unwrap()).unwrap();
+    let (peer, _) = listener.accept().unwrap();
+
+    link.start_transfer(0x42);
+    let closer = thread::spawn(move || {
+        thread::sleep(Duration::from_millis(20));
+        drop(peer);
+    });
+    let start = Instant::now();
+    assert_eq!(link.finish_transfer(), None);
+    assert!(start.elapsed() < LINK_TIMEOUT);
+    closer.join().unwrap();
+}
@@ -0,0 +1,89 @@
+mod common;
+
+use cashew_tools::cashew_gb::memory::Registers;
+use common::Cart;
+
+const PROGRAM: [u8; 2] = [0x18, 0xFE /* jr -2 */];
+
+#[test]
+fn poke_through_echo_ram() {
+    let cart = Cart::new(&PROGRAM, false);
+    let mut gb = cart.gb();
+    gb.gb_poke(0xE123, 0x5A);
+    assert_eq!(gb.gb_peek(0xC123), 0x5A);
+    assert_eq!(gb.get_wram_bank(0).unwrap()[0x123], 0x5A);
+    gb.gb_poke(0xDDFF, 0xA5);
+    assert_eq!(gb.gb_peek(0xFDFF), 0xA5);
+
+    /* ROM stays as it is, cart RAM and OAM take the byte. */
+    gb.gb_poke(0x0150, 0x00);
+    assert_eq!(gb.gb_peek(0x0150), 0x18);
+    gb.gb_poke(0xA005, 0x77);
+    assert_eq!(cart.ram(5), 0x77);
+    gb.gb_poke(0xFE02, 0x10);
+    assert_eq!(gb.get_oam()[2], 0x10);
+}
+
+#[test]
+#[cfg(feature = "gbc")]
+fn poke_vbk_and_svbk() {
+    let cart = Cart::new(&PROGRAM, true);
+    let mut gb = cart.gb();
+    gb.gb_poke(0x8000, 0x11);
+    gb.gb_poke(0xFF4F, 0x01);
+    gb.gb_poke(0x8000, 0x22);
+    assert_eq!(gb.get_vram_bank(0).unwrap()[0], 0x11);
+    assert_eq!(gb.get_vram_bank(1).unwrap()[0], 0x22);
+    assert_eq!(gb.get_bank(0x8000), 1);
+    gb.gb_poke(0xFF4F, 0x00);
+    assert_eq!(gb.gb_peek(0x8000), 0x11);
+
+    gb.gb_poke(0xFF70, 0x03);
+    gb.gb_poke(0xD010, 0x33);
+    assert_eq!(gb.get_wram_bank(3).unwrap()[0x10], 0x33);
+    assert_eq!(gb.get_bank(0xD010), 3);
+    /* Echo RAM follows the bank too. */
+    gb.gb_poke(0xF011, 0x34);
+    assert_eq!(gb.get_wram_bank(3).unwrap()[0x11], 0x34);
+    /* Bank 0 selects bank 1. */
+    gb.gb_poke(0xFF70, 0x00);
+    assert_eq!(gb.get_bank(0xD010), 1);
+    assert_eq!(gb.gb_peek(0xD010), 0x00);
+}
+
+#[test]
+#[cfg(feature = "gbc")]
+fn dmg_game_ignores_vbk() {
+    let cart = Cart::new(&PROGRAM, false);
+    let mut gb = cart.gb();
+    gb.gb_poke(0xFF4F, 0x01);
+    gb.gb_poke(0x8000, 0x22);
+    assert_eq!(gb.get_vram_bank(0).unwrap()[0], 0x22);
+    assert_eq!(gb.get_vram_bank(1).unwrap()[0], 0x00);
+}
+
+#[test]
+fn registers_round_trip() {
+    let cart = Cart::new(&PROGRAM, false);
+    let mut gb = cart.gb();
+    let registers = Registers {
+        a: 0x12,
+        f: 0xFF,
+        b: 0x34,
+        c: 0x56,
+        d: 0x78,
+        e: 0x9A,
+        h: 0xBC,
+        l: 0xDE,
+        sp: 0xDFF0,
+        pc: 0x0150,
+    };
+    gb.gb_set_registers(&registers);
+    assert_eq!(
+        gb.get_registers(),
+        Registers {
+            f: 0xF0,
+            ..registers
+        }
+    );
+}
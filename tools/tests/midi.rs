@@ -0,0 +1,116 @@
+use cashew_tools::cashew_gb::{
+    midi::{MidiPort, SmfWriter, MIDI_CLOCK, MIDI_NOTE_OFF, MIDI_NOTE_ON},
+    DMG_CLOCK_FREQ,
+};
+
+const SECOND: u64 = DMG_CLOCK_FREQ as u64;
+
+/// A big endian number of `N` bytes at `at`.
+fn be<const N: usize>(bytes: &[u8], at: usize) -> u64 {
+    bytes[at..at + N]
+        .iter()
+        .fold(0, |value, &byte| value << 8 | byte as u64)
+}
+
+fn read_variable_length(track: &[u8], at: &mut usize) -> u64 {
+    let mut value = 0;
+    loop {
+        let byte = track[*at];
+        *at += 1;
+        value = value << 7 | (byte & 0x7F) as u64;
+        if byte & 0x80 == 0 {
+            return value;
+        }
+    }
+}
+
+/// The absolute tick and bytes of every event in the file's only track,
+/// after checking the header and track length.
+fn parse(smf: &[u8]) -> Vec<(u64, Vec<u8>)> {
+    assert_eq!(&smf[..4], b"MThd");
+    assert_eq!(be::<4>(smf, 4), 6);
+    /* Format 0, one track, 480 ticks a quarter note. */
+    assert_eq!(be::<2>(smf, 8), 0);
+    assert_eq!(be::<2>(smf, 10), 1);
+    assert_eq!(be::<2>(smf, 12), 480);
+    assert_eq!(&smf[14..18], b"MTrk");
+    let track = &smf[22..];
+    assert_eq!(be::<4>(smf, 18), track.len() as u64);
+
+    let mut events = Vec::new();
+    let (mut at, mut tick) = (0, 0);
+    while at < track.len() {
+        tick += read_variable_length(track, &mut at);
+        let length = match track[at] {
+            0xFF => {
+                let length = track[at + 2] as usize;
+                events.push((tick, track[at..at + 3 + length].to_vec()));
+                at += 3 + length;
+                continue;
+            }
+            0xF7 => {
+                at += 1;
+                read_variable_length(track, &mut at) as usize
+            }
+            0xC0..=0xDF => 2,
+            _ => 3,
+        };
+        events.push((tick, track[at..at + length].to_vec()));
+        at += length;
+    }
+    events
+}
+
+fn write(smf: &SmfWriter) -> Vec<u8> {
+    let mut out = Vec::new();
+    smf.write(&mut out).unwrap();
+    out
+}
+
+#[test]
+fn empty() {
+    let events = parse(&write(&SmfWriter::new()));
+    /* 120 BPM, then the end of the track. */
+    assert_eq!(
+        events,
+        [
+            (0, vec![0xFF, 0x51, 0x03, 0x07, 0xA1, 0x20]),
+            (0, vec![0xFF, 0x2F, 0x00]),
+        ]
+    );
+}
+
+#[test]
+fn ticks_from_the_first_message() {
+    let mut smf = SmfWriter::new();
+    let start = 1000;
+    smf.send(start, &[MIDI_NOTE_ON, 60, 100]);
+    smf.send(start + SECOND / 2, &[0xC1, 5]);
+    smf.send(start + SECOND, &[MIDI_CLOCK]);
+    smf.send(start + SECOND, &[MIDI_NOTE_OFF, 60, 0]);
+    /* Past the 2 byte variable length limit. */
+    smf.send(start + 20 * SECOND, &[MIDI_NOTE_ON, 61, 100]);
+
+    let events = parse(&write(&smf));
+    /* 960 ticks a second, system messages escaped with 0xF7. */
+    assert_eq!(
+        events[1..events.len() - 1],
+        [
+            (0, vec![MIDI_NOTE_ON, 60, 100]),
+            (480, vec![0xC1, 5]),
+            (960, vec![MIDI_CLOCK]),
+            (960, vec![MIDI_NOTE_OFF, 60, 0]),
+            (19200, vec![MIDI_NOTE_ON, 61, 100]),
+        ]
+    );
+    assert_eq!(events.last().unwrap(), &(19200, vec![0xFF, 0x2F, 0x00]));
+}
+
+#[test]
+fn escaped_system_message_bytes() {
+    let mut smf = SmfWriter::new();
+    smf.send(0, &[MIDI_CLOCK]);
+    let out = write(&smf);
+    /* Delta 0, escape, length 1, the message. */
+    assert_eq!(&out[22 + 7..22 + 11], &[0x00, 0xF7, 0x01, MIDI_CLOCK]);
+}
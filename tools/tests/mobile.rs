@@ -0,0 +1,417 @@
+use cashew_tools::cashew_gb::{
+    link::LinkTransport,
+    mobile::{MobileAdapter, MobileEndpoints, MOBILE_MAX_TRANSFER, MOBILE_PHONE_CONNECTION},
+};
+use std::{
+    io::{Read, Write},
+    net::{Ipv4Addr, SocketAddr, TcpListener},
+    thread,
+    time::{Duration, Instant},
+};
+
+const MAGIC: [u8; 2] = [0x99, 0x66];
+const ADAPTER_IDLE: u8 = 0xD2;
+const CONSOLE_IDLE: u8 = 0x4B;
+const CONSOLE_ID: u8 = 0x80;
+const ADAPTER_ID: u8 = 0x88;
+const ERROR_UNKNOWN_COMMAND: u8 = 0xF0;
+const ERROR_CHECKSUM: u8 = 0xF1;
+
+const BEGIN_SESSION: u8 = 0x10;
+const END_SESSION: u8 = 0x11;
+const DIAL: u8 = 0x12;
+const HANG_UP: u8 = 0x13;
+const TRANSFER_DATA: u8 = 0x15;
+const TELEPHONE_STATUS: u8 = 0x17;
+const READ_CONFIG: u8 = 0x19;
+const WRITE_CONFIG: u8 = 0x1A;
+const CONNECTION_CLOSED: u8 = 0x1F;
+const ISP_LOGIN: u8 = 0x21;
+const ISP_LOGOUT: u8 = 0x22;
+const OPEN_TCP: u8 = 0x23;
+const CLOSE_TCP: u8 = 0x24;
+const DNS_QUERY: u8 = 0x28;
+const ERROR: u8 = 0x6E;
+
+const INVALID_STATE: u8 = 0x01;
+const INVALID_DATA: u8 = 0x02;
+const CONNECTION_FAILED: u8 = 0x03;
+
+const TELEPHONE_IDLE: u8 = 0x00;
+const TELEPHONE_CONNECTED: u8 = 0x04;
+
+fn clock(adapter: &mut MobileAdapter, byte: u8) -> u8 {
+    adapter.start_transfer(byte);
+    adapter.finish_transfer().unwrap()
+}
+
+/// Clocks idle bytes until the reply starts, as games do while the adapter
+/// is busy.
+fn wait_for_reply(adapter: &mut MobileAdapter) {
+    let start = Instant::now();
+    loop {
+        match clock(adapter, CONSOLE_IDLE) {
+            ADAPTER_IDLE => {}
+            byte => return assert_eq!(byte, MAGIC[0]),
+        }
+        assert!(start.elapsed() < Duration::from_secs(5), "no reply");
+        thread::sleep(Duration::from_millis(1));
+    }
+}
+
+/// Sends a packet with its checksum off by `checksum_error`. Returns the
+/// acknowledgement, and the command and data of the reply if there is one.
+fn send(
+    adapter: &mut MobileAdapter,
+    command: u8,
+    data: &[u8],
+    checksum_error: u16,
+) -> (u8, Option<(u8, Vec<u8>)>) {
+    let mut packet = vec![MAGIC[0], MAGIC[1], command, 0x00];
+    packet.extend_from_slice(&(data.len() as u16).to_be_bytes());
+    packet.extend_from_slice(data);
+    let checksum = packet[2..]
+        .iter()
+        .fold(checksum_error, |sum, &byte| sum.wrapping_add(byte as u16));
+    packet.extend_from_slice(&checksum.to_be_bytes());
+    for byte in packet {
+        assert_eq!(clock(adapter, byte), ADAPTER_IDLE);
+    }
+    assert_eq!(clock(adapter, CONSOLE_ID), ADAPTER_ID);
+    let ack = clock(adapter, 0x00);
+    if ack != command ^ 0x80 {
+        return (ack, None);
+    }
+
+    wait_for_reply(adapter);
+    let mut reply = vec![MAGIC[0]];
+    for _ in 0..5 {
+        reply.push(clock(adapter, CONSOLE_IDLE));
+    }
+    assert_eq!(reply[1], MAGIC[1]);
+    let length = u16::from_be_bytes([reply[4], reply[5]]) as usize;
+    for _ in 0..length + 2 {
+        reply.push(clock(adapter, CONSOLE_IDLE));
+    }
+    let checksum = reply[2..6 + length]
+        .iter()
+        .fold(0_u16, |sum, &byte| sum.wrapping_add(byte as u16));
+    assert_eq!(checksum.to_be_bytes(), reply[6 + length..]);
+    assert_eq!(clock(adapter, CONSOLE_ID), ADAPTER_ID);
+    assert_eq!(clock(adapter, reply[2] ^ 0x80), 0x00);
+    (ack, Some((reply[2] & 0x7F, reply[6..6 + length].to_vec())))
+}
+
+/// The command and data of the reply to a packet that was accepted.
+fn command(adapter: &mut MobileAdapter, command: u8, data: &[u8]) -> (u8, Vec<u8>) {
+    let (ack, reply) = send(adapter, command, data, 0);
+    assert_eq!(ack, command ^ 0x80);
+    reply.unwrap()
+}
+
+/// Sends `data` on connection `id`, then polls until something comes back.
+fn transfer(adapter: &mut MobileAdapter, id: u8, data: &[u8]) -> Vec<u8> {
+    let mut packet = vec![id];
+    packet.extend_from_slice(data);
+    let mut reply = command(adapter, TRANSFER_DATA, &packet);
+    let start = Instant::now();
+    while reply.0 == TRANSFER_DATA && reply.1.len() == 1 {
+        assert!(
+            start.elapsed() < Duration::from_secs(5),
+            "nothing came back"
+        );
+        thread::sleep(Duration::from_millis(5));
+        reply = command(adapter, TRANSFER_DATA, &[id]);
+    }
+    assert_eq!(reply.0, TRANSFER_DATA);
+    assert_eq!(reply.1[0], id);
+    reply.1[1..].to_vec()
+}
+
+/// A server on 127.0.0.1 that sends back whatever it is sent.
+fn echo_server() -> SocketAddr {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let mut stream = stream.unwrap();
+            thread::spawn(move || {
+                let mut buffer = [0; 64];
+                while let Ok(size @ 1..) = stream.read(&mut buffer) {
+                    stream.write_all(&buffer[..size]).unwrap();
+                }
+            });
+        }
+    });
+    addr
+}
+
+/// An address nothing listens on.
+fn closed_port() -> SocketAddr {
+    TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+}
+
+fn start_session(adapter: &mut MobileAdapter) {
+    assert_eq!(
+        command(adapter, BEGIN_SESSION, b"NINTENDO"),
+        (BEGIN_SESSION, b"NINTENDO".to_vec())
+    );
+}
+
+/// An adapter in a session with its call to the ISP's number made.
+fn dialled(endpoints: MobileEndpoints) -> MobileAdapter {
+    let mut adapter = MobileAdapter::new(endpoints.phone("#9677", echo_server()));
+    start_session(&mut adapter);
+    assert_eq!(command(&mut adapter, DIAL, b"\x00#9677"), (DIAL, vec![]));
+    adapter
+}
+
+#[test]
+fn begin_session() {
+    let mut adapter = MobileAdapter::new(MobileEndpoints::new());
+    assert_eq!(
+        command(&mut adapter, TELEPHONE_STATUS, &[]),
+        (ERROR, vec![TELEPHONE_STATUS, INVALID_STATE])
+    );
+    assert_eq!(
+        command(&mut adapter, BEGIN_SESSION, b"NINTENDX"),
+        (ERROR, vec![BEGIN_SESSION, INVALID_DATA])
+    );
+    assert_eq!(
+        send(&mut adapter, BEGIN_SESSION, b"NINTENDO", 1),
+        (ERROR_CHECKSUM, None)
+    );
+    start_session(&mut adapter);
+    assert_eq!(
+        send(&mut adapter, 0x7F, &[], 0),
+        (ERROR_UNKNOWN_COMMAND, None)
+    );
+    assert_eq!(
+        command(&mut adapter, END_SESSION, &[]),
+        (END_SESSION, vec![])
+    );
+    assert_eq!(command(&mut adapter, TELEPHONE_STATUS, &[]).0, ERROR);
+}
+
+#[test]
+fn config() {
+    let mut adapter = MobileAdapter::new(MobileEndpoints::new());
+    start_session(&mut adapter);
+    assert_eq!(
+        command(&mut adapter, WRITE_CONFIG, &[0x10, 1, 2, 3]),
+        (WRITE_CONFIG, vec![0x10, 3])
+    );
+    assert_eq!(
+        command(&mut adapter, READ_CONFIG, &[0x0F, 5]),
+        (READ_CONFIG, vec![0x0F, 0, 1, 2, 3, 0])
+    );
+    assert_eq!(adapter.get_config()[0x11], 2);
+    assert_eq!(
+        command(&mut adapter, READ_CONFIG, &[0xBF, 2]),
+        (ERROR, vec![READ_CONFIG, INVALID_DATA])
+    );
+}
+
+#[test]
+fn malformed_packets() {
+    let mut adapter = MobileAdapter::new(MobileEndpoints::new());
+    start_session(&mut adapter);
+    for (command_id, data) in [
+        (READ_CONFIG, &[0x00][..]),
+        (WRITE_CONFIG, &[]),
+        (OPEN_TCP, &[127, 0, 0, 1, 0]),
+        (CLOSE_TCP, &[]),
+        (TRANSFER_DATA, &[]),
+    ] {
+        assert_eq!(
+            command(&mut adapter, command_id, data),
+            (ERROR, vec![command_id, INVALID_DATA])
+        );
+    }
+}
+
+#[test]
+fn dial_and_telephone_status() {
+    let mut adapter = MobileAdapter::new(
+        MobileEndpoints::new()
+            .phone("#9677", echo_server())
+            .phone("#0000", closed_port()),
+    );
+    start_session(&mut adapter);
+    assert_eq!(
+        command(&mut adapter, TELEPHONE_STATUS, &[]),
+        (TELEPHONE_STATUS, vec![TELEPHONE_IDLE, 0x4D, 0x00])
+    );
+    assert_eq!(
+        command(&mut adapter, DIAL, b"\x00#1234"),
+        (ERROR, vec![DIAL, CONNECTION_FAILED])
+    );
+    /* A number that doesn't answer fails once the connection does. */
+    assert_eq!(
+        command(&mut adapter, DIAL, b"\x00#0000"),
+        (ERROR, vec![DIAL, CONNECTION_FAILED])
+    );
+
+    assert_eq!(command(&mut adapter, DIAL, b"\x00#9677"), (DIAL, vec![]));
+    assert_eq!(
+        command(&mut adapter, TELEPHONE_STATUS, &[]),
+        (TELEPHONE_STATUS, vec![TELEPHONE_CONNECTED, 0x4D, 0x00])
+    );
+    assert_eq!(
+        transfer(&mut adapter, MOBILE_PHONE_CONNECTION, b"hello"),
+        b"hello"
+    );
+    assert_eq!(command(&mut adapter, HANG_UP, &[]), (HANG_UP, vec![]));
+    assert_eq!(
+        command(&mut adapter, TELEPHONE_STATUS, &[]).1[0],
+        TELEPHONE_IDLE
+    );
+    assert_eq!(
+        command(&mut adapter, TRANSFER_DATA, &[MOBILE_PHONE_CONNECTION, 1]),
+        (ERROR, vec![TRANSFER_DATA, INVALID_STATE])
+    );
+}
+
+#[test]
+fn isp_login() {
+    let mut adapter = MobileAdapter::new(MobileEndpoints::new());
+    start_session(&mut adapter);
+    let login = [2, b'i', b'd', 1, b'p', 1, 2, 3, 4, 5, 6, 7, 8];
+    assert_eq!(
+        command(&mut adapter, ISP_LOGIN, &login),
+        (ERROR, vec![ISP_LOGIN, INVALID_STATE])
+    );
+
+    let mut adapter = dialled(MobileEndpoints::new().address(Ipv4Addr::new(10, 0, 0, 2)));
+    /* The address handed out, then the DNS servers as given. */
+    assert_eq!(
+        command(&mut adapter, ISP_LOGIN, &login),
+        (ISP_LOGIN, vec![10, 0, 0, 2, 1, 2, 3, 4, 5, 6, 7, 8])
+    );
+    assert_eq!(
+        command(&mut adapter, ISP_LOGIN, &login[..9]),
+        (ERROR, vec![ISP_LOGIN, INVALID_DATA])
+    );
+    assert_eq!(command(&mut adapter, ISP_LOGOUT, &[]), (ISP_LOGOUT, vec![]));
+}
+
+#[test]
+fn tcp_open_transfer_close() {
+    let fake = Ipv4Addr::new(203, 0, 113, 5);
+    let mut adapter = dialled(
+        MobileEndpoints::new()
+            .resolve("gameboy.datacenter.ne.jp", fake)
+            .route(fake, 80, echo_server())
+            .route(fake, 81, closed_port()),
+    );
+    assert_eq!(
+        command(&mut adapter, DNS_QUERY, b"GameBoy.datacenter.ne.jp"),
+        (DNS_QUERY, fake.octets().to_vec())
+    );
+    assert_eq!(
+        command(&mut adapter, DNS_QUERY, b"example.com"),
+        (ERROR, vec![DNS_QUERY, CONNECTION_FAILED])
+    );
+    assert_eq!(
+        command(&mut adapter, OPEN_TCP, &[203, 0, 113, 6, 0, 80]),
+        (ERROR, vec![OPEN_TCP, CONNECTION_FAILED])
+    );
+    assert_eq!(
+        command(&mut adapter, OPEN_TCP, &[203, 0, 113, 5, 0, 81]),
+        (ERROR, vec![OPEN_TCP, CONNECTION_FAILED])
+    );
+
+    let (reply, id) = command(&mut adapter, OPEN_TCP, &[203, 0, 113, 5, 0, 80]);
+    assert_eq!((reply, id.len()), (OPEN_TCP, 1));
+    let id = id[0];
+    assert_eq!(
+        transfer(&mut adapter, id, b"GET / HTTP/1.0"),
+        b"GET / HTTP/1.0"
+    );
+    assert_eq!(
+        command(&mut adapter, CLOSE_TCP, &[id]),
+        (CLOSE_TCP, vec![id])
+    );
+    assert_eq!(
+        command(&mut adapter, CLOSE_TCP, &[id]),
+        (ERROR, vec![CLOSE_TCP, INVALID_DATA])
+    );
+    assert_eq!(
+        command(&mut adapter, TRANSFER_DATA, &[id, 1]),
+        (ERROR, vec![TRANSFER_DATA, INVALID_STATE])
+    );
+}
+
+#[test]
+fn transfers_arrive_whole() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let sink = thread::spawn(move || {
+        let (mut stream, _) = listener.accept().unwrap();
+        /* Only starts reading once the game has sent everything. */
+        thread::sleep(Duration::from_millis(100));
+        let mut received = Vec::new();
+        stream.read_to_end(&mut received).unwrap();
+        received
+    });
+
+    let mut adapter = dialled(MobileEndpoints::new().route_all(addr.ip()));
+    let port = addr.port().to_be_bytes();
+    let (_, id) = command(&mut adapter, OPEN_TCP, &[1, 2, 3, 4, port[0], port[1]]);
+    let id = id[0];
+    let mut sent = Vec::new();
+    for i in 0..256 {
+        let mut packet = vec![id];
+        packet.extend((0..MOBILE_MAX_TRANSFER).map(|j| (i + j) as u8));
+        assert_eq!(
+            command(&mut adapter, TRANSFER_DATA, &packet),
+            (TRANSFER_DATA, vec![id])
+        );
+        sent.extend_from_slice(&packet[1..]);
+    }
+    /* Polls until everything left over went out, then hangs up. */
+    let start = Instant::now();
+    while start.elapsed() < Duration::from_millis(500) {
+        command(&mut adapter, TRANSFER_DATA, &[id]);
+        thread::sleep(Duration::from_millis(5));
+    }
+    command(&mut adapter, CLOSE_TCP, &[id]);
+    assert_eq!(sink.join().unwrap(), sent);
+}
+
+#[test]
+fn remote_close() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let server = thread::spawn(move || {
+        let (mut stream, _) = listener.accept().unwrap();
+        stream.write_all(b"bye").unwrap();
+    });
+    let mut adapter = dialled(MobileEndpoints::new().route_all(addr.ip()));
+    let port = addr.port().to_be_bytes();
+    let (_, id) = command(&mut adapter, OPEN_TCP, &[1, 2, 3, 4, port[0], port[1]]);
+    let id = id[0];
+    server.join().unwrap();
+
+    /* What was sent before closing still arrives, then the close. */
+    let mut received = Vec::new();
+    let start = Instant::now();
+    loop {
+        let (reply, data) = command(&mut adapter, TRANSFER_DATA, &[id]);
+        if reply == CONNECTION_CLOSED {
+            assert_eq!(data, [id]);
+            break;
+        }
+        received.extend_from_slice(&data[1..]);
+        assert!(start.elapsed() < Duration::from_secs(5), "never closed");
+        thread::sleep(Duration::from_millis(5));
+    }
+    assert_eq!(received, b"bye");
+    assert_eq!(
+        command(&mut adapter, CLOSE_TCP, &[id]),
+        (ERROR, vec![CLOSE_TCP, INVALID_DATA])
+    );
+}
@@ -0,0 +1,327 @@
+use cashew_tools::{
+    cashew_gb::{crc32, CartridgeHeader},
+    patch::{self, GbPatchError, PatchFormat},
+};
+use std::{env, fs};
+
+/// 32KB of counting bytes with a valid header checksum.
+fn rom() -> Vec<u8> {
+    let mut rom: Vec<u8> = (0..0x8000).map(|i| i as u8).collect();
+    rom[0x134..0x144].copy_from_slice(b"PATCHTEST\0\0\0\0\0\0\0");
+    patch::fix_header_checksum(&mut rom).unwrap();
+    rom
+}
+
+fn varint(patch: &mut Vec<u8>, mut value: usize) {
+    loop {
+        let bits = (value & 0x7F) as u8;
+        value >>= 7;
+        if value == 0 {
+            patch.push(0x80 | bits);
+            return;
+        }
+        patch.push(bits);
+        value -= 1;
+    }
+}
+
+/// Appends the source, target and patch CRC32s.
+fn footer(patch: &mut Vec<u8>, source: &[u8], target: &[u8]) {
+    patch.extend_from_slice(&crc32(source).to_le_bytes());
+    patch.extend_from_slice(&crc32(target).to_le_bytes());
+    let patch_crc = crc32(patch);
+    patch.extend_from_slice(&patch_crc.to_le_bytes());
+}
+
+#[test]
+fn crc32_check_value() {
+    assert_eq!(crc32(b"123456789"), 0xCBF43926);
+}
+
+#[test]
+fn detect() {
+    assert_eq!(patch::detect(b"PATCHEOF"), Some(PatchFormat::Ips));
+    assert_eq!(patch::detect(b"UPS1"), Some(PatchFormat::Ups));
+    assert_eq!(patch::detect(b"BPS1"), Some(PatchFormat::Bps));
+    assert_eq!(patch::detect(b"PK\x03\x04"), None);
+    assert_eq!(
+        patch::apply(&rom(), b"PK\x03\x04"),
+        Err(GbPatchError::GbPatchUnknownFormat)
+    );
+}
+
+#[test]
+fn ips_records_and_rle() {
+    let rom = rom();
+    let mut ips = b"PATCH".to_vec();
+    ips.extend_from_slice(&[0x00, 0x10, 0x00, 0x00, 0x03, 0xAA, 0xBB, 0xCC]);
+    ips.extend_from_slice(&[0x00, 0x20, 0x00, 0x00, 0x00, 0x00, 0x05, 0x99]);
+    ips.extend_from_slice(b"EOF");
+
+    let patched = patch::apply(&rom, &ips).unwrap();
+    assert_eq!(patched.len(), rom.len());
+    assert_eq!(&patched[0x1000..0x1003], &[0xAA, 0xBB, 0xCC]);
+    assert_eq!(&patched[0x2000..0x2005], &[0x99; 5]);
+    assert_eq!(patched[0x2005], rom[0x2005]);
+    assert_eq!(&patched[..0x1000], &rom[..0x1000]);
+}
+
+#[test]
+fn ips_grows_the_rom() {
+    let rom = rom();
+    let mut ips = b"PATCH".to_vec();
+    ips.extend_from_slice(&[0x00, 0x80, 0x02, 0x00, 0x00, 0x00, 0x02, 0x11]);
+    ips.extend_from_slice(b"EOF");
+
+    let patched = patch::apply(&rom, &ips).unwrap();
+    assert_eq!(patched.len(), 0x8004);
+    assert_eq!(&patched[0x8000..], &[0x00, 0x00, 0x11, 0x11]);
+}
+
+#[test]
+fn ips_truncate_extension() {
+    let rom = rom();
+    let mut ips = b"PATCH".to_vec();
+    ips.extend_from_slice(b"EOF");
+    ips.extend_from_slice(&[0x00, 0x40, 0x00]);
+
+    let patched = patch::apply(&rom, &ips).unwrap();
+    assert_eq!(patched, &rom[..0x4000]);
+}
+
+#[test]
+fn ips_truncated() {
+    let mut ips = b"PATCH".to_vec();
+    ips.extend_from_slice(&[0x00, 0x10, 0x00, 0x00, 0x03, 0xAA]);
+    assert_eq!(
+        patch::apply(&rom(), &ips),
+        Err(GbPatchError::GbPatchTruncated)
+    );
+}
+
+#[test]
+fn ips_header_checksum() {
+    let rom = rom();
+    let mut ips = b"PATCH".to_vec();
+    ips.extend_from_slice(&[0x00, 0x01, 0x34, 0x00, 0x01, b'X']);
+    ips.extend_from_slice(b"EOF");
+
+    let Err(GbPatchError::GbPatchHeaderChecksum { stored, computed }) = patch::apply(&rom, &ips)
+    else {
+        panic!("the title changed without the checksum");
+    };
+    assert_ne!(stored, computed);
+
+    let mut patched = patch::apply_unchecked(&rom, &ips).unwrap();
+    patch::fix_header_checksum(&mut patched).unwrap();
+    assert_eq!(patched[0x14D], computed);
+    assert!(CartridgeHeader::new(&patched)
+        .unwrap()
+        .is_header_checksum_valid());
+}
+
+fn ups(source: &[u8], target: &[u8]) -> Vec<u8> {
+    let mut ups = b"UPS1".to_vec();
+    varint(&mut ups, source.len());
+    varint(&mut ups, target.len());
+    let mut last = 0;
+    let mut offset = 0;
+    while offset < target.len() {
+        let xor = |i: usize| source.get(i).copied().unwrap_or(0) ^ target[i];
+        if xor(offset) == 0 {
+            offset += 1;
+            continue;
+        }
+        varint(&mut ups, offset - last);
+        while offset < target.len() && xor(offset) != 0 {
+            ups.push(xor(offset));
+            offset += 1;
+        }
+        ups.push(0);
+        offset += 1;
+        last = offset;
+    }
+    footer(&mut ups, source, target);
+    ups
+}
+
+#[test]
+fn ups_round_trip() {
+    let rom = rom();
+    let mut target = rom.clone();
+    target[0x150..0x158].copy_from_slice(b"UPSPATCH");
+    target[0x7FFF] = 0;
+    target.extend_from_slice(&[1, 2, 3]);
+
+    let ups = ups(&rom, &target);
+    assert_eq!(patch::apply(&rom, &ups).unwrap(), target);
+}
+
+#[test]
+fn ups_checks_the_source() {
+    let rom = rom();
+    let mut target = rom.clone();
+    target[0x200] ^= 0xFF;
+    let ups = ups(&rom, &target);
+
+    let mut other = rom.clone();
+    other[0x300] ^= 0xFF;
+    let Err(GbPatchError::GbPatchSourceChecksum { expected, actual }) = patch::apply(&other, &ups)
+    else {
+        panic!("patched the wrong ROM");
+    };
+    assert_eq!((expected, actual), (crc32(&rom), crc32(&other)));
+
+    assert_eq!(
+        patch::apply(&rom[..0x4000], &ups),
+        Err(GbPatchError::GbPatchSourceSize {
+            expected: 0x8000,
+            actual: 0x4000
+        })
+    );
+}
+
+#[test]
+fn ups_checks_the_target_and_patch() {
+    let rom = rom();
+    let mut target = rom.clone();
+    target[0x200] ^= 0xFF;
+
+    let mut ups = ups(&rom, &target);
+    let target_crc = ups.len() - 8;
+    ups[target_crc] ^= 1;
+    let patch_crc = ups.len() - 4;
+    let crc = crc32(&ups[..patch_crc]);
+    ups[patch_crc..].copy_from_slice(&crc.to_le_bytes());
+    assert!(matches!(
+        patch::apply(&rom, &ups),
+        Err(GbPatchError::GbPatchTargetChecksum { .. })
+    ));
+
+    let mut ups = self::ups(&rom, &target);
+    ups[6] ^= 1;
+    assert!(matches!(
+        patch::apply(&rom, &ups),
+        Err(GbPatchError::GbPatchPatchChecksum { .. })
+    ));
+}
+
+const BPS_SOURCE_READ: usize = 0;
+const BPS_TARGET_READ: usize = 1;
+const BPS_SOURCE_COPY: usize = 2;
+const BPS_TARGET_COPY: usize = 3;
+
+fn bps_action(bps: &mut Vec<u8>, command: usize, length: usize) {
+    varint(bps, (length - 1) << 2 | command);
+}
+
+fn bps_offset(bps: &mut Vec<u8>, relative: isize) {
+    varint(bps, relative.unsigned_abs() << 1 | (relative < 0) as usize);
+}
+
+#[test]
+fn bps_every_action() {
+    let rom = rom();
+    let mut target = rom[..0x4000].to_vec();
+    target.extend_from_slice(b"BPS");
+    /* Overlapping target copy, repeats the last three bytes. */
+    target.extend_from_slice(b"BPSBPSBPS");
+    target.extend_from_slice(&rom[0x100..0x200]);
+    target.extend_from_slice(&rom[0x10..0x20]);
+
+    let mut bps = b"BPS1".to_vec();
+    varint(&mut bps, rom.len());
+    varint(&mut bps, target.len());
+    varint(&mut bps, 4);
+    bps.extend_from_slice(b"meta");
+    bps_action(&mut bps, BPS_SOURCE_READ, 0x4000);
+    bps_action(&mut bps, BPS_TARGET_READ, 3);
+    bps.extend_from_slice(b"BPS");
+    bps_action(&mut bps, BPS_TARGET_COPY, 9);
+    bps_offset(&mut bps, 0x4000);
+    bps_action(&mut bps, BPS_SOURCE_COPY, 0x100);
+    bps_offset(&mut bps, 0x100);
+    bps_action(&mut bps, BPS_SOURCE_COPY, 0x10);
+    bps_offset(&mut bps, 0x10 - 0x200);
+    footer(&mut bps, &rom, &target);
+
+    assert_eq!(patch::apply(&rom, &bps).unwrap(), target);
+
+    let mut other = rom.clone();
+    other[0x7000] ^= 0xFF;
+    assert!(matches!(
+        patch::apply(&other, &bps),
+        Err(GbPatchError::GbPatchSourceChecksum { .. })
+    ));
+}
+
+#[test]
+fn bps_copy_out_of_bounds() {
+    let rom = rom();
+    let mut bps = b"BPS1".to_vec();
+    varint(&mut bps, rom.len());
+    varint(&mut bps, 4);
+    varint(&mut bps, 0);
+    bps_action(&mut bps, BPS_SOURCE_COPY, 4);
+    bps_offset(&mut bps, -1);
+    footer(&mut bps, &rom, &[]);
+    assert_eq!(
+        patch::apply(&rom, &bps),
+        Err(GbPatchError::GbPatchOutOfBounds)
+    );
+}
+
+#[test]
+fn crafted_sizes_and_offsets() {
+    let rom = rom();
+    /* Past the largest ROM, before anything is allocated. */
+    for magic in [b"UPS1", b"BPS1"] {
+        let mut patch = magic.to_vec();
+        varint(&mut patch, rom.len());
+        varint(&mut patch, usize::MAX >> 1);
+        varint(&mut patch, 0);
+        footer(&mut patch, &rom, &[]);
+        assert_eq!(
+            patch::apply(&rom, &patch),
+            Err(GbPatchError::GbPatchOutOfBounds)
+        );
+    }
+
+    /* A UPS record skipping past the end of the address space. */
+    let mut ups = b"UPS1".to_vec();
+    varint(&mut ups, rom.len());
+    varint(&mut ups, rom.len());
+    varint(&mut ups, usize::MAX);
+    ups.push(0);
+    varint(&mut ups, 1);
+    ups.push(0);
+    footer(&mut ups, &rom, &rom);
+    assert_eq!(
+        patch::apply(&rom, &ups),
+        Err(GbPatchError::GbPatchOutOfBounds)
+    );
+
+    /* A BPS action longer than the target. */
+    let mut bps = b"BPS1".to_vec();
+    varint(&mut bps, rom.len());
+    varint(&mut bps, 4);
+    varint(&mut bps, 0);
+    bps_action(&mut bps, BPS_TARGET_COPY, usize::MAX >> 2);
+    bps_offset(&mut bps, 0);
+    footer(&mut bps, &rom, &[]);
+    assert_eq!(
+        patch::apply(&rom, &bps),
+        Err(GbPatchError::GbPatchOutOfBounds)
+    );
+}
+
+#[test]
+fn find_patch_next_to_the_rom() {
+    let dir = env::temp_dir().join(format!("cashew-patch-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let rom_path = dir.join("game.gb");
+    assert_eq!(patch::find_patch(&rom_path), None);
+    fs::write(dir.join("game.BPS"), b"BPS1").unwrap();
+    assert_eq!(patch::find_patch(&rom_path), Some(dir.join("game.BPS")));
+    fs::remove_dir_all(&dir).unwrap();
+}
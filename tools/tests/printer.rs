@@ -0,0 +1,295 @@
+use cashew_tools::cashew_gb::{
+    link::LinkTransport,
+    printer::{
+        PrintDirectory, PrintSink, PrintStrip, Printer, PRINTER_BAND_HEIGHT,
+        PRINTER_STATUS_CHECKSUM, PRINTER_STATUS_FULL, PRINTER_STATUS_OTHER_ERROR,
+        PRINTER_STATUS_PACKET_ERROR, PRINTER_STATUS_PRINTING, PRINTER_STATUS_UNPROCESSED,
+        PRINTER_WIDTH,
+    },
+};
+use std::{cell::RefCell, env, fs, io, rc::Rc};
+
+const INIT: u8 = 0x01;
+const PRINT: u8 = 0x02;
+const DATA: u8 = 0x04;
+const BREAK: u8 = 0x08;
+const STATUS: u8 = 0x0F;
+
+const ALIVE: u8 = 0x81;
+/// A band of 2 rows of 20 tiles.
+const BAND_SIZE: usize = 640;
+
+/// Clocks one byte through, as the console does.
+fn clock(printer: &mut Printer, byte: u8) -> u8 {
+    printer.start_transfer(byte);
+    printer.finish_transfer().unwrap()
+}
+
+/// Sends a packet with its checksum off by `checksum_error`, returns the
+/// alive and status bytes the printer answered the last two with.
+fn send(
+    printer: &mut Printer,
+    command: u8,
+    compressed: bool,
+    data: &[u8],
+    checksum_error: u16,
+) -> (u8, u8) {
+    let mut packet = vec![
+        0x88,
+        0x33,
+        command,
+        compressed as u8,
+        data.len() as u8,
+        (data.len() >> 8) as u8,
+    ];
+    packet.extend_from_slice(data);
+    let checksum = packet[2..]
+        .iter()
+        .fold(checksum_error, |sum, &byte| sum.wrapping_add(byte as u16));
+    packet.extend_from_slice(&checksum.to_le_bytes());
+    for byte in packet {
+        assert_eq!(clock(printer, byte), 0x00);
+    }
+    (clock(printer, 0x00), clock(printer, 0x00))
+}
+
+fn packet(printer: &mut Printer, command: u8, compressed: bool, data: &[u8]) -> (u8, u8) {
+    send(printer, command, compressed, data, 0)
+}
+
+/// A printer whose prints are kept in the returned list.
+fn printer() -> (Printer, Rc<RefCell<Vec<PrintStrip>>>) {
+    let strips = Rc::new(RefCell::new(Vec::new()));
+    let sink = strips.clone();
+    let printer = Printer::new(Box::new(move |strip| {
+        sink.borrow_mut().push(strip);
+        Ok(())
+    }));
+    (printer, strips)
+}
+
+/// A band whose every tile row is `low`, `high`.
+fn band(low: u8, high: u8) -> Vec<u8> {
+    [low, high].repeat(BAND_SIZE / 2)
+}
+
+#[test]
+fn packet_state_machine() {
+    let (mut printer, _) = printer();
+    /* Noise before the magic bytes is answered with 0 and ignored. */
+    for byte in [0x00, 0x33, 0x88, 0x88] {
+        assert_eq!(clock(&mut printer, byte), 0x00);
+    }
+    for byte in [0x33, INIT, 0x00, 0x00, 0x00, 0x01, 0x00] {
+        assert_eq!(clock(&mut printer, byte), 0x00);
+    }
+    assert_eq!(clock(&mut printer, 0x00), ALIVE);
+    assert_eq!(clock(&mut printer, 0x00), 0x00);
+
+    assert_eq!(packet(&mut printer, STATUS, false, &[]), (ALIVE, 0x00));
+    assert_eq!(
+        packet(&mut printer, DATA, false, &band(0xFF, 0x00)),
+        (ALIVE, PRINTER_STATUS_UNPROCESSED)
+    );
+    /* An empty DATA packet ends the image without changing it. */
+    assert_eq!(
+        packet(&mut printer, DATA, false, &[]),
+        (ALIVE, PRINTER_STATUS_UNPROCESSED)
+    );
+    assert_eq!(
+        packet(&mut printer, 0x05, false, &[]).1 & PRINTER_STATUS_PACKET_ERROR,
+        PRINTER_STATUS_PACKET_ERROR
+    );
+    assert_eq!(packet(&mut printer, INIT, false, &[]), (ALIVE, 0x00));
+}
+
+#[test]
+fn full_after_nine_bands() {
+    let (mut printer, _) = printer();
+    for _ in 0..8 {
+        packet(&mut printer, DATA, false, &band(0, 0));
+    }
+    assert_eq!(
+        packet(&mut printer, STATUS, false, &[]).1,
+        PRINTER_STATUS_UNPROCESSED
+    );
+    assert_eq!(
+        packet(&mut printer, DATA, false, &band(0, 0)).1,
+        PRINTER_STATUS_UNPROCESSED | PRINTER_STATUS_FULL
+    );
+}
+
+#[test]
+fn checksum_failure() {
+    let (mut printer, strips) = printer();
+    let (alive, status) = send(&mut printer, DATA, false, &band(0xFF, 0xFF), 1);
+    assert_eq!(alive, ALIVE);
+    assert_eq!(status, PRINTER_STATUS_CHECKSUM);
+
+    /* The corrupt band was dropped, the next good packet clears the error. */
+    assert_eq!(packet(&mut printer, STATUS, false, &[]).1, 0x00);
+    packet(&mut printer, DATA, false, &band(0xFF, 0x00));
+    packet(&mut printer, PRINT, false, &[1, 0x00, 0xE4, 0x40]);
+    let strips = strips.borrow();
+    assert_eq!(strips[0].height, PRINTER_BAND_HEIGHT);
+    assert!(strips[0].pixels.iter().all(|&shade| shade == 1));
+}
+
+#[test]
+fn print_with_margins_and_palette() {
+    let (mut printer, strips) = printer();
+    packet(&mut printer, DATA, false, &band(0xFF, 0x00));
+    packet(&mut printer, DATA, false, &band(0x00, 0xFF));
+    /* One margin unit before, two after, colours 1 and 2 swapped. */
+    assert_eq!(
+        packet(&mut printer, PRINT, false, &[1, 0x12, 0b11_01_10_00, 0x40]),
+        (ALIVE, PRINTER_STATUS_PRINTING)
+    );
+
+    let mut polls = 0;
+    while packet(&mut printer, STATUS, false, &[]).1 & PRINTER_STATUS_PRINTING != 0 {
+        polls += 1;
+        assert!(polls < 10);
+    }
+    assert_eq!(packet(&mut printer, STATUS, false, &[]).1, 0x00);
+
+    let strips = strips.borrow();
+    assert_eq!(strips.len(), 1);
+    let strip = &strips[0];
+    let band = PRINTER_BAND_HEIGHT;
+    assert_eq!(strip.height, band + 2 * band + 2 * band);
+    let row = |y: usize| &strip.pixels[y * PRINTER_WIDTH..][..PRINTER_WIDTH];
+    assert!(row(0).iter().all(|&shade| shade == 0));
+    assert!(row(band).iter().all(|&shade| shade == 2));
+    assert!(row(2 * band).iter().all(|&shade| shade == 1));
+    assert!(row(3 * band).iter().all(|&shade| shade == 0));
+}
+
+#[test]
+fn partial_band_is_dropped() {
+    let (mut printer, strips) = printer();
+    /* One tile, less than a band. */
+    packet(&mut printer, DATA, false, &[0xFF; 16]);
+    packet(&mut printer, PRINT, false, &[1, 0x00, 0xE4, 0x40]);
+    assert_eq!(strips.borrow()[0].height, 0);
+
+    /* A band and a half prints the band. */
+    let mut data = band(0xFF, 0xFF);
+    data.extend_from_slice(&band(0xFF, 0xFF)[..BAND_SIZE / 2]);
+    packet(&mut printer, DATA, false, &data);
+    packet(&mut printer, PRINT, false, &[1, 0x00, 0xE4, 0x40]);
+    let strips = strips.borrow();
+    assert_eq!(strips[1].height, PRINTER_BAND_HEIGHT);
+    assert!(strips[1].pixels.iter().all(|&shade| shade == 3));
+}
+
+#[test]
+fn break_stops_printing() {
+    let (mut printer, _) = printer();
+    packet(&mut printer, DATA, false, &band(0, 0));
+    packet(&mut printer, PRINT, false, &[1, 0x00, 0xE4, 0x40]);
+    assert_eq!(packet(&mut printer, BREAK, false, &[]).1, 0x00);
+}
+
+#[test]
+fn rle_compressed_data() {
+    let (mut printer, strips) = printer();
+    /* The first tile row as 2 literal bytes, colour 1, then the other 638
+     * bytes of the band as runs of 0xFF, colour 3, 129 at most each. */
+    let mut rle = vec![0x01, 0xFF, 0x00];
+    for _ in 0..4 {
+        rle.extend_from_slice(&[0xFF, 0xFF]); /* 129 copies of 0xFF */
+    }
+    rle.extend_from_slice(&[0x80 | (122 - 2), 0xFF]);
+    assert_eq!(
+        packet(&mut printer, DATA, true, &rle).1,
+        PRINTER_STATUS_UNPROCESSED
+    );
+    packet(&mut printer, PRINT, false, &[1, 0x00, 0xE4, 0x40]);
+
+    let strips = strips.borrow();
+    assert_eq!(strips[0].height, PRINTER_BAND_HEIGHT);
+    /* The first tile's first row is colour 1, everything else colour 3. */
+    assert!(strips[0].pixels[..8].iter().all(|&shade| shade == 1));
+    assert!(strips[0].pixels[8..].iter().enumerate().all(|(i, &shade)| {
+        let (x, y) = ((i + 8) % PRINTER_WIDTH, (i + 8) / PRINTER_WIDTH);
+        shade == 3 || (x < 8 && y == 0)
+    }));
+}
+
+#[test]
+fn rle_truncated_run() {
+    let (mut printer, strips) = printer();
+    /* A literal run longer than the packet, then a run without its byte. */
+    packet(&mut printer, DATA, true, &[0x7F, 0xFF, 0xFF]);
+    packet(&mut printer, DATA, true, &[0x85]);
+    packet(&mut printer, PRINT, false, &[1, 0x00, 0xE4, 0x40]);
+    /* Less than a band isn't printed. */
+    assert_eq!(strips.borrow()[0].height, 0);
+}
+
+#[test]
+fn sink_error() {
+    let mut printer = Printer::new(Box::new(|_: PrintStrip| Err(io::Error::other("full"))));
+    packet(&mut printer, DATA, false, &band(0, 0));
+    packet(&mut printer, PRINT, false, &[1, 0x00, 0xE4, 0x40]);
+    assert_ne!(
+        packet(&mut printer, STATUS, false, &[]).1 & PRINTER_STATUS_OTHER_ERROR,
+        0
+    );
+    assert_eq!(packet(&mut printer, INIT, false, &[]).1, 0x00);
+}
+
+#[test]
+fn png_decodes() {
+    let pixels: Vec<u8> = (0..PRINTER_WIDTH * 3).map(|i| (i % 4) as u8).collect();
+    let strip = PrintStrip { height: 3, pixels };
+    let png = strip.to_png();
+
+    let mut reader = png::Decoder::new(png.as_slice()).read_info().unwrap();
+    let mut image = vec![0; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut image).unwrap();
+    assert_eq!((info.width, info.height), (PRINTER_WIDTH as u32, 3));
+    assert_eq!(info.color_type, png::ColorType::Grayscale);
+    assert_eq!(info.bit_depth, png::BitDepth::Two);
+
+    /* Shade 0 is white, the highest grey level. */
+    let decoded: Vec<u8> = image[..info.buffer_size()]
+        .iter()
+        .flat_map(|&byte| (0..4).rev().map(move |i| 3 - (byte >> (i * 2) & 3)))
+        .collect();
+    assert_eq!(decoded, strip.pixels);
+}
+
+#[test]
+fn png_larger_than_a_stored_block() {
+    /* 41 bytes a row, more than 0xFFFF bytes need two deflate blocks. */
+    let height = 0x10000 / 41 + 1;
+    let strip = PrintStrip {
+        height,
+        pixels: vec![2; PRINTER_WIDTH * height],
+    };
+    let png = strip.to_png();
+    let mut reader = png::Decoder::new(png.as_slice()).read_info().unwrap();
+    let mut image = vec![0; reader.output_buffer_size()];
+    reader.next_frame(&mut image).unwrap();
+    assert!(image.iter().all(|&byte| byte == 0b01_01_01_01));
+}
+
+#[test]
+fn directory_numbers_prints() {
+    let dir = env::temp_dir().join(format!("cashew-prints-{}", std::process::id()));
+    let strip = PrintStrip {
+        height: 1,
+        pixels: vec![0; PRINTER_WIDTH],
+    };
+    PrintDirectory::new(&dir)
+        .unwrap()
+        .print(strip.clone())
+        .unwrap();
+    /* A new session carries on after the prints already there. */
+    PrintDirectory::new(&dir).unwrap().print(strip).unwrap();
+    assert!(dir.join("print_0000.png").is_file());
+    assert!(dir.join("print_0001.png").is_file());
+    fs::remove_dir_all(&dir).unwrap();
+}
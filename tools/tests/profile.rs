@@ -0,0 +1,116 @@
+mod common;
+
+use cashew_tools::cashew_gb::{debug::RunUntil, disasm::SymbolTable, profile::Profiler};
+use common::Cart;
+
+const PROGRAM: [u8; 21] = [
+    0xCD, 0x80, 0x01, /* call Sub */
+    0xFF, /* rst 0x38 */
+    0xCD, 0x90, 0x01, /* call Drop */
+    0xAF, /* 0x157: xor a */
+    0xC4, 0x80, 0x01, /* call nz, Sub */
+    0x3E, 0x02, /* ld a, 2 */
+    0xEA, 0x00, 0x20, /* ld (0x2000), a */
+    0xCD, 0x00, 0x40, /* call 0x4000 */
+    0x18, 0xFE, /* 0x163: jr -2 */
+];
+const SUB: [u8; 4] = [
+    0xCD, 0x88, 0x01, /* 0x180: call Leaf */
+    0xC9, /* ret */
+];
+const LEAF: [u8; 2] = [0x00 /* 0x188: nop */, 0xC9 /* ret */];
+/// Pops its return address and jumps back.
+const DROP: [u8; 4] = [
+    0xE1, /* 0x190: pop hl */
+    0xC3, 0x57, 0x01, /* jp 0x157 */
+];
+const SYMBOLS: &str = "00:0150 Main\n00:0180 Sub\n00:0188 Leaf\n00:0190 Drop\n02:4000 Banked\n";
+
+fn profile(cart: &Cart) -> Profiler {
+    let mut gb = cart.gb();
+    gb.gb_debug_run(RunUntil::Pc(0x0150));
+    gb.gb_set_profiler(Some(Profiler::new()));
+    gb.gb_debug_run(RunUntil::Pc(0x0163));
+    gb.gb_set_profiler(None).unwrap()
+}
+
+fn cart() -> Cart {
+    /* Bank 2's function and rst 0x38 only return. */
+    let mut cart = Cart::banked(&PROGRAM, 4, &[0xC9]);
+    cart.rom[0x38] = 0xC9;
+    cart.rom[0x180..0x184].copy_from_slice(&SUB);
+    cart.rom[0x188..0x18A].copy_from_slice(&LEAF);
+    cart.rom[0x190..0x194].copy_from_slice(&DROP);
+    cart
+}
+
+#[test]
+fn calls_and_rst_push_and_unwind() {
+    let cart = cart();
+    let profiler = profile(&cart);
+    let mut collapsed = Vec::new();
+    profiler.write_collapsed(&mut collapsed, None).unwrap();
+    let collapsed = String::from_utf8(collapsed).unwrap();
+    let lines: Vec<&str> = collapsed.lines().collect();
+
+    /* Clocks at 4MiHz: a call's 24 go to the caller, the ret's 16 to the
+     * callee. Drop's frame is gone once its pop leaves SP above it, the
+     * call not taken pushes nothing. */
+    assert_eq!(
+        lines[1..],
+        [
+            "[reset];00:0038 16",
+            "[reset];00:0180 40",
+            "[reset];00:0180;00:0188 20",
+            "[reset];00:0190 12",
+            "[reset];02:4000 16",
+        ]
+    );
+    let root = 24 + 16 + 24 + 16 + 4 + 12 + 8 + 16 + 24;
+    assert_eq!(lines[0], format!("[reset] {root}"));
+    assert_eq!(profiler.get_total(), root + 16 + 40 + 20 + 12 + 16);
+    assert_eq!(profiler.get_halt(), 0);
+}
+
+#[test]
+fn functions_and_hotspots() {
+    let cart = cart();
+    let profiler = profile(&cart);
+    let symbols = SymbolTable::parse(SYMBOLS).unwrap();
+    let functions = profiler.get_functions(&symbols);
+    let function = |name: &str| {
+        functions
+            .iter()
+            .find(|function| function.name == name)
+            .unwrap()
+    };
+
+    assert_eq!(
+        (
+            function("Sub").own,
+            function("Sub").total,
+            function("Sub").calls
+        ),
+        (40, 60, 1)
+    );
+    assert_eq!((function("Leaf").own, function("Leaf").calls), (20, 1));
+    assert_eq!(function("Drop").calls, 1);
+    assert_eq!(function("Banked").total, 16);
+    /* The rst vector comes before the first label. */
+    assert_eq!(function("Main").calls, 0);
+    assert_eq!(function("bank 00").own, 16);
+
+    let hotspots = profiler.get_hotspots();
+    assert_eq!((hotspots[0].bank, hotspots[0].addr), (0, 0x0150));
+    assert_eq!(hotspots[0].cycles, 24);
+    assert!(hotspots
+        .iter()
+        .any(|hotspot| (hotspot.bank, hotspot.addr, hotspot.cycles) == (2, 0x4000, 16)));
+
+    let mut collapsed = Vec::new();
+    profiler
+        .write_collapsed(&mut collapsed, Some(&symbols))
+        .unwrap();
+    let collapsed = String::from_utf8(collapsed).unwrap();
+    assert!(collapsed.contains("[reset];Sub;Leaf 20\n"));
+}
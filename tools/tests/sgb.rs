@@ -0,0 +1,306 @@
+use cashew_tools::cashew_gb::{
+    palette::to_rgb555,
+    sgb::{Sgb, SGB_HEIGHT, SGB_PIXEL_BLACK, SGB_WIDTH},
+};
+
+const JOYP_RESET: u8 = 0x00;
+const JOYP_BIT_1: u8 = 0x10;
+const JOYP_BIT_0: u8 = 0x20;
+const JOYP_IDLE: u8 = 0x30;
+
+const PAL01: u8 = 0x00;
+const ATTR_BLK: u8 = 0x04;
+const ATTR_DIV: u8 = 0x06;
+const ATTR_CHR: u8 = 0x07;
+const PAL_SET: u8 = 0x0A;
+const PAL_TRN: u8 = 0x0B;
+const MLT_REQ: u8 = 0x11;
+const CHR_TRN: u8 = 0x13;
+const PCT_TRN: u8 = 0x14;
+const MASK_EN: u8 = 0x17;
+
+/// The first byte of a command sent in `packets` packets.
+fn command(command: u8, packets: u8) -> u8 {
+    command << 3 | packets
+}
+
+/// A command byte and its arguments, zero padded to a packet.
+fn packet(bytes: &[u8]) -> [u8; 16] {
+    let mut packet = [0; 16];
+    packet[..bytes.len()].copy_from_slice(bytes);
+    packet
+}
+
+/// Pulses the packets through JOYP the way games do, returning what the
+/// idle writes answered.
+fn send(sgb: &mut Sgb, packets: &[[u8; 16]]) -> Vec<Option<u8>> {
+    let mut ids = Vec::new();
+    for packet in packets {
+        sgb.write_joyp(JOYP_RESET);
+        sgb.write_joyp(JOYP_IDLE);
+        for bit in 0..128 {
+            let line = if packet[bit / 8] >> (bit % 8) & 1 != 0 {
+                JOYP_BIT_1
+            } else {
+                JOYP_BIT_0
+            };
+            sgb.write_joyp(line);
+            ids.push(sgb.write_joyp(JOYP_IDLE));
+        }
+        /* Stop bit. */
+        sgb.write_joyp(JOYP_BIT_0);
+        sgb.write_joyp(JOYP_IDLE);
+    }
+    ids
+}
+
+/// The attribute palette `draw_line` gave the 8 pixels of cell `x`, `y`.
+fn attribute(sgb: &mut Sgb, x: usize, y: usize) -> u8 {
+    let mut pixels = [0; 160];
+    assert!(sgb.draw_line(y as u8 * 8, &mut pixels));
+    pixels[x * 8] >> 2
+}
+
+/// Draws a frame whose tiles, row by row, hold `data`, what VRAM transfers
+/// read.
+fn draw_frame(sgb: &mut Sgb, data: &[u8]) {
+    let mut data = data.to_vec();
+    data.resize(20 * 18 * 16, 0);
+    for line in 0..144 {
+        let mut pixels = [0; 160];
+        for (x, pixel) in pixels.iter_mut().enumerate() {
+            let tile = (line / 8) * 20 + x / 8;
+            let row = tile * 16 + (line % 8) * 2;
+            let bit = 7 - x % 8;
+            *pixel = (data[row] >> bit & 1) | (data[row + 1] >> bit & 1) << 1;
+        }
+        sgb.draw_line(line as u8, &mut pixels);
+    }
+}
+
+#[test]
+fn pal01() {
+    let mut sgb = Sgb::new();
+    send(
+        &mut sgb,
+        &[packet(&[
+            command(PAL01, 1),
+            0xFF,
+            0x7F,
+            0x01,
+            0x00,
+            0x02,
+            0x00,
+            0x03,
+            0x00,
+            0x04,
+            0x00,
+            0x05,
+            0x00,
+            0x06,
+            0x00,
+        ])],
+    );
+    let palette = sgb.get_palette();
+    assert_eq!(
+        palette[..8],
+        [0x7FFF, 1, 2, 3, 0x7FFF, 4, 5, 6].map(to_rgb555)
+    );
+    /* Colour 0 is shared with the palettes PAL01 didn't set. */
+    assert_eq!(palette[8], to_rgb555(0x7FFF));
+    assert_eq!(palette[12], to_rgb555(0x7FFF));
+}
+
+#[test]
+fn attr_blk() {
+    let mut sgb = Sgb::new();
+    /* Inside palette 1, border 2, outside 3, cells 2,2 to 5,5. */
+    send(
+        &mut sgb,
+        &[packet(&[
+            command(ATTR_BLK, 1),
+            1,
+            0x07,
+            0b11_10_01,
+            2,
+            2,
+            5,
+            5,
+        ])],
+    );
+    assert_eq!(attribute(&mut sgb, 3, 3), 1);
+    assert_eq!(attribute(&mut sgb, 2, 3), 2);
+    assert_eq!(attribute(&mut sgb, 5, 5), 2);
+    assert_eq!(attribute(&mut sgb, 6, 3), 3);
+    assert_eq!(attribute(&mut sgb, 0, 0), 3);
+}
+
+#[test]
+fn attr_blk_inside_only_colours_the_border() {
+    let mut sgb = Sgb::new();
+    send(
+        &mut sgb,
+        &[packet(&[command(ATTR_BLK, 1), 1, 0x01, 0x01, 2, 2, 5, 5])],
+    );
+    let mut pixels = [3; 160];
+    assert!(sgb.draw_line(16, &mut pixels));
+    assert_eq!(pixels[8], 3);
+    assert_eq!(pixels[16], 0x04 | 3);
+    assert_eq!(pixels[40], 0x04 | 3);
+    assert_eq!(pixels[48], 3);
+}
+
+#[test]
+fn attr_blk_over_two_packets() {
+    let mut sgb = Sgb::new();
+    /* The third set starts in the first packet and ends in the second. */
+    let sets = [
+        [0x01, 0x01, 0, 0, 0, 0],
+        [0x01, 0x02, 1, 0, 1, 0],
+        [0x01, 0x03, 2, 0, 2, 0],
+    ];
+    let mut data = vec![command(ATTR_BLK, 2), sets.len() as u8];
+    data.extend(sets.iter().flatten());
+    data.resize(32, 0);
+    send(&mut sgb, &[packet(&data[..16]), packet(&data[16..])]);
+    assert_eq!(attribute(&mut sgb, 0, 0), 1);
+    assert_eq!(attribute(&mut sgb, 1, 0), 2);
+    assert_eq!(attribute(&mut sgb, 2, 0), 3);
+    assert_eq!(attribute(&mut sgb, 3, 0), 0);
+}
+
+#[test]
+fn attr_div_and_chr() {
+    let mut sgb = Sgb::new();
+    /* Split at row 10: above palette 2, on it 3, below 1. */
+    send(
+        &mut sgb,
+        &[packet(&[
+            command(ATTR_DIV, 1),
+            0x40 | 3 << 4 | 2 << 2 | 1,
+            10,
+        ])],
+    );
+    assert_eq!(attribute(&mut sgb, 0, 0), 2);
+    assert_eq!(attribute(&mut sgb, 0, 10), 3);
+    assert_eq!(attribute(&mut sgb, 0, 17), 1);
+
+    /* 5 cells from 18,0 left to right, wrapping onto the next row. */
+    send(
+        &mut sgb,
+        &[packet(&[
+            command(ATTR_CHR, 1),
+            18,
+            0,
+            5,
+            0,
+            0,
+            0b11_10_01_00,
+            0b11_00_00_00,
+        ])],
+    );
+    assert_eq!(attribute(&mut sgb, 18, 0), 3);
+    assert_eq!(attribute(&mut sgb, 19, 0), 2);
+    assert_eq!(attribute(&mut sgb, 0, 1), 1);
+    assert_eq!(attribute(&mut sgb, 1, 1), 0);
+    assert_eq!(attribute(&mut sgb, 2, 1), 3);
+}
+
+#[test]
+fn mask_en() {
+    let mut sgb = Sgb::new();
+    send(&mut sgb, &[packet(&[command(MASK_EN, 1), 1])]);
+    assert!(!sgb.draw_line(0, &mut [0; 160]));
+
+    send(&mut sgb, &[packet(&[command(MASK_EN, 1), 2])]);
+    let mut pixels = [0; 160];
+    assert!(sgb.draw_line(0, &mut pixels));
+    assert!(pixels.iter().all(|&pixel| pixel == SGB_PIXEL_BLACK));
+    assert_eq!(sgb.get_palette()[SGB_PIXEL_BLACK as usize], 0);
+
+    send(&mut sgb, &[packet(&[command(MASK_EN, 1), 0])]);
+    assert_eq!(attribute(&mut sgb, 0, 0), 0);
+}
+
+#[test]
+fn mlt_req() {
+    let mut sgb = Sgb::new();
+    assert_eq!(sgb.get_player_count(), 1);
+    assert_eq!(sgb.write_joyp(JOYP_IDLE), None);
+
+    let ids = send(&mut sgb, &[packet(&[command(MLT_REQ, 1), 1])]);
+    assert_eq!(sgb.get_player_count(), 2);
+    assert_eq!(ids.last(), Some(&Some(0x0F)));
+
+    /* Each time P15 goes back high the next joypad is selected. */
+    assert_eq!(sgb.write_joyp(JOYP_BIT_0), None);
+    assert_eq!(sgb.write_joyp(JOYP_BIT_1), None);
+    assert_eq!(sgb.write_joyp(JOYP_IDLE), Some(0x0E));
+    assert_eq!(sgb.get_player(), 1);
+    assert_eq!(sgb.write_joyp(JOYP_BIT_1), None);
+    assert_eq!(sgb.write_joyp(JOYP_IDLE), Some(0x0F));
+    assert_eq!(sgb.get_player(), 0);
+
+    send(&mut sgb, &[packet(&[command(MLT_REQ, 1), 3])]);
+    assert_eq!(sgb.get_player_count(), 4);
+    send(&mut sgb, &[packet(&[command(MLT_REQ, 1), 0])]);
+    assert_eq!(sgb.get_player_count(), 1);
+    assert_eq!(sgb.write_joyp(JOYP_IDLE), None);
+}
+
+#[test]
+fn pal_trn_and_pal_set() {
+    let mut sgb = Sgb::new();
+    /* System palette colours counting up from 0. */
+    let mut data = vec![0; 0x1000];
+    for (i, colour) in data.chunks_mut(2).enumerate() {
+        colour.copy_from_slice(&(i as u16).to_le_bytes());
+    }
+    send(&mut sgb, &[packet(&[command(PAL_TRN, 1)])]);
+    /* The transfer waits for a frame drawn whole after the command. */
+    draw_frame(&mut sgb, &data);
+
+    send(
+        &mut sgb,
+        &[packet(&[command(PAL_SET, 1), 5, 0, 6, 0, 7, 0, 8, 0, 0])],
+    );
+    let palette = sgb.get_palette();
+    assert_eq!(palette[0], to_rgb555(20));
+    assert_eq!(palette[1], to_rgb555(21));
+    assert_eq!(palette[5], to_rgb555(25));
+    assert_eq!(palette[15], to_rgb555(35));
+}
+
+#[test]
+fn border() {
+    let mut sgb = Sgb::new();
+    let mut frame = vec![0; SGB_WIDTH * SGB_HEIGHT];
+    assert!(!sgb.render_border(&mut frame));
+
+    /* Tile 1 is colour 1 all over. */
+    let mut tiles = vec![0; 0x1000];
+    for row in 0..8 {
+        tiles[32 + row * 2] = 0xFF;
+    }
+    send(&mut sgb, &[packet(&[command(CHR_TRN, 1), 0])]);
+    draw_frame(&mut sgb, &tiles);
+    assert!(sgb.take_border_changed());
+    assert!(!sgb.render_border(&mut frame));
+
+    /* Tile 1 with border palette 4 in the top left corner only. */
+    let mut map = vec![0; 0x1000];
+    map[0] = 1;
+    map[1] = 4 << 2;
+    map[0x800 + 2..0x800 + 4].copy_from_slice(&0x1234_u16.to_le_bytes());
+    send(&mut sgb, &[packet(&[command(PCT_TRN, 1)])]);
+    draw_frame(&mut sgb, &map);
+    assert!(sgb.take_border_changed());
+    assert!(!sgb.take_border_changed());
+
+    assert!(sgb.render_border(&mut frame));
+    assert_eq!(frame[0], to_rgb555(0x1234));
+    assert_eq!(frame[7 * SGB_WIDTH + 7], to_rgb555(0x1234));
+    /* Transparent tiles show colour 0. */
+    assert_eq!(frame[8], sgb.get_palette()[0]);
+    assert_eq!(frame[SGB_WIDTH * SGB_HEIGHT - 1], sgb.get_palette()[0]);
+}
@@ -0,0 +1,106 @@
+mod common;
+
+use cashew_tools::cashew_gb::single_step::{BusAccess, CpuState};
+use common::Cart;
+use serde_json::{json, Value};
+use std::{env, fs, process::Command};
+
+const CODE: u16 = 0xC000;
+
+fn read(addr: u16, val: u8) -> BusAccess {
+    BusAccess {
+        addr,
+        val,
+        write: false,
+    }
+}
+
+fn write(addr: u16, val: u8) -> BusAccess {
+    BusAccess {
+        addr,
+        val,
+        write: true,
+    }
+}
+
+#[test]
+fn bus_accesses_in_order() {
+    let cart = Cart::new(&[], false);
+    let mut gb = cart.gb();
+    let initial = CpuState {
+        a: 0x42,
+        b: 0x12,
+        c: 0x34,
+        h: 0xD0,
+        sp: 0xFFFE,
+        pc: CODE,
+        ..Default::default()
+    };
+
+    /* ld (hl), a */
+    let (_, cycles) = gb.gb_run_cpu_test(&initial, &[(CODE, 0x77)]);
+    assert_eq!(cycles, 2);
+    assert_eq!(
+        gb.take_bus_accesses(),
+        [read(CODE, 0x77), write(0xD000, 0x42)]
+    );
+    assert_eq!(gb.take_bus_accesses(), []);
+
+    /* push bc, high byte first */
+    gb.gb_run_cpu_test(&initial, &[(CODE, 0xC5)]);
+    assert_eq!(
+        gb.take_bus_accesses(),
+        [read(CODE, 0xC5), write(0xFFFD, 0x12), write(0xFFFC, 0x34)]
+    );
+
+    /* Peeks and pokes aren't the CPU's. */
+    gb.gb_peek(CODE);
+    gb.gb_poke(0xD000, 0);
+    assert_eq!(gb.take_bus_accesses(), []);
+}
+
+/// A vector running the opcode at 0xC000, with the M-cycles given.
+fn vector(name: &str, opcode: u8, pc: u16, cycles: Value) -> Value {
+    let state = |pc: u16| {
+        json!({
+            "a": 0, "b": 0, "c": 0, "d": 0, "e": 0, "f": 0, "h": 0, "l": 0,
+            "sp": 0xFFFE, "pc": pc, "ime": 0, "ram": [[CODE, opcode]],
+        })
+    };
+    json!({
+        "name": name,
+        "initial": state(CODE),
+        "final": state(pc),
+        "cycles": cycles,
+    })
+}
+
+#[test]
+fn runner_compares_the_bus_and_skips_halt() {
+    let path = env::temp_dir().join(format!("cashew-sm83-{}.json", std::process::id()));
+    let cases = json!([
+        vector("nop", 0x00, CODE + 1, json!([[CODE, 0x00, "r-m"]])),
+        vector(
+            "nop wrong bus",
+            0x00,
+            CODE + 1,
+            json!([[CODE, 0x01, "r-m"]])
+        ),
+        vector("halt", 0x76, CODE + 1, json!([[CODE, 0x76, "r-m"], null])),
+    ]);
+    fs::write(&path, cases.to_string()).unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_sm83-tests"))
+        .arg(&path)
+        .output()
+        .unwrap();
+    fs::remove_file(&path).unwrap();
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(!output.status.success());
+    assert!(stdout.contains("1 of 2 failed, 1 skipped"), "{stdout}");
+    assert!(
+        stdout.contains("nop wrong bus: bus [r C000:00]\n  expected [r C000:01]"),
+        "{stdout}"
+    );
+    assert!(stdout.contains("1 passed, 1 failed, 1 skipped"), "{stdout}");
+}
@@ -0,0 +1,126 @@
+mod common;
+
+use cashew_tools::cashew_gb::{trace::Tracer, Gb};
+use common::Cart;
+use std::{cell::RefCell, io, io::Write, rc::Rc};
+
+const PROGRAM: [u8; 6] = [
+    0x3E, 0x42, /* ld a, 0x42 */
+    0x06, 0x07, /* ld b, 7 */
+    0x18, 0xFE, /* 0x154: jr -2 */
+];
+
+/// A writer the test keeps a handle on.
+#[derive(Clone, Default)]
+struct Log(Rc<RefCell<Vec<u8>>>);
+impl Log {
+    fn lines(&self) -> Vec<String> {
+        String::from_utf8(self.0.borrow().clone())
+            .unwrap()
+            .lines()
+            .map(str::to_string)
+            .collect()
+    }
+}
+impl Write for Log {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.borrow_mut().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Runs `instructions` instructions, as `Gb::cycle` counts them.
+fn run(gb: &mut Gb<Cart>, instructions: usize) {
+    for _ in 0..instructions {
+        gb._step_cpu();
+    }
+}
+
+#[test]
+fn gameboy_doctor_layout() {
+    let cart = Cart::new(&PROGRAM, false);
+    let mut gb = cart.gb();
+    let log = Log::default();
+    gb.gb_set_tracer(Some(Tracer::new(Box::new(log.clone()))));
+    run(&mut gb, 5);
+
+    let lines = log.lines();
+    let pcs: Vec<&str> = lines
+        .iter()
+        .map(|line| line.split(' ').nth(9).unwrap())
+        .collect();
+    assert_eq!(pcs, ["PC:0100", "PC:0101", "PC:0150", "PC:0152", "PC:0154"]);
+    /* Before each instruction runs, F's flags in the high nibble. */
+    let fields: Vec<&str> = lines[3].split(' ').collect();
+    assert_eq!(fields[0], "A:42");
+    assert!(fields[1].starts_with("F:") && fields[1].ends_with('0'));
+    assert_eq!(fields[2], "B:00");
+    assert_eq!(fields[8], "SP:FFFE");
+    assert_eq!(fields[10], "PCMEM:06,07,18,FE");
+    assert_eq!(fields.len(), 11);
+    assert_eq!(lines[4].split(' ').nth(2), Some("B:07"));
+}
+
+#[test]
+fn sampling_window() {
+    let cart = Cart::new(&PROGRAM, false);
+    let mut gb = cart.gb();
+    let log = Log::default();
+    let tracer = Tracer::new(Box::new(log.clone())).cycles(2..12).every(3);
+    gb.gb_set_tracer(Some(tracer));
+    run(&mut gb, 20);
+
+    /* Instructions 2, 5, 8 and 11, counted from the window's start. */
+    let pcs: Vec<String> = log
+        .lines()
+        .iter()
+        .map(|line| line.split(' ').nth(9).unwrap().to_string())
+        .collect();
+    assert_eq!(pcs, ["PC:0150", "PC:0154", "PC:0154", "PC:0154"]);
+}
+
+#[test]
+fn pc_range() {
+    let cart = Cart::new(&PROGRAM, false);
+    let mut gb = cart.gb();
+    let log = Log::default();
+    let tracer = Tracer::new(Box::new(log.clone())).pc_range(0x0150..=0x0153);
+    gb.gb_set_tracer(Some(tracer));
+    run(&mut gb, 10);
+    assert_eq!(log.lines().len(), 2);
+}
+
+#[test]
+fn sampled_every_ten_thousand() {
+    let cart = Cart::new(&PROGRAM, false);
+    let mut gb = cart.gb();
+    let log = Log::default();
+    gb.gb_set_tracer(Some(Tracer::sampled(Box::new(log.clone()))));
+    run(&mut gb, 10_000);
+    assert_eq!(log.lines().len(), 1);
+    run(&mut gb, 1);
+    assert_eq!(log.lines().len(), 2);
+    assert!(log.lines()[1].contains("PC:0154"));
+}
+
+struct Broken;
+impl Write for Broken {
+    fn write(&mut self, _: &[u8]) -> io::Result<usize> {
+        Err(io::ErrorKind::BrokenPipe.into())
+    }
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn failing_writer_stops_tracing() {
+    let cart = Cart::new(&PROGRAM, false);
+    let mut gb = cart.gb();
+    gb.gb_set_tracer(Some(Tracer::new(Box::new(Broken))));
+    run(&mut gb, 1);
+    assert!(gb.gb_set_tracer(None).is_none());
+}
@@ -0,0 +1,113 @@
+mod common;
+
+use cashew_tools::cashew_gb::{
+    palette,
+    viewer::{Image, TileMap},
+    Gb,
+};
+use common::Cart;
+
+/// BGP as is, so each colour has its own shade.
+const PROGRAM: [u8; 6] = [
+    0x3E, 0xE4, /* ld a, 0xE4 */
+    0xE0, 0x47, /* ldh (BGP), a */
+    0x18, 0xFE, /* jr -2 */
+];
+const COLOUR_VIEWPORT: u16 = palette::to_rgb555(palette::rgb(0xFF, 0x20, 0x20));
+
+/// Fills the tile at `addr` with colour `colour`.
+fn tile(gb: &mut Gb<Cart>, addr: u16, colour: u8) {
+    let low = if colour & 1 != 0 { 0xFF } else { 0x00 };
+    let high = if colour & 2 != 0 { 0xFF } else { 0x00 };
+    for row in 0..8 {
+        gb.gb_poke(addr + row * 2, low);
+        gb.gb_poke(addr + row * 2 + 1, high);
+    }
+}
+
+/// The colour of map entry `entry`'s top left pixel.
+fn entry(image: &Image, entry: usize) -> u16 {
+    image.get((entry % 32) * 8 + 1, (entry / 32) * 8 + 1)
+}
+
+/// Tiles 0x8000, 0x8800 and 0x9000 in colours 0, 1 and 3, with the map at
+/// 0x9800 naming tile 0 then tile 0x80.
+fn setup(cart: &Cart) -> Gb<'_, Cart> {
+    let mut gb = cart.gb();
+    gb.gb_set_dmg_palette(&palette::DMG_GREEN);
+    /* nop, jp 0x150, then the program up to the loop. */
+    for _ in 0..4 {
+        gb._step_cpu();
+    }
+    gb.gb_poke(0xFF42, 0x00);
+    gb.gb_poke(0xFF43, 0x00);
+    tile(&mut gb, 0x8000, 0);
+    tile(&mut gb, 0x8800, 1);
+    tile(&mut gb, 0x9000, 3);
+    for addr in 0x9800..0x9C00 {
+        gb.gb_poke(addr, 0x00);
+    }
+    gb.gb_poke(0x9801, 0x80);
+    gb
+}
+
+#[test]
+fn signed_tile_addressing() {
+    let cart = Cart::new(&PROGRAM, false);
+    let mut gb = setup(&cart);
+    gb.gb_poke(0xFF40, 0x91);
+    let unsigned = gb.get_tile_map(TileMap::Map9800);
+    gb.gb_poke(0xFF40, 0x81);
+    let signed = gb.get_tile_map(TileMap::Map9800);
+
+    /* Tile 0 is 0x8000 unsigned and 0x9000 signed, tile 0x80 is 0x8800
+     * either way. */
+    assert_ne!(entry(&unsigned, 0), entry(&signed, 0));
+    assert_eq!(entry(&unsigned, 1), entry(&signed, 1));
+    assert_ne!(entry(&unsigned, 1), entry(&unsigned, 0));
+    assert_ne!(entry(&signed, 1), entry(&signed, 0));
+
+    let sheet = gb.get_tile_sheet(0).unwrap();
+    assert_eq!(sheet.get(1, 1), entry(&unsigned, 0));
+    /* Tile 0x100 is 0x9000, 16 to a row. */
+    assert_eq!(sheet.get(1, 16 * 8 + 1), entry(&signed, 0));
+}
+
+#[test]
+fn other_map() {
+    let cart = Cart::new(&PROGRAM, false);
+    let mut gb = setup(&cart);
+    gb.gb_poke(0xFF40, 0x91);
+    for addr in 0x9C00..0xA000 {
+        gb.gb_poke(addr, 0x80);
+    }
+    let low = gb.get_tile_map(TileMap::Map9800);
+    let high = gb.get_tile_map(TileMap::Map9C00);
+    assert_eq!(entry(&high, 0), entry(&low, 1));
+    assert_eq!(entry(&high, 500), entry(&low, 1));
+}
+
+#[test]
+fn viewport_wraps() {
+    let cart = Cart::new(&PROGRAM, false);
+    let mut gb = setup(&cart);
+    gb.gb_poke(0xFF40, 0x91);
+    gb.gb_poke(0xFF43, 200);
+    gb.gb_poke(0xFF42, 250);
+    let map = gb.get_tile_map(TileMap::Map9800);
+
+    /* The corners, the last three past the map's edges. */
+    assert_eq!(map.get(200, 250), COLOUR_VIEWPORT);
+    assert_eq!(map.get(103, 250), COLOUR_VIEWPORT);
+    assert_eq!(map.get(200, 137), COLOUR_VIEWPORT);
+    assert_eq!(map.get(103, 137), COLOUR_VIEWPORT);
+    /* Edges carry on across the wrap. */
+    assert_eq!(map.get(0, 250), COLOUR_VIEWPORT);
+    assert_eq!(map.get(200, 0), COLOUR_VIEWPORT);
+    assert_eq!(map.get(103, 0), COLOUR_VIEWPORT);
+    /* Inside and outside it, the map. */
+    assert_ne!(map.get(210, 10), COLOUR_VIEWPORT);
+    assert_ne!(map.get(150, 200), COLOUR_VIEWPORT);
+    assert_ne!(map.get(104, 250), COLOUR_VIEWPORT);
+    assert_ne!(map.get(200, 138), COLOUR_VIEWPORT);
+}